@@ -180,6 +180,8 @@ func (app *keymanagerApplication) onEpochChange(ctx *tmapi.Context, epoch beacon
 				"is_initialized", newStatus.IsInitialized,
 				"is_secure", newStatus.IsSecure,
 				"checksum", hex.EncodeToString(newStatus.Checksum),
+				"generation", newStatus.Generation,
+				"rotation_epoch", newStatus.RotationEpoch,
 				"rsk", newStatus.RSK,
 				"nodes", newStatus.Nodes,
 			)
@@ -227,9 +229,17 @@ func (app *keymanagerApplication) generateStatus(
 		IsInitialized: oldStatus.IsInitialized,
 		IsSecure:      oldStatus.IsSecure,
 		Checksum:      oldStatus.Checksum,
+		Generation:    oldStatus.Generation,
+		RotationEpoch: oldStatus.RotationEpoch,
 		Policy:        oldStatus.Policy,
 	}
 
+	// Nodes may propose the next master secret generation once the rotation is due.
+	// The first proposal gets to be the source of truth, every other node will
+	// replicate it, including those which proposed a different one.
+	rotationDue := oldStatus.RotationDue(epoch)
+	var nextChecksum []byte
+
 	var rawPolicy []byte
 	if status.Policy != nil {
 		rawPolicy = cbor.Marshal(status.Policy)
@@ -254,8 +264,10 @@ nextNode:
 		isInitialized := status.IsInitialized
 		isSecure := status.IsSecure
 		checksum := status.Checksum
+		generation := status.Generation
 		RSK := status.RSK
 
+		var proposal []byte
 		var numVersions int
 		for _, nodeRt := range n.Runtimes {
 			if !nodeRt.ID.Equal(&kmrt.ID) {
@@ -307,6 +319,7 @@ nextNode:
 				isInitialized = true
 				isSecure = initResponse.IsSecure
 				checksum = initResponse.Checksum
+				generation = initResponse.Generation
 			}
 
 			// Skip nodes with mismatched status fields.
@@ -314,6 +327,12 @@ nextNode:
 				ctx.Logger().Error("Security status mismatch for runtime", vars...)
 				continue nextNode
 			}
+			if initResponse.Generation != generation {
+				ctx.Logger().Error("Master secret generation mismatch for runtime",
+					append(vars, "generation", initResponse.Generation)...,
+				)
+				continue nextNode
+			}
 			if !bytes.Equal(initResponse.Checksum, checksum) {
 				ctx.Logger().Error("Checksum mismatch for runtime", vars...)
 				continue nextNode
@@ -332,6 +351,10 @@ nextNode:
 				continue nextNode
 			}
 
+			if proposal == nil && len(initResponse.NextChecksum) > 0 {
+				proposal = initResponse.NextChecksum
+			}
+
 			numVersions++
 		}
 
@@ -348,12 +371,26 @@ nextNode:
 			status.IsInitialized = true
 			status.IsSecure = isSecure
 			status.Checksum = checksum
+			status.Generation = generation
 		}
 		status.RSK = RSK
 
+		// Accept the first proposal of the next master secret generation.
+		if rotationDue && nextChecksum == nil {
+			nextChecksum = proposal
+		}
+
 		status.Nodes = append(status.Nodes, n.ID)
 	}
 
+	// Rotate the master secret. Nodes stay in the committee until the next epoch
+	// so that they have time to replicate the accepted generation.
+	if nextChecksum != nil {
+		status.Checksum = nextChecksum
+		status.Generation++
+		status.RotationEpoch = epoch
+	}
+
 	return status
 }
 
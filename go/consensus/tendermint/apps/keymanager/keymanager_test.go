@@ -1,6 +1,7 @@
 package keymanager
 
 import (
+	"fmt"
 	"testing"
 	"time"
 
@@ -276,6 +277,81 @@ func TestGenerateStatus(t *testing.T) {
 		newStatus = app.generateStatus(ctx, runtimes[1], initializedStatus, reverse(nodes), params, epoch)
 		require.Equal(expStatus, newStatus, "node 4 and 8 should form the committee")
 	})
+
+	t.Run("Rotation", func(t *testing.T) {
+		require := require.New(t)
+
+		rotationPolicy := api.SignedPolicySGX{
+			Policy: api.PolicySGX{
+				Serial:                       1,
+				MasterSecretRotationInterval: 5,
+			},
+		}
+		rotationPolicyChecksum := sha3.Sum256(cbor.Marshal(rotationPolicy))
+
+		// Nodes 9 and 10 propose different next generations, node 11 doesn't propose any.
+		rotationNodes := make([]*node.Node, 3)
+		nextChecksums := [][]byte{{6, 7, 8, 9, 10}, {11, 12, 13, 14, 15}, nil}
+		for i, nextChecksum := range nextChecksums {
+			rotationResponse := api.InitResponse{
+				IsSecure:       true,
+				Checksum:       checksum,
+				PolicyChecksum: rotationPolicyChecksum[:],
+				NextChecksum:   nextChecksum,
+			}
+			sigRotationResponse, err := api.SignInitResponse(rakSigner, &rotationResponse)
+			require.NoError(err, "SignInitResponse")
+
+			rotationNodes[i] = &node.Node{
+				ID:         memorySigner.NewTestSigner(fmt.Sprintf("node %d", 9+i)).Public(),
+				Expiration: uint64(epoch),
+				Roles:      node.RoleKeyManager,
+				Runtimes: []*node.Runtime{
+					{
+						ID:        runtimeIDs[0],
+						Version:   version.Version{Major: 2, Minor: 0, Patch: 0},
+						ExtraInfo: cbor.Marshal(sigRotationResponse),
+					},
+				},
+			}
+		}
+		oldStatus := &api.Status{
+			ID:            runtimeIDs[0],
+			IsInitialized: true,
+			IsSecure:      true,
+			Checksum:      checksum,
+			RotationEpoch: epoch - 4,
+			Policy:        &rotationPolicy,
+		}
+
+		// Proposals are ignored until the rotation is due.
+		expStatus := &api.Status{
+			ID:            runtimeIDs[0],
+			IsInitialized: true,
+			IsSecure:      true,
+			Checksum:      checksum,
+			RotationEpoch: epoch - 4,
+			Policy:        &rotationPolicy,
+			Nodes:         []signature.PublicKey{rotationNodes[0].ID, rotationNodes[1].ID, rotationNodes[2].ID},
+		}
+		newStatus := app.generateStatus(ctx, runtimes[0], oldStatus, rotationNodes, params, epoch)
+		require.Equal(expStatus, newStatus, "master secret should not be rotated before the interval")
+
+		// The first proposal is the source of truth, the committee stays the same
+		// so that nodes can replicate the new generation.
+		oldStatus.RotationEpoch = epoch - 5
+		expStatus.Checksum = nextChecksums[0]
+		expStatus.Generation = 1
+		expStatus.RotationEpoch = epoch
+		newStatus = app.generateStatus(ctx, runtimes[0], oldStatus, rotationNodes, params, epoch)
+		require.Equal(expStatus, newStatus, "node 9 should rotate the master secret")
+
+		// Once rotated, nodes with an older generation are ignored and the master secret
+		// is not rotated again.
+		expStatus.Nodes = nil
+		newStatus = app.generateStatus(ctx, runtimes[0], newStatus, rotationNodes, params, epoch)
+		require.Equal(expStatus, newStatus, "nodes with older generations should be ignored")
+	})
 }
 
 func reverse(nodes []*node.Node) []*node.Node {
@@ -12,12 +12,14 @@ import (
 	beacon "github.com/oasisprotocol/oasis-core/go/beacon/api"
 	"github.com/oasisprotocol/oasis-core/go/common"
 	"github.com/oasisprotocol/oasis-core/go/common/cbor"
+	"github.com/oasisprotocol/oasis-core/go/common/crypto/hash"
 	"github.com/oasisprotocol/oasis-core/go/common/crypto/signature"
 	memorySigner "github.com/oasisprotocol/oasis-core/go/common/crypto/signature/signers/memory"
 	"github.com/oasisprotocol/oasis-core/go/common/errors"
 	"github.com/oasisprotocol/oasis-core/go/common/logging"
 	"github.com/oasisprotocol/oasis-core/go/common/node"
 	"github.com/oasisprotocol/oasis-core/go/common/pubsub"
+	"github.com/oasisprotocol/oasis-core/go/common/sgx"
 	"github.com/oasisprotocol/oasis-core/go/consensus/api/transaction"
 	registry "github.com/oasisprotocol/oasis-core/go/registry/api"
 )
@@ -78,12 +80,33 @@ var (
 	// RPCMethodGetPublicEphemeralKey is the name of the `get_public_ephemeral_key` method.
 	RPCMethodGetPublicEphemeralKey = "get_public_ephemeral_key"
 
+	// RPCMethodGetPublicSigningKey is the name of the `get_public_signing_key` method.
+	RPCMethodGetPublicSigningKey = "get_public_signing_key"
+
+	// RPCMethodGetPublicEphemeralSigningKey is the name of the `get_public_ephemeral_signing_key`
+	// method.
+	RPCMethodGetPublicEphemeralSigningKey = "get_public_ephemeral_signing_key"
+
+	// RPCMethodCommitMasterSecretContribution is the name of the
+	// `commit_master_secret_contribution` method.
+	RPCMethodCommitMasterSecretContribution = "commit_master_secret_contribution"
+
+	// RPCMethodRevealMasterSecretContribution is the name of the
+	// `reveal_master_secret_contribution` method.
+	RPCMethodRevealMasterSecretContribution = "reveal_master_secret_contribution"
+
+	// RPCMethodConfirmMasterSecret is the name of the `confirm_master_secret` method.
+	RPCMethodConfirmMasterSecret = "confirm_master_secret"
+
 	// RPCMethodGenerateEphemeralSecret is the name of the `generate_ephemeral_secret` RPC method.
 	RPCMethodGenerateEphemeralSecret = "generate_ephemeral_secret"
 
 	// RPCMethodLoadEphemeralSecret is the name of the `load_ephemeral_secret` RPC method.
 	RPCMethodLoadEphemeralSecret = "load_ephemeral_secret"
 
+	// RPCMethodExportAuditLog is the name of the `export_audit_log` RPC method.
+	RPCMethodExportAuditLog = "export_audit_log"
+
 	// initResponseSignatureContext is the context used to sign key manager init responses.
 	initResponseSignatureContext = signature.NewContext("oasis-core/keymanager: init response")
 )
@@ -122,6 +145,12 @@ type Status struct {
 	// Checksum is the key manager master secret verification checksum.
 	Checksum []byte `json:"checksum"`
 
+	// Generation is the latest master secret generation, which the checksum belongs to.
+	Generation uint64 `json:"generation,omitempty"`
+
+	// RotationEpoch is the epoch in which the latest master secret generation was accepted.
+	RotationEpoch beacon.EpochTime `json:"rotation_epoch,omitempty"`
+
 	// Nodes is the list of currently active key manager node IDs.
 	Nodes []signature.PublicKey `json:"nodes"`
 
@@ -132,6 +161,16 @@ type Status struct {
 	RSK *signature.PublicKey `json:"rsk,omitempty"`
 }
 
+// RotationDue returns true iff the policy allows the master secret to be rotated
+// in the given epoch.
+func (s *Status) RotationDue(epoch beacon.EpochTime) bool {
+	if !s.IsInitialized || s.Policy == nil {
+		return false
+	}
+	interval := s.Policy.Policy.MasterSecretRotationInterval
+	return interval > 0 && epoch >= s.RotationEpoch+interval
+}
+
 // Backend is a key manager management implementation.
 type Backend interface {
 	// GetStatus returns a key manager status by key manager ID.
@@ -172,6 +211,14 @@ type InitRequest struct {
 	Checksum    []byte `json:"checksum"`
 	Policy      []byte `json:"policy"`
 	MayGenerate bool   `json:"may_generate"`
+	// Generation is the master secret generation the checksum belongs to.
+	Generation uint64 `json:"generation,omitempty"`
+	// MayRotate is true iff the enclave may propose a new master secret generation.
+	// It should only be set when the rotation is due according to the key manager status.
+	MayRotate bool `json:"may_rotate,omitempty"`
+	// MinContributors is the minimum number of key manager enclaves, including this one,
+	// which must contribute randomness to a newly generated master secret.
+	MinContributors uint16 `json:"min_contributors,omitempty"`
 }
 
 // InitResponse is the initialization RPC response, returned as part of a
@@ -181,6 +228,87 @@ type InitResponse struct {
 	Checksum       []byte               `json:"checksum"`
 	PolicyChecksum []byte               `json:"policy_checksum"`
 	RSK            *signature.PublicKey `json:"rsk,omitempty"`
+	// Generation is the latest master secret generation, which the checksum belongs to.
+	Generation uint64 `json:"generation,omitempty"`
+	// NextChecksum is the checksum of the proposed next master secret generation.
+	// The proposal is neither persisted nor used until consensus accepts it.
+	NextChecksum []byte `json:"next_checksum,omitempty"`
+	// Transcript is the transcript of the distributed generation of the master secret,
+	// if the enclave generated it together with other key manager enclaves.
+	Transcript *SignedMasterSecretTranscript `json:"transcript,omitempty"`
+}
+
+// ContributionCommitment is a commitment of a key manager enclave to its contribution
+// to a distributed master secret generation.
+type ContributionCommitment struct {
+	// RAK is the runtime attestation key of the contributing enclave.
+	RAK signature.PublicKey `json:"rak"`
+	// Commitment is the hash of the generation round and the contribution.
+	Commitment hash.Hash `json:"commitment"`
+}
+
+// MasterSecretTranscript is the transcript of a distributed master secret generation.
+type MasterSecretTranscript struct {
+	// RuntimeID is the key manager runtime ID.
+	RuntimeID common.Namespace `json:"runtime_id"`
+	// Round is the generation round.
+	Round hash.Hash `json:"round"`
+	// Commitments are the commitments of the contributing enclaves.
+	Commitments []ContributionCommitment `json:"commitments"`
+	// Checksum is the checksum of the master secret.
+	Checksum []byte `json:"checksum"`
+}
+
+// SignedMasterSecretTranscript is a master secret transcript signed by all
+// contributing enclaves.
+type SignedMasterSecretTranscript struct {
+	Transcript MasterSecretTranscript `json:"transcript"`
+	Signatures []signature.Signature  `json:"signatures"`
+}
+
+// ExportAuditLogRequest is the audit log export RPC request, sent to the key
+// manager enclave.
+type ExportAuditLogRequest struct {
+	// FromIndex is the sequence number of the first exported record.
+	FromIndex uint64 `json:"from_index"`
+}
+
+// AuditRecord is an audit log record of a key derivation or secret replication.
+type AuditRecord struct {
+	// Index is the sequence number of the record.
+	Index uint64 `json:"index"`
+	// PrevHash is the hash of the previous record.
+	PrevHash hash.Hash `json:"prev_hash"`
+	// Method is the name of the called method.
+	Method string `json:"method"`
+	// Requester is the identity of the requesting enclave.
+	Requester *sgx.EnclaveIdentity `json:"requester"`
+	// RequesterRAK is the runtime attestation key of the requesting enclave.
+	RequesterRAK *signature.PublicKey `json:"requester_rak"`
+	// RuntimeID is the runtime ID the keys were derived for, or the key manager
+	// runtime ID for replications.
+	RuntimeID common.Namespace `json:"runtime_id"`
+	// KeyPairIDHash is the hash of the key pair ID.
+	KeyPairIDHash *hash.Hash `json:"key_pair_id_hash"`
+	// Epoch is the epoch of the ephemeral keys or secret.
+	Epoch *beacon.EpochTime `json:"epoch"`
+	// Generation is the master secret generation.
+	Generation *uint64 `json:"generation"`
+}
+
+// AuditLogExport are the audit log records exported for review.
+type AuditLogExport struct {
+	// RuntimeID is the key manager runtime ID.
+	RuntimeID common.Namespace `json:"runtime_id"`
+	// Records are the records, in the order in which they were appended.
+	Records []AuditRecord `json:"records"`
+}
+
+// SignedAuditLogExport are the exported audit log records, signed with the RAK
+// of the key manager enclave.
+type SignedAuditLogExport struct {
+	Export    AuditLogExport      `json:"export"`
+	Signature signature.Signature `json:"signature"`
 }
 
 // SignedInitResponse is the signed initialization RPC response, returned
@@ -27,6 +27,10 @@ type PolicySGX struct {
 
 	// MaxEphemeralSecretAge is the maximum age of an ephemeral secret in the number of epochs.
 	MaxEphemeralSecretAge beacon.EpochTime `json:"max_ephemeral_secret_age,omitempty"`
+
+	// MasterSecretRotationInterval is the minimum number of epochs between master secret
+	// rotations. Zero disables rotations.
+	MasterSecretRotationInterval beacon.EpochTime `json:"master_secret_rotation_interval,omitempty"`
 }
 
 // EnclavePolicySGX is the per-SGX key manager enclave ID access control policy.
@@ -42,6 +46,21 @@ type EnclavePolicySGX struct {
 	// secret (Note: Each enclave ID may always implicitly replicate from other
 	// instances of itself).
 	MayReplicate []sgx.EnclaveIdentity `json:"may_replicate"`
+
+	// RuntimeRules is the map of runtime IDs to the rules restricting how the runtime's
+	// enclaves may use private key material.
+	RuntimeRules map[common.Namespace]*RuntimeRulesSGX `json:"runtime_rules,omitempty"`
+}
+
+// RuntimeRulesSGX are the per-runtime key usage rules.
+type RuntimeRulesSGX struct {
+	// AllowedMethods is the vector of private key methods the runtime's enclaves
+	// may call. All methods are allowed if empty.
+	AllowedMethods []string `json:"allowed_methods,omitempty"`
+
+	// Quotas is the map of private key methods to the maximum number of calls
+	// per epoch. Quotas are enforced by each key manager node separately.
+	Quotas map[string]uint64 `json:"quotas,omitempty"`
 }
 
 // SignedPolicySGX is a signed SGX key manager access control policy.
@@ -13,6 +13,7 @@ import (
 	flag "github.com/spf13/pflag"
 	"github.com/spf13/viper"
 
+	beacon "github.com/oasisprotocol/oasis-core/go/beacon/api"
 	"github.com/oasisprotocol/oasis-core/go/common"
 	"github.com/oasisprotocol/oasis-core/go/common/cbor"
 	"github.com/oasisprotocol/oasis-core/go/common/crypto/signature"
@@ -33,6 +34,7 @@ const (
 	CfgPolicyEnclaveID    = "keymanager.policy.enclave.id"
 	CfgPolicyMayQuery     = "keymanager.policy.may.query"
 	CfgPolicyMayReplicate = "keymanager.policy.may.replicate"
+	CfgPolicyRotation     = "keymanager.policy.master_secret.rotation_interval"
 	CfgPolicyKeyFile      = "keymanager.policy.key.file"
 	CfgPolicyTestKey      = "keymanager.policy.testkey"
 	CfgPolicySigFile      = "keymanager.policy.signature.file"
@@ -203,9 +205,10 @@ func policyFromFlags() (*kmApi.PolicySGX, error) {
 	}
 
 	return &kmApi.PolicySGX{
-		Serial:   serial,
-		ID:       id,
-		Enclaves: enclaves,
+		Serial:                       serial,
+		ID:                           id,
+		Enclaves:                     enclaves,
+		MasterSecretRotationInterval: beacon.EpochTime(viper.GetUint64(CfgPolicyRotation)),
 	}, nil
 }
 
@@ -549,6 +552,7 @@ func registerKMInitPolicyFlags(cmd *cobra.Command) {
 		cmd.Flags().String(CfgPolicyEnclaveID, "", "512-bit Key Manager Enclave ID in hex (concatenated MRENCLAVE and MRSIGNER). Multiple Enclave IDs with corresponding permissions can be provided respectively.")
 		cmd.Flags().StringSlice(CfgPolicyMayReplicate, []string{}, "enclave_id1,enclave_id2... list of new enclaves which are allowed to access the master secret. Requires "+CfgPolicyEnclaveID)
 		cmd.Flags().StringToString(CfgPolicyMayQuery, map[string]string{}, "runtime_id=enclave_id1,enclave_id2... sets enclave query permission for runtime_id. Requires "+CfgPolicyEnclaveID)
+		cmd.Flags().Uint64(CfgPolicyRotation, 0, "minimum number of epochs between master secret rotations (0 disables rotations)")
 	}
 
 	cmd.Flags().AddFlagSet(policyFileFlag)
@@ -566,6 +570,7 @@ func registerKMInitPolicyFlags(cmd *cobra.Command) {
 		CfgPolicyEnclaveID,
 		CfgPolicyMayReplicate,
 		CfgPolicyMayQuery,
+		CfgPolicyRotation,
 	} {
 		_ = viper.BindPFlag(v, cmd.Flags().Lookup(v))
 	}
@@ -284,6 +284,8 @@ func (f *RuntimeFixture) Create(netFixture *NetworkFixture, net *Network) (*Runt
 type KeymanagerPolicyFixture struct {
 	Runtime int `json:"runtime"`
 	Serial  int `json:"serial"`
+
+	MasterSecretRotationInterval beacon.EpochTime `json:"master_secret_rotation_interval,omitempty"`
 }
 
 // Create instantiates the key manager policy described in the fixture.
@@ -294,8 +296,9 @@ func (f *KeymanagerPolicyFixture) Create(net *Network) (*KeymanagerPolicy, error
 	}
 
 	return net.NewKeymanagerPolicy(&KeymanagerPolicyCfg{
-		Runtime: runtime,
-		Serial:  f.Serial,
+		Runtime:                      runtime,
+		Serial:                       f.Serial,
+		MasterSecretRotationInterval: f.MasterSecretRotationInterval,
 	})
 }
 
@@ -324,6 +327,8 @@ type KeymanagerFixture struct {
 	LogWatcherHandlerFactories []log.WatcherHandlerFactory `json:"-"`
 
 	PrivatePeerPubKeys []string `json:"private_peer_pub_keys,omitempty"`
+
+	MayRotate bool `json:"may_rotate,omitempty"`
 }
 
 // Create instantiates the key manager described by the fixture.
@@ -360,6 +365,7 @@ func (f *KeymanagerFixture) Create(net *Network) (*Keymanager, error) {
 		Policy:             policy,
 		SentryIndices:      f.Sentries,
 		PrivatePeerPubKeys: f.PrivatePeerPubKeys,
+		MayRotate:          f.MayRotate,
 	})
 }
 
@@ -6,6 +6,7 @@ import (
 	"path/filepath"
 	"strconv"
 
+	beacon "github.com/oasisprotocol/oasis-core/go/beacon/api"
 	"github.com/oasisprotocol/oasis-core/go/common/crypto/signature"
 	"github.com/oasisprotocol/oasis-core/go/common/node"
 	"github.com/oasisprotocol/oasis-core/go/config"
@@ -31,14 +32,16 @@ type KeymanagerPolicy struct {
 
 	statusArgs []string
 
-	runtime *Runtime
-	serial  int
+	runtime          *Runtime
+	serial           int
+	rotationInterval beacon.EpochTime
 }
 
 // KeymanagerPolicyCfg is an Oasis key manager policy document configuration.
 type KeymanagerPolicyCfg struct {
-	Runtime *Runtime
-	Serial  int
+	Runtime                      *Runtime
+	Serial                       int
+	MasterSecretRotationInterval beacon.EpochTime
 }
 
 func (pol *KeymanagerPolicy) provisionStatusArgs() []string {
@@ -58,6 +61,7 @@ func (pol *KeymanagerPolicy) provision() error {
 			"--" + kmCmd.CfgPolicyFile, policyPath,
 			"--" + kmCmd.CfgPolicyID, pol.runtime.ID().String(),
 			"--" + kmCmd.CfgPolicySerial, strconv.Itoa(pol.serial),
+			"--" + kmCmd.CfgPolicyRotation, strconv.FormatUint(uint64(pol.rotationInterval), 10),
 		}
 		policyArgs = append(policyArgs, []string{
 			"--" + kmCmd.CfgPolicyEnclaveID, pol.runtime.GetEnclaveIdentity(0).String(),
@@ -137,8 +141,9 @@ func (net *Network) NewKeymanagerPolicy(cfg *KeymanagerPolicyCfg) (*KeymanagerPo
 	newPol := &KeymanagerPolicy{
 		net:     net,
 		dir:     policyDir,
-		runtime: cfg.Runtime,
-		serial:  cfg.Serial,
+		runtime:          cfg.Runtime,
+		serial:           cfg.Serial,
+		rotationInterval: cfg.MasterSecretRotationInterval,
 	}
 	net.keymanagerPolicies = append(net.keymanagerPolicies, newPol)
 
@@ -160,6 +165,7 @@ type Keymanager struct { // nolint: maligned
 	p2pPort       uint16
 
 	mayGenerate bool
+	mayRotate   bool
 
 	privatePeerPubKeys []string
 }
@@ -176,6 +182,9 @@ type KeymanagerCfg struct {
 
 	// PrivatePeerPubKeys is a list of base64-encoded libp2p public keys of peers who may call non-public methods.
 	PrivatePeerPubKeys []string
+
+	// MayRotate is true iff the key manager may rotate the master secret.
+	MayRotate bool
 }
 
 // IdentityKeyPath returns the paths to the node's identity key.
@@ -296,6 +305,9 @@ func (km *Keymanager) ModifyConfig() error {
 	if km.mayGenerate {
 		km.Config.Keymanager.MayGenerate = true
 	}
+	if km.mayRotate {
+		km.Config.Keymanager.MayRotate = true
+	}
 
 	// Sentry configuration.
 	sentries, err := resolveSentries(km.net, km.sentryIndices)
@@ -357,6 +369,7 @@ func (net *Network) NewKeymanager(cfg *KeymanagerCfg) (*Keymanager, error) {
 		consensusPort:      host.getProvisionedPort(nodePortConsensus),
 		p2pPort:            host.getProvisionedPort(nodePortP2P),
 		mayGenerate:        len(net.keymanagers) == 0,
+		mayRotate:          cfg.MayRotate,
 		privatePeerPubKeys: cfg.PrivatePeerPubKeys,
 	}
 
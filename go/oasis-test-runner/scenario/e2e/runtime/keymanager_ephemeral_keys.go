@@ -680,7 +680,7 @@ func (sc *kmEphemeralKeysImpl) fetchEphemeralPublicKey(ctx context.Context, epoc
 	}
 
 	if rsp.Body.Error != nil {
-		msg := *rsp.Body.Error
+		msg := rsp.Body.Error.Message
 		if msg == fmt.Sprintf("ephemeral secret for epoch %d not found", epoch) {
 			return nil, nil
 		}
@@ -0,0 +1,193 @@
+package runtime
+
+import (
+	"context"
+	"fmt"
+	"time"
+
+	"github.com/oasisprotocol/oasis-core/go/common/cbor"
+	"github.com/oasisprotocol/oasis-core/go/common/crypto/signature"
+	"github.com/oasisprotocol/oasis-core/go/common/node"
+	"github.com/oasisprotocol/oasis-core/go/common/version"
+	consensus "github.com/oasisprotocol/oasis-core/go/consensus/api"
+	keymanager "github.com/oasisprotocol/oasis-core/go/keymanager/api"
+	"github.com/oasisprotocol/oasis-core/go/oasis-test-runner/env"
+	"github.com/oasisprotocol/oasis-core/go/oasis-test-runner/oasis"
+	"github.com/oasisprotocol/oasis-core/go/oasis-test-runner/scenario"
+	registry "github.com/oasisprotocol/oasis-core/go/registry/api"
+)
+
+const (
+	// kmRotationGenerations is the number of master secret rotations to wait for.
+	kmRotationGenerations = 2
+	// kmRotationTimeout is the maximum time to wait for the master secret rotations.
+	kmRotationTimeout = 5 * time.Minute
+	// kmRotationReplicaEpochs is the number of epochs the replica has to catch up.
+	kmRotationReplicaEpochs = 5
+)
+
+// KeymanagerRotation is the keymanager master secret rotation scenario.
+var KeymanagerRotation scenario.Scenario = newKmRotationImpl()
+
+type kmRotationImpl struct {
+	runtimeImpl
+}
+
+func newKmRotationImpl() scenario.Scenario {
+	return &kmRotationImpl{
+		runtimeImpl: *newRuntimeImpl("keymanager-rotation", BasicKVEncTestClient),
+	}
+}
+
+func (sc *kmRotationImpl) Clone() scenario.Scenario {
+	return &kmRotationImpl{
+		runtimeImpl: *sc.runtimeImpl.Clone().(*runtimeImpl),
+	}
+}
+
+func (sc *kmRotationImpl) Fixture() (*oasis.NetworkFixture, error) {
+	f, err := sc.runtimeImpl.Fixture()
+	if err != nil {
+		return nil, err
+	}
+
+	// Allow the master secret to be rotated every epoch.
+	f.KeymanagerPolicies[0].MasterSecretRotationInterval = 1
+
+	// Only the first key manager proposes new generations, the second one replicates
+	// the accepted generations.
+	f.Keymanagers = []oasis.KeymanagerFixture{
+		{Runtime: 0, Entity: 1, MayRotate: true},
+		{Runtime: 0, Entity: 1},
+	}
+
+	return f, nil
+}
+
+func (sc *kmRotationImpl) Run(childEnv *env.Env) error {
+	ctx := context.Background()
+
+	// Rotation intervals are part of the key manager policy, which only exists in SGX mode.
+	tee, err := sc.getTEEHardware()
+	if err != nil {
+		return err
+	}
+	if tee != node.TEEHardwareIntelSGX {
+		sc.Logger.Info("skipping master secret rotation test, key manager policy requires SGX")
+		return nil
+	}
+
+	if err = sc.startNetworkAndTestClient(ctx, childEnv); err != nil {
+		return err
+	}
+
+	// Wait for the client to exit.
+	if err = sc.waitTestClientOnly(); err != nil {
+		return err
+	}
+
+	if kmLen := len(sc.Net.Keymanagers()); kmLen < 2 {
+		return fmt.Errorf("expected more than 1 keymanager, have: %v", kmLen)
+	}
+
+	// Wait for the master secret to be rotated and make sure that the runtime signing key
+	// stays the same.
+	sc.Logger.Info("waiting for master secret rotations")
+	if err = sc.waitForRotations(ctx); err != nil {
+		return err
+	}
+
+	// Make sure that the replica replicated the rotated generations.
+	sc.Logger.Info("waiting for the replica to replicate rotated generations")
+	return sc.waitForReplica(ctx, sc.Net.Keymanagers()[1])
+}
+
+func (sc *kmRotationImpl) waitForRotations(ctx context.Context) error {
+	stCh, stSub, err := sc.Net.Controller().Keymanager.WatchStatuses(ctx)
+	if err != nil {
+		return err
+	}
+	defer stSub.Close()
+
+	var rsk *signature.PublicKey
+	for {
+		select {
+		case status, ok := <-stCh:
+			if !ok {
+				return fmt.Errorf("key manager status watch channel closed")
+			}
+			if !status.ID.Equal(&keymanagerID) || !status.IsInitialized || status.RSK == nil {
+				continue
+			}
+
+			sc.Logger.Info("key manager status updated",
+				"generation", status.Generation,
+				"rsk", status.RSK,
+			)
+
+			if rsk == nil {
+				rsk = status.RSK
+			}
+			if !status.RSK.Equal(*rsk) {
+				return fmt.Errorf("runtime signing key changed after rotation (expected: %s got: %s)", rsk, status.RSK)
+			}
+			if status.Generation >= kmRotationGenerations {
+				return nil
+			}
+		case <-time.After(kmRotationTimeout):
+			return fmt.Errorf("timed out waiting for master secret rotations")
+		}
+	}
+}
+
+func (sc *kmRotationImpl) waitForReplica(ctx context.Context, replica *oasis.Keymanager) error {
+	ctrl, err := oasis.NewController(replica.SocketPath())
+	if err != nil {
+		return err
+	}
+
+	epoch, err := sc.Net.Controller().Beacon.GetEpoch(ctx, consensus.HeightLatest)
+	if err != nil {
+		return fmt.Errorf("failed to get current epoch: %w", err)
+	}
+
+	for i := 0; i < kmRotationReplicaEpochs; i++ {
+		var generation uint64
+		if generation, err = sc.replicaGeneration(ctx, ctrl, replica); err != nil {
+			return err
+		}
+		if generation > 0 {
+			return nil
+		}
+
+		epoch++
+		if err = sc.Net.Controller().Beacon.WaitEpoch(ctx, epoch); err != nil {
+			return fmt.Errorf("failed waiting for epoch %d: %w", epoch, err)
+		}
+	}
+
+	return fmt.Errorf("replica failed to replicate rotated generations")
+}
+
+func (sc *kmRotationImpl) replicaGeneration(ctx context.Context, ctrl *oasis.Controller, replica *oasis.Keymanager) (uint64, error) {
+	// Extract the replica's ExtraInfo.
+	node, err := ctrl.Registry.GetNode(
+		ctx,
+		&registry.IDQuery{
+			ID: replica.NodeID,
+		},
+	)
+	if err != nil {
+		return 0, err
+	}
+	rt := node.GetRuntime(keymanagerID, version.Version{})
+	if rt == nil {
+		return 0, fmt.Errorf("replica is missing keymanager runtime from descriptor")
+	}
+	var signedInitResponse keymanager.SignedInitResponse
+	if err = cbor.Unmarshal(rt.ExtraInfo, &signedInitResponse); err != nil {
+		return 0, fmt.Errorf("failed to unmarshal replica extrainfo")
+	}
+
+	return signedInitResponse.InitResponse.Generation, nil
+}
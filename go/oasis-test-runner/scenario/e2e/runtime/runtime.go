@@ -765,6 +765,8 @@ func RegisterScenarios() error {
 		KeymanagerRestart,
 		// Keymanager replicate test.
 		KeymanagerReplicate,
+		// Keymanager rotation test.
+		KeymanagerRotation,
 		// Dump/restore test.
 		DumpRestore,
 		DumpRestoreRuntimeRoundAdvance,
@@ -1,7 +1,11 @@
 // Package api defines the EnclaveRPC interface.
 package api
 
-import "github.com/oasisprotocol/oasis-core/go/common/cbor"
+import (
+	"fmt"
+
+	"github.com/oasisprotocol/oasis-core/go/common/cbor"
+)
 
 // Kind is the RPC call kind.
 type Kind uint8
@@ -28,10 +32,41 @@ type Request struct {
 	Args   interface{} `json:"args"`
 }
 
+// Error is an EnclaveRPC error.
+type Error struct {
+	Module  string `json:"module,omitempty"`
+	Code    uint32 `json:"code,omitempty"`
+	Message string `json:"message,omitempty"`
+}
+
+// Error returns a string representation of the error.
+func (e *Error) Error() string {
+	return fmt.Sprintf("module: %s code: %d message: %s", e.Module, e.Code, e.Message)
+}
+
+// UnmarshalCBOR decodes a CBOR marshalled error.
+//
+// Runtimes which don't support coded errors only send the message.
+func (e *Error) UnmarshalCBOR(data []byte) error {
+	var message string
+	if err := cbor.Unmarshal(data, &message); err == nil {
+		*e = Error{Message: message}
+		return nil
+	}
+
+	type coded Error
+	var ce coded
+	if err := cbor.Unmarshal(data, &ce); err != nil {
+		return err
+	}
+	*e = Error(ce)
+	return nil
+}
+
 // Body is an EnclaveRPC response body.
 type Body struct {
 	Success cbor.RawMessage `json:",omitempty"`
-	Error   *string         `json:",omitempty"`
+	Error   *Error          `json:",omitempty"`
 }
 
 // Response is an EnclaveRPC response.
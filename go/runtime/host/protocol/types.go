@@ -472,6 +472,14 @@ type HostRPCCallRequest struct {
 	//
 	// In case no feedback is given success is assumed.
 	PeerFeedback *enclaverpc.PeerFeedback `json:"pf,omitempty"`
+	// PeerFeedbackNode is the optional identity of the node the peer feedback refers to.
+	//
+	// In case it is not set, the feedback refers to the peer that handled the last RPC call.
+	PeerFeedbackNode *signature.PublicKey `json:"pf_node,omitempty"`
+	// Timeout is an optional timeout of the call in milliseconds.
+	//
+	// In case the timeout expires before a response is received, the call fails.
+	Timeout *uint64 `json:"timeout,omitempty"`
 }
 
 // HostRPCCallResponse is a host RPC call response message body.
@@ -183,6 +183,12 @@ func (h *runtimeHostHandler) handleHostRPCCall(
 	ctx context.Context,
 	rq *protocol.HostRPCCallRequest,
 ) (*protocol.HostRPCCallResponse, error) {
+	if rq.Timeout != nil {
+		var cancel context.CancelFunc
+		ctx, cancel = context.WithTimeout(ctx, time.Duration(*rq.Timeout)*time.Millisecond)
+		defer cancel()
+	}
+
 	switch rq.Endpoint {
 	case runtimeKeymanager.EnclaveRPCEndpoint:
 		// Call into the remote key manager.
@@ -190,7 +196,7 @@ func (h *runtimeHostHandler) handleHostRPCCall(
 		if err != nil {
 			return nil, err
 		}
-		res, node, err := kmCli.CallEnclave(ctx, rq.Request, rq.Nodes, rq.Kind, rq.PeerFeedback)
+		res, node, err := kmCli.CallEnclave(ctx, rq.Request, rq.Nodes, rq.Kind, rq.PeerFeedback, rq.PeerFeedbackNode)
 		if err != nil {
 			return nil, err
 		}
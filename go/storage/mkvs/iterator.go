@@ -26,6 +26,10 @@ func (t *tree) SyncIterate(ctx context.Context, request *syncer.IterateRequest)
 		return nil, syncer.ErrDirtyRoot
 	}
 
+	if request.Reverse || request.SeekLast {
+		return t.syncIterateReverse(ctx, request)
+	}
+
 	// Create an iterator which generates proofs. Always anchor the proof at the
 	// root as an iterator may encompass many subtrees. Make sure to propagate
 	// prefetching to any upstream remote syncers.
@@ -57,7 +61,125 @@ func (t *tree) SyncIterate(ctx context.Context, request *syncer.IterateRequest)
 	}, nil
 }
 
-func (t *tree) newFetcherSyncIterate(key node.Key, prefetch uint16) readSyncFetcher {
+// syncIterateReverse generates a proof for the item at the given key or the previous smaller key
+// and the specified number of preceding items.
+//
+// Must be called with the cache lock held.
+func (t *tree) syncIterateReverse(ctx context.Context, request *syncer.IterateRequest) (*syncer.ProofResponse, error) {
+	// Always anchor the proof at the root as the items may span many subtrees.
+	pb := syncer.NewProofBuilder(request.Tree.Root.Hash, request.Tree.Root.Hash)
+
+	// Besides the item the iterator is positioned at, include the preceding items.
+	remaining := int(request.Prefetch) + 1
+	// When seeking to the last item, all keys are taken into account.
+	bounded := !request.SeekLast
+	err := t.doSyncIterateReverse(ctx, pb, t.cache.pendingRoot, 0, node.Key{}, request.Key, bounded, request.SeekLast, &remaining)
+	if err != nil {
+		return nil, err
+	}
+
+	proof, err := pb.Build(ctx)
+	if err != nil {
+		return nil, err
+	}
+
+	return &syncer.ProofResponse{
+		Proof: *proof,
+	}, nil
+}
+
+// doSyncIterateReverse visits the subtree in reverse key order, including all visited nodes in
+// the proof, until the given number of items has been visited. If bounded is set, only items
+// with keys smaller than or equal to the given key are taken into account. If seekLast is set,
+// the key is ignored and all items are taken into account.
+func (t *tree) doSyncIterateReverse(
+	ctx context.Context,
+	pb *syncer.ProofBuilder,
+	ptr *node.Pointer,
+	bitDepth node.Depth,
+	path node.Key,
+	key node.Key,
+	bounded bool,
+	seekLast bool,
+	remaining *int,
+) error {
+	if *remaining <= 0 {
+		return nil
+	}
+
+	// Dereference the node, possibly making a remote request.
+	nd, err := t.cache.derefNodePtr(ctx, ptr, t.newFetcherSyncIterate(key, uint16(*remaining-1), true, seekLast))
+	if err != nil {
+		return err
+	}
+	if ptr != nil {
+		pb.Include(nd)
+	}
+
+	switch n := nd.(type) {
+	case nil:
+		// Reached a nil node, there is nothing here.
+		return nil
+	case *node.InternalNode:
+		bitLength := bitDepth + n.LabelBitLength
+		newPath := path.Merge(bitDepth, n.Label, n.LabelBitLength)
+
+		// Larger keys are in the right subtree, followed by the left subtree and finally the
+		// leaf node, whose key is a prefix of all the others.
+		for _, child := range []struct {
+			ptr  *node.Pointer
+			path node.Key
+		}{
+			{n.Right, newPath.AppendBit(bitLength, true)},
+			{n.Left, newPath.AppendBit(bitLength, false)},
+		} {
+			childBounded := bounded
+			if bounded {
+				switch compareSubtree(child.path, bitLength+1, key) {
+				case 1:
+					// All keys in the subtree are larger, skip it.
+					continue
+				case -1:
+					childBounded = false
+				}
+			}
+
+			if err = t.doSyncIterateReverse(ctx, pb, child.ptr, bitLength, child.path, key, childBounded, seekLast, remaining); err != nil {
+				return err
+			}
+		}
+
+		return t.doSyncIterateReverse(ctx, pb, n.LeafNode, bitLength, path, key, bounded, seekLast, remaining)
+	case *node.LeafNode:
+		if !bounded || n.Key.Compare(key) <= 0 {
+			*remaining--
+		}
+	}
+
+	return nil
+}
+
+// compareSubtree compares all keys in the subtree with the given path against the given key. It
+// returns -1 if all keys are smaller, 1 if all keys are larger and 0 otherwise.
+func compareSubtree(path node.Key, bitLength node.Depth, key node.Key) int {
+	for bit := node.Depth(0); bit < bitLength; bit++ {
+		if bit >= key.BitLength() {
+			// The key is a prefix of all keys in the subtree.
+			return 1
+		}
+
+		switch a, b := path.GetBit(bit), key.GetBit(bit); {
+		case a == b:
+		case b:
+			return -1
+		default:
+			return 1
+		}
+	}
+	return 0
+}
+
+func (t *tree) newFetcherSyncIterate(key node.Key, prefetch uint16, reverse, seekLast bool) readSyncFetcher {
 	return func(ctx context.Context, ptr *node.Pointer, rs syncer.ReadSyncer) (*syncer.Proof, error) {
 		rsp, err := rs.SyncIterate(ctx, &syncer.IterateRequest{
 			Tree: syncer.TreeID{
@@ -66,6 +188,8 @@ func (t *tree) newFetcherSyncIterate(key node.Key, prefetch uint16) readSyncFetc
 			},
 			Key:      key,
 			Prefetch: prefetch,
+			Reverse:  reverse,
+			SeekLast: seekLast,
 		})
 		if err != nil {
 			return nil, err
@@ -244,7 +368,7 @@ func (it *treeIterator) Next() {
 
 func (it *treeIterator) doNext(ptr *node.Pointer, bitDepth node.Depth, path, key node.Key, state visitState) error { // nolint: gocyclo
 	// Dereference the node, possibly making a remote request.
-	nd, err := it.tree.cache.derefNodePtr(it.ctx, ptr, it.tree.newFetcherSyncIterate(key, it.prefetch))
+	nd, err := it.tree.cache.derefNodePtr(it.ctx, ptr, it.tree.newFetcherSyncIterate(key, it.prefetch, false, false))
 	if err != nil {
 		return err
 	}
@@ -137,6 +137,84 @@ func TestIterator(t *testing.T) {
 	})
 }
 
+func TestSyncIterateReverse(t *testing.T) {
+	ctx := context.Background()
+	tree := New(nil, nil, 0)
+	defer tree.Close()
+
+	items := writelog.WriteLog{
+		writelog.LogEntry{Key: []byte("key"), Value: []byte("first")},
+		writelog.LogEntry{Key: []byte("key 1"), Value: []byte("one")},
+		writelog.LogEntry{Key: []byte("key 2"), Value: []byte("two")},
+		writelog.LogEntry{Key: []byte("key 5"), Value: []byte("five")},
+		writelog.LogEntry{Key: []byte("key 8"), Value: []byte("eight")},
+		writelog.LogEntry{Key: []byte("key 9"), Value: []byte("nine")},
+	}
+	err := tree.ApplyWriteLog(ctx, writelog.NewStaticIterator(items))
+	require.NoError(t, err, "ApplyWriteLog")
+
+	var root node.Root
+	_, rootHash, err := tree.Commit(ctx, root.Namespace, root.Version)
+	require.NoError(t, err, "Commit")
+	root.Hash = rootHash
+
+	for _, tc := range []struct {
+		key      node.Key
+		seekLast bool
+		prefetch uint16
+		expected []string
+		excluded []string
+	}{
+		{seekLast: true, prefetch: 0, expected: []string{"key 9"}},
+		{seekLast: true, prefetch: 2, expected: []string{"key 9", "key 8", "key 5"}},
+		{key: node.Key{}, prefetch: 0, expected: nil, excluded: []string{"key 9"}},
+		{key: node.Key("key 5"), prefetch: 1, expected: []string{"key 5", "key 2"}},
+		{key: node.Key("key 7"), prefetch: 2, expected: []string{"key 5", "key 2", "key 1"}},
+		{key: node.Key("key 3"), prefetch: 10, expected: []string{"key 2", "key 1", "key"}},
+		{key: node.Key("k"), prefetch: 10, expected: nil},
+	} {
+		rsp, err := tree.SyncIterate(ctx, &syncer.IterateRequest{
+			Tree: syncer.TreeID{
+				Root:     root,
+				Position: root.Hash,
+			},
+			Key:      tc.key,
+			Prefetch: tc.prefetch,
+			Reverse:  !tc.seekLast,
+			SeekLast: tc.seekLast,
+		})
+		require.NoError(t, err, "SyncIterate")
+
+		var pv syncer.ProofVerifier
+		ptr, err := pv.VerifyProof(ctx, root.Hash, &rsp.Proof)
+		require.NoError(t, err, "VerifyProof")
+
+		keys := make(map[string]bool)
+		collectProofKeys(ptr, keys)
+		for _, key := range tc.expected {
+			require.True(t, keys[key], "proof should include %s (seek: %s)", key, tc.key)
+		}
+		for _, key := range tc.excluded {
+			require.False(t, keys[key], "proof should not include %s (seek: %s)", key, tc.key)
+		}
+	}
+}
+
+func collectProofKeys(ptr *node.Pointer, keys map[string]bool) {
+	if ptr == nil || ptr.Node == nil {
+		return
+	}
+
+	switch n := ptr.Node.(type) {
+	case *node.InternalNode:
+		collectProofKeys(n.LeafNode, keys)
+		collectProofKeys(n.Left, keys)
+		collectProofKeys(n.Right, keys)
+	case *node.LeafNode:
+		keys[string(n.Key)] = true
+	}
+}
+
 func TestIteratorCase1(t *testing.T) {
 	ctx := context.Background()
 	tree := New(nil, nil, 0)
@@ -48,6 +48,12 @@ type IterateRequest struct {
 	Tree     TreeID `json:"tree"`
 	Key      []byte `json:"key"`
 	Prefetch uint16 `json:"prefetch"`
+	// Reverse specifies whether to seek to the given key or the previous smaller key and then
+	// fetch the preceding items instead of the following ones.
+	Reverse bool `json:"reverse,omitempty"`
+	// SeekLast specifies whether to ignore the key and seek to the last item in the tree
+	// instead. Implies Reverse.
+	SeekLast bool `json:"seek_last,omitempty"`
 }
 
 // ProofResponse is a response for requests that produce proofs.
@@ -36,6 +36,7 @@ type KeyManagerClientWrapper struct {
 	logger       *logging.Logger
 
 	lastPeerFeedback rpc.PeerFeedback
+	peerFeedbacks    map[signature.PublicKey]rpc.PeerFeedback
 }
 
 // Initialized returns a channel that gets closed when the client is initialized.
@@ -83,6 +84,7 @@ func (km *KeyManagerClientWrapper) SetKeyManagerID(id *common.Namespace) {
 	}
 
 	km.lastPeerFeedback = nil
+	km.peerFeedbacks = make(map[signature.PublicKey]rpc.PeerFeedback)
 }
 
 // CallEnclave implements runtimeKeymanager.Client.
@@ -92,12 +94,18 @@ func (km *KeyManagerClientWrapper) CallEnclave(
 	nodes []signature.PublicKey,
 	kind enclaverpc.Kind,
 	pf *enclaverpc.PeerFeedback,
+	pfNode *signature.PublicKey,
 ) ([]byte, signature.PublicKey, error) {
 	var node signature.PublicKey
 
 	km.l.Lock()
 	cli := km.cli
 	lastPf := km.lastPeerFeedback
+	if pfNode != nil {
+		// Feedback refers to the last call handled by the given node, which need not be the
+		// last call overall as the runtime may have multiple calls in flight.
+		lastPf = km.peerFeedbacks[*pfNode]
+	}
 	km.l.Unlock()
 
 	if cli == nil {
@@ -114,6 +122,7 @@ func (km *KeyManagerClientWrapper) CallEnclave(
 
 		km.logger.Debug("received peer feedback from runtime",
 			"peer_feedback", *pf,
+			"node", pfNode,
 		)
 
 		switch *pf {
@@ -153,6 +162,7 @@ func (km *KeyManagerClientWrapper) CallEnclave(
 	km.l.Lock()
 	if km.cli == cli { // Key manager could get updated while we are doing the call.
 		km.lastPeerFeedback = nextPf
+		km.peerFeedbacks[node] = nextPf
 	}
 	km.l.Unlock()
 
@@ -166,6 +176,8 @@ func NewKeyManagerClientWrapper(p2p p2p.Service, consensus consensus.Backend, ch
 		consensus:    consensus,
 		chainContext: chainContext,
 		logger:       logger,
+
+		peerFeedbacks: make(map[signature.PublicKey]rpc.PeerFeedback),
 	}
 }
 
@@ -7,6 +7,12 @@ type Config struct {
 	RuntimeID string `yaml:"runtime_id"`
 	// Key manager may generate a new master secret.
 	MayGenerate bool `yaml:"may_generate"`
+	// Key manager may propose new master secret generations once the rotation interval
+	// of the key manager policy elapses.
+	MayRotate bool `yaml:"may_rotate"`
+	// Minimum number of key manager enclaves which must contribute to a newly generated
+	// master secret. Values below 2 let the enclave generate the master secret on its own.
+	MinContributors uint16 `yaml:"min_contributors"`
 	// Base64-encoded public keys of unadvertised peers that may call protected methods.
 	PrivatePeerPubKeys []string `yaml:"private_peer_pub_keys"`
 }
@@ -21,6 +27,8 @@ func DefaultConfig() Config {
 	return Config{
 		RuntimeID:          "",
 		MayGenerate:        false,
+		MayRotate:          false,
+		MinContributors:    0,
 		PrivatePeerPubKeys: []string{},
 	}
 }
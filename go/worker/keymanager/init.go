@@ -56,6 +56,8 @@ func New(
 		backend:             backend,
 		enabled:             enabled,
 		mayGenerate:         config.GlobalConfig.Keymanager.MayGenerate,
+		mayRotate:           config.GlobalConfig.Keymanager.MayRotate,
+		minContributors:     config.GlobalConfig.Keymanager.MinContributors,
 	}
 
 	if !w.enabled {
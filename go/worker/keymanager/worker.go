@@ -101,8 +101,10 @@ type Worker struct { // nolint: maligned
 	numGeneratedSecrets int
 	lastGeneratedSecret beacon.EpochTime
 
-	enabled     bool
-	mayGenerate bool
+	enabled         bool
+	mayGenerate     bool
+	mayRotate       bool
+	minContributors uint16
 }
 
 func (w *Worker) Name() string {
@@ -155,6 +157,10 @@ func (w *Worker) Initialized() <-chan struct{} {
 }
 
 func (w *Worker) CallEnclave(ctx context.Context, data []byte, kind enclaverpc.Kind) ([]byte, error) {
+	// Distributed master secret generation happens while the enclaves are being initialized,
+	// so sessions and contributions must not wait for initialization to complete.
+	var bootstrap bool
+
 	switch kind {
 	case enclaverpc.KindNoiseSession:
 		// Handle access control as only peers on the access list can call this method.
@@ -174,7 +180,14 @@ func (w *Worker) CallEnclave(ctx context.Context, data []byte, kind enclaverpc.K
 		switch frame.UntrustedPlaintext {
 		case "":
 			// Anyone can connect.
-		case api.RPCMethodGetPublicKey, api.RPCMethodGetPublicEphemeralKey:
+			bootstrap = true
+		case api.RPCMethodCommitMasterSecretContribution, api.RPCMethodRevealMasterSecretContribution,
+			api.RPCMethodConfirmMasterSecret:
+			// Key manager enclaves contribute before they are registered, the enclave only
+			// accepts contributions from enclaves it may replicate secrets to.
+			bootstrap = true
+		case api.RPCMethodGetPublicKey, api.RPCMethodGetPublicEphemeralKey,
+			api.RPCMethodGetPublicSigningKey, api.RPCMethodGetPublicEphemeralSigningKey:
 			// Anyone can get public keys.
 		default:
 			if _, privatePeered := w.privatePeers[peerID]; !privatePeered {
@@ -198,10 +211,12 @@ func (w *Worker) CallEnclave(ctx context.Context, data []byte, kind enclaverpc.K
 	defer cancel()
 
 	// Wait for initialization to complete.
-	select {
-	case <-w.initCh:
-	case <-ctx.Done():
-		return nil, ctx.Err()
+	if !bootstrap {
+		select {
+		case <-w.initCh:
+		case <-ctx.Done():
+			return nil, ctx.Err()
+		}
 	}
 
 	req := &protocol.Body{
@@ -211,8 +226,12 @@ func (w *Worker) CallEnclave(ctx context.Context, data []byte, kind enclaverpc.K
 		},
 	}
 
-	// NOTE: Hosted runtime should not be nil as we wait for initialization above.
+	// NOTE: Hosted runtime should not be nil as we wait for initialization above,
+	// unless the call is part of the bootstrap.
 	rt := w.GetHostedRuntime()
+	if rt == nil {
+		return nil, fmt.Errorf("worker/keymanager: runtime not available")
+	}
 	response, err := rt.Call(ctx, req)
 	if err != nil {
 		w.logger.Error("failed to dispatch RPC call to runtime",
@@ -232,6 +251,27 @@ func (w *Worker) CallEnclave(ctx context.Context, data []byte, kind enclaverpc.K
 	return resp.Response, nil
 }
 
+// ExportAuditLog exports the audit log records of the key manager enclave, starting with
+// the given sequence number, signed with the enclave's RAK.
+func (w *Worker) ExportAuditLog(fromIndex uint64) (*api.SignedAuditLogExport, error) {
+	select {
+	case <-w.initCh:
+	default:
+		return nil, fmt.Errorf("worker/keymanager: not initialized")
+	}
+
+	args := api.ExportAuditLogRequest{
+		FromIndex: fromIndex,
+	}
+
+	var rsp api.SignedAuditLogExport
+	if err := w.localCallEnclave(api.RPCMethodExportAuditLog, args, &rsp); err != nil {
+		return nil, fmt.Errorf("worker/keymanager: failed to export audit log: %w", err)
+	}
+
+	return &rsp, nil
+}
+
 func (w *Worker) localCallEnclave(method string, args interface{}, rsp interface{}) error {
 	req := enclaverpc.Request{
 		Method: method,
@@ -272,7 +312,7 @@ func (w *Worker) localCallEnclave(method string, args interface{}, rsp interface
 	switch {
 	case msg.Response.Body.Success != nil:
 	case msg.Response.Body.Error != nil:
-		return fmt.Errorf("rpc failure: '%s'", *msg.Response.Body.Error)
+		return fmt.Errorf("rpc failure: %w", msg.Response.Body.Error)
 	default:
 		return fmt.Errorf("unknown rpc response status: '%s'", hex.EncodeToString(resp.Response))
 	}
@@ -284,7 +324,7 @@ func (w *Worker) localCallEnclave(method string, args interface{}, rsp interface
 	return nil
 }
 
-func (w *Worker) updateStatus(status *api.Status, runtimeStatus *runtimeStatus) error {
+func (w *Worker) updateStatus(status *api.Status, runtimeStatus *runtimeStatus, epoch beacon.EpochTime) error {
 	var initOk bool
 	defer func() {
 		if !initOk {
@@ -303,9 +343,12 @@ func (w *Worker) updateStatus(status *api.Status, runtimeStatus *runtimeStatus)
 	}
 
 	args := api.InitRequest{
-		Checksum:    status.Checksum,
-		Policy:      policy,
-		MayGenerate: w.mayGenerate,
+		Checksum:        status.Checksum,
+		Policy:          policy,
+		MayGenerate:     w.mayGenerate,
+		Generation:      status.Generation,
+		MayRotate:       w.mayRotate && status.RotationDue(epoch),
+		MinContributors: w.minContributors,
 	}
 
 	var signedInitResp api.SignedInitResponse
@@ -340,6 +383,8 @@ func (w *Worker) updateStatus(status *api.Status, runtimeStatus *runtimeStatus)
 
 	w.logger.Info("Key manager initialized",
 		"checksum", hex.EncodeToString(signedInitResp.InitResponse.Checksum),
+		"generation", signedInitResp.InitResponse.Generation,
+		"next_checksum", hex.EncodeToString(signedInitResp.InitResponse.NextChecksum),
 	)
 	if w.initTicker != nil {
 		w.initTickerCh = nil
@@ -893,7 +938,7 @@ func (w *Worker) worker() { // nolint: gocyclo
 				}
 
 				// Forward status update to key manager runtime.
-				if err = w.updateStatus(currentStatus, currentRuntimeStatus); err != nil {
+				if err = w.updateStatus(currentStatus, currentRuntimeStatus, epoch); err != nil {
 					w.logger.Error("failed to handle status update",
 						"err", err,
 					)
@@ -976,7 +1021,7 @@ func (w *Worker) worker() { // nolint: gocyclo
 			}
 
 			// Forward status update to key manager runtime.
-			if err = w.updateStatus(currentStatus, currentRuntimeStatus); err != nil {
+			if err = w.updateStatus(currentStatus, currentRuntimeStatus, epoch); err != nil {
 				w.logger.Error("failed to handle status update",
 					"err", err,
 				)
@@ -993,7 +1038,7 @@ func (w *Worker) worker() { // nolint: gocyclo
 			if currentStatus == nil || currentRuntimeStatus == nil {
 				continue
 			}
-			if err = w.updateStatus(currentStatus, currentRuntimeStatus); err != nil {
+			if err = w.updateStatus(currentStatus, currentRuntimeStatus, epoch); err != nil {
 				w.logger.Error("failed to handle status update", "err", err)
 				continue
 			}
@@ -1017,6 +1062,15 @@ func (w *Worker) worker() { // nolint: gocyclo
 				crw.epochTransition()
 			}
 
+			// Propose the next master secret generation once the rotation is due.
+			if w.mayRotate && currentStatus != nil && currentRuntimeStatus != nil && currentStatus.RotationDue(epoch) {
+				if err = w.updateStatus(currentStatus, currentRuntimeStatus, epoch); err != nil {
+					w.logger.Error("failed to propose master secret rotation",
+						"err", err,
+					)
+				}
+			}
+
 			// Choose a random height for ephemeral secret generation. Avoid blocks at the end
 			// of the epoch as secret generation, publication and replication takes some time.
 			if genSecretHeight, err = w.randomBlockHeight(epoch, 90); err != nil {
@@ -1,5 +1,15 @@
+use anyhow::anyhow;
 use thiserror::Error;
 
+use oasis_core_runtime::enclave_rpc::{
+    client::RpcClientError,
+    dispatcher::{CODE_ACCESS_DENIED, MODULE_NAME as DISPATCHER_MODULE_NAME},
+    types::Error as RpcError,
+};
+
+/// Name of the module used for key manager errors returned over EnclaveRPC.
+pub const MODULE_NAME: &str = "keymanager";
+
 /// Key manager error.
 #[derive(Error, Debug)]
 pub enum KeyManagerError {
@@ -49,6 +59,182 @@ pub enum KeyManagerError {
     RuntimeNotFound,
     #[error("active deployment not found")]
     ActiveDeploymentNotFound,
+    #[error("master secret generation {0} not found")]
+    MasterSecretGenerationNotFound(u64),
+    #[error("unsupported key type")]
+    UnsupportedKeyType,
+    #[error("master secret generation round not found")]
+    MasterSecretRoundNotFound,
+    #[error("invalid master secret contribution")]
+    InvalidContribution,
+    #[error("insufficient master secret contributions: expected {0}, got {1}")]
+    InsufficientContributions(usize, usize),
+    #[error("invalid master secret transcript")]
+    InvalidTranscript,
+    #[error("method not allowed for runtime")]
+    MethodNotAllowed,
+    #[error("key usage quota exceeded")]
+    QuotaExceeded,
+    #[error("invalid audit log")]
+    InvalidAuditLog,
+    #[error("audit log storage unavailable")]
+    AuditLogUnavailable,
+    #[error("operation not supported")]
+    Unsupported,
     #[error(transparent)]
     Other(anyhow::Error),
 }
+
+impl KeyManagerError {
+    /// Error code, unique within the key manager module.
+    pub fn code(&self) -> u32 {
+        match self {
+            KeyManagerError::NotAuthenticated => 1,
+            KeyManagerError::NotAuthorized => 2,
+            KeyManagerError::InvalidEpoch(..) => 3,
+            KeyManagerError::HeightNotFresh => 4,
+            KeyManagerError::NotInitialized => 5,
+            KeyManagerError::StateCorrupted => 6,
+            KeyManagerError::ReplicationRequired => 7,
+            KeyManagerError::PolicyRollback => 8,
+            KeyManagerError::PolicyChanged => 9,
+            KeyManagerError::PolicyInvalidRuntime => 10,
+            KeyManagerError::PolicyInvalid(_) => 11,
+            KeyManagerError::PolicyInsufficientSignatures => 12,
+            KeyManagerError::RSKMissing => 13,
+            KeyManagerError::REKNotPublished => 14,
+            KeyManagerError::InvalidSignature(_) => 15,
+            KeyManagerError::EphemeralSecretNotFound(_) => 16,
+            KeyManagerError::EphemeralSecretNotReplicated(_) => 17,
+            KeyManagerError::EphemeralSecretNotPublished => 18,
+            KeyManagerError::EphemeralSecretChecksumMismatch => 19,
+            KeyManagerError::InvalidCiphertext => 20,
+            KeyManagerError::StatusNotFound => 21,
+            KeyManagerError::RuntimeNotFound => 22,
+            KeyManagerError::ActiveDeploymentNotFound => 23,
+            KeyManagerError::Other(_) => 24,
+            KeyManagerError::MasterSecretGenerationNotFound(_) => 25,
+            KeyManagerError::UnsupportedKeyType => 26,
+            KeyManagerError::MasterSecretRoundNotFound => 27,
+            KeyManagerError::InvalidContribution => 28,
+            KeyManagerError::InsufficientContributions(..) => 29,
+            KeyManagerError::InvalidTranscript => 30,
+            KeyManagerError::MethodNotAllowed => 31,
+            KeyManagerError::QuotaExceeded => 32,
+            KeyManagerError::InvalidAuditLog => 33,
+            KeyManagerError::AuditLogUnavailable => 34,
+            KeyManagerError::Unsupported => 35,
+        }
+    }
+
+    /// Reconstruct an error from its code.
+    ///
+    /// Errors carrying additional data cannot be reconstructed.
+    fn from_code(code: u32) -> Option<Self> {
+        let err = match code {
+            1 => KeyManagerError::NotAuthenticated,
+            2 => KeyManagerError::NotAuthorized,
+            4 => KeyManagerError::HeightNotFresh,
+            5 => KeyManagerError::NotInitialized,
+            6 => KeyManagerError::StateCorrupted,
+            7 => KeyManagerError::ReplicationRequired,
+            8 => KeyManagerError::PolicyRollback,
+            9 => KeyManagerError::PolicyChanged,
+            10 => KeyManagerError::PolicyInvalidRuntime,
+            12 => KeyManagerError::PolicyInsufficientSignatures,
+            13 => KeyManagerError::RSKMissing,
+            14 => KeyManagerError::REKNotPublished,
+            18 => KeyManagerError::EphemeralSecretNotPublished,
+            19 => KeyManagerError::EphemeralSecretChecksumMismatch,
+            20 => KeyManagerError::InvalidCiphertext,
+            21 => KeyManagerError::StatusNotFound,
+            22 => KeyManagerError::RuntimeNotFound,
+            23 => KeyManagerError::ActiveDeploymentNotFound,
+            26 => KeyManagerError::UnsupportedKeyType,
+            27 => KeyManagerError::MasterSecretRoundNotFound,
+            28 => KeyManagerError::InvalidContribution,
+            30 => KeyManagerError::InvalidTranscript,
+            31 => KeyManagerError::MethodNotAllowed,
+            32 => KeyManagerError::QuotaExceeded,
+            33 => KeyManagerError::InvalidAuditLog,
+            34 => KeyManagerError::AuditLogUnavailable,
+            35 => KeyManagerError::Unsupported,
+            _ => return None,
+        };
+        Some(err)
+    }
+
+    /// Whether the failed call may succeed if retried, possibly on another key manager node.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            KeyManagerError::NotInitialized
+                | KeyManagerError::HeightNotFresh
+                | KeyManagerError::REKNotPublished
+                | KeyManagerError::EphemeralSecretNotPublished
+                | KeyManagerError::StatusNotFound
+                | KeyManagerError::AuditLogUnavailable
+        )
+    }
+}
+
+impl From<KeyManagerError> for RpcError {
+    fn from(err: KeyManagerError) -> Self {
+        RpcError::new(MODULE_NAME, err.code(), &err.to_string())
+    }
+}
+
+impl From<RpcClientError> for KeyManagerError {
+    fn from(err: RpcClientError) -> Self {
+        match err {
+            RpcClientError::CallFailed(err) if err.module == MODULE_NAME => {
+                KeyManagerError::from_code(err.code)
+                    .unwrap_or_else(|| KeyManagerError::Other(anyhow!(err.message)))
+            }
+            // Access policies of key manager methods are enforced by the dispatcher.
+            RpcClientError::CallFailed(err)
+                if err.module == DISPATCHER_MODULE_NAME && err.code == CODE_ACCESS_DENIED =>
+            {
+                KeyManagerError::NotAuthorized
+            }
+            err => KeyManagerError::Other(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_error_roundtrip() {
+        let err: RpcError = KeyManagerError::NotInitialized.into();
+        assert_eq!(err.module, MODULE_NAME);
+        assert_eq!(err.code, 5);
+
+        let err: KeyManagerError = RpcClientError::CallFailed(err).into();
+        assert!(matches!(err, KeyManagerError::NotInitialized));
+        assert!(err.is_retryable());
+
+        let err: RpcError = KeyManagerError::EphemeralSecretNotFound(1).into();
+        let err: KeyManagerError = RpcClientError::CallFailed(err).into();
+        assert!(matches!(err, KeyManagerError::Other(_)));
+        assert!(!err.is_retryable());
+
+        let err = RpcError::new("other", 5, "failure");
+        let err: KeyManagerError = RpcClientError::CallFailed(err).into();
+        assert!(matches!(err, KeyManagerError::Other(_)));
+    }
+
+    #[test]
+    fn test_rpc_error_access_denied() {
+        let err = RpcError::new(DISPATCHER_MODULE_NAME, CODE_ACCESS_DENIED, "access denied");
+        let err: KeyManagerError = RpcClientError::CallFailed(err).into();
+        assert!(matches!(err, KeyManagerError::NotAuthorized));
+        assert!(!err.is_retryable());
+
+        let err = RpcError::new(DISPATCHER_MODULE_NAME, 1, "method not found");
+        let err: KeyManagerError = RpcClientError::CallFailed(err).into();
+        assert!(matches!(err, KeyManagerError::Other(_)));
+    }
+}
@@ -1,3 +1,23 @@
+use oasis_core_runtime::{common::crypto::signature::SignatureBundle, enclave_rpc_service};
+
+use crate::{
+    audit::SignedAuditLogExport,
+    crypto::{
+        contribution::ContributionCommitment, KeyPair, SignedPublicKey, SignedPublicSigningKey,
+        SigningKeyPair,
+    },
+    policy::Policy,
+};
+
+use super::requests::{
+    CommitMasterSecretContributionRequest, ConfirmMasterSecretRequest, EphemeralKeyRequest,
+    ExportAuditLogRequest, GenerateEphemeralSecretRequest, GenerateEphemeralSecretResponse,
+    InitRequest, LoadEphemeralSecretRequest, LongTermKeyRequest, ReplicateEphemeralSecretRequest,
+    ReplicateEphemeralSecretResponse, ReplicateMasterSecretRequest, ReplicateMasterSecretResponse,
+    ReplicateMasterSecretsRequest, RevealMasterSecretContributionRequest,
+    RevealMasterSecretContributionResponse, SignedInitResponse,
+};
+
 /// Name of the `get_or_create_keys` method.
 pub const METHOD_GET_OR_CREATE_KEYS: &str = "get_or_create_keys";
 /// Name of the `get_public_key` method.
@@ -6,14 +26,102 @@ pub const METHOD_GET_PUBLIC_KEY: &str = "get_public_key";
 pub const METHOD_GET_OR_CREATE_EPHEMERAL_KEYS: &str = "get_or_create_ephemeral_keys";
 /// Name of the `get_public_ephemeral_key` method.
 pub const METHOD_GET_PUBLIC_EPHEMERAL_KEY: &str = "get_public_ephemeral_key";
+/// Name of the `get_or_create_signing_keys` method.
+pub const METHOD_GET_OR_CREATE_SIGNING_KEYS: &str = "get_or_create_signing_keys";
+/// Name of the `get_public_signing_key` method.
+pub const METHOD_GET_PUBLIC_SIGNING_KEY: &str = "get_public_signing_key";
+/// Name of the `get_or_create_ephemeral_signing_keys` method.
+pub const METHOD_GET_OR_CREATE_EPHEMERAL_SIGNING_KEYS: &str =
+    "get_or_create_ephemeral_signing_keys";
+/// Name of the `get_public_ephemeral_signing_key` method.
+pub const METHOD_GET_PUBLIC_EPHEMERAL_SIGNING_KEY: &str = "get_public_ephemeral_signing_key";
 /// Name of the `replicate_master_secret` method.
 pub const METHOD_REPLICATE_MASTER_SECRET: &str = "replicate_master_secret";
+/// Name of the `replicate_master_secrets` method.
+pub const METHOD_REPLICATE_MASTER_SECRETS: &str = "replicate_master_secrets";
 /// Name of the `replicate_ephemeral_secret` method.
 pub const METHOD_REPLICATE_EPHEMERAL_SECRET: &str = "replicate_ephemeral_secret";
 
+/// Name of the `commit_master_secret_contribution` method.
+pub const METHOD_COMMIT_MASTER_SECRET_CONTRIBUTION: &str = "commit_master_secret_contribution";
+/// Name of the `reveal_master_secret_contribution` method.
+pub const METHOD_REVEAL_MASTER_SECRET_CONTRIBUTION: &str = "reveal_master_secret_contribution";
+/// Name of the `confirm_master_secret` method.
+pub const METHOD_CONFIRM_MASTER_SECRET: &str = "confirm_master_secret";
+
 /// Name of the `init` local method.
 pub const LOCAL_METHOD_INIT: &str = "init";
 /// Name of the `generate_ephemeral_secret` local method.
 pub const LOCAL_METHOD_GENERATE_EPHEMERAL_SECRET: &str = "generate_ephemeral_secret";
 /// Name of the `load_ephemeral_secret` local method.
 pub const LOCAL_METHOD_LOAD_EPHEMERAL_SECRET: &str = "load_ephemeral_secret";
+/// Name of the `export_audit_log` local method.
+pub const LOCAL_METHOD_EXPORT_AUDIT_LOG: &str = "export_audit_log";
+
+enclave_rpc_service! {
+    /// Key manager EnclaveRPC service.
+    pub trait KeyManagerService, client KeyManagerRpcClient {
+        /// Generate or fetch a runtime's long-term key pair.
+        secure fn get_or_create_keys(LongTermKeyRequest) -> KeyPair = METHOD_GET_OR_CREATE_KEYS,
+            access = Policy::key_query_access();
+        /// Fetch a runtime's long-term public key.
+        insecure fn get_public_key(LongTermKeyRequest) -> SignedPublicKey = METHOD_GET_PUBLIC_KEY;
+        /// Generate or fetch a runtime's ephemeral key pair for the given epoch.
+        secure fn get_or_create_ephemeral_keys(EphemeralKeyRequest) -> KeyPair =
+            METHOD_GET_OR_CREATE_EPHEMERAL_KEYS,
+            access = Policy::key_query_access();
+        /// Fetch a runtime's ephemeral public key for the given epoch.
+        insecure fn get_public_ephemeral_key(EphemeralKeyRequest) -> SignedPublicKey =
+            METHOD_GET_PUBLIC_EPHEMERAL_KEY;
+        /// Generate or fetch a runtime's long-term signing key pair.
+        secure fn get_or_create_signing_keys(LongTermKeyRequest) -> SigningKeyPair =
+            METHOD_GET_OR_CREATE_SIGNING_KEYS,
+            access = Policy::key_query_access();
+        /// Fetch a runtime's long-term public signing key.
+        insecure fn get_public_signing_key(LongTermKeyRequest) -> SignedPublicSigningKey =
+            METHOD_GET_PUBLIC_SIGNING_KEY;
+        /// Generate or fetch a runtime's ephemeral signing key pair for the given epoch.
+        secure fn get_or_create_ephemeral_signing_keys(EphemeralKeyRequest) -> SigningKeyPair =
+            METHOD_GET_OR_CREATE_EPHEMERAL_SIGNING_KEYS,
+            access = Policy::key_query_access();
+        /// Fetch a runtime's ephemeral public signing key for the given epoch.
+        insecure fn get_public_ephemeral_signing_key(EphemeralKeyRequest) ->
+            SignedPublicSigningKey = METHOD_GET_PUBLIC_EPHEMERAL_SIGNING_KEY;
+        /// Replicate the master secret to another key manager enclave.
+        secure fn replicate_master_secret(ReplicateMasterSecretRequest) ->
+            ReplicateMasterSecretResponse = METHOD_REPLICATE_MASTER_SECRET,
+            access = Policy::replication_access();
+        /// Replicate all master secret generations starting with the given one to another key
+        /// manager enclave, one generation per chunk.
+        secure(stream) fn replicate_master_secrets(ReplicateMasterSecretsRequest) ->
+            ReplicateMasterSecretResponse = METHOD_REPLICATE_MASTER_SECRETS,
+            access = Policy::replication_access();
+        /// Replicate an ephemeral secret to another key manager enclave.
+        secure fn replicate_ephemeral_secret(ReplicateEphemeralSecretRequest) ->
+            ReplicateEphemeralSecretResponse = METHOD_REPLICATE_EPHEMERAL_SECRET,
+            access = Policy::replication_access();
+        /// Contribute to a distributed master secret generation by committing to randomness.
+        secure fn commit_master_secret_contribution(CommitMasterSecretContributionRequest) ->
+            ContributionCommitment = METHOD_COMMIT_MASTER_SECRET_CONTRIBUTION,
+            access = Policy::replication_access();
+        /// Reveal the committed randomness once the commitments of all contributors are fixed.
+        secure fn reveal_master_secret_contribution(RevealMasterSecretContributionRequest) ->
+            RevealMasterSecretContributionResponse = METHOD_REVEAL_MASTER_SECRET_CONTRIBUTION,
+            access = Policy::replication_access();
+        /// Verify the contributions and sign the transcript of the generated master secret.
+        secure fn confirm_master_secret(ConfirmMasterSecretRequest) -> SignatureBundle =
+            METHOD_CONFIRM_MASTER_SECRET,
+            access = Policy::replication_access();
+        /// Initialize the key manager.
+        local fn init(InitRequest) -> SignedInitResponse = LOCAL_METHOD_INIT;
+        /// Generate an ephemeral secret for the next epoch.
+        local fn generate_ephemeral_secret(GenerateEphemeralSecretRequest) ->
+            GenerateEphemeralSecretResponse = LOCAL_METHOD_GENERATE_EPHEMERAL_SECRET;
+        /// Load an ephemeral secret published in the consensus layer.
+        local fn load_ephemeral_secret(LoadEphemeralSecretRequest) -> () =
+            LOCAL_METHOD_LOAD_EPHEMERAL_SECRET;
+        /// Export the audit log, signed with the RAK.
+        local fn export_audit_log(ExportAuditLogRequest) -> SignedAuditLogExport =
+            LOCAL_METHOD_EXPORT_AUDIT_LOG;
+    }
+}
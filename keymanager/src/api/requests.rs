@@ -1,12 +1,18 @@
 use oasis_core_runtime::{
     common::{
-        crypto::signature::{self, Signature},
+        crypto::{
+            hash::Hash,
+            signature::{self, Signature},
+        },
         namespace::Namespace,
     },
     consensus::{beacon::EpochTime, keymanager::SignedEncryptedEphemeralSecret},
 };
 
-use crate::crypto::{KeyPairId, Secret};
+use crate::crypto::{
+    contribution::{ContributionCommitment, SignedMasterSecretTranscript},
+    KeyPairId, KeyType, Secret,
+};
 
 /// Key manager initialization request.
 #[derive(Clone, Default, cbor::Encode, cbor::Decode)]
@@ -17,6 +23,19 @@ pub struct InitRequest {
     pub policy: Vec<u8>,
     /// True iff the enclave may generate a new master secret.
     pub may_generate: bool,
+    /// Master secret generation the checksum belongs to.
+    #[cbor(optional)]
+    pub generation: u64,
+    /// True iff the enclave may propose a new master secret generation following
+    /// the one the checksum belongs to. The proposal is only used once the consensus
+    /// layer accepts it.
+    #[cbor(optional)]
+    pub may_rotate: bool,
+    /// Minimum number of key manager enclaves, including this one, which must contribute
+    /// randomness to a newly generated master secret. Values below 2 let the enclave generate
+    /// the master secret on its own.
+    #[cbor(optional)]
+    pub min_contributors: u16,
 }
 
 /// Key manager initialization response.
@@ -30,6 +49,16 @@ pub struct InitResponse {
     pub policy_checksum: Vec<u8>,
     /// Runtime signing key.
     pub rsk: signature::PublicKey,
+    /// Latest master secret generation, which the checksum belongs to.
+    #[cbor(optional)]
+    pub generation: u64,
+    /// Checksum of the proposed next master secret generation, if any.
+    #[cbor(optional)]
+    pub next_checksum: Vec<u8>,
+    /// Transcript of the distributed generation of the master secret, if this enclave
+    /// generated it together with other key manager enclaves.
+    #[cbor(optional)]
+    pub transcript: Option<SignedMasterSecretTranscript>,
 }
 
 /// Signed InitResponse.
@@ -46,6 +75,20 @@ pub struct SignedInitResponse {
 pub struct ReplicateMasterSecretRequest {
     /// Latest trust root height.
     pub height: Option<u64>,
+    /// Master secret generation.
+    #[cbor(optional)]
+    pub generation: u64,
+}
+
+/// Key manager request for replication of all master secret generations starting with
+/// the given one.
+#[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+pub struct ReplicateMasterSecretsRequest {
+    /// Latest trust root height.
+    pub height: Option<u64>,
+    /// First replicated master secret generation.
+    #[cbor(optional)]
+    pub from_generation: u64,
 }
 
 /// Key manager master secret replication response.
@@ -55,6 +98,38 @@ pub struct ReplicateMasterSecretResponse {
     pub master_secret: Secret,
 }
 
+/// Master secret contribution commitment request.
+#[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+pub struct CommitMasterSecretContributionRequest {
+    /// Generation round.
+    pub round: Hash,
+}
+
+/// Master secret contribution reveal request.
+#[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+pub struct RevealMasterSecretContributionRequest {
+    /// Generation round.
+    pub round: Hash,
+    /// Commitments of all contributing enclaves.
+    pub commitments: Vec<ContributionCommitment>,
+}
+
+/// Master secret contribution reveal response.
+#[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+pub struct RevealMasterSecretContributionResponse {
+    /// Contribution.
+    pub contribution: Secret,
+}
+
+/// Master secret confirmation request.
+#[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+pub struct ConfirmMasterSecretRequest {
+    /// Generation round.
+    pub round: Hash,
+    /// Contributions of all contributing enclaves, in the order of their commitments.
+    pub contributions: Vec<Secret>,
+}
+
 /// Key manager ephemeral secret replication request.
 #[derive(Clone, Default, cbor::Encode, cbor::Decode)]
 pub struct ReplicateEphemeralSecretRequest {
@@ -92,10 +167,17 @@ pub struct LoadEphemeralSecretRequest {
     pub signed_secret: SignedEncryptedEphemeralSecret,
 }
 
+/// Audit log export request.
+#[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+pub struct ExportAuditLogRequest {
+    /// Sequence number of the first exported record.
+    pub from_index: u64,
+}
+
 /// Long-term key request for private/public key generation and retrieval.
 ///
 /// Long-term keys are runtime-scoped long-lived keys derived by the key manager
-/// from the master secret. They can be generated at any time.
+/// from a master secret generation. They can be generated at any time.
 #[derive(Clone, Default, cbor::Encode, cbor::Decode)]
 pub struct LongTermKeyRequest {
     /// Latest trust root height.
@@ -104,6 +186,12 @@ pub struct LongTermKeyRequest {
     pub runtime_id: Namespace,
     /// Key pair ID.
     pub key_pair_id: KeyPairId,
+    /// Generation of the master secret the key pair is derived from.
+    #[cbor(optional)]
+    pub generation: u64,
+    /// Key type.
+    #[cbor(optional)]
+    pub key_type: KeyType,
 }
 
 impl LongTermKeyRequest {
@@ -112,8 +200,22 @@ impl LongTermKeyRequest {
             height,
             runtime_id,
             key_pair_id,
+            generation: 0,
+            key_type: KeyType::X25519,
         }
     }
+
+    /// Derive the key pair from the given master secret generation.
+    pub fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// Derive a key pair of the given type.
+    pub fn with_key_type(mut self, key_type: KeyType) -> Self {
+        self.key_type = key_type;
+        self
+    }
 }
 
 /// Ephemeral key request for private/public key generation and retrieval.
@@ -131,6 +233,9 @@ pub struct EphemeralKeyRequest {
     pub key_pair_id: KeyPairId,
     /// Epoch time.
     pub epoch: EpochTime,
+    /// Key type.
+    #[cbor(optional)]
+    pub key_type: KeyType,
 }
 
 impl EphemeralKeyRequest {
@@ -145,6 +250,13 @@ impl EphemeralKeyRequest {
             runtime_id,
             key_pair_id,
             epoch,
+            key_type: KeyType::X25519,
         }
     }
+
+    /// Derive a key pair of the given type.
+    pub fn with_key_type(mut self, key_type: KeyType) -> Self {
+        self.key_type = key_type;
+        self
+    }
 }
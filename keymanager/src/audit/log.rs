@@ -0,0 +1,610 @@
+//! Tamper-evident audit log of key derivations and secret replications.
+//!
+//! Every record links to the hash of the previous one, so removing or altering records
+//! breaks the chain. The log is sealed to untrusted local storage after every few appends,
+//! so that the cost of sealing is spread over multiple records. Whenever the sealed log is
+//! loaded, e.g. after the enclave restarts, a marker record is appended and sealed right away,
+//! so that losing unsealed records or rolling back the sealed log forks the chain at a visible
+//! point.
+use std::{collections::VecDeque, sync::Mutex};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use sgx_isa::Keypolicy;
+
+use oasis_core_runtime::{
+    common::{
+        crypto::{
+            hash::Hash,
+            signature::{self, SignatureBundle, Signer},
+        },
+        namespace::Namespace,
+        sgx::{
+            seal::{seal, try_unseal},
+            EnclaveIdentity,
+        },
+    },
+    consensus::beacon::EpochTime,
+    enclave_rpc::Context as RpcContext,
+    storage::KeyValue,
+};
+
+use crate::{api::KeyManagerError, crypto::KeyPairId};
+
+lazy_static! {
+    // Global audit log object.
+    static ref AUDIT_LOG: AuditLog = AuditLog::new();
+}
+
+/// Context used to sign exported audit logs.
+const AUDIT_LOG_SIGNATURE_CONTEXT: &[u8] = b"oasis-core/keymanager: audit log";
+
+const AUDIT_LOG_STORAGE_KEY: &[u8] = b"keymanager_audit_log";
+const AUDIT_LOG_SEAL_CONTEXT: &[u8] = b"Ekiden Keymanager Seal audit log v0";
+
+/// Maximum number of records kept in the audit log, older records are dropped.
+const MAX_AUDIT_RECORDS: usize = 1024;
+/// Number of appended records after which the audit log is sealed.
+const AUDIT_LOG_SEAL_INTERVAL: usize = 16;
+
+/// Method of the marker record appended whenever the sealed audit log is loaded.
+///
+/// Marker records carry the default runtime ID.
+pub const AUDIT_LOG_LOAD_METHOD: &str = "load_audit_log";
+
+/// Audit log record of a key derivation or secret replication.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct AuditRecord {
+    /// Sequence number of the record.
+    pub index: u64,
+    /// Hash of the previous record.
+    pub prev_hash: Hash,
+    /// Name of the called method.
+    pub method: String,
+    /// Identity of the requesting enclave.
+    pub requester: Option<EnclaveIdentity>,
+    /// Runtime attestation key of the requesting enclave.
+    pub requester_rak: Option<signature::PublicKey>,
+    /// Runtime ID the keys were derived for, or the key manager runtime ID
+    /// for replications.
+    pub runtime_id: Namespace,
+    /// Hash of the key pair ID.
+    pub key_pair_id_hash: Option<Hash>,
+    /// Epoch of the ephemeral keys or secret.
+    pub epoch: Option<EpochTime>,
+    /// Master secret generation.
+    pub generation: Option<u64>,
+}
+
+impl AuditRecord {
+    /// Create a new record of a call of the given method.
+    pub fn new(method: &str, runtime_id: Namespace) -> Self {
+        Self {
+            method: method.to_string(),
+            runtime_id,
+            ..Default::default()
+        }
+    }
+
+    /// Record the key pair ID the keys were derived for.
+    pub fn with_key_pair_id(mut self, key_pair_id: KeyPairId) -> Self {
+        self.key_pair_id_hash = Some(Hash::digest_bytes(key_pair_id.as_ref()));
+        self
+    }
+
+    /// Record the epoch of the ephemeral keys or secret.
+    pub fn with_epoch(mut self, epoch: EpochTime) -> Self {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    /// Record the master secret generation.
+    pub fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = Some(generation);
+        self
+    }
+
+    /// Hash of the record, which the next record links to.
+    pub fn hash(&self) -> Hash {
+        Hash::digest_bytes(&cbor::to_vec(self.clone()))
+    }
+}
+
+/// Audit log records exported for review.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct AuditLogExport {
+    /// Key manager runtime ID.
+    pub runtime_id: Namespace,
+    /// Records, in the order in which they were appended.
+    pub records: Vec<AuditRecord>,
+}
+
+impl AuditLogExport {
+    /// Sign the exported records with the given signer.
+    pub fn sign(
+        self,
+        signer: &dyn Signer,
+        public_key: signature::PublicKey,
+    ) -> Result<SignedAuditLogExport> {
+        let body = cbor::to_vec(self.clone());
+        let signature = signer.sign(AUDIT_LOG_SIGNATURE_CONTEXT, &body)?;
+
+        Ok(SignedAuditLogExport {
+            export: self,
+            signature: SignatureBundle {
+                public_key,
+                signature,
+            },
+        })
+    }
+}
+
+/// Exported audit log records signed with the RAK of the key manager enclave.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct SignedAuditLogExport {
+    /// Exported records.
+    pub export: AuditLogExport,
+    /// Signature of the key manager enclave.
+    pub signature: SignatureBundle,
+}
+
+impl SignedAuditLogExport {
+    /// Verify the signature and that the records form a hash chain.
+    ///
+    /// The caller is responsible for checking that the signer is a key manager enclave.
+    pub fn verify(&self) -> Result<()> {
+        let body = cbor::to_vec(self.export.clone());
+        self.signature
+            .signature
+            .verify(
+                &self.signature.public_key,
+                AUDIT_LOG_SIGNATURE_CONTEXT,
+                &body,
+            )
+            .map_err(KeyManagerError::InvalidSignature)?;
+
+        verify_chain(&self.export.records)
+    }
+}
+
+/// Audit log, which records key derivations and secret replications.
+pub struct AuditLog {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    /// True iff the sealed records have been loaded from untrusted local storage.
+    loaded: bool,
+    /// The most recent records.
+    records: VecDeque<AuditRecord>,
+    /// Hash of the last record.
+    head: Hash,
+    /// Sequence number of the next record.
+    next_index: u64,
+    /// Number of records appended since the log was last sealed.
+    unsealed: usize,
+}
+
+impl Inner {
+    /// Load the sealed records from untrusted local storage, unless already loaded,
+    /// and mark the load with a new record.
+    fn load(&mut self, untrusted_local: &dyn KeyValue) -> Result<()> {
+        if self.loaded {
+            return Ok(());
+        }
+
+        self.records.clear();
+        self.head = Hash::empty_hash();
+        self.next_index = 0;
+        self.unsealed = 0;
+
+        let ciphertext = untrusted_local
+            .get(AUDIT_LOG_STORAGE_KEY.to_vec())
+            .map_err(|_| KeyManagerError::AuditLogUnavailable)?;
+        // Unsealing and deserialization failures are state corruption.
+        let plaintext = try_unseal(Keypolicy::MRENCLAVE, AUDIT_LOG_SEAL_CONTEXT, &ciphertext)
+            .map_err(|_| KeyManagerError::StateCorrupted)?;
+        if let Some(plaintext) = plaintext {
+            let records: Vec<AuditRecord> =
+                cbor::from_slice(&plaintext).map_err(|_| KeyManagerError::StateCorrupted)?;
+            if let Some(last) = records.last() {
+                self.head = last.hash();
+                self.next_index = last.index + 1;
+            }
+            self.records = records.into();
+        }
+
+        self.push(AuditRecord::new(
+            AUDIT_LOG_LOAD_METHOD,
+            Namespace::default(),
+        ));
+        self.loaded = true;
+        self.persist(untrusted_local)
+    }
+
+    /// Seal the records to untrusted local storage.
+    ///
+    /// If sealing fails, the records which were not sealed are dropped and the sealed
+    /// records are loaded again on next access.
+    fn persist(&mut self, untrusted_local: &dyn KeyValue) -> Result<()> {
+        let records: Vec<_> = self.records.iter().cloned().collect();
+        let ciphertext = seal(
+            Keypolicy::MRENCLAVE,
+            AUDIT_LOG_SEAL_CONTEXT,
+            &cbor::to_vec(records),
+        );
+
+        untrusted_local
+            .insert(AUDIT_LOG_STORAGE_KEY.to_vec(), ciphertext)
+            .map_err(|_| {
+                self.loaded = false;
+                KeyManagerError::AuditLogUnavailable
+            })?;
+        self.unsealed = 0;
+
+        Ok(())
+    }
+
+    /// Link the record to the last one and add it to the log.
+    fn push(&mut self, mut record: AuditRecord) {
+        record.index = self.next_index;
+        record.prev_hash = self.head;
+
+        self.head = record.hash();
+        self.next_index += 1;
+        self.unsealed += 1;
+        self.records.push_back(record);
+        if self.records.len() > MAX_AUDIT_RECORDS {
+            self.records.pop_front();
+        }
+    }
+}
+
+impl AuditLog {
+    fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                loaded: false,
+                records: VecDeque::new(),
+                head: Hash::empty_hash(),
+                next_index: 0,
+                unsealed: 0,
+            }),
+        }
+    }
+
+    /// Global AuditLog instance.
+    pub fn global<'a>() -> &'a AuditLog {
+        &AUDIT_LOG
+    }
+
+    /// Append the record of a call made in the given context to the log, sealing the log
+    /// if enough records have been appended since it was last sealed.
+    pub fn append(&self, ctx: &RpcContext, mut record: AuditRecord) -> Result<()> {
+        if let Some(si) = ctx.session_info.as_ref() {
+            record.requester = Some(si.verified_quote.identity.clone());
+            record.requester_rak = Some(si.rak_binding.rak_pub());
+        }
+
+        self.append_record(ctx.untrusted_local_storage, record)
+    }
+
+    fn append_record(&self, untrusted_local: &dyn KeyValue, record: AuditRecord) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load(untrusted_local)?;
+        inner.push(record);
+        if inner.unsealed < AUDIT_LOG_SEAL_INTERVAL {
+            return Ok(());
+        }
+        inner.persist(untrusted_local)
+    }
+
+    /// Export the records starting with the given sequence number, signed with the RAK.
+    pub fn export(
+        &self,
+        ctx: &RpcContext,
+        runtime_id: Namespace,
+        from_index: u64,
+    ) -> Result<SignedAuditLogExport> {
+        self.export_records(ctx.untrusted_local_storage, runtime_id, from_index)?
+            .sign(ctx.identity.as_ref(), ctx.identity.public_rak())
+    }
+
+    fn export_records(
+        &self,
+        untrusted_local: &dyn KeyValue,
+        runtime_id: Namespace,
+        from_index: u64,
+    ) -> Result<AuditLogExport> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load(untrusted_local)?;
+
+        let records = inner
+            .records
+            .iter()
+            .filter(|record| record.index >= from_index)
+            .cloned()
+            .collect();
+
+        Ok(AuditLogExport {
+            runtime_id,
+            records,
+        })
+    }
+}
+
+/// Verify that every record links to the previous one.
+fn verify_chain(records: &[AuditRecord]) -> Result<()> {
+    for pair in records.windows(2) {
+        if pair[1].index != pair[0].index + 1 || pair[1].prev_hash != pair[0].hash() {
+            return Err(KeyManagerError::InvalidAuditLog.into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use oasis_core_runtime::{
+        common::{crypto::signature::PrivateKey, namespace::Namespace},
+        storage::KeyValue,
+        types::Error as RuntimeError,
+    };
+
+    use crate::{api::KeyManagerError, crypto::KeyPairId};
+
+    use super::{
+        verify_chain, AuditLog, AuditRecord, Hash, AUDIT_LOG_LOAD_METHOD, AUDIT_LOG_SEAL_INTERVAL,
+        AUDIT_LOG_STORAGE_KEY, MAX_AUDIT_RECORDS,
+    };
+
+    /// Untrusted local storage kept in memory.
+    #[derive(Default)]
+    struct MemoryStorage {
+        values: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+        unavailable: bool,
+    }
+
+    impl KeyValue for MemoryStorage {
+        fn get(&self, key: Vec<u8>) -> Result<Vec<u8>, RuntimeError> {
+            if self.unavailable {
+                return Err(RuntimeError::new("storage", 1, "unavailable"));
+            }
+            Ok(self
+                .values
+                .lock()
+                .unwrap()
+                .get(&key)
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        fn insert(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(), RuntimeError> {
+            if self.unavailable {
+                return Err(RuntimeError::new("storage", 1, "unavailable"));
+            }
+            self.values.lock().unwrap().insert(key, value);
+            Ok(())
+        }
+    }
+
+    fn record(method: &str, generation: u64) -> AuditRecord {
+        AuditRecord::new(method, Namespace::from(vec![1u8; 32])).with_generation(generation)
+    }
+
+    #[test]
+    fn test_audit_record_chain() {
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+
+        let mut records: Vec<AuditRecord> = vec![
+            AuditRecord::new("get_or_create_keys", runtime_id)
+                .with_key_pair_id(KeyPairId::from(vec![2u8; 32]))
+                .with_generation(0),
+            AuditRecord::new("get_or_create_ephemeral_keys", runtime_id)
+                .with_key_pair_id(KeyPairId::from(vec![3u8; 32]))
+                .with_epoch(10),
+            AuditRecord::new("replicate_master_secret", runtime_id).with_generation(1),
+        ];
+        let mut prev_hash = records[0].hash();
+        for (index, record) in records.iter_mut().enumerate().skip(1) {
+            record.index = index as u64;
+            record.prev_hash = prev_hash;
+            prev_hash = record.hash();
+        }
+        verify_chain(&records).expect("chain should be valid");
+
+        // Key pair IDs should be hashed.
+        assert_ne!(records[0].key_pair_id_hash, records[1].key_pair_id_hash);
+        assert!(records[2].key_pair_id_hash.is_none());
+
+        // Altered records should break the chain.
+        let mut altered = records.clone();
+        altered[1].epoch = Some(11);
+        verify_chain(&altered).expect_err("altered record should break the chain");
+
+        // Removed records should break the chain.
+        let removed = vec![records[0].clone(), records[2].clone()];
+        verify_chain(&removed).expect_err("removed record should break the chain");
+    }
+
+    #[test]
+    fn test_audit_log_append() {
+        let storage = MemoryStorage::default();
+        let log = AuditLog::new();
+
+        for generation in 0..3 {
+            log.append_record(&storage, record("replicate_master_secret", generation))
+                .expect("append should succeed");
+        }
+
+        // The first record marks the load of the (empty) sealed log.
+        let inner = log.inner.lock().unwrap();
+        let records: Vec<_> = inner.records.iter().cloned().collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].method, AUDIT_LOG_LOAD_METHOD);
+        assert_eq!(records[0].prev_hash, Hash::empty_hash());
+        for (index, record) in records.iter().enumerate() {
+            assert_eq!(record.index, index as u64);
+        }
+        assert_eq!(records[3].generation, Some(2));
+        assert_eq!(inner.head, records[3].hash());
+        assert_eq!(inner.next_index, 4);
+        verify_chain(&records).expect("chain should be valid");
+    }
+
+    #[test]
+    fn test_audit_log_max_records() {
+        let storage = MemoryStorage::default();
+        let log = AuditLog::new();
+        log.append_record(&storage, record("replicate_master_secret", 0))
+            .expect("append should succeed");
+
+        // Older records should be dropped.
+        let mut inner = log.inner.lock().unwrap();
+        let total = MAX_AUDIT_RECORDS as u64 + 10;
+        for generation in 2..total {
+            inner.push(record("replicate_master_secret", generation));
+        }
+        assert_eq!(inner.records.len(), MAX_AUDIT_RECORDS);
+        assert_eq!(inner.records.front().unwrap().index, 10);
+        assert_eq!(inner.records.back().unwrap().index, total - 1);
+        assert_eq!(inner.next_index, total);
+
+        let records: Vec<_> = inner.records.iter().cloned().collect();
+        verify_chain(&records).expect("chain should be valid");
+    }
+
+    #[test]
+    fn test_audit_log_export() {
+        let storage = MemoryStorage::default();
+        let log = AuditLog::new();
+        let runtime_id = Namespace::from(vec![2u8; 32]);
+        for generation in 0..3 {
+            log.append_record(&storage, record("replicate_master_secret", generation))
+                .expect("append should succeed");
+        }
+
+        // Only records starting with the given index should be exported.
+        let export = log
+            .export_records(&storage, runtime_id, 2)
+            .expect("export should succeed");
+        assert_eq!(export.runtime_id, runtime_id);
+        assert_eq!(
+            export.records.iter().map(|r| r.index).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        let export = log
+            .export_records(&storage, runtime_id, 4)
+            .expect("export should succeed");
+        assert!(export.records.is_empty());
+
+        // Signed exports should verify.
+        let export = log
+            .export_records(&storage, runtime_id, 0)
+            .expect("export should succeed");
+        let sk = PrivateKey::generate();
+        let signed = export
+            .sign(&sk, sk.public_key())
+            .expect("signing should succeed");
+        signed.verify().expect("signed export should verify");
+
+        // Altered exports should not.
+        let mut altered = signed.clone();
+        altered.export.runtime_id = Namespace::from(vec![3u8; 32]);
+        altered
+            .verify()
+            .expect_err("export with invalid signature should not verify");
+
+        let mut altered = signed.clone();
+        altered.export.records.remove(1);
+        let altered = altered
+            .export
+            .sign(&sk, sk.public_key())
+            .expect("signing should succeed");
+        altered
+            .verify()
+            .expect_err("export with broken chain should not verify");
+    }
+
+    #[test]
+    fn test_audit_log_persist_load() {
+        let storage = MemoryStorage::default();
+        let log = AuditLog::new();
+        // The log is sealed once the load marker and enough records have been appended.
+        let sealed = AUDIT_LOG_SEAL_INTERVAL as u64;
+        for generation in 0..=sealed {
+            log.append_record(&storage, record("replicate_master_secret", generation))
+                .expect("append should succeed");
+        }
+        let records: Vec<_> = log.inner.lock().unwrap().records.iter().cloned().collect();
+        assert_eq!(records.len(), AUDIT_LOG_SEAL_INTERVAL + 2);
+        assert_eq!(log.inner.lock().unwrap().unsealed, 1);
+
+        // Sealed records should survive restarts, which are marked in the chain, while unsealed
+        // records are lost.
+        let restarted = AuditLog::new();
+        restarted
+            .append_record(&storage, record("replicate_master_secret", sealed + 1))
+            .expect("append should succeed");
+        let inner = restarted.inner.lock().unwrap();
+        let loaded: Vec<_> = inner.records.iter().cloned().collect();
+        let n = AUDIT_LOG_SEAL_INTERVAL + 1;
+        assert_eq!(loaded.len(), n + 2);
+        assert_eq!(loaded[..n], records[..n]);
+        assert_eq!(loaded[n].method, AUDIT_LOG_LOAD_METHOD);
+        assert_eq!(loaded[n].index, records[n].index);
+        assert_eq!(loaded[n].prev_hash, records[n - 1].hash());
+        assert_eq!(loaded[n + 1].generation, Some(sealed + 1));
+        verify_chain(&loaded).expect("chain should be valid");
+        drop(inner);
+
+        // The load marker should be sealed right away.
+        let restarted = AuditLog::new();
+        restarted
+            .export_records(&storage, Namespace::default(), 0)
+            .expect("export should succeed");
+        let inner = restarted.inner.lock().unwrap();
+        assert_eq!(inner.records.len(), n + 2);
+        assert_eq!(inner.records[n].method, AUDIT_LOG_LOAD_METHOD);
+        assert_eq!(inner.records[n + 1].method, AUDIT_LOG_LOAD_METHOD);
+    }
+
+    #[test]
+    fn test_audit_log_corrupted() {
+        let storage = MemoryStorage::default();
+        storage
+            .insert(AUDIT_LOG_STORAGE_KEY.to_vec(), vec![1u8; 64])
+            .unwrap();
+        let log = AuditLog::new();
+
+        // Corrupted sealed logs should be rejected without panicking.
+        let err = log
+            .append_record(&storage, record("replicate_master_secret", 0))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<KeyManagerError>(),
+            Some(KeyManagerError::StateCorrupted)
+        ));
+        assert!(!log.inner.lock().unwrap().loaded);
+    }
+
+    #[test]
+    fn test_audit_log_unavailable_storage() {
+        let storage = MemoryStorage {
+            unavailable: true,
+            ..Default::default()
+        };
+        let log = AuditLog::new();
+
+        let err = log
+            .append_record(&storage, record("replicate_master_secret", 0))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<KeyManagerError>(),
+            Some(KeyManagerError::AuditLogUnavailable)
+        ));
+        assert!(!log.inner.lock().unwrap().loaded);
+    }
+}
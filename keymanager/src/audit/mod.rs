@@ -0,0 +1,5 @@
+//! Audit log support.
+mod log;
+
+// Re-exports.
+pub use self::log::*;
@@ -1,14 +1,20 @@
 //! Key manager client.
 use std::sync::Arc;
 
-use futures::future::BoxFuture;
+use futures::future::{self, BoxFuture};
 use io_context::Context;
 
-use oasis_core_runtime::consensus::beacon::EpochTime;
+use oasis_core_runtime::{
+    common::crypto::{hash::Hash, signature::SignatureBundle},
+    consensus::beacon::EpochTime,
+};
 
 use crate::{
     api::KeyManagerError,
-    crypto::{KeyPair, KeyPairId, Secret, SignedPublicKey},
+    crypto::{
+        contribution::ContributionCommitment, KeyPair, KeyPairId, KeyType, Secret, SignedPublicKey,
+        SignedPublicSigningKey, SigningKeyPair,
+    },
 };
 
 /// Key manager client interface.
@@ -36,6 +42,37 @@ pub trait KeyManagerClient: Send + Sync {
         key_pair_id: KeyPairId,
     ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>>;
 
+    /// Get or create named long-term key pair derived from the given master secret generation.
+    ///
+    /// The default implementation only supports the first generation.
+    fn get_or_create_keys_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        generation: u64,
+    ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
+        if generation != 0 {
+            return Box::pin(future::err(KeyManagerError::Unsupported));
+        }
+        self.get_or_create_keys(ctx, key_pair_id)
+    }
+
+    /// Get long-term public key for a key pair id derived from the given master secret
+    /// generation.
+    ///
+    /// The default implementation only supports the first generation.
+    fn get_public_key_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        generation: u64,
+    ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>> {
+        if generation != 0 {
+            return Box::pin(future::err(KeyManagerError::Unsupported));
+        }
+        self.get_public_key(ctx, key_pair_id)
+    }
+
     /// Get or create named ephemeral key pair for given epoch.
     ///
     /// If the key does not yet exist, the key manager will generate one. If
@@ -56,8 +93,111 @@ pub trait KeyManagerClient: Send + Sync {
         epoch: EpochTime,
     ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>>;
 
-    /// Get a copy of the master secret for replication.
-    fn replicate_master_secret(&self, ctx: Context) -> BoxFuture<Result<Secret, KeyManagerError>>;
+    /// Get or create named long-term signing key pair of the given type.
+    ///
+    /// If the key does not yet exist, the key manager will generate one. If
+    /// the key has already been cached locally, it will be retrieved from
+    /// cache.
+    ///
+    /// The default implementation returns `KeyManagerError::Unsupported`.
+    fn get_or_create_signing_keys(
+        &self,
+        _ctx: Context,
+        _key_pair_id: KeyPairId,
+        _key_type: KeyType,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        Box::pin(future::err(KeyManagerError::Unsupported))
+    }
+
+    /// Get long-term public signing key of the given type for a key pair id.
+    ///
+    /// The default implementation returns `KeyManagerError::Unsupported`.
+    fn get_public_signing_key(
+        &self,
+        _ctx: Context,
+        _key_pair_id: KeyPairId,
+        _key_type: KeyType,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        Box::pin(future::err(KeyManagerError::Unsupported))
+    }
+
+    /// Get or create named long-term signing key pair of the given type derived from the given
+    /// master secret generation.
+    ///
+    /// The default implementation only supports the first generation.
+    fn get_or_create_signing_keys_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        if generation != 0 {
+            return Box::pin(future::err(KeyManagerError::Unsupported));
+        }
+        self.get_or_create_signing_keys(ctx, key_pair_id, key_type)
+    }
+
+    /// Get long-term public signing key of the given type for a key pair id derived from
+    /// the given master secret generation.
+    ///
+    /// The default implementation only supports the first generation.
+    fn get_public_signing_key_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        if generation != 0 {
+            return Box::pin(future::err(KeyManagerError::Unsupported));
+        }
+        self.get_public_signing_key(ctx, key_pair_id, key_type)
+    }
+
+    /// Get or create named ephemeral signing key pair of the given type for given epoch.
+    ///
+    /// If the key does not yet exist, the key manager will generate one. If
+    /// the key has already been cached locally, it will be retrieved from
+    /// cache.
+    ///
+    /// The default implementation returns `KeyManagerError::Unsupported`.
+    fn get_or_create_ephemeral_signing_keys(
+        &self,
+        _ctx: Context,
+        _key_pair_id: KeyPairId,
+        _key_type: KeyType,
+        _epoch: EpochTime,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        Box::pin(future::err(KeyManagerError::Unsupported))
+    }
+
+    /// Get ephemeral public signing key of the given type for an epoch and a key pair id.
+    ///
+    /// The default implementation returns `KeyManagerError::Unsupported`.
+    fn get_public_ephemeral_signing_key(
+        &self,
+        _ctx: Context,
+        _key_pair_id: KeyPairId,
+        _key_type: KeyType,
+        _epoch: EpochTime,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        Box::pin(future::err(KeyManagerError::Unsupported))
+    }
+
+    /// Get a copy of the given master secret generation for replication.
+    fn replicate_master_secret(
+        &self,
+        ctx: Context,
+        generation: u64,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>>;
+
+    /// Get a copy of all master secret generations starting with the given one for replication.
+    fn replicate_master_secrets(
+        &self,
+        ctx: Context,
+        from_generation: u64,
+    ) -> BoxFuture<Result<Vec<Secret>, KeyManagerError>>;
 
     /// Get a copy of the ephemeral secret for replication.
     fn replicate_ephemeral_secret(
@@ -65,6 +205,30 @@ pub trait KeyManagerClient: Send + Sync {
         ctx: Context,
         epoch: EpochTime,
     ) -> BoxFuture<Result<Secret, KeyManagerError>>;
+
+    /// Ask for a commitment to a contribution to a distributed master secret generation round.
+    fn commit_master_secret_contribution(
+        &self,
+        ctx: Context,
+        round: Hash,
+    ) -> BoxFuture<Result<ContributionCommitment, KeyManagerError>>;
+
+    /// Ask for the contribution to a distributed master secret generation round once
+    /// the commitments of all contributors are fixed.
+    fn reveal_master_secret_contribution(
+        &self,
+        ctx: Context,
+        round: Hash,
+        commitments: Vec<ContributionCommitment>,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>>;
+
+    /// Ask for a signature of the transcript of a distributed master secret generation round.
+    fn confirm_master_secret(
+        &self,
+        ctx: Context,
+        round: Hash,
+        contributions: Vec<Secret>,
+    ) -> BoxFuture<Result<SignatureBundle, KeyManagerError>>;
 }
 
 impl<T: ?Sized + KeyManagerClient> KeyManagerClient for Arc<T> {
@@ -88,6 +252,24 @@ impl<T: ?Sized + KeyManagerClient> KeyManagerClient for Arc<T> {
         KeyManagerClient::get_public_key(&**self, ctx, key_pair_id)
     }
 
+    fn get_or_create_keys_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        generation: u64,
+    ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
+        KeyManagerClient::get_or_create_keys_for_generation(&**self, ctx, key_pair_id, generation)
+    }
+
+    fn get_public_key_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        generation: u64,
+    ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>> {
+        KeyManagerClient::get_public_key_for_generation(&**self, ctx, key_pair_id, generation)
+    }
+
     fn get_or_create_ephemeral_keys(
         &self,
         ctx: Context,
@@ -106,8 +288,102 @@ impl<T: ?Sized + KeyManagerClient> KeyManagerClient for Arc<T> {
         KeyManagerClient::get_public_ephemeral_key(&**self, ctx, key_pair_id, epoch)
     }
 
-    fn replicate_master_secret(&self, ctx: Context) -> BoxFuture<Result<Secret, KeyManagerError>> {
-        KeyManagerClient::replicate_master_secret(&**self, ctx)
+    fn get_or_create_signing_keys(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        KeyManagerClient::get_or_create_signing_keys(&**self, ctx, key_pair_id, key_type)
+    }
+
+    fn get_public_signing_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        KeyManagerClient::get_public_signing_key(&**self, ctx, key_pair_id, key_type)
+    }
+
+    fn get_or_create_signing_keys_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        KeyManagerClient::get_or_create_signing_keys_for_generation(
+            &**self,
+            ctx,
+            key_pair_id,
+            key_type,
+            generation,
+        )
+    }
+
+    fn get_public_signing_key_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        KeyManagerClient::get_public_signing_key_for_generation(
+            &**self,
+            ctx,
+            key_pair_id,
+            key_type,
+            generation,
+        )
+    }
+
+    fn get_or_create_ephemeral_signing_keys(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        epoch: EpochTime,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        KeyManagerClient::get_or_create_ephemeral_signing_keys(
+            &**self,
+            ctx,
+            key_pair_id,
+            key_type,
+            epoch,
+        )
+    }
+
+    fn get_public_ephemeral_signing_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        epoch: EpochTime,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        KeyManagerClient::get_public_ephemeral_signing_key(
+            &**self,
+            ctx,
+            key_pair_id,
+            key_type,
+            epoch,
+        )
+    }
+
+    fn replicate_master_secret(
+        &self,
+        ctx: Context,
+        generation: u64,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>> {
+        KeyManagerClient::replicate_master_secret(&**self, ctx, generation)
+    }
+
+    fn replicate_master_secrets(
+        &self,
+        ctx: Context,
+        from_generation: u64,
+    ) -> BoxFuture<Result<Vec<Secret>, KeyManagerError>> {
+        KeyManagerClient::replicate_master_secrets(&**self, ctx, from_generation)
     }
 
     fn replicate_ephemeral_secret(
@@ -117,4 +393,30 @@ impl<T: ?Sized + KeyManagerClient> KeyManagerClient for Arc<T> {
     ) -> BoxFuture<Result<Secret, KeyManagerError>> {
         KeyManagerClient::replicate_ephemeral_secret(&**self, ctx, epoch)
     }
+
+    fn commit_master_secret_contribution(
+        &self,
+        ctx: Context,
+        round: Hash,
+    ) -> BoxFuture<Result<ContributionCommitment, KeyManagerError>> {
+        KeyManagerClient::commit_master_secret_contribution(&**self, ctx, round)
+    }
+
+    fn reveal_master_secret_contribution(
+        &self,
+        ctx: Context,
+        round: Hash,
+        commitments: Vec<ContributionCommitment>,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>> {
+        KeyManagerClient::reveal_master_secret_contribution(&**self, ctx, round, commitments)
+    }
+
+    fn confirm_master_secret(
+        &self,
+        ctx: Context,
+        round: Hash,
+        contributions: Vec<Secret>,
+    ) -> BoxFuture<Result<SignatureBundle, KeyManagerError>> {
+        KeyManagerClient::confirm_master_secret(&**self, ctx, round, contributions)
+    }
 }
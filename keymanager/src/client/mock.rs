@@ -7,19 +7,35 @@ use futures::{
 };
 use io_context::Context;
 
-use oasis_core_runtime::{common::crypto::signature::Signature, consensus::beacon::EpochTime};
+use oasis_core_runtime::{
+    common::{
+        crypto::{
+            hash::Hash,
+            signature::{PrivateKey, Signature, SignatureBundle},
+        },
+        namespace::Namespace,
+    },
+    consensus::beacon::EpochTime,
+};
 
 use crate::{
     api::KeyManagerError,
-    crypto::{KeyPair, KeyPairId, Secret, SignedPublicKey},
+    crypto::{
+        contribution::{ContributionCommitment, Contributions},
+        KeyPair, KeyPairId, KeyType, Secret, SignedPublicKey, SignedPublicSigningKey,
+        SigningKeyPair,
+    },
 };
 
 use super::KeyManagerClient;
 
 /// Mock key manager client which stores everything locally.
-#[derive(Default)]
 pub struct MockClient {
-    keys: Mutex<HashMap<(KeyPairId, Option<EpochTime>), KeyPair>>,
+    keys: Mutex<HashMap<(KeyPairId, Option<EpochTime>, u64), KeyPair>>,
+    signing_keys: Mutex<HashMap<(KeyPairId, Option<EpochTime>, u64, KeyType), SigningKeyPair>>,
+    runtime_id: Namespace,
+    rak: PrivateKey,
+    contributions: Contributions,
 }
 
 impl MockClient {
@@ -27,40 +43,78 @@ impl MockClient {
     pub fn new() -> Self {
         Self {
             keys: Mutex::new(HashMap::new()),
+            signing_keys: Mutex::new(HashMap::new()),
+            runtime_id: Namespace::default(),
+            rak: PrivateKey::generate(),
+            contributions: Contributions::new(),
         }
     }
+
+    /// Set the key manager runtime ID master secrets are generated for.
+    pub fn with_runtime_id(mut self, runtime_id: Namespace) -> Self {
+        self.runtime_id = runtime_id;
+        self
+    }
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl KeyManagerClient for MockClient {
     fn clear_cache(&self) {}
 
     fn get_or_create_keys(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+    ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
+        self.get_or_create_keys_for_generation(ctx, key_pair_id, 0)
+    }
+
+    fn get_public_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+    ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>> {
+        self.get_public_key_for_generation(ctx, key_pair_id, 0)
+    }
+
+    fn get_or_create_keys_for_generation(
         &self,
         _ctx: Context,
         key_pair_id: KeyPairId,
+        generation: u64,
     ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
         let mut keys = self.keys.lock().unwrap();
         let key = keys
-            .entry((key_pair_id, None))
+            .entry((key_pair_id, None, generation))
             .or_insert_with(KeyPair::generate_mock)
             .clone();
 
         Box::pin(future::ok(key))
     }
 
-    fn get_public_key(
+    fn get_public_key_for_generation(
         &self,
         ctx: Context,
         key_pair_id: KeyPairId,
+        generation: u64,
     ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>> {
-        Box::pin(self.get_or_create_keys(ctx, key_pair_id).and_then(|ck| {
-            future::ok(SignedPublicKey {
-                key: ck.input_keypair.pk,
-                checksum: vec![],
-                signature: Signature::default(),
-                expiration: None,
-            })
-        }))
+        Box::pin(
+            self.get_or_create_keys_for_generation(ctx, key_pair_id, generation)
+                .and_then(move |ck| {
+                    future::ok(SignedPublicKey {
+                        key: ck.input_keypair.pk,
+                        checksum: vec![],
+                        signature: Signature::default(),
+                        expiration: None,
+                        generation,
+                    })
+                }),
+        )
     }
 
     fn get_or_create_ephemeral_keys(
@@ -71,7 +125,7 @@ impl KeyManagerClient for MockClient {
     ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
         let mut keys = self.keys.lock().unwrap();
         let key = keys
-            .entry((key_pair_id, Some(epoch)))
+            .entry((key_pair_id, Some(epoch), 0))
             .or_insert_with(KeyPair::generate_mock)
             .clone();
 
@@ -92,12 +146,127 @@ impl KeyManagerClient for MockClient {
                         checksum: vec![],
                         signature: Signature::default(),
                         expiration: None,
+                        generation: 0,
+                    })
+                }),
+        )
+    }
+
+    fn get_or_create_signing_keys(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        self.get_or_create_signing_keys_for_generation(ctx, key_pair_id, key_type, 0)
+    }
+
+    fn get_public_signing_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        self.get_public_signing_key_for_generation(ctx, key_pair_id, key_type, 0)
+    }
+
+    fn get_or_create_signing_keys_for_generation(
+        &self,
+        _ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        if key_type != KeyType::Ed25519 {
+            return Box::pin(future::err(KeyManagerError::UnsupportedKeyType));
+        }
+
+        let mut keys = self.signing_keys.lock().unwrap();
+        let key = keys
+            .entry((key_pair_id, None, generation, key_type))
+            .or_insert_with(SigningKeyPair::generate_mock)
+            .clone();
+
+        Box::pin(future::ok(key))
+    }
+
+    fn get_public_signing_key_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        Box::pin(
+            self.get_or_create_signing_keys_for_generation(ctx, key_pair_id, key_type, generation)
+                .and_then(move |ck| {
+                    future::ok(SignedPublicSigningKey {
+                        key_type: ck.key_type,
+                        key: ck.pk,
+                        checksum: vec![],
+                        signature: Signature::default(),
+                        expiration: None,
+                        generation,
+                    })
+                }),
+        )
+    }
+
+    fn get_or_create_ephemeral_signing_keys(
+        &self,
+        _ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        epoch: EpochTime,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        if key_type != KeyType::Ed25519 {
+            return Box::pin(future::err(KeyManagerError::UnsupportedKeyType));
+        }
+
+        let mut keys = self.signing_keys.lock().unwrap();
+        let key = keys
+            .entry((key_pair_id, Some(epoch), 0, key_type))
+            .or_insert_with(SigningKeyPair::generate_mock)
+            .clone();
+
+        Box::pin(future::ok(key))
+    }
+
+    fn get_public_ephemeral_signing_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        epoch: EpochTime,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        Box::pin(
+            self.get_or_create_ephemeral_signing_keys(ctx, key_pair_id, key_type, epoch)
+                .and_then(|ck| {
+                    future::ok(SignedPublicSigningKey {
+                        key_type: ck.key_type,
+                        key: ck.pk,
+                        checksum: vec![],
+                        signature: Signature::default(),
+                        expiration: None,
+                        generation: 0,
                     })
                 }),
         )
     }
 
-    fn replicate_master_secret(&self, _ctx: Context) -> BoxFuture<Result<Secret, KeyManagerError>> {
+    fn replicate_master_secret(
+        &self,
+        _ctx: Context,
+        _generation: u64,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>> {
+        unimplemented!();
+    }
+
+    fn replicate_master_secrets(
+        &self,
+        _ctx: Context,
+        _from_generation: u64,
+    ) -> BoxFuture<Result<Vec<Secret>, KeyManagerError>> {
         unimplemented!();
     }
 
@@ -108,4 +277,46 @@ impl KeyManagerClient for MockClient {
     ) -> BoxFuture<Result<Secret, KeyManagerError>> {
         unimplemented!();
     }
+
+    fn commit_master_secret_contribution(
+        &self,
+        _ctx: Context,
+        round: Hash,
+    ) -> BoxFuture<Result<ContributionCommitment, KeyManagerError>> {
+        let commitment = ContributionCommitment {
+            rak: self.rak.public_key(),
+            commitment: self.contributions.commit(round),
+        };
+
+        Box::pin(future::ok(commitment))
+    }
+
+    fn reveal_master_secret_contribution(
+        &self,
+        _ctx: Context,
+        round: Hash,
+        commitments: Vec<ContributionCommitment>,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>> {
+        let result = self
+            .contributions
+            .reveal(round, self.rak.public_key(), commitments)
+            .map_err(KeyManagerError::Other);
+
+        Box::pin(future::ready(result))
+    }
+
+    fn confirm_master_secret(
+        &self,
+        _ctx: Context,
+        round: Hash,
+        contributions: Vec<Secret>,
+    ) -> BoxFuture<Result<SignatureBundle, KeyManagerError>> {
+        let result = self
+            .contributions
+            .confirm(round, self.runtime_id, contributions)
+            .and_then(|transcript| transcript.sign_with(&self.rak, self.rak.public_key()))
+            .map_err(KeyManagerError::Other);
+
+        Box::pin(future::ready(result))
+    }
 }
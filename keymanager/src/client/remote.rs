@@ -6,13 +6,20 @@ use std::{
     sync::{Arc, RwLock},
 };
 
-use futures::future::{self, BoxFuture};
+use anyhow::anyhow;
+use futures::{
+    future::{self, BoxFuture},
+    Future,
+};
 use io_context::Context;
 use lru::LruCache;
 
 use oasis_core_runtime::{
     common::{
-        crypto::signature::{self, PublicKey},
+        crypto::{
+            hash::Hash,
+            signature::{self, PublicKey, SignatureBundle},
+        },
         namespace::Namespace,
         sgx::{EnclaveIdentity, QuotePolicy},
     },
@@ -21,20 +28,26 @@ use oasis_core_runtime::{
         state::{beacon::ImmutableState as BeaconState, keymanager::Status as KeyManagerStatus},
         verifier::Verifier,
     },
-    enclave_rpc::{client::RpcClient, session},
+    enclave_rpc::{
+        client::{RpcClient, RpcClientError},
+        session,
+    },
     identity::Identity,
     protocol::Protocol,
 };
 
 use crate::{
     api::{
-        EphemeralKeyRequest, KeyManagerError, LongTermKeyRequest, ReplicateEphemeralSecretRequest,
+        CommitMasterSecretContributionRequest, ConfirmMasterSecretRequest, EphemeralKeyRequest,
+        KeyManagerError, KeyManagerRpcClient, LongTermKeyRequest, ReplicateEphemeralSecretRequest,
         ReplicateEphemeralSecretResponse, ReplicateMasterSecretRequest,
-        ReplicateMasterSecretResponse, METHOD_GET_OR_CREATE_EPHEMERAL_KEYS,
-        METHOD_GET_OR_CREATE_KEYS, METHOD_GET_PUBLIC_EPHEMERAL_KEY, METHOD_GET_PUBLIC_KEY,
-        METHOD_REPLICATE_EPHEMERAL_SECRET, METHOD_REPLICATE_MASTER_SECRET,
+        ReplicateMasterSecretResponse, ReplicateMasterSecretsRequest,
+        RevealMasterSecretContributionRequest, RevealMasterSecretContributionResponse,
+    },
+    crypto::{
+        contribution::ContributionCommitment, KeyPair, KeyPairId, KeyType, Secret, SignedPublicKey,
+        SignedPublicSigningKey, SigningKeyPair,
     },
-    crypto::{KeyPair, KeyPairId, Secret, SignedPublicKey},
     policy::{set_trusted_policy_signers, verify_policy_and_trusted_signers, TrustedPolicySigners},
 };
 
@@ -42,22 +55,71 @@ use super::KeyManagerClient;
 
 /// Key manager RPC endpoint.
 const KEY_MANAGER_ENDPOINT: &str = "key-manager";
+/// Maximum number of concurrent sessions with key manager nodes.
+const KEY_MANAGER_MAX_SESSIONS: usize = 3;
+/// Maximum number of attempts of a key manager call failing with retryable errors.
+const KEY_MANAGER_MAX_CALL_ATTEMPTS: usize = 3;
 
 struct Inner {
     /// Runtime identifier for which we are going to request keys.
     runtime_id: Namespace,
     /// RPC client.
-    rpc_client: RpcClient,
+    rpc_client: KeyManagerRpcClient,
     /// Consensus verifier.
     consensus_verifier: Arc<dyn Verifier>,
     /// Local cache for the long-term and ephemeral private keys fetched from
     /// get_or_create_keys and get_or_create_ephemeral_keys KeyManager endpoints.
-    private_key_cache: RwLock<LruCache<(KeyPairId, Option<EpochTime>), KeyPair>>,
+    private_key_cache: RwLock<LruCache<(KeyPairId, Option<EpochTime>, u64), KeyPair>>,
     /// Local cache for the long-term and ephemeral public keys fetched from
     /// get_public_key and get_public_ephemeral_key KeyManager endpoints.
-    public_key_cache: RwLock<LruCache<(KeyPairId, Option<EpochTime>), SignedPublicKey>>,
+    public_key_cache: RwLock<LruCache<(KeyPairId, Option<EpochTime>, u64), SignedPublicKey>>,
+    /// Local cache for the long-term and ephemeral signing keys fetched from
+    /// get_or_create_signing_keys and get_or_create_ephemeral_signing_keys KeyManager endpoints.
+    signing_key_cache:
+        RwLock<LruCache<(KeyPairId, Option<EpochTime>, u64, KeyType), SigningKeyPair>>,
+    /// Local cache for the long-term and ephemeral public signing keys fetched from
+    /// get_public_signing_key and get_public_ephemeral_signing_key KeyManager endpoints.
+    public_signing_key_cache:
+        RwLock<LruCache<(KeyPairId, Option<EpochTime>, u64, KeyType), SignedPublicSigningKey>>,
     /// Key manager's runtime signing key.
     rsk: RwLock<Option<PublicKey>>,
+    /// Latest master secret generation accepted by the consensus layer and its checksum.
+    checksum: RwLock<Option<(u64, Vec<u8>)>>,
+}
+
+/// Perform a call to the key manager at the latest consensus height, retrying it in case it fails
+/// with a retryable error.
+///
+/// Failed calls cause the RPC client to fail over to another key manager node, so retries are
+/// likely handled by a different node.
+async fn call_with_retries<T, F, Fut>(
+    inner: &Arc<Inner>,
+    ctx: Context,
+    call: F,
+) -> Result<T, KeyManagerError>
+where
+    F: Fn(Arc<Inner>, Context, u64) -> Fut,
+    Fut: Future<Output = Result<T, RpcClientError>>,
+{
+    let ctx = ctx.freeze();
+    let mut attempt = 1;
+    loop {
+        let height = inner
+            .consensus_verifier
+            .latest_height()
+            .map_err(|err| KeyManagerError::Other(err.into()))?;
+
+        match call(inner.clone(), Context::create_child(&ctx), height).await {
+            Ok(result) => return Ok(result),
+            Err(err) => {
+                let err = KeyManagerError::from(err);
+                if !err.is_retryable() || attempt >= KEY_MANAGER_MAX_CALL_ATTEMPTS {
+                    return Err(err);
+                }
+                attempt += 1;
+            }
+        }
+    }
 }
 
 /// A key manager client which talks to a remote key manager enclave.
@@ -75,7 +137,7 @@ impl RemoteClient {
         Self {
             inner: Arc::new(Inner {
                 runtime_id,
-                rpc_client,
+                rpc_client: KeyManagerRpcClient::new(rpc_client),
                 consensus_verifier,
                 private_key_cache: RwLock::new(LruCache::new(
                     NonZeroUsize::new(keys_cache_sizes).unwrap(),
@@ -83,7 +145,14 @@ impl RemoteClient {
                 public_key_cache: RwLock::new(LruCache::new(
                     NonZeroUsize::new(keys_cache_sizes).unwrap(),
                 )),
+                signing_key_cache: RwLock::new(LruCache::new(
+                    NonZeroUsize::new(keys_cache_sizes).unwrap(),
+                )),
+                public_signing_key_cache: RwLock::new(LruCache::new(
+                    NonZeroUsize::new(keys_cache_sizes).unwrap(),
+                )),
                 rsk: RwLock::new(None),
+                checksum: RwLock::new(None),
             }),
         }
     }
@@ -104,7 +173,7 @@ impl RemoteClient {
     ) -> Self {
         Self::new(
             runtime_id,
-            RpcClient::new_runtime(
+            RpcClient::new_runtime_with_sessions(
                 session::Builder::default()
                     .remote_enclaves(enclaves)
                     .quote_policy(policy)
@@ -114,6 +183,7 @@ impl RemoteClient {
                 protocol,
                 KEY_MANAGER_ENDPOINT,
                 nodes,
+                KEY_MANAGER_MAX_SESSIONS,
             ),
             consensus_verifier,
             keys_cache_sizes,
@@ -181,6 +251,15 @@ impl RemoteClient {
             self.inner.rsk.write().unwrap().replace(rsk);
         }
 
+        // Set checksum of the latest master secret generation.
+        if status.is_initialized {
+            self.inner
+                .checksum
+                .write()
+                .unwrap()
+                .replace((status.generation, status.checksum));
+        }
+
         // Set key manager runtime ID.
         self.inner.rpc_client.update_runtime_id(Some(status.id));
 
@@ -211,14 +290,83 @@ impl RemoteClient {
         key: &SignedPublicKey,
         key_pair_id: KeyPairId,
         epoch: Option<EpochTime>,
+        generation: u64,
         now: Option<EpochTime>,
     ) -> Result<(), KeyManagerError> {
+        self.verify_generation(&key.checksum, key.generation, epoch, generation)?;
+
+        let pk = self.inner.rsk.read().unwrap();
+        let pk = pk.as_ref().ok_or(KeyManagerError::RSKMissing)?;
+
+        key.verify(self.inner.runtime_id, key_pair_id, epoch, now, pk)
+            .map_err(KeyManagerError::InvalidSignature)
+    }
+
+    fn verify_public_signing_key(
+        &self,
+        key: &SignedPublicSigningKey,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        epoch: Option<EpochTime>,
+        generation: u64,
+        now: Option<EpochTime>,
+    ) -> Result<(), KeyManagerError> {
+        if key.key_type != key_type {
+            return Err(KeyManagerError::UnsupportedKeyType);
+        }
+        self.verify_generation(&key.checksum, key.generation, epoch, generation)?;
+
         let pk = self.inner.rsk.read().unwrap();
         let pk = pk.as_ref().ok_or(KeyManagerError::RSKMissing)?;
 
         key.verify(self.inner.runtime_id, key_pair_id, epoch, now, pk)
             .map_err(KeyManagerError::InvalidSignature)
     }
+
+    /// Verify that a signed public key is derived from the requested master secret generation.
+    ///
+    /// Checksums of long-term keys derived from the latest generation must also match
+    /// the checksum accepted by the consensus layer.
+    fn verify_generation(
+        &self,
+        checksum: &[u8],
+        key_generation: u64,
+        epoch: Option<EpochTime>,
+        generation: u64,
+    ) -> Result<(), KeyManagerError> {
+        if key_generation != generation {
+            return Err(KeyManagerError::InvalidSignature(anyhow!(
+                "master secret generation mismatch: expected {}, got {}",
+                generation,
+                key_generation,
+            )));
+        }
+        if epoch.is_some() {
+            // Ephemeral keys are not derived from master secrets.
+            return Ok(());
+        }
+
+        match self.inner.checksum.read().unwrap().as_ref() {
+            Some((latest, expected)) if *latest == generation && expected != checksum => Err(
+                KeyManagerError::InvalidSignature(anyhow!("master secret checksum mismatch")),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fetch current epoch from the consensus layer.
+    fn consensus_epoch(&self, ctx: Context) -> Result<EpochTime, KeyManagerError> {
+        let consensus_state = self
+            .inner
+            .consensus_verifier
+            .latest_state()
+            .map_err(|err| KeyManagerError::Other(err.into()))?;
+        let beacon_state = BeaconState::new(&consensus_state);
+        let consensus_epoch = beacon_state
+            .epoch(ctx)
+            .map_err(|err| KeyManagerError::Other(err.into()))?;
+        Ok(consensus_epoch)
+    }
 }
 
 impl KeyManagerClient for RemoteClient {
@@ -232,16 +380,41 @@ impl KeyManagerClient for RemoteClient {
         let mut cache = self.inner.public_key_cache.write().unwrap();
         cache.clear();
         drop(cache);
+
+        let mut cache = self.inner.signing_key_cache.write().unwrap();
+        cache.clear();
+        drop(cache);
+
+        let mut cache = self.inner.public_signing_key_cache.write().unwrap();
+        cache.clear();
+        drop(cache);
     }
 
     fn get_or_create_keys(
         &self,
         ctx: Context,
         key_pair_id: KeyPairId,
+    ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
+        self.get_or_create_keys_for_generation(ctx, key_pair_id, 0)
+    }
+
+    fn get_public_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+    ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>> {
+        self.get_public_key_for_generation(ctx, key_pair_id, 0)
+    }
+
+    fn get_or_create_keys_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        generation: u64,
     ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
         // Fetch from cache.
         let mut cache = self.inner.private_key_cache.write().unwrap();
-        let id = &(key_pair_id, None);
+        let id = &(key_pair_id, None, generation);
         if let Some(keys) = cache.get(id) {
             return Box::pin(future::ok(keys.clone()));
         }
@@ -249,39 +422,37 @@ impl KeyManagerClient for RemoteClient {
         // No entry in cache, fetch from key manager.
         let inner = self.inner.clone();
         Box::pin(async move {
-            let height = inner
-                .consensus_verifier
-                .latest_height()
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
-
-            let keys: KeyPair = inner
-                .rpc_client
-                .secure_call(
-                    ctx,
-                    METHOD_GET_OR_CREATE_KEYS,
-                    LongTermKeyRequest::new(Some(height), inner.runtime_id, key_pair_id),
-                )
-                .await
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
+            let keys: KeyPair = call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                inner
+                    .rpc_client
+                    .get_or_create_keys(
+                        ctx,
+                        LongTermKeyRequest::new(Some(height), inner.runtime_id, key_pair_id)
+                            .with_generation(generation),
+                    )
+                    .await
+            })
+            .await?;
 
             // Cache key.
             let mut cache = inner.private_key_cache.write().unwrap();
-            cache.put((key_pair_id, None), keys.clone());
+            cache.put((key_pair_id, None, generation), keys.clone());
 
             Ok(keys)
         })
     }
 
-    fn get_public_key(
+    fn get_public_key_for_generation(
         &self,
         ctx: Context,
         key_pair_id: KeyPairId,
+        generation: u64,
     ) -> BoxFuture<Result<SignedPublicKey, KeyManagerError>> {
         // Fetch from cache.
         let mut cache = self.inner.public_key_cache.write().unwrap();
-        let id = &(key_pair_id, None);
+        let id = &(key_pair_id, None, generation);
         if let Some(key) = cache.get(id) {
-            match self.verify_public_key(key, key_pair_id, None, None) {
+            match self.verify_public_key(key, key_pair_id, None, generation, None) {
                 Ok(()) => return Box::pin(future::ok(key.clone())),
                 Err(_) => {
                     cache.pop(id);
@@ -292,27 +463,25 @@ impl KeyManagerClient for RemoteClient {
         // No entry in cache, fetch from key manager.
         let inner = self.inner.clone();
         Box::pin(async move {
-            let height = inner
-                .consensus_verifier
-                .latest_height()
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
-
-            let key: SignedPublicKey = inner
-                .rpc_client
-                .insecure_call(
-                    ctx,
-                    METHOD_GET_PUBLIC_KEY,
-                    LongTermKeyRequest::new(Some(height), inner.runtime_id, key_pair_id),
-                )
-                .await
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
+            let key: SignedPublicKey =
+                call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .get_public_key(
+                            ctx,
+                            LongTermKeyRequest::new(Some(height), inner.runtime_id, key_pair_id)
+                                .with_generation(generation),
+                        )
+                        .await
+                })
+                .await?;
 
             // Verify the signature.
-            self.verify_public_key(&key, key_pair_id, None, None)?;
+            self.verify_public_key(&key, key_pair_id, None, generation, None)?;
 
             // Cache key.
             let mut cache = inner.public_key_cache.write().unwrap();
-            cache.put((key_pair_id, None), key.clone());
+            cache.put((key_pair_id, None, generation), key.clone());
 
             Ok(key)
         })
@@ -326,7 +495,7 @@ impl KeyManagerClient for RemoteClient {
     ) -> BoxFuture<Result<KeyPair, KeyManagerError>> {
         // Fetch from cache.
         let mut cache = self.inner.private_key_cache.write().unwrap();
-        let id = &(key_pair_id, Some(epoch));
+        let id = &(key_pair_id, Some(epoch), 0);
         if let Some(keys) = cache.get(id) {
             return Box::pin(future::ok(keys.clone()));
         }
@@ -334,24 +503,25 @@ impl KeyManagerClient for RemoteClient {
         // No entry in cache, fetch from key manager.
         let inner = self.inner.clone();
         Box::pin(async move {
-            let height = inner
-                .consensus_verifier
-                .latest_height()
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
-
-            let keys: KeyPair = inner
-                .rpc_client
-                .secure_call(
-                    ctx,
-                    METHOD_GET_OR_CREATE_EPHEMERAL_KEYS,
-                    EphemeralKeyRequest::new(Some(height), inner.runtime_id, key_pair_id, epoch),
-                )
-                .await
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
+            let keys: KeyPair = call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                inner
+                    .rpc_client
+                    .get_or_create_ephemeral_keys(
+                        ctx,
+                        EphemeralKeyRequest::new(
+                            Some(height),
+                            inner.runtime_id,
+                            key_pair_id,
+                            epoch,
+                        ),
+                    )
+                    .await
+            })
+            .await?;
 
             // Cache key.
             let mut cache = inner.private_key_cache.write().unwrap();
-            cache.put((key_pair_id, Some(epoch)), keys.clone());
+            cache.put((key_pair_id, Some(epoch), 0), keys.clone());
 
             Ok(keys)
         })
@@ -366,22 +536,16 @@ impl KeyManagerClient for RemoteClient {
         let ctx = ctx.freeze();
 
         // Fetch current epoch.
-        let get_consensus_epoch = || -> Result<EpochTime, anyhow::Error> {
-            let consensus_state = self.inner.consensus_verifier.latest_state()?;
-            let beacon_state = BeaconState::new(&consensus_state);
-            let consensus_epoch = beacon_state.epoch(Context::create_child(&ctx))?;
-            Ok(consensus_epoch)
-        };
-        let consensus_epoch = match get_consensus_epoch() {
+        let consensus_epoch = match self.consensus_epoch(Context::create_child(&ctx)) {
             Ok(epoch) => epoch,
-            Err(err) => return Box::pin(future::err(KeyManagerError::Other(err))),
+            Err(err) => return Box::pin(future::err(err)),
         };
 
         // Fetch from cache.
         let mut cache = self.inner.public_key_cache.write().unwrap();
-        let id = &(key_pair_id, Some(epoch));
+        let id = &(key_pair_id, Some(epoch), 0);
         if let Some(key) = cache.get(id) {
-            match self.verify_public_key(key, key_pair_id, Some(epoch), Some(consensus_epoch)) {
+            match self.verify_public_key(key, key_pair_id, Some(epoch), 0, Some(consensus_epoch)) {
                 Ok(()) => return Box::pin(future::ok(key.clone())),
                 Err(_) => {
                     cache.pop(id);
@@ -392,55 +556,311 @@ impl KeyManagerClient for RemoteClient {
         // No entry in cache, fetch from key manager.
         let inner = self.inner.clone();
         Box::pin(async move {
-            let height = inner
-                .consensus_verifier
-                .latest_height()
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
-
-            let key: SignedPublicKey = inner
-                .rpc_client
-                .insecure_call(
-                    Context::create_child(&ctx),
-                    METHOD_GET_PUBLIC_EPHEMERAL_KEY,
-                    EphemeralKeyRequest::new(Some(height), inner.runtime_id, key_pair_id, epoch),
-                )
-                .await
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
+            let key: SignedPublicKey = call_with_retries(
+                &inner,
+                Context::create_child(&ctx),
+                |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .get_public_ephemeral_key(
+                            ctx,
+                            EphemeralKeyRequest::new(
+                                Some(height),
+                                inner.runtime_id,
+                                key_pair_id,
+                                epoch,
+                            ),
+                        )
+                        .await
+                },
+            )
+            .await?;
 
             // Verify the signature.
-            self.verify_public_key(&key, key_pair_id, Some(epoch), Some(consensus_epoch))?;
+            self.verify_public_key(&key, key_pair_id, Some(epoch), 0, Some(consensus_epoch))?;
 
             // Cache key.
             let mut cache = inner.public_key_cache.write().unwrap();
-            cache.put((key_pair_id, Some(epoch)), key.clone());
+            cache.put((key_pair_id, Some(epoch), 0), key.clone());
 
             Ok(key)
         })
     }
 
-    fn replicate_master_secret(&self, ctx: Context) -> BoxFuture<Result<Secret, KeyManagerError>> {
+    fn get_or_create_signing_keys(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        self.get_or_create_signing_keys_for_generation(ctx, key_pair_id, key_type, 0)
+    }
+
+    fn get_public_signing_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        self.get_public_signing_key_for_generation(ctx, key_pair_id, key_type, 0)
+    }
+
+    fn get_or_create_signing_keys_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        // Fetch from cache.
+        let mut cache = self.inner.signing_key_cache.write().unwrap();
+        let id = &(key_pair_id, None, generation, key_type);
+        if let Some(keys) = cache.get(id) {
+            return Box::pin(future::ok(keys.clone()));
+        }
+
+        // No entry in cache, fetch from key manager.
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let keys: SigningKeyPair =
+                call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .get_or_create_signing_keys(
+                            ctx,
+                            LongTermKeyRequest::new(Some(height), inner.runtime_id, key_pair_id)
+                                .with_generation(generation)
+                                .with_key_type(key_type),
+                        )
+                        .await
+                })
+                .await?;
+
+            // Cache key.
+            let mut cache = inner.signing_key_cache.write().unwrap();
+            cache.put((key_pair_id, None, generation, key_type), keys.clone());
+
+            Ok(keys)
+        })
+    }
+
+    fn get_public_signing_key_for_generation(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        // Fetch from cache.
+        let mut cache = self.inner.public_signing_key_cache.write().unwrap();
+        let id = &(key_pair_id, None, generation, key_type);
+        if let Some(key) = cache.get(id) {
+            match self.verify_public_signing_key(key, key_pair_id, key_type, None, generation, None)
+            {
+                Ok(()) => return Box::pin(future::ok(key.clone())),
+                Err(_) => {
+                    cache.pop(id);
+                }
+            }
+        }
+
+        // No entry in cache, fetch from key manager.
         let inner = self.inner.clone();
         Box::pin(async move {
-            let height = inner
-                .consensus_verifier
-                .latest_height()
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
-
-            let rsp: ReplicateMasterSecretResponse = inner
-                .rpc_client
-                .secure_call(
-                    ctx,
-                    METHOD_REPLICATE_MASTER_SECRET,
-                    ReplicateMasterSecretRequest {
-                        height: Some(height),
-                    },
-                )
-                .await
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
+            let key: SignedPublicSigningKey =
+                call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .get_public_signing_key(
+                            ctx,
+                            LongTermKeyRequest::new(Some(height), inner.runtime_id, key_pair_id)
+                                .with_generation(generation)
+                                .with_key_type(key_type),
+                        )
+                        .await
+                })
+                .await?;
+
+            // Verify the signature.
+            self.verify_public_signing_key(&key, key_pair_id, key_type, None, generation, None)?;
+
+            // Cache key.
+            let mut cache = inner.public_signing_key_cache.write().unwrap();
+            cache.put((key_pair_id, None, generation, key_type), key.clone());
+
+            Ok(key)
+        })
+    }
+
+    fn get_or_create_ephemeral_signing_keys(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        epoch: EpochTime,
+    ) -> BoxFuture<Result<SigningKeyPair, KeyManagerError>> {
+        // Fetch from cache.
+        let mut cache = self.inner.signing_key_cache.write().unwrap();
+        let id = &(key_pair_id, Some(epoch), 0, key_type);
+        if let Some(keys) = cache.get(id) {
+            return Box::pin(future::ok(keys.clone()));
+        }
+
+        // No entry in cache, fetch from key manager.
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let keys: SigningKeyPair =
+                call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .get_or_create_ephemeral_signing_keys(
+                            ctx,
+                            EphemeralKeyRequest::new(
+                                Some(height),
+                                inner.runtime_id,
+                                key_pair_id,
+                                epoch,
+                            )
+                            .with_key_type(key_type),
+                        )
+                        .await
+                })
+                .await?;
+
+            // Cache key.
+            let mut cache = inner.signing_key_cache.write().unwrap();
+            cache.put((key_pair_id, Some(epoch), 0, key_type), keys.clone());
+
+            Ok(keys)
+        })
+    }
+
+    fn get_public_ephemeral_signing_key(
+        &self,
+        ctx: Context,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        epoch: EpochTime,
+    ) -> BoxFuture<Result<SignedPublicSigningKey, KeyManagerError>> {
+        let ctx = ctx.freeze();
+
+        // Fetch current epoch.
+        let consensus_epoch = match self.consensus_epoch(Context::create_child(&ctx)) {
+            Ok(epoch) => epoch,
+            Err(err) => return Box::pin(future::err(err)),
+        };
+
+        // Fetch from cache.
+        let mut cache = self.inner.public_signing_key_cache.write().unwrap();
+        let id = &(key_pair_id, Some(epoch), 0, key_type);
+        if let Some(key) = cache.get(id) {
+            match self.verify_public_signing_key(
+                key,
+                key_pair_id,
+                key_type,
+                Some(epoch),
+                0,
+                Some(consensus_epoch),
+            ) {
+                Ok(()) => return Box::pin(future::ok(key.clone())),
+                Err(_) => {
+                    cache.pop(id);
+                }
+            }
+        }
+
+        // No entry in cache, fetch from key manager.
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let key: SignedPublicSigningKey = call_with_retries(
+                &inner,
+                Context::create_child(&ctx),
+                |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .get_public_ephemeral_signing_key(
+                            ctx,
+                            EphemeralKeyRequest::new(
+                                Some(height),
+                                inner.runtime_id,
+                                key_pair_id,
+                                epoch,
+                            )
+                            .with_key_type(key_type),
+                        )
+                        .await
+                },
+            )
+            .await?;
+
+            // Verify the signature.
+            self.verify_public_signing_key(
+                &key,
+                key_pair_id,
+                key_type,
+                Some(epoch),
+                0,
+                Some(consensus_epoch),
+            )?;
+
+            // Cache key.
+            let mut cache = inner.public_signing_key_cache.write().unwrap();
+            cache.put((key_pair_id, Some(epoch), 0, key_type), key.clone());
+
+            Ok(key)
+        })
+    }
+
+    fn replicate_master_secret(
+        &self,
+        ctx: Context,
+        generation: u64,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let rsp: ReplicateMasterSecretResponse =
+                call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .replicate_master_secret(
+                            ctx,
+                            ReplicateMasterSecretRequest {
+                                height: Some(height),
+                                generation,
+                            },
+                        )
+                        .await
+                })
+                .await?;
             Ok(rsp.master_secret)
         })
     }
 
+    fn replicate_master_secrets(
+        &self,
+        ctx: Context,
+        from_generation: u64,
+    ) -> BoxFuture<Result<Vec<Secret>, KeyManagerError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let rsp: Vec<ReplicateMasterSecretResponse> =
+                call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .replicate_master_secrets(
+                            ctx,
+                            ReplicateMasterSecretsRequest {
+                                height: Some(height),
+                                from_generation,
+                            },
+                        )
+                        .await
+                })
+                .await?;
+            Ok(rsp.into_iter().map(|rsp| rsp.master_secret).collect())
+        })
+    }
+
     fn replicate_ephemeral_secret(
         &self,
         ctx: Context,
@@ -448,24 +868,336 @@ impl KeyManagerClient for RemoteClient {
     ) -> BoxFuture<Result<Secret, KeyManagerError>> {
         let inner = self.inner.clone();
         Box::pin(async move {
-            let height = inner
-                .consensus_verifier
-                .latest_height()
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
-
-            let rsp: ReplicateEphemeralSecretResponse = inner
-                .rpc_client
-                .secure_call(
-                    ctx,
-                    METHOD_REPLICATE_EPHEMERAL_SECRET,
-                    ReplicateEphemeralSecretRequest {
-                        height: Some(height),
-                        epoch,
-                    },
-                )
-                .await
-                .map_err(|err| KeyManagerError::Other(err.into()))?;
+            let rsp: ReplicateEphemeralSecretResponse =
+                call_with_retries(&inner, ctx, |inner, ctx, height| async move {
+                    inner
+                        .rpc_client
+                        .replicate_ephemeral_secret(
+                            ctx,
+                            ReplicateEphemeralSecretRequest {
+                                height: Some(height),
+                                epoch,
+                            },
+                        )
+                        .await
+                })
+                .await?;
             Ok(rsp.ephemeral_secret)
         })
     }
+
+    fn commit_master_secret_contribution(
+        &self,
+        ctx: Context,
+        round: Hash,
+    ) -> BoxFuture<Result<ContributionCommitment, KeyManagerError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            call_with_retries(&inner, ctx, |inner, ctx, _| async move {
+                inner
+                    .rpc_client
+                    .commit_master_secret_contribution(
+                        ctx,
+                        CommitMasterSecretContributionRequest { round },
+                    )
+                    .await
+            })
+            .await
+        })
+    }
+
+    fn reveal_master_secret_contribution(
+        &self,
+        ctx: Context,
+        round: Hash,
+        commitments: Vec<ContributionCommitment>,
+    ) -> BoxFuture<Result<Secret, KeyManagerError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let rsp: RevealMasterSecretContributionResponse =
+                call_with_retries(&inner, ctx, |inner, ctx, _| {
+                    let commitments = commitments.clone();
+                    async move {
+                        inner
+                            .rpc_client
+                            .reveal_master_secret_contribution(
+                                ctx,
+                                RevealMasterSecretContributionRequest { round, commitments },
+                            )
+                            .await
+                    }
+                })
+                .await?;
+            Ok(rsp.contribution)
+        })
+    }
+
+    fn confirm_master_secret(
+        &self,
+        ctx: Context,
+        round: Hash,
+        contributions: Vec<Secret>,
+    ) -> BoxFuture<Result<SignatureBundle, KeyManagerError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            call_with_retries(&inner, ctx, |inner, ctx, _| {
+                let contributions = contributions.clone();
+                async move {
+                    inner
+                        .rpc_client
+                        .confirm_master_secret(
+                            ctx,
+                            ConfirmMasterSecretRequest {
+                                round,
+                                contributions,
+                            },
+                        )
+                        .await
+                }
+            })
+            .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use anyhow::anyhow;
+    use futures::future::{self, BoxFuture};
+    use io_context::Context;
+
+    use oasis_core_runtime::{
+        common::{
+            crypto::{
+                signature::{self, PrivateKey, Signer},
+                x25519,
+            },
+            namespace::Namespace,
+        },
+        consensus::{
+            beacon::EpochTime,
+            roothash::{ComputeResultsHeader, Header},
+            state::{keymanager::Status as KeyManagerStatus, ConsensusState},
+            verifier::{Error as VerifierError, Verifier},
+            Event, LightBlock,
+        },
+        enclave_rpc::{client::RpcClient, session, transport::Transport, types},
+        types::EventKind,
+    };
+
+    use crate::{
+        api::{KeyManagerError, LongTermKeyRequest, METHOD_GET_PUBLIC_KEY},
+        client::KeyManagerClient,
+        crypto::{KeyPairId, SignedPublicKey},
+    };
+
+    use super::RemoteClient;
+
+    struct MockVerifier;
+
+    impl Verifier for MockVerifier {
+        fn sync(&self, _height: u64) -> Result<(), VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn verify(
+            &self,
+            _consensus_block: LightBlock,
+            _runtime_header: Header,
+            _epoch: EpochTime,
+        ) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn verify_for_query(
+            &self,
+            _consensus_block: LightBlock,
+            _runtime_header: Header,
+            _epoch: EpochTime,
+        ) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn unverified_state(
+            &self,
+            _consensus_block: LightBlock,
+        ) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn latest_state(&self) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn state_at(&self, _height: u64) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn events_at(&self, _height: u64, _kind: EventKind) -> Result<Vec<Event>, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn latest_height(&self) -> Result<u64, VerifierError> {
+            Ok(1)
+        }
+
+        fn trust(&self, _header: &ComputeResultsHeader) -> Result<(), VerifierError> {
+            Err(VerifierError::Internal)
+        }
+    }
+
+    /// Transport which serves signed public keys for the requested master secret generations.
+    #[derive(Clone)]
+    struct MockTransport {
+        signer: Arc<dyn Signer>,
+        checksums: Vec<Vec<u8>>,
+        /// Generation to sign the served keys with instead of the requested one.
+        forced_generation: Arc<Mutex<Option<u64>>>,
+        /// Generations requested so far.
+        requests: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl MockTransport {
+        fn serve_public_key(&self, req: LongTermKeyRequest) -> Result<SignedPublicKey, String> {
+            self.requests.lock().unwrap().push(req.generation);
+
+            let generation = self
+                .forced_generation
+                .lock()
+                .unwrap()
+                .unwrap_or(req.generation);
+            let checksum = self
+                .checksums
+                .get(generation as usize)
+                .cloned()
+                .ok_or_else(|| "generation not found".to_string())?;
+            let key = x25519::PublicKey::from([generation as u8 + 1; 32]);
+
+            SignedPublicKey::new(
+                key,
+                checksum,
+                req.runtime_id,
+                req.key_pair_id,
+                None,
+                generation,
+                &self.signer,
+            )
+            .map_err(|err| err.to_string())
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn write_message_impl(
+            &self,
+            _ctx: Context,
+            request: Vec<u8>,
+            kind: types::Kind,
+            _nodes: Vec<signature::PublicKey>,
+            _timeout: Option<Duration>,
+        ) -> BoxFuture<Result<(Vec<u8>, signature::PublicKey), anyhow::Error>> {
+            if kind != types::Kind::InsecureQuery {
+                return Box::pin(future::err(anyhow!("unsupported RPC kind")));
+            }
+
+            let rq: types::Request = cbor::from_slice(&request).unwrap();
+            assert_eq!(rq.method, METHOD_GET_PUBLIC_KEY);
+            let body = match self.serve_public_key(cbor::from_value(rq.args).unwrap()) {
+                Ok(key) => types::Body::Success(cbor::to_value(key)),
+                Err(msg) => types::Body::Error(types::Error::new("test", 1, &msg)),
+            };
+            let response = types::Response { id: rq.id, body };
+
+            Box::pin(future::ok((cbor::to_vec(response), Default::default())))
+        }
+    }
+
+    #[test]
+    fn test_public_key_generations() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let signer = PrivateKey::from_bytes(vec![1u8; 32]);
+        let rsk = signer.public_key();
+        let transport = MockTransport {
+            signer: Arc::new(signer),
+            checksums: vec![vec![1u8; 32], vec![2u8; 32]],
+            forced_generation: Arc::new(Mutex::new(None)),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let rpc_client = RpcClient::new(
+            Box::new(transport.clone()),
+            session::Builder::default(),
+            vec![],
+        );
+        let client =
+            RemoteClient::new(Namespace::default(), rpc_client, Arc::new(MockVerifier), 10);
+        client
+            .set_status(KeyManagerStatus {
+                is_initialized: true,
+                checksum: vec![2u8; 32],
+                generation: 1,
+                rsk: Some(rsk),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let key_pair_id = KeyPairId::from(vec![1u8; 32]);
+
+        // Keys should be derived from the requested generation.
+        for generation in [0, 1] {
+            let key = rt
+                .block_on(client.get_public_key_for_generation(
+                    Context::background(),
+                    key_pair_id,
+                    generation,
+                ))
+                .expect("public key should be fetched");
+            assert_eq!(key.generation, generation);
+            assert_eq!(key.checksum, transport.checksums[generation as usize]);
+        }
+        assert_eq!(*transport.requests.lock().unwrap(), vec![0, 1]);
+
+        // Keys from the first generation should be served from cache.
+        let key = rt
+            .block_on(client.get_public_key(Context::background(), key_pair_id))
+            .expect("public key should be fetched");
+        assert_eq!(key.generation, 0);
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+
+        // Keys derived from a different generation should be rejected.
+        client.clear_cache();
+        *transport.forced_generation.lock().unwrap() = Some(0);
+        let result = rt.block_on(client.get_public_key_for_generation(
+            Context::background(),
+            key_pair_id,
+            1,
+        ));
+        assert!(matches!(result, Err(KeyManagerError::InvalidSignature(_))));
+
+        // Keys from the latest generation with a checksum not accepted by the consensus layer
+        // should be rejected.
+        *transport.forced_generation.lock().unwrap() = None;
+        client
+            .set_status(KeyManagerStatus {
+                is_initialized: true,
+                checksum: vec![3u8; 32],
+                generation: 1,
+                rsk: Some(rsk),
+                ..Default::default()
+            })
+            .unwrap();
+        let result = rt.block_on(client.get_public_key_for_generation(
+            Context::background(),
+            key_pair_id,
+            1,
+        ));
+        assert!(matches!(result, Err(KeyManagerError::InvalidSignature(_))));
+    }
 }
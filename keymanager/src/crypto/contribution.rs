@@ -0,0 +1,683 @@
+//! Distributed master secret generation.
+//!
+//! Instead of generating the master secret on its own, the initiating key manager enclave can
+//! run a commit-reveal protocol with other key manager enclaves:
+//!
+//! 1. Every participant generates a random contribution and commits to it.
+//! 2. Once all commitments are fixed, participants reveal their contributions.
+//! 3. The contributions are combined into the master secret and every participant signs
+//!    a transcript of the commitments and the checksum of the master secret.
+use std::{
+    collections::HashSet,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use io_context::Context as IoContext;
+use lazy_static::lazy_static;
+use lru::LruCache;
+
+use oasis_core_runtime::{
+    common::{
+        crypto::{
+            hash::Hash,
+            signature::{self, SignatureBundle, Signer},
+        },
+        namespace::Namespace,
+    },
+    consensus::state::registry::ImmutableState as RegistryState,
+    enclave_rpc::Context as RpcContext,
+    runtime_context,
+};
+
+use crate::{
+    api::KeyManagerError,
+    client::{KeyManagerClient, RemoteClient},
+    crypto::{kdf::Kdf, Secret},
+    policy::Policy,
+    runtime::context::Context as KmContext,
+};
+
+/// Context used for the master secret transcript signatures.
+const TRANSCRIPT_SIGNATURE_CONTEXT: &[u8] = b"oasis-core/keymanager: master secret transcript";
+
+/// Context used for the master secret contribution commitments.
+const COMMITMENT_CONTEXT: &[u8] = b"oasis-core/keymanager: master secret contribution";
+
+/// Maximum number of generation rounds an enclave can concurrently contribute to.
+const MAX_PENDING_ROUNDS: usize = 16;
+
+lazy_static! {
+    // Global contributions object.
+    static ref CONTRIBUTIONS: Contributions = Contributions::new();
+}
+
+/// Commitment of a key manager enclave to its master secret contribution.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct ContributionCommitment {
+    /// Runtime attestation key of the contributing enclave.
+    pub rak: signature::PublicKey,
+    /// Hash of the generation round and the contribution.
+    pub commitment: Hash,
+}
+
+/// Transcript of a distributed master secret generation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct MasterSecretTranscript {
+    /// Key manager runtime ID.
+    pub runtime_id: Namespace,
+    /// Generation round.
+    pub round: Hash,
+    /// Commitments of the contributing enclaves, in the order in which their contributions
+    /// were combined.
+    pub commitments: Vec<ContributionCommitment>,
+    /// Checksum of the master secret.
+    pub checksum: Vec<u8>,
+}
+
+impl MasterSecretTranscript {
+    /// Sign the transcript with the RAK of the given enclave.
+    pub fn sign(&self, ctx: &RpcContext) -> Result<SignatureBundle> {
+        self.sign_with(ctx.identity.as_ref(), ctx.identity.public_rak())
+    }
+
+    /// Sign the transcript with the given signer.
+    pub fn sign_with(
+        &self,
+        signer: &dyn Signer,
+        public_key: signature::PublicKey,
+    ) -> Result<SignatureBundle> {
+        let body = cbor::to_vec(self.clone());
+        let signature = signer.sign(TRANSCRIPT_SIGNATURE_CONTEXT, &body)?;
+
+        Ok(SignatureBundle {
+            public_key,
+            signature,
+        })
+    }
+}
+
+/// Master secret transcript signed by all contributing enclaves.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct SignedMasterSecretTranscript {
+    /// Transcript.
+    pub transcript: MasterSecretTranscript,
+    /// Signatures of the contributing enclaves.
+    pub signatures: Vec<SignatureBundle>,
+}
+
+impl SignedMasterSecretTranscript {
+    /// Verify that every contributing enclave signed the transcript.
+    pub fn verify(&self) -> Result<()> {
+        let body = cbor::to_vec(self.transcript.clone());
+        let signers: HashSet<_> = self
+            .signatures
+            .iter()
+            .filter(|sig| sig.verify(TRANSCRIPT_SIGNATURE_CONTEXT, &body))
+            .map(|sig| sig.public_key)
+            .collect();
+
+        for commitment in self.transcript.commitments.iter() {
+            if !signers.contains(&commitment.rak) {
+                return Err(KeyManagerError::InvalidTranscript.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Contribution of this enclave to a generation round run by another enclave.
+struct PendingContribution {
+    /// Contribution.
+    contribution: Secret,
+    /// Commitments of all contributing enclaves, fixed once the contribution is revealed.
+    commitments: Option<Vec<ContributionCommitment>>,
+}
+
+/// Contributions of this enclave to generation rounds run by other enclaves.
+pub struct Contributions {
+    rounds: Mutex<LruCache<Hash, PendingContribution>>,
+}
+
+impl Contributions {
+    /// Create a new set of contributions, enclaves should use the global instance.
+    pub(crate) fn new() -> Self {
+        Self {
+            rounds: Mutex::new(LruCache::new(
+                NonZeroUsize::new(MAX_PENDING_ROUNDS).unwrap(),
+            )),
+        }
+    }
+
+    /// Global contributions instance.
+    pub fn global<'a>() -> &'a Contributions {
+        &CONTRIBUTIONS
+    }
+
+    /// Generate a contribution to the given round and commit to it.
+    ///
+    /// Repeated calls for the same round return the same commitment.
+    pub fn commit(&self, round: Hash) -> Hash {
+        let mut rounds = self.rounds.lock().unwrap();
+        if let Some(pending) = rounds.get(&round) {
+            return commit(&round, &pending.contribution);
+        }
+
+        let contribution = Secret::generate();
+        let commitment = commit(&round, &contribution);
+        rounds.put(
+            round,
+            PendingContribution {
+                contribution,
+                commitments: None,
+            },
+        );
+
+        commitment
+    }
+
+    /// Reveal the contribution to the given round.
+    ///
+    /// The commitments of all contributing enclaves must include ours and can no longer change
+    /// once the contribution has been revealed.
+    pub fn reveal(
+        &self,
+        round: Hash,
+        rak: signature::PublicKey,
+        commitments: Vec<ContributionCommitment>,
+    ) -> Result<Secret> {
+        let mut rounds = self.rounds.lock().unwrap();
+        let pending = rounds
+            .get_mut(&round)
+            .ok_or(KeyManagerError::MasterSecretRoundNotFound)?;
+
+        let ours = ContributionCommitment {
+            rak,
+            commitment: commit(&round, &pending.contribution),
+        };
+        if commitments.iter().filter(|c| c.rak == rak).count() != 1 || !commitments.contains(&ours)
+        {
+            return Err(KeyManagerError::InvalidContribution.into());
+        }
+
+        match pending.commitments {
+            Some(ref fixed) if *fixed != commitments => {
+                return Err(KeyManagerError::InvalidContribution.into())
+            }
+            Some(_) => (),
+            None => pending.commitments = Some(commitments),
+        }
+
+        Ok(pending.contribution.clone())
+    }
+
+    /// Verify the revealed contributions to the given round and return the transcript
+    /// of the combined master secret.
+    ///
+    /// The round is over once the transcript has been returned.
+    pub fn confirm(
+        &self,
+        round: Hash,
+        runtime_id: Namespace,
+        contributions: Vec<Secret>,
+    ) -> Result<MasterSecretTranscript> {
+        let mut rounds = self.rounds.lock().unwrap();
+        let pending = rounds
+            .peek(&round)
+            .ok_or(KeyManagerError::MasterSecretRoundNotFound)?;
+        let commitments = pending
+            .commitments
+            .clone()
+            .ok_or(KeyManagerError::InvalidContribution)?;
+
+        verify_contributions(&round, &commitments, &contributions)?;
+        if !contributions
+            .iter()
+            .any(|c| c.as_ref() == pending.contribution.as_ref())
+        {
+            return Err(KeyManagerError::InvalidContribution.into());
+        }
+        rounds.pop(&round);
+
+        let master_secret =
+            Kdf::combine_master_secret_contributions(&contributions, &runtime_id, &round);
+        let checksum = master_secret_checksum(&master_secret, &runtime_id);
+
+        Ok(MasterSecretTranscript {
+            runtime_id,
+            round,
+            commitments,
+            checksum,
+        })
+    }
+}
+
+/// Generate the first master secret generation together with other key manager enclaves.
+///
+/// At least `min_contributors` enclaves, including this one, must contribute randomness to
+/// the master secret and sign the transcript of its generation. Enclaves which fail to reveal
+/// their contribution or to confirm the master secret are left out of a new round.
+pub fn generate_master_secret(
+    ctx: &mut RpcContext,
+    runtime_id: Namespace,
+    min_contributors: usize,
+) -> Result<(Secret, SignedMasterSecretTranscript)> {
+    let rctx = runtime_context!(ctx, KmContext);
+
+    let km_client = Arc::new(RemoteClient::new_runtime_with_enclaves_and_policy(
+        rctx.runtime_id,
+        Some(rctx.runtime_id),
+        Policy::global().may_replicate_from(),
+        ctx.identity.quote_policy(),
+        rctx.protocol.clone(),
+        ctx.consensus_verifier.clone(),
+        ctx.identity.clone(),
+        1, // Not used, doesn't matter.
+        vec![],
+    ));
+
+    let nodes = key_manager_nodes(ctx, runtime_id)?;
+    let initiator = Initiator {
+        io_ctx: ctx.io_ctx.clone(),
+        runtime_id,
+        rak: ctx.identity.public_rak(),
+        signer: ctx.identity.clone(),
+        min_contributors,
+    };
+
+    initiator.generate(nodes, |node| -> Arc<dyn KeyManagerClient> {
+        km_client.set_nodes(vec![node]);
+        km_client.clone()
+    })
+}
+
+/// Initiator of distributed master secret generation rounds.
+struct Initiator {
+    io_ctx: Arc<IoContext>,
+    runtime_id: Namespace,
+    rak: signature::PublicKey,
+    signer: Arc<dyn Signer>,
+    min_contributors: usize,
+}
+
+impl Initiator {
+    /// Run generation rounds with the given key manager nodes until one succeeds, leaving
+    /// the nodes which failed a round out of the next one.
+    fn generate<F>(
+        &self,
+        mut nodes: Vec<(signature::PublicKey, Option<signature::PublicKey>)>,
+        client: F,
+    ) -> Result<(Secret, SignedMasterSecretTranscript)>
+    where
+        F: Fn(signature::PublicKey) -> Arc<dyn KeyManagerClient>,
+    {
+        loop {
+            match self.round(&nodes, &client)? {
+                Ok(result) => return Ok(result),
+                Err(failed) => nodes.retain(|(node, _)| *node != failed),
+            }
+        }
+    }
+
+    /// Run a single generation round, returning the node which failed it, if any.
+    fn round<F>(
+        &self,
+        nodes: &[(signature::PublicKey, Option<signature::PublicKey>)],
+        client: &F,
+    ) -> Result<std::result::Result<(Secret, SignedMasterSecretTranscript), signature::PublicKey>>
+    where
+        F: Fn(signature::PublicKey) -> Arc<dyn KeyManagerClient>,
+    {
+        let round = Hash::digest_bytes(Secret::generate().as_ref());
+
+        // Commit to our own contribution.
+        let contribution = Secret::generate();
+        let mut commitments = vec![ContributionCommitment {
+            rak: self.rak,
+            commitment: commit(&round, &contribution),
+        }];
+
+        // Collect commitments from other key manager enclaves. Enclaves which fail to commit
+        // are left out of the round.
+        let mut participants = Vec::new();
+        for (node, node_rak) in nodes.iter() {
+            if *node_rak == Some(self.rak) {
+                continue;
+            }
+
+            let result = client(*node)
+                .commit_master_secret_contribution(IoContext::create_child(&self.io_ctx), round);
+            let commitment = match tokio::runtime::Handle::current().block_on(result) {
+                Ok(commitment) => commitment,
+                Err(_) => continue,
+            };
+
+            if node_rak.map_or(false, |node_rak| node_rak != commitment.rak)
+                || commitments.iter().any(|c| c.rak == commitment.rak)
+            {
+                continue;
+            }
+
+            commitments.push(commitment);
+            participants.push(*node);
+        }
+
+        if commitments.len() < self.min_contributors {
+            return Err(KeyManagerError::InsufficientContributions(
+                self.min_contributors,
+                commitments.len(),
+            )
+            .into());
+        }
+
+        // Collect the contributions, which can no longer change.
+        let mut contributions = vec![contribution];
+        for (node, commitment) in participants.iter().zip(commitments.iter().skip(1)) {
+            let result = client(*node).reveal_master_secret_contribution(
+                IoContext::create_child(&self.io_ctx),
+                round,
+                commitments.clone(),
+            );
+            match tokio::runtime::Handle::current().block_on(result) {
+                Ok(contribution) if commitment.commitment == commit(&round, &contribution) => {
+                    contributions.push(contribution)
+                }
+                _ => return Ok(Err(*node)),
+            }
+        }
+        verify_contributions(&round, &commitments, &contributions)?;
+
+        // Combine the contributions and have all participants sign the transcript.
+        let master_secret =
+            Kdf::combine_master_secret_contributions(&contributions, &self.runtime_id, &round);
+        let transcript = MasterSecretTranscript {
+            runtime_id: self.runtime_id,
+            round,
+            commitments,
+            checksum: master_secret_checksum(&master_secret, &self.runtime_id),
+        };
+        let body = cbor::to_vec(transcript.clone());
+
+        let mut signatures = vec![transcript.sign_with(self.signer.as_ref(), self.rak)?];
+        for (node, commitment) in participants
+            .iter()
+            .zip(transcript.commitments.iter().skip(1))
+        {
+            let result = client(*node).confirm_master_secret(
+                IoContext::create_child(&self.io_ctx),
+                round,
+                contributions.clone(),
+            );
+            match tokio::runtime::Handle::current().block_on(result) {
+                Ok(signature)
+                    if signature.public_key == commitment.rak
+                        && signature.verify(TRANSCRIPT_SIGNATURE_CONTEXT, &body) =>
+                {
+                    signatures.push(signature)
+                }
+                _ => return Ok(Err(*node)),
+            }
+        }
+
+        let transcript = SignedMasterSecretTranscript {
+            transcript,
+            signatures,
+        };
+        transcript.verify()?;
+
+        Ok(Ok((master_secret, transcript)))
+    }
+}
+
+/// Commit to the contribution to the given round.
+fn commit(round: &Hash, contribution: &Secret) -> Hash {
+    Hash::digest_bytes_list(&[COMMITMENT_CONTEXT, round.as_ref(), contribution.as_ref()])
+}
+
+/// Verify that the contributions match the commitments.
+fn verify_contributions(
+    round: &Hash,
+    commitments: &[ContributionCommitment],
+    contributions: &[Secret],
+) -> Result<()> {
+    if commitments.len() != contributions.len() {
+        return Err(KeyManagerError::InvalidContribution.into());
+    }
+
+    for (commitment, contribution) in commitments.iter().zip(contributions) {
+        if commitment.commitment != commit(round, contribution) {
+            return Err(KeyManagerError::InvalidContribution.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Checksum of the first master secret generation.
+fn master_secret_checksum(master_secret: &Secret, runtime_id: &Namespace) -> Vec<u8> {
+    Kdf::checksum_master_secrets(&[master_secret.clone()], runtime_id)
+        .pop()
+        .expect("there should be one checksum")
+}
+
+/// Fetch the nodes registered for the key manager runtime from the consensus layer, together
+/// with the RAKs of their enclaves.
+///
+/// RAKs are only known in an SGX environment.
+fn key_manager_nodes(
+    ctx: &RpcContext,
+    id: Namespace,
+) -> Result<Vec<(signature::PublicKey, Option<signature::PublicKey>)>> {
+    let consensus_state = ctx.consensus_verifier.latest_state()?;
+    let registry_state = RegistryState::new(&consensus_state);
+    let nodes = registry_state.nodes(IoContext::create_child(&ctx.io_ctx))?;
+
+    let mut km_nodes = Vec::new();
+    for node in nodes {
+        let runtimes = node.runtimes.unwrap_or_default();
+        // Skipping version check as key managers are running exactly one version of the runtime.
+        let runtime = match runtimes.iter().find(|nr| nr.id == id) {
+            Some(runtime) => runtime,
+            None => continue,
+        };
+
+        // In an SGX environment we use RAK from the consensus layer.
+        #[cfg(target_env = "sgx")]
+        let rak = match runtime.capabilities.tee.as_ref() {
+            Some(tee) => Some(tee.rak),
+            None => continue,
+        };
+
+        // Otherwise the RAK is not known.
+        #[cfg(not(target_env = "sgx"))]
+        let rak = {
+            let _ = runtime;
+            None
+        };
+
+        km_nodes.push((node.id, rak));
+    }
+
+    Ok(km_nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use io_context::Context as IoContext;
+
+    use oasis_core_runtime::common::{
+        crypto::{
+            hash::Hash,
+            signature::{PrivateKey, PublicKey},
+        },
+        namespace::Namespace,
+    };
+
+    use crate::{
+        api::KeyManagerError,
+        client::{KeyManagerClient, MockClient},
+        crypto::{kdf::Kdf, Secret},
+    };
+
+    use super::{commit, ContributionCommitment, Contributions, Initiator};
+
+    /// Mock key manager enclaves, the last of which restarts after committing to its
+    /// contribution and therefore fails to reveal it.
+    struct MockNodes {
+        nodes: Vec<(PublicKey, Option<PublicKey>)>,
+        clients: Vec<Arc<MockClient>>,
+        restarted: Arc<MockClient>,
+        calls: Mutex<usize>,
+    }
+
+    impl MockNodes {
+        fn new(num_nodes: u8, runtime_id: Namespace) -> Self {
+            Self {
+                nodes: (0..num_nodes)
+                    .map(|i| (PublicKey::from(vec![i; 32]), None))
+                    .collect(),
+                clients: (0..num_nodes)
+                    .map(|_| Arc::new(MockClient::new().with_runtime_id(runtime_id)))
+                    .collect(),
+                restarted: Arc::new(MockClient::new().with_runtime_id(runtime_id)),
+                calls: Mutex::new(0),
+            }
+        }
+
+        fn client(&self, node: PublicKey) -> Arc<dyn KeyManagerClient> {
+            let index = self.nodes.iter().position(|(n, _)| *n == node).unwrap();
+            if index == self.nodes.len() - 1 {
+                let mut calls = self.calls.lock().unwrap();
+                *calls += 1;
+                if *calls > 1 {
+                    return self.restarted.clone();
+                }
+            }
+            self.clients[index].clone()
+        }
+    }
+
+    fn initiator(runtime_id: Namespace, min_contributors: usize) -> Initiator {
+        let rak = PrivateKey::generate();
+        Initiator {
+            io_ctx: Arc::new(IoContext::background()),
+            runtime_id,
+            rak: rak.public_key(),
+            signer: Arc::new(rak),
+            min_contributors,
+        }
+    }
+
+    #[test]
+    fn initiator_restarts_failed_rounds() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let _guard = rt.enter();
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+
+        // The round should be restarted without the node which failed to reveal.
+        let mock = MockNodes::new(3, runtime_id);
+        let initiator = initiator(runtime_id, 3);
+        let (master_secret, transcript) = initiator
+            .generate(mock.nodes.clone(), |node| mock.client(node))
+            .expect("generation should succeed");
+
+        transcript.verify().expect("transcript should be valid");
+        assert_eq!(transcript.transcript.commitments.len(), 3);
+        assert_eq!(transcript.transcript.commitments[0].rak, initiator.rak);
+        assert_eq!(transcript.signatures.len(), 3);
+        assert_eq!(transcript.transcript.runtime_id, runtime_id);
+        assert_eq!(
+            transcript.transcript.checksum,
+            Kdf::checksum_master_secrets(&[master_secret], &runtime_id)[0]
+        );
+
+        // Generation should fail if too few nodes are left.
+        let mock = MockNodes::new(3, runtime_id);
+        let err = initiator(runtime_id, 4)
+            .generate(mock.nodes.clone(), |node| mock.client(node))
+            .map(|_| ())
+            .expect_err("generation with too few contributors should fail");
+        assert!(matches!(
+            err.downcast_ref::<KeyManagerError>(),
+            Some(KeyManagerError::InsufficientContributions(4, 3))
+        ));
+    }
+
+    #[test]
+    fn contributions_commit_reveal_confirm() {
+        let contributions = Contributions::new();
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let round = Hash::digest_bytes(b"round");
+        let rak = PublicKey::from(vec![2u8; 32]);
+        let other_rak = PublicKey::from(vec![3u8; 32]);
+        let other_contribution = Secret([4u8; 32]);
+
+        // Commitments should be stable within a round.
+        let commitment = contributions.commit(round);
+        assert_eq!(commitment, contributions.commit(round));
+
+        let commitments = vec![
+            ContributionCommitment {
+                rak: other_rak,
+                commitment: commit(&round, &other_contribution),
+            },
+            ContributionCommitment { rak, commitment },
+        ];
+
+        // Contributions should only be revealed if our commitment is included.
+        contributions
+            .reveal(round, rak, commitments[..1].to_vec())
+            .map(|_| ())
+            .expect_err("reveal without our commitment should fail");
+        contributions
+            .reveal(Hash::digest_bytes(b"other"), rak, commitments.clone())
+            .map(|_| ())
+            .expect_err("reveal for unknown round should fail");
+
+        let contribution = contributions
+            .reveal(round, rak, commitments.clone())
+            .expect("reveal should succeed");
+        assert_eq!(commit(&round, &contribution), commitment);
+
+        // Commitments can no longer change.
+        contributions
+            .reveal(round, rak, commitments[1..].to_vec())
+            .map(|_| ())
+            .expect_err("reveal with different commitments should fail");
+
+        // Contributions should match the commitments.
+        contributions
+            .confirm(
+                round,
+                runtime_id,
+                vec![Secret([5u8; 32]), contribution.clone()],
+            )
+            .map(|_| ())
+            .expect_err("confirm with invalid contributions should fail");
+
+        let all = vec![other_contribution, contribution];
+        let transcript = contributions
+            .confirm(round, runtime_id, all.clone())
+            .expect("confirm should succeed");
+        assert_eq!(transcript.commitments, commitments);
+        assert_eq!(transcript.round, round);
+
+        let master_secret = Kdf::combine_master_secret_contributions(&all, &runtime_id, &round);
+        assert_eq!(
+            transcript.checksum,
+            Kdf::checksum_master_secrets(&[master_secret], &runtime_id)[0]
+        );
+
+        // The round is over.
+        contributions
+            .confirm(round, runtime_id, all)
+            .map(|_| ())
+            .expect_err("confirm of a finished round should fail");
+    }
+}
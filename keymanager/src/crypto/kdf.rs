@@ -18,6 +18,7 @@ use zeroize::Zeroize;
 use oasis_core_runtime::{
     common::{
         crypto::{
+            hash::Hash,
             mrae::{
                 deoxysii::{DeoxysII, NONCE_SIZE, TAG_SIZE},
                 nonce::Nonce,
@@ -38,7 +39,11 @@ use oasis_core_runtime::{
 use crate::{
     api::{InitRequest, InitResponse, KeyManagerError, SignedInitResponse},
     client::{KeyManagerClient, RemoteClient},
-    crypto::{KeyPair, Secret, SignedPublicKey, StateKey},
+    crypto::{
+        contribution::{self, SignedMasterSecretTranscript},
+        KeyPair, KeyType, Secret, SignedPublicKey, SignedPublicSigningKey, SigningKeyPair,
+        StateKey, SECRET_SIZE,
+    },
     policy::Policy,
     runtime::context::Context as KmContext,
 };
@@ -107,11 +112,43 @@ lazy_static! {
             false => b"ekiden-derive-signing-key-insecure",
         }
     };
+
+    static ref SIGNING_KDF_CUSTOM: &'static [u8] = {
+        match BUILD_INFO.is_secure {
+            true => b"ekiden-derive-runtime-signing-secret",
+            false => b"ekiden-derive-runtime-signing-secret-insecure",
+        }
+    };
+
+    static ref EPHEMERAL_SIGNING_KDF_CUSTOM: &'static [u8] = {
+        match BUILD_INFO.is_secure {
+            true => b"ekiden-derive-ephemeral-signing-secret",
+            false => b"ekiden-derive-ephemeral-signing-secret-insecure",
+        }
+    };
+
+    static ref ED25519_XOF_CUSTOM: &'static [u8] = {
+        match BUILD_INFO.is_secure {
+            true => b"ekiden-derive-ed25519-keys",
+            false => b"ekiden-derive-ed25519-keys-insecure",
+        }
+    };
+
+    static ref COMBINE_MASTER_SECRET_CUSTOM: &'static [u8] = {
+        match BUILD_INFO.is_secure {
+            true => b"ekiden-combine-master-secret",
+            false => b"ekiden-combine-master-secret-insecure",
+        }
+    };
 }
 
+/// Storage key of the first master secret generation. Keys of later generations are suffixed
+/// with the generation.
 const MASTER_SECRET_STORAGE_KEY: &[u8] = b"keymanager_master_secret";
 const MASTER_SECRET_STORAGE_SIZE: usize = 32 + TAG_SIZE + NONCE_SIZE;
 const MASTER_SECRET_SEAL_CONTEXT: &[u8] = b"Ekiden Keymanager Seal master secret v0";
+/// Storage key of the transcript of the distributed master secret generation.
+const MASTER_SECRET_TRANSCRIPT_STORAGE_KEY: &[u8] = b"keymanager_master_secret_transcript";
 
 const EPHEMERAL_SECRET_CACHE_SIZE: usize = 20;
 
@@ -121,37 +158,49 @@ pub struct Kdf {
 }
 
 struct Inner {
-    /// Master secret used to derive long-term runtime keys, RSK key, etc.
-    master_secret: Option<Secret>,
+    /// Master secret generations used to derive long-term runtime keys, RSK key, etc.,
+    /// indexed by generation.
+    master_secrets: Vec<Secret>,
     // Ephemeral secrets used to derive ephemeral runtime keys.
     ephemeral_secrets: HashMap<EpochTime, Secret>,
-    /// Checksum of the master secret and the key manager runtime ID.
-    checksum: Option<Vec<u8>>,
+    /// Checksums of the master secret generations and the key manager runtime ID,
+    /// indexed by generation.
+    checksums: Vec<Vec<u8>>,
     /// Key manager runtime ID.
     runtime_id: Option<Namespace>,
-    /// Key manager committee signer derived from the master secret and
-    /// the key manager runtime ID.
+    /// Key manager committee signer derived from the latest master secret
+    /// generation and the key manager runtime ID.
     ///
     /// Used to sign derived long-term and ephemeral public runtime keys.
     signer: Option<Arc<dyn signature::Signer>>,
-    /// Cache for storing derived key pairs.
-    cache: LruCache<Vec<u8>, KeyPair>,
+    /// Cache for storing derived key pairs, keyed by seed and master secret generation.
+    cache: LruCache<(Vec<u8>, u64), KeyPair>,
+    /// Cache for storing derived signing key pairs, keyed by seed, master secret generation
+    /// and key type.
+    signing_cache: LruCache<(Vec<u8>, u64, KeyType), SigningKeyPair>,
+    /// Transcript of the distributed generation of the first master secret generation, if this
+    /// enclave generated it together with other key manager enclaves.
+    transcript: Option<SignedMasterSecretTranscript>,
+    /// Proposed next master secret generation, kept in memory until the consensus layer
+    /// accepts its checksum. It is neither persisted nor used to derive keys before that.
+    next_master_secret: Option<Secret>,
 }
 
 impl Inner {
     fn reset(&mut self) {
-        self.master_secret = None;
+        self.master_secrets.clear();
         self.ephemeral_secrets.clear();
-        self.checksum = None;
+        self.checksums.clear();
         self.runtime_id = None;
         self.signer = None;
         self.cache.clear();
+        self.signing_cache.clear();
+        self.transcript = None;
+        self.next_master_secret = None;
     }
 
     // Derive ephemeral or long-term keys from the given secret.
-    fn derive_keys(&self, secret: Secret, xof_custom: &[u8]) -> Result<KeyPair> {
-        let checksum = self.get_checksum()?;
-
+    fn derive_keys(&self, secret: Secret, xof_custom: &[u8], checksum: Vec<u8>) -> Result<KeyPair> {
         // Note: The `name` parameter for cSHAKE is reserved for use by NIST.
         let mut xof = CShake::new_cshake256(&[], xof_custom);
         xof.update(secret.as_ref());
@@ -171,6 +220,28 @@ impl Inner {
         Ok(KeyPair::new(pk, sk, state_key, checksum))
     }
 
+    // Derive ephemeral or long-term signing keys of the given type from the given secret.
+    fn derive_signing_keys(
+        &self,
+        secret: Secret,
+        key_type: KeyType,
+        checksum: Vec<u8>,
+    ) -> Result<SigningKeyPair> {
+        let xof_custom: &[u8] = match key_type {
+            KeyType::Ed25519 => &ED25519_XOF_CUSTOM,
+            _ => return Err(KeyManagerError::UnsupportedKeyType.into()),
+        };
+
+        let mut xof = CShake::new_cshake256(&[], xof_custom);
+        xof.update(secret.as_ref());
+        let mut xof = xof.xof();
+
+        let mut sk = Secret::default();
+        xof.squeeze(&mut sk.0);
+
+        Ok(SigningKeyPair::new_ed25519(sk, checksum))
+    }
+
     /// Derive ephemeral secret from the key manager's ephemeral secret.
     fn derive_ephemeral_secret(
         &self,
@@ -186,16 +257,44 @@ impl Inner {
         Self::derive_secret(secret, kdf_custom, seed)
     }
 
-    /// Derive long-term secret from the key manager's master secret.
-    fn derive_static_secret(&self, kdf_custom: &[u8], seed: &[u8]) -> Result<Secret> {
-        let secret = match self.master_secret.as_ref() {
-            Some(secret) => secret,
-            None => return Err(KeyManagerError::NotInitialized.into()),
-        };
+    /// Derive long-term secret from the given generation of the key manager's master secret.
+    fn derive_static_secret(
+        &self,
+        kdf_custom: &[u8],
+        seed: &[u8],
+        generation: u64,
+    ) -> Result<Secret> {
+        let secret = self.master_secret(generation)?;
 
         Self::derive_secret(secret, kdf_custom, seed)
     }
 
+    /// Return the given master secret generation.
+    fn master_secret(&self, generation: u64) -> Result<&Secret> {
+        if self.master_secrets.is_empty() {
+            return Err(KeyManagerError::NotInitialized.into());
+        }
+        self.master_secrets
+            .get(generation as usize)
+            .ok_or_else(|| KeyManagerError::MasterSecretGenerationNotFound(generation).into())
+    }
+
+    /// Latest master secret generation.
+    fn latest_generation(&self) -> Result<u64> {
+        match self.master_secrets.len() {
+            0 => Err(KeyManagerError::NotInitialized.into()),
+            n => Ok(n as u64 - 1),
+        }
+    }
+
+    /// Replace the master secret generations and recompute their checksums.
+    fn set_master_secrets(&mut self, master_secrets: Vec<Secret>, runtime_id: &Namespace) {
+        self.checksums = Kdf::checksum_master_secrets(&master_secrets, runtime_id);
+        self.master_secrets = master_secrets;
+        self.cache.clear();
+        self.signing_cache.clear();
+    }
+
     fn derive_secret(secret: &Secret, kdf_custom: &[u8], seed: &[u8]) -> Result<Secret> {
         let mut k = Secret::default();
 
@@ -207,24 +306,45 @@ impl Inner {
         Ok(k)
     }
 
+    /// Checksum of the latest master secret generation.
     fn get_checksum(&self) -> Result<Vec<u8>> {
-        match self.checksum.as_ref() {
+        match self.checksums.last() {
             Some(checksum) => Ok(checksum.clone()),
             None => Err(KeyManagerError::NotInitialized.into()),
         }
     }
+
+    /// Checksum of the proposed next master secret generation, if any.
+    fn get_next_checksum(&self, runtime_id: &Namespace) -> Option<Vec<u8>> {
+        let next_master_secret = self.next_master_secret.as_ref()?;
+        Some(Kdf::checksum_master_secret(
+            next_master_secret,
+            runtime_id,
+            self.master_secrets.len() as u64,
+            self.checksums.last().map(Vec::as_slice),
+        ))
+    }
+
+    /// Checksum of the given master secret generation.
+    fn get_generation_checksum(&self, generation: u64) -> Result<Vec<u8>> {
+        self.master_secret(generation)?;
+        Ok(self.checksums[generation as usize].clone())
+    }
 }
 
 impl Kdf {
     fn new() -> Self {
         Self {
             inner: RwLock::new(Inner {
-                master_secret: None,
+                master_secrets: Vec::new(),
                 ephemeral_secrets: HashMap::new(),
-                checksum: None,
+                checksums: Vec::new(),
                 runtime_id: None,
                 signer: None,
                 cache: LruCache::new(NonZeroUsize::new(1024).unwrap()),
+                signing_cache: LruCache::new(NonZeroUsize::new(1024).unwrap()),
+                transcript: None,
+                next_master_secret: None,
             }),
         }
     }
@@ -266,118 +386,158 @@ impl Kdf {
 
         // How initialization proceeds depends on the state and the request.
         //
-        // WARNING: Once a master secret has been persisted to disk, it is
-        // intended that manual intervention by the operator is required to
-        // remove/alter it.
-        if inner.master_secret.is_some() {
-            // A master secret is set.  This enclave has initialized successfully
-            // at least once.
-
-            let checksum = inner.get_checksum()?;
-            if !req.checksum.is_empty() && req.checksum != checksum {
-                // The init request provided a checksum and there was a mismatch.
-                // The global key manager state disagrees with the enclave state.
-                inner.reset();
-                return Err(KeyManagerError::StateCorrupted.into());
+        // WARNING: Once a master secret generation has been persisted to disk,
+        // it is intended that manual intervention by the operator is required
+        // to remove/alter it. New generations may only be added.
+        if inner.master_secrets.is_empty() {
+            // Attempt to load the master secret generations, the caller may
+            // just be behind the rest of the world.
+            let master_secrets =
+                Self::load_master_secrets(ctx.untrusted_local_storage, &km_runtime_id);
+            inner.set_master_secrets(master_secrets, &km_runtime_id);
+            inner.transcript = Self::load_master_secret_transcript(
+                ctx.untrusted_local_storage,
+                inner.checksums.first(),
+            );
+        }
+
+        if inner.master_secrets.is_empty() && req.checksum.is_empty() {
+            // A master secret is not set, and there is no checksum in the
+            // request. Either this key manager instance has never been
+            // initialized, or our view of the external state is not current.
+            if !req.may_generate {
+                return Err(KeyManagerError::ReplicationRequired.into());
             }
-        } else if !req.checksum.is_empty() {
-            // A master secret is not set, and there is a checksum in the
-            // request.  An enclave somewhere, has initialized at least
-            // once.
-
-            // Attempt to load the master secret.
-            let (master_secret, did_replicate) =
-                match Self::load_master_secret(ctx.untrusted_local_storage, &km_runtime_id) {
-                    Some(master_secret) => (master_secret, false),
-                    None => {
-                        // Couldn't load, fetch the master secret from another
-                        // enclave instance.
-
-                        let rctx = runtime_context!(ctx, KmContext);
-
-                        let km_client = RemoteClient::new_runtime_with_enclaves_and_policy(
-                            rctx.runtime_id,
-                            Some(rctx.runtime_id),
-                            Policy::global().may_replicate_from(),
-                            ctx.identity.quote_policy(),
-                            rctx.protocol.clone(),
-                            ctx.consensus_verifier.clone(),
-                            ctx.identity.clone(),
-                            1, // Not used, doesn't matter.
-                            vec![],
-                        );
-
-                        let result =
-                            km_client.replicate_master_secret(IoContext::create_child(&ctx.io_ctx));
-                        let master_secret = tokio::runtime::Handle::current().block_on(result)?;
-                        (master_secret, true)
-                    }
-                };
 
-            let checksum = Self::checksum_master_secret(&master_secret, &km_runtime_id);
-            if req.checksum != checksum {
-                // We either loaded or replicated something that does
-                // not match the rest of the world.
-                inner.reset();
-                return Err(KeyManagerError::StateCorrupted.into());
+            // TODO: Support static keying for debugging.
+            let (master_secret, transcript) = match req.min_contributors {
+                0 | 1 => (Secret::generate(), None),
+                n => {
+                    let (master_secret, transcript) =
+                        contribution::generate_master_secret(ctx, km_runtime_id, n.into())?;
+                    (master_secret, Some(transcript))
+                }
+            };
+            Self::save_master_secret(
+                ctx.untrusted_local_storage,
+                &master_secret,
+                &km_runtime_id,
+                0,
+            );
+            if let Some(transcript) = transcript.as_ref() {
+                Self::save_master_secret_transcript(ctx.untrusted_local_storage, transcript);
             }
+            inner.transcript = transcript;
 
-            // The loaded/replicated master secret is consistent with the rest
-            // of the world.   Ok to proceed.
-            if did_replicate {
-                Self::save_master_secret(
-                    ctx.untrusted_local_storage,
-                    &master_secret,
-                    &km_runtime_id,
-                );
+            // There is no checksum to compare against, but that is expected
+            // when bootstrapping.
+            inner.set_master_secrets(vec![master_secret], &km_runtime_id);
+        }
+
+        if !req.checksum.is_empty() {
+            // There is a checksum in the request. An enclave somewhere has
+            // initialized at least once, possibly with generations we are
+            // missing.
+            let mut master_secrets = inner.master_secrets.clone();
+            let num_persisted = master_secrets.len() as u64;
+
+            // Commit our proposal of the next generation if the rest of the world accepted
+            // it. A proposal which lost to another enclave's proposal was never persisted
+            // nor used, so it is simply dropped and the accepted one is replicated below.
+            if req.generation >= num_persisted {
+                let next_checksum = inner.get_next_checksum(&km_runtime_id);
+                if let Some(next_master_secret) = inner.next_master_secret.take() {
+                    if req.generation == num_persisted
+                        && next_checksum.as_ref() == Some(&req.checksum)
+                    {
+                        master_secrets.push(next_master_secret);
+                    }
+                }
             }
-            inner.master_secret = Some(master_secret);
-            inner.checksum = Some(checksum);
-        } else {
-            // A master secret is not set, and there is no checksum in the
-            // request. Either this key manager instance has never been
-            // initialized, or our view of the external state is not current.
+            let num_loaded = master_secrets.len() as u64;
+            if num_loaded <= req.generation {
+                // Fetch the missing generations from another enclave instance.
+                let rctx = runtime_context!(ctx, KmContext);
+
+                let km_client = RemoteClient::new_runtime_with_enclaves_and_policy(
+                    rctx.runtime_id,
+                    Some(rctx.runtime_id),
+                    Policy::global().may_replicate_from(),
+                    ctx.identity.quote_policy(),
+                    rctx.protocol.clone(),
+                    ctx.consensus_verifier.clone(),
+                    ctx.identity.clone(),
+                    1, // Not used, doesn't matter.
+                    vec![],
+                );
 
-            // Attempt to load the master secret, the caller may just be
-            // behind the rest of the world.
-            let master_secret =
-                match Self::load_master_secret(ctx.untrusted_local_storage, &km_runtime_id) {
-                    Some(master_secret) => master_secret,
-                    None => {
-                        // Unable to load, perhaps we can generate?
-                        if !req.may_generate {
-                            return Err(KeyManagerError::ReplicationRequired.into());
+                // Fetch all missing generations in one streamed call, falling back to
+                // one call per generation for key managers which don't support it.
+                let result = km_client
+                    .replicate_master_secrets(IoContext::create_child(&ctx.io_ctx), num_loaded);
+                match tokio::runtime::Handle::current().block_on(result) {
+                    Ok(replicated) => {
+                        let missing = (req.generation + 1 - num_loaded) as usize;
+                        master_secrets.extend(replicated.into_iter().take(missing));
+                    }
+                    Err(_) => {
+                        for generation in num_loaded..=req.generation {
+                            let result = km_client.replicate_master_secret(
+                                IoContext::create_child(&ctx.io_ctx),
+                                generation,
+                            );
+                            let master_secret =
+                                tokio::runtime::Handle::current().block_on(result)?;
+                            master_secrets.push(master_secret);
                         }
+                    }
+                }
+            }
 
-                        // TODO: Support static keying for debugging.
-                        let master_secret = Secret::generate();
+            let checksums = Self::checksum_master_secrets(&master_secrets, &km_runtime_id);
+            if checksums.get(req.generation as usize) != Some(&req.checksum) {
+                // We either have, loaded or replicated something that does
+                // not match the rest of the world.
+                inner.reset();
+                return Err(KeyManagerError::StateCorrupted.into());
+            }
 
-                        Self::save_master_secret(
-                            ctx.untrusted_local_storage,
-                            &master_secret,
-                            &km_runtime_id,
-                        );
+            // The replicated generations are consistent with the rest of the
+            // world. Ok to proceed.
+            for (generation, master_secret) in master_secrets.iter().enumerate() {
+                if generation as u64 >= num_persisted {
+                    Self::save_master_secret(
+                        ctx.untrusted_local_storage,
+                        master_secret,
+                        &km_runtime_id,
+                        generation as u64,
+                    );
+                }
+            }
 
-                        master_secret
-                    }
-                };
+            // Propose the next master secret generation if the rest of the world agrees
+            // on our latest generation. The proposal is kept until the consensus layer
+            // accepts or rejects it, so repeated requests don't generate new secrets.
+            if !req.may_rotate || master_secrets.len() as u64 != req.generation + 1 {
+                inner.next_master_secret = None;
+            } else if inner.next_master_secret.is_none() {
+                inner.next_master_secret = Some(Secret::generate());
+            }
 
-            // Loaded or generated a master secret.  There is no checksum to
-            // compare against, but that is expected when bootstrapping or
-            // lagging.
-            inner.checksum = Some(Self::checksum_master_secret(&master_secret, &km_runtime_id));
-            inner.master_secret = Some(master_secret);
+            inner.set_master_secrets(master_secrets, &km_runtime_id);
         }
 
-        // If we make it this far, we have a master secret and checksum
-        // that either matches the global state, will become the global
-        // state, or should become the global state (rare).
+        // If we make it this far, we have master secret generations and
+        // checksums that either match the global state, will become the
+        // global state, or should become the global state (rare).
         //
         // It is ok to derive the signing key and generate a response.
 
-        // Derive signing key from the master secret.
+        // Derive signing key from the first master secret generation, so that it stays
+        // the same when the master secret is rotated.
+        let generation = inner.latest_generation()?;
         let secret =
-            inner.derive_static_secret(&RUNTIME_SIGNING_KEY_CUSTOM, km_runtime_id.as_ref())?;
+            inner.derive_static_secret(&RUNTIME_SIGNING_KEY_CUSTOM, km_runtime_id.as_ref(), 0)?;
         let sk = signature::PrivateKey::from_bytes(secret.0.to_vec());
         let pk = sk.public_key();
         inner.signer = Some(Arc::new(sk));
@@ -385,9 +545,12 @@ impl Kdf {
         // Build the response and sign it with the RAK.
         let init_response = InitResponse {
             is_secure: BUILD_INFO.is_secure && !Policy::unsafe_skip(),
-            checksum: inner.checksum.as_ref().unwrap().clone(),
+            checksum: inner.get_checksum()?,
             policy_checksum,
             rsk: pk,
+            generation,
+            next_checksum: inner.get_next_checksum(&km_runtime_id).unwrap_or_default(),
+            transcript: inner.transcript.clone(),
         };
 
         let body = cbor::to_vec(init_response.clone());
@@ -400,42 +563,43 @@ impl Kdf {
     }
 
     /// Get or create long-term or ephemeral keys.
+    ///
+    /// Long-term keys are derived from the given master secret generation, while ephemeral
+    /// keys do not depend on it.
     pub fn get_or_create_keys(
         &self,
         runtime_id: Namespace,
         key_pair_id: KeyPairId,
+        generation: u64,
         epoch: Option<EpochTime>,
     ) -> Result<KeyPair> {
-        // Construct a seed that must be unique for every key request.
-        // Long-term keys: seed = runtime_id || key_pair_id
-        // Ephemeral keys: seed = runtime_id || key_pair_id || epoch
-        let mut seed = runtime_id.as_ref().to_vec();
-        seed.extend_from_slice(key_pair_id.as_ref());
-        if let Some(epoch) = epoch {
-            seed.extend_from_slice(epoch.to_be_bytes().as_ref());
-        }
-
         // Check to see if the cached value exists.
+        let seed = Self::seed(runtime_id, key_pair_id, epoch);
+        let generation = if epoch.is_some() { 0 } else { generation };
+        let cache_key = (seed, generation);
         let mut inner = self.inner.write().unwrap();
-        if let Some(keys) = inner.cache.get(&seed) {
+        if let Some(keys) = inner.cache.get(&cache_key) {
             return Ok(keys.clone());
         };
 
         // Generate keys.
+        let seed = &cache_key.0;
         let keys = match epoch {
             Some(epoch) => {
-                let secret = inner.derive_ephemeral_secret(&EPHEMERAL_KDF_CUSTOM, &seed, epoch)?;
-                inner.derive_keys(secret, &EPHEMERAL_XOF_CUSTOM)?
+                let secret = inner.derive_ephemeral_secret(&EPHEMERAL_KDF_CUSTOM, seed, epoch)?;
+                let checksum = inner.get_generation_checksum(generation)?;
+                inner.derive_keys(secret, &EPHEMERAL_XOF_CUSTOM, checksum)?
             }
             None => {
-                let secret = inner.derive_static_secret(&RUNTIME_KDF_CUSTOM, &seed)?;
+                let secret = inner.derive_static_secret(&RUNTIME_KDF_CUSTOM, seed, generation)?;
+                let checksum = inner.get_generation_checksum(generation)?;
                 // FIXME: Replace KDF custom with XOF custom when possible.
-                inner.derive_keys(secret, &RUNTIME_KDF_CUSTOM)?
+                inner.derive_keys(secret, &RUNTIME_KDF_CUSTOM, checksum)?
             }
         };
 
         // Insert into the cache.
-        inner.cache.put(seed, keys.clone());
+        inner.cache.put(cache_key, keys.clone());
 
         Ok(keys)
     }
@@ -445,42 +609,157 @@ impl Kdf {
         &self,
         runtime_id: Namespace,
         key_pair_id: KeyPairId,
+        generation: u64,
         epoch: Option<EpochTime>,
     ) -> Result<x25519::PublicKey> {
-        let keys = self.get_or_create_keys(runtime_id, key_pair_id, epoch)?;
+        let keys = self.get_or_create_keys(runtime_id, key_pair_id, generation, epoch)?;
         Ok(keys.input_keypair.pk)
     }
 
+    /// Get or create long-term or ephemeral signing keys of the given type.
+    ///
+    /// Signing keys are derived using customization strings different from the ones used
+    /// for encryption keys, so the two never share secrets.
+    pub fn get_or_create_signing_keys(
+        &self,
+        runtime_id: Namespace,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+        epoch: Option<EpochTime>,
+    ) -> Result<SigningKeyPair> {
+        // Check to see if the cached value exists.
+        let seed = Self::seed(runtime_id, key_pair_id, epoch);
+        let generation = if epoch.is_some() { 0 } else { generation };
+        let cache_key = (seed, generation, key_type);
+        let mut inner = self.inner.write().unwrap();
+        if let Some(keys) = inner.signing_cache.get(&cache_key) {
+            return Ok(keys.clone());
+        };
+
+        // Generate keys.
+        let seed = &cache_key.0;
+        let (secret, checksum) = match epoch {
+            Some(epoch) => (
+                inner.derive_ephemeral_secret(&EPHEMERAL_SIGNING_KDF_CUSTOM, seed, epoch)?,
+                inner.get_generation_checksum(generation)?,
+            ),
+            None => (
+                inner.derive_static_secret(&SIGNING_KDF_CUSTOM, seed, generation)?,
+                inner.get_generation_checksum(generation)?,
+            ),
+        };
+        let keys = inner.derive_signing_keys(secret, key_type, checksum)?;
+
+        // Insert into the cache.
+        inner.signing_cache.put(cache_key, keys.clone());
+
+        Ok(keys)
+    }
+
+    /// Get the public part of the signing key.
+    pub fn get_public_signing_key(
+        &self,
+        runtime_id: Namespace,
+        key_pair_id: KeyPairId,
+        key_type: KeyType,
+        generation: u64,
+        epoch: Option<EpochTime>,
+    ) -> Result<signature::PublicKey> {
+        let keys =
+            self.get_or_create_signing_keys(runtime_id, key_pair_id, key_type, generation, epoch)?;
+        Ok(keys.pk)
+    }
+
     /// Signs the public key using the key manager key.
+    ///
+    /// The signature binds the key to the given master secret generation and its checksum.
+    /// Ephemeral keys are always bound to the first generation.
     pub fn sign_public_key(
         &self,
         key: x25519::PublicKey,
         runtime_id: Namespace,
         key_pair_id: KeyPairId,
+        generation: u64,
         epoch: Option<EpochTime>,
     ) -> Result<SignedPublicKey> {
+        let generation = if epoch.is_some() { 0 } else { generation };
         let inner = self.inner.read().unwrap();
-        let checksum = inner.get_checksum()?;
+        let checksum = inner.get_generation_checksum(generation)?;
         let signer = inner
             .signer
             .as_ref()
             .ok_or(KeyManagerError::NotInitialized)?;
 
-        SignedPublicKey::new(key, checksum, runtime_id, key_pair_id, epoch, signer)
+        SignedPublicKey::new(
+            key,
+            checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            generation,
+            signer,
+        )
     }
 
-    /// Replicate master secret.
-    pub fn replicate_master_secret(&self) -> Result<Secret> {
+    /// Signs the public signing key using the key manager key.
+    ///
+    /// The signature binds the key to the given master secret generation and its checksum.
+    /// Ephemeral keys are always bound to the first generation.
+    pub fn sign_public_signing_key(
+        &self,
+        key_type: KeyType,
+        key: signature::PublicKey,
+        runtime_id: Namespace,
+        key_pair_id: KeyPairId,
+        generation: u64,
+        epoch: Option<EpochTime>,
+    ) -> Result<SignedPublicSigningKey> {
+        let generation = if epoch.is_some() { 0 } else { generation };
         let inner = self.inner.read().unwrap();
-
-        let secret = inner
-            .master_secret
+        let checksum = inner.get_generation_checksum(generation)?;
+        let signer = inner
+            .signer
             .as_ref()
-            .cloned()
             .ok_or(KeyManagerError::NotInitialized)?;
+
+        SignedPublicSigningKey::new(
+            key_type,
+            key,
+            checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            generation,
+            signer,
+        )
+    }
+
+    /// Replicate the given master secret generation.
+    pub fn replicate_master_secret(&self, generation: u64) -> Result<Secret> {
+        let inner = self.inner.read().unwrap();
+
+        let secret = inner.master_secret(generation)?.clone();
         Ok(secret)
     }
 
+    /// Replicate all master secret generations starting with the given one.
+    ///
+    /// Generations are only copied as the returned iterator is consumed, so they never need to
+    /// be held in memory at once.
+    pub fn replicate_master_secrets(
+        &'static self,
+        from_generation: u64,
+    ) -> Result<impl Iterator<Item = Result<Secret>> + Send> {
+        let latest = self.inner.read().unwrap().latest_generation()?;
+        if from_generation > latest {
+            return Err(KeyManagerError::MasterSecretGenerationNotFound(from_generation).into());
+        }
+
+        Ok((from_generation..=latest)
+            .map(move |generation| self.replicate_master_secret(generation)))
+    }
+
     /// Replicate ephemeral secret.
     pub fn replicate_ephemeral_secret(&self, epoch: EpochTime) -> Result<Secret> {
         let inner = self.inner.read().unwrap();
@@ -509,12 +788,37 @@ impl Kdf {
         }
     }
 
+    /// Construct a seed that must be unique for every key request.
+    ///
+    /// Long-term keys: seed = runtime_id || key_pair_id
+    /// Ephemeral keys: seed = runtime_id || key_pair_id || epoch
+    fn seed(runtime_id: Namespace, key_pair_id: KeyPairId, epoch: Option<EpochTime>) -> Vec<u8> {
+        let mut seed = runtime_id.as_ref().to_vec();
+        seed.extend_from_slice(key_pair_id.as_ref());
+        if let Some(epoch) = epoch {
+            seed.extend_from_slice(epoch.to_be_bytes().as_ref());
+        }
+        seed
+    }
+
+    /// Load all persisted master secret generations.
+    fn load_master_secrets(untrusted_local: &dyn KeyValue, runtime_id: &Namespace) -> Vec<Secret> {
+        let mut master_secrets = Vec::new();
+        while let Some(master_secret) =
+            Self::load_master_secret(untrusted_local, runtime_id, master_secrets.len() as u64)
+        {
+            master_secrets.push(master_secret);
+        }
+        master_secrets
+    }
+
     fn load_master_secret(
         untrusted_local: &dyn KeyValue,
         runtime_id: &Namespace,
+        generation: u64,
     ) -> Option<Secret> {
         let ciphertext = untrusted_local
-            .get(MASTER_SECRET_STORAGE_KEY.to_vec())
+            .get(Self::master_secret_storage_key(generation))
             .unwrap();
 
         match ciphertext.len() {
@@ -532,8 +836,9 @@ impl Kdf {
 
         // Decrypt the persisted master secret.
         let d2 = Self::new_d2();
+        let additional_data = Self::master_secret_additional_data(runtime_id, generation);
         let plaintext = d2
-            .open(&nonce, ciphertext.to_vec(), runtime_id.as_ref())
+            .open(&nonce, ciphertext.to_vec(), additional_data)
             .expect("persisted state is corrupted");
 
         Some(Secret(plaintext.try_into().unwrap()))
@@ -543,30 +848,142 @@ impl Kdf {
         untrusted_local: &dyn KeyValue,
         master_secret: &Secret,
         runtime_id: &Namespace,
+        generation: u64,
     ) {
         // Encrypt the master secret.
         let nonce = Nonce::generate();
         let d2 = Self::new_d2();
-        let mut ciphertext = d2.seal(&nonce, master_secret.as_ref(), runtime_id.as_ref());
+        let additional_data = Self::master_secret_additional_data(runtime_id, generation);
+        let mut ciphertext = d2.seal(&nonce, master_secret.as_ref(), additional_data);
         ciphertext.extend_from_slice(&nonce.to_vec());
 
         // Persist the encrypted master secret.
         untrusted_local
-            .insert(MASTER_SECRET_STORAGE_KEY.to_vec(), ciphertext)
+            .insert(Self::master_secret_storage_key(generation), ciphertext)
             .expect("failed to persist master secret");
     }
 
-    fn checksum_master_secret(master_secret: &Secret, runtime_id: &Namespace) -> Vec<u8> {
+    /// Load the persisted transcript of the distributed master secret generation.
+    ///
+    /// The transcript is ignored unless it was signed by all contributors and belongs to the
+    /// given checksum of the first master secret generation.
+    fn load_master_secret_transcript(
+        untrusted_local: &dyn KeyValue,
+        checksum: Option<&Vec<u8>>,
+    ) -> Option<SignedMasterSecretTranscript> {
+        let raw = untrusted_local
+            .get(MASTER_SECRET_TRANSCRIPT_STORAGE_KEY.to_vec())
+            .unwrap();
+        if raw.is_empty() {
+            return None;
+        }
+
+        let transcript: SignedMasterSecretTranscript = cbor::from_slice(&raw).ok()?;
+        if Some(&transcript.transcript.checksum) != checksum || transcript.verify().is_err() {
+            return None;
+        }
+
+        Some(transcript)
+    }
+
+    fn save_master_secret_transcript(
+        untrusted_local: &dyn KeyValue,
+        transcript: &SignedMasterSecretTranscript,
+    ) {
+        untrusted_local
+            .insert(
+                MASTER_SECRET_TRANSCRIPT_STORAGE_KEY.to_vec(),
+                cbor::to_vec(transcript.clone()),
+            )
+            .expect("failed to persist master secret transcript");
+    }
+
+    fn master_secret_storage_key(generation: u64) -> Vec<u8> {
+        let mut key = MASTER_SECRET_STORAGE_KEY.to_vec();
+        if generation > 0 {
+            key.extend_from_slice(&generation.to_le_bytes());
+        }
+        key
+    }
+
+    /// Additional data used to seal a master secret generation, which prevents the host from
+    /// swapping generations.
+    fn master_secret_additional_data(runtime_id: &Namespace, generation: u64) -> Vec<u8> {
+        let mut additional_data = runtime_id.as_ref().to_vec();
+        if generation > 0 {
+            additional_data.extend_from_slice(&generation.to_le_bytes());
+        }
+        additional_data
+    }
+
+    /// Compute the checksums of the master secret generations.
+    ///
+    /// The checksum of every generation but the first one also covers the checksum of
+    /// the previous generation, so the checksum of the latest generation commits to all of them.
+    pub fn checksum_master_secrets(
+        master_secrets: &[Secret],
+        runtime_id: &Namespace,
+    ) -> Vec<Vec<u8>> {
+        let mut checksums: Vec<Vec<u8>> = Vec::with_capacity(master_secrets.len());
+        for (generation, master_secret) in master_secrets.iter().enumerate() {
+            let checksum = Self::checksum_master_secret(
+                master_secret,
+                runtime_id,
+                generation as u64,
+                checksums.last().map(Vec::as_slice),
+            );
+            checksums.push(checksum);
+        }
+        checksums
+    }
+
+    fn checksum_master_secret(
+        master_secret: &Secret,
+        runtime_id: &Namespace,
+        generation: u64,
+        prev_checksum: Option<&[u8]>,
+    ) -> Vec<u8> {
         let mut k = [0u8; 32];
 
         // KMAC256(master_secret, kmRuntimeID, 32, "ekiden-checksum-master-secret")
+        // for the first generation, and
+        // KMAC256(master_secret, kmRuntimeID || generation || prev_checksum, 32, "ekiden-checksum-master-secret")
+        // for all later generations.
         let mut f = KMac::new_kmac256(master_secret.as_ref(), &CHECKSUM_MASTER_SECRET_CUSTOM);
         f.update(runtime_id.as_ref());
+        if let Some(prev_checksum) = prev_checksum {
+            f.update(generation.to_le_bytes().as_ref());
+            f.update(prev_checksum);
+        }
         f.finalize(&mut k);
 
         k.to_vec()
     }
 
+    /// Combine the contributions of key manager enclaves to a distributed master secret
+    /// generation round into the master secret.
+    pub fn combine_master_secret_contributions(
+        contributions: &[Secret],
+        runtime_id: &Namespace,
+        round: &Hash,
+    ) -> Secret {
+        let mut k = Secret::default();
+
+        // KMAC256(contribution_0 || ... || contribution_n, kmRuntimeID || round, 32,
+        // "ekiden-combine-master-secret")
+        let mut key = Vec::with_capacity(contributions.len() * SECRET_SIZE);
+        for contribution in contributions {
+            key.extend_from_slice(contribution.as_ref());
+        }
+        let mut f = KMac::new_kmac256(&key, &COMBINE_MASTER_SECRET_CUSTOM);
+        key.zeroize();
+        f.update(runtime_id.as_ref());
+        f.update(round.as_ref());
+        f.finalize(&mut k.0);
+
+        k
+    }
+
     /// Compute the checksum of the ephemeral secret.
     pub fn checksum_ephemeral_secret(
         ephemeral_secret: &Secret,
@@ -619,17 +1036,20 @@ mod tests {
             CHECKSUM_CUSTOM, CHECKSUM_EPHEMERAL_SECRET_CUSTOM, CHECKSUM_MASTER_SECRET_CUSTOM,
             EPHEMERAL_SECRET_CACHE_SIZE, RUNTIME_SIGNING_KEY_CUSTOM,
         },
-        KeyPairId, Secret, SECRET_SIZE,
+        KeyPairId, KeyType, Secret, SECRET_SIZE,
     };
 
     use super::{
-        Inner, Kdf, EPHEMERAL_KDF_CUSTOM, EPHEMERAL_XOF_CUSTOM, RUNTIME_KDF_CUSTOM,
-        RUNTIME_XOF_CUSTOM,
+        Inner, Kdf, COMBINE_MASTER_SECRET_CUSTOM, ED25519_XOF_CUSTOM, EPHEMERAL_KDF_CUSTOM,
+        EPHEMERAL_SIGNING_KDF_CUSTOM, EPHEMERAL_XOF_CUSTOM, RUNTIME_KDF_CUSTOM, RUNTIME_XOF_CUSTOM,
+        SIGNING_KDF_CUSTOM,
     };
 
     impl Kdf {
         fn clear_cache(&self) {
-            self.inner.write().unwrap().cache.clear();
+            let mut inner = self.inner.write().unwrap();
+            inner.cache.clear();
+            inner.signing_cache.clear();
         }
     }
 
@@ -637,11 +1057,14 @@ mod tests {
         fn default() -> Self {
             Self {
                 inner: RwLock::new(Inner {
-                    master_secret: Some(Secret([1u8; 32])),
-                    checksum: Some(vec![2u8; 32]),
+                    master_secrets: vec![Secret([1u8; 32])],
+                    checksums: vec![vec![2u8; 32]],
                     runtime_id: Some(Namespace([3u8; 32])),
                     signer: Some(Arc::new(PrivateKey::from_bytes(vec![4u8; 32]))),
                     cache: LruCache::new(NonZeroUsize::new(1).unwrap()),
+                    signing_cache: LruCache::new(NonZeroUsize::new(1).unwrap()),
+                    transcript: None,
+                    next_master_secret: None,
                     ephemeral_secrets: HashMap::from([
                         (1, Secret([1u8; SECRET_SIZE])),
                         (2, Secret([2u8; SECRET_SIZE])),
@@ -661,12 +1084,12 @@ mod tests {
         // Long-term keys.
         kdf.clear_cache();
         let sk1 = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, None)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, None)
             .expect("private key should be created");
 
         kdf.clear_cache();
         let sk2 = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, None)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, None)
             .expect("private key should be created");
 
         assert_eq!(
@@ -678,12 +1101,12 @@ mod tests {
         // Ephemeral keys.
         kdf.clear_cache();
         let sk1 = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, epoch)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, epoch)
             .expect("private key should be created");
 
         kdf.clear_cache();
         let sk2 = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, epoch)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, epoch)
             .expect("private key should be created");
 
         assert_eq!(
@@ -703,27 +1126,27 @@ mod tests {
 
         // Long-terms keys should depend on runtime_id and key_pair_id.
         let sk1 = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, None)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, None)
             .expect("private key should be created");
         let sk2 = kdf
-            .get_or_create_keys(vec![2u8; 32].into(), key_pair_id, None)
+            .get_or_create_keys(vec![2u8; 32].into(), key_pair_id, 0, None)
             .expect("private key should be created");
         let sk3 = kdf
-            .get_or_create_keys(runtime_id, vec![3u8; 32].into(), None)
+            .get_or_create_keys(runtime_id, vec![3u8; 32].into(), 0, None)
             .expect("private key should be created");
 
         // Ephemeral keys should depend on runtime_id, key_pair_id and epoch.
         let sk4 = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, epoch)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, epoch)
             .expect("private key should be created");
         let sk5 = kdf
-            .get_or_create_keys(vec![2u8; 32].into(), key_pair_id, epoch)
+            .get_or_create_keys(vec![2u8; 32].into(), key_pair_id, 0, epoch)
             .expect("private key should be created");
         let sk6 = kdf
-            .get_or_create_keys(runtime_id, vec![3u8; 32].into(), epoch)
+            .get_or_create_keys(runtime_id, vec![3u8; 32].into(), 0, epoch)
             .expect("private key should be created");
         let sk7 = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, Some(2))
+            .get_or_create_keys(runtime_id, key_pair_id, 0, Some(2))
             .expect("private key should be created");
 
         let keys = HashSet::from(
@@ -741,17 +1164,21 @@ mod tests {
 
         // Long-term keys.
         let sk = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, None)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, None)
             .expect("private key should be created");
-        let pk = kdf.get_public_key(runtime_id, key_pair_id, None).unwrap();
+        let pk = kdf
+            .get_public_key(runtime_id, key_pair_id, 0, None)
+            .unwrap();
 
         assert_eq!(sk.input_keypair.pk, pk);
 
         // Ephemeral keys.
         let sk = kdf
-            .get_or_create_keys(runtime_id, key_pair_id, epoch)
+            .get_or_create_keys(runtime_id, key_pair_id, 0, epoch)
             .expect("private key should be created");
-        let pk = kdf.get_public_key(runtime_id, key_pair_id, epoch).unwrap();
+        let pk = kdf
+            .get_public_key(runtime_id, key_pair_id, 0, epoch)
+            .unwrap();
 
         assert_eq!(sk.input_keypair.pk, pk);
     }
@@ -767,11 +1194,11 @@ mod tests {
         let now = Some(15);
 
         let sig = kdf
-            .sign_public_key(pk, runtime_id, key_pair_id, epoch)
+            .sign_public_key(pk, runtime_id, key_pair_id, 0, epoch)
             .expect("public key should be signed");
 
         let mut body = pk.0.to_bytes().to_vec();
-        let checksum = kdf.inner.into_inner().unwrap().checksum.unwrap();
+        let checksum = kdf.inner.into_inner().unwrap().checksums.pop().unwrap();
         body.extend_from_slice(&checksum);
 
         let pk = PrivateKey::from_bytes(vec![4u8; 32]).public_key();
@@ -779,15 +1206,173 @@ mod tests {
             .expect("signature should be valid");
     }
 
+    #[test]
+    fn public_key_signature_binds_generation() {
+        let kdf = Kdf::default();
+        {
+            let mut inner = kdf.inner.write().unwrap();
+            inner.master_secrets.push(Secret([5u8; 32]));
+            inner.checksums.push(vec![6u8; 32]);
+        }
+
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let key_pair_id = KeyPairId::from(vec![1u8; 32]);
+        let signer_pk = PrivateKey::from_bytes(vec![4u8; 32]).public_key();
+
+        for (generation, checksum) in [(0, vec![2u8; 32]), (1, vec![6u8; 32])] {
+            let pk = kdf
+                .get_public_key(runtime_id, key_pair_id, generation, None)
+                .unwrap();
+            let sig = kdf
+                .sign_public_key(pk, runtime_id, key_pair_id, generation, None)
+                .expect("public key should be signed");
+            assert_eq!(sig.generation, generation);
+            assert_eq!(sig.checksum, checksum);
+            sig.verify(runtime_id, key_pair_id, None, None, &signer_pk)
+                .expect("signature should be valid");
+        }
+
+        // Ephemeral keys are always bound to the first generation.
+        let pk = x25519::PublicKey::from([1u8; 32]);
+        let sig = kdf
+            .sign_public_key(pk, runtime_id, key_pair_id, 1, Some(10))
+            .expect("public key should be signed");
+        assert_eq!(sig.generation, 0);
+        assert_eq!(sig.checksum, vec![2u8; 32]);
+
+        // Unknown generations cannot be signed.
+        let result = kdf.sign_public_key(pk, runtime_id, key_pair_id, 2, None);
+        assert!(result.is_err(), "unknown generation should not be signed");
+    }
+
+    #[test]
+    fn signing_keys_are_deterministic_and_unique() {
+        let kdf = Kdf::default();
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let key_pair_id = KeyPairId::from(vec![2u8; 32]);
+        let epoch = Some(1);
+
+        for epoch in [None, epoch] {
+            kdf.clear_cache();
+            let sk1 = kdf
+                .get_or_create_signing_keys(runtime_id, key_pair_id, KeyType::Ed25519, 0, epoch)
+                .expect("signing key should be created");
+
+            kdf.clear_cache();
+            let sk2 = kdf
+                .get_or_create_signing_keys(runtime_id, key_pair_id, KeyType::Ed25519, 0, epoch)
+                .expect("signing key should be created");
+
+            assert_eq!(sk1.sk.0, sk2.sk.0);
+            assert_eq!(sk1.pk, sk2.pk);
+            assert_eq!(
+                sk1.pk,
+                PrivateKey::from_bytes(sk1.sk.0.to_vec()).public_key()
+            );
+
+            let pk = kdf
+                .get_public_signing_key(runtime_id, key_pair_id, KeyType::Ed25519, 0, epoch)
+                .unwrap();
+            assert_eq!(sk1.pk, pk);
+
+            // Signing keys should be domain-separated from encryption keys.
+            let keys = kdf
+                .get_or_create_keys(runtime_id, key_pair_id, 0, epoch)
+                .expect("private key should be created");
+            assert_ne!(sk1.sk.0, keys.input_keypair.sk.0.to_bytes());
+            assert_ne!(sk1.sk.0, keys.state_key.0);
+        }
+
+        // Long-term and ephemeral signing keys should differ.
+        let lk = kdf
+            .get_or_create_signing_keys(runtime_id, key_pair_id, KeyType::Ed25519, 0, None)
+            .unwrap();
+        let ek = kdf
+            .get_or_create_signing_keys(runtime_id, key_pair_id, KeyType::Ed25519, 0, epoch)
+            .unwrap();
+        assert_ne!(lk.pk, ek.pk);
+
+        // Only signing key types are supported.
+        let error = kdf
+            .get_or_create_signing_keys(runtime_id, key_pair_id, KeyType::X25519, 0, None)
+            .map(|_| ())
+            .expect_err("x25519 signing keys should not be created");
+        assert_eq!(error.to_string(), "unsupported key type");
+    }
+
     #[test]
     fn master_secret_can_be_replicated() {
         let kdf = Kdf::default();
         let secret = kdf
-            .replicate_master_secret()
+            .replicate_master_secret(0)
             .expect("master secret should be replicated");
         let inner = kdf.inner.into_inner().unwrap();
 
-        assert_eq!(secret.0, inner.master_secret.unwrap().0);
+        assert_eq!(secret.0, inner.master_secrets[0].0);
+    }
+
+    #[test]
+    fn master_secret_generations() {
+        let kdf = Kdf::default();
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let key_pair_id = KeyPairId::from(vec![2u8; 32]);
+        let km_runtime_id = Namespace([3u8; 32]);
+
+        let sk0 = kdf
+            .get_or_create_keys(runtime_id, key_pair_id, 0, None)
+            .expect("private key should be created");
+        kdf.get_or_create_keys(runtime_id, key_pair_id, 1, None)
+            .map(|_| ())
+            .expect_err("private key of a missing generation should not be created");
+        kdf.replicate_master_secret(1)
+            .map(|_| ())
+            .expect_err("missing generation should not be replicated");
+
+        // Add a new generation.
+        {
+            let mut inner = kdf.inner.write().unwrap();
+            let mut master_secrets = inner.master_secrets.clone();
+            master_secrets.push(Secret([5u8; SECRET_SIZE]));
+            inner.set_master_secrets(master_secrets, &km_runtime_id);
+        }
+
+        // Keys of the previous generation should stay the same.
+        let sk0_again = kdf
+            .get_or_create_keys(runtime_id, key_pair_id, 0, None)
+            .expect("private key should be created");
+        assert_eq!(
+            sk0.input_keypair.sk.0.to_bytes(),
+            sk0_again.input_keypair.sk.0.to_bytes()
+        );
+
+        // Keys of the new generation should differ.
+        let sk1 = kdf
+            .get_or_create_keys(runtime_id, key_pair_id, 1, None)
+            .expect("private key should be created");
+        assert_ne!(
+            sk0.input_keypair.sk.0.to_bytes(),
+            sk1.input_keypair.sk.0.to_bytes()
+        );
+
+        // The new generation can be replicated.
+        let secret = kdf
+            .replicate_master_secret(1)
+            .expect("master secret should be replicated");
+        assert_eq!(secret.0, [5u8; SECRET_SIZE]);
+
+        // The checksum of the new generation should commit to the previous ones.
+        let inner = kdf.inner.read().unwrap();
+        let checksums = Kdf::checksum_master_secrets(&inner.master_secrets, &km_runtime_id);
+        assert_eq!(inner.checksums, checksums);
+        assert_eq!(
+            checksums[0],
+            Kdf::checksum_master_secret(&inner.master_secrets[0], &km_runtime_id, 0, None)
+        );
+        let other = Kdf::checksum_master_secrets(
+            &[Secret([6u8; SECRET_SIZE]), inner.master_secrets[1].clone()],
+            &km_runtime_id,
+        );
+        assert_ne!(checksums[1], other[1]);
     }
 
     #[test]
@@ -887,6 +1472,10 @@ mod tests {
             &CHECKSUM_MASTER_SECRET_CUSTOM,
             &CHECKSUM_EPHEMERAL_SECRET_CUSTOM,
             &RUNTIME_SIGNING_KEY_CUSTOM,
+            &SIGNING_KDF_CUSTOM,
+            &EPHEMERAL_SIGNING_KDF_CUSTOM,
+            &ED25519_XOF_CUSTOM,
+            &COMBINE_MASTER_SECRET_CUSTOM,
         ];
         let total = customs.len();
         let set: HashSet<&[u8]> = customs.into_iter().collect();
@@ -1000,13 +1589,13 @@ mod tests {
         for v in vectors {
             let kdf = Kdf {
                 inner: RwLock::new(Inner {
-                    master_secret: Some(Secret(
+                    master_secrets: vec![Secret(
                         v.master_secret
                             .from_hex::<Vec<u8>>()
                             .unwrap()
                             .try_into()
                             .unwrap(),
-                    )),
+                    )],
                     ephemeral_secrets: HashMap::from([(
                         v.epoch,
                         Secret(
@@ -1017,10 +1606,12 @@ mod tests {
                                 .unwrap(),
                         ),
                     )]),
-                    checksum: Some(checksum.from_hex().unwrap()),
+                    checksums: vec![checksum.from_hex().unwrap()],
                     runtime_id: Some(Namespace::from(runtime_id)),
                     signer: Some(Arc::new(PrivateKey::from_bytes(signer.from_hex().unwrap()))),
                     cache: LruCache::new(NonZeroUsize::new(1).unwrap()),
+                    signing_cache: LruCache::new(NonZeroUsize::new(1).unwrap()),
+                    transcript: None,
                 }),
             };
 
@@ -1028,11 +1619,11 @@ mod tests {
             let key_pair_id = KeyPairId::from(v.key_pair_id);
 
             let lk = kdf
-                .get_or_create_keys(runtime_id, key_pair_id, None)
+                .get_or_create_keys(runtime_id, key_pair_id, 0, None)
                 .expect("private key should be created");
 
             let ek = kdf
-                .get_or_create_keys(runtime_id, key_pair_id, Some(v.epoch))
+                .get_or_create_keys(runtime_id, key_pair_id, 0, Some(v.epoch))
                 .expect("private key should be created");
 
             assert_eq!(lk.input_keypair.sk.0.to_bytes().to_hex::<String>(), v.lk_sk);
@@ -1,4 +1,5 @@
 //! Key manager crypto types and primitives.
+pub mod contribution;
 pub mod kdf;
 mod types;
 
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{convert::TryInto, sync::Arc};
 
 use anyhow::Result;
 use rand::{rngs::OsRng, Rng};
@@ -22,6 +22,9 @@ impl_bytes!(KeyPairId, 32, "A 256-bit key pair identifier.");
 /// Context used for the public key signature.
 const PUBLIC_KEY_SIGNATURE_CONTEXT: &[u8] = b"oasis-core/keymanager: pk signature";
 
+/// Context used for the public signing key signature.
+const PUBLIC_SIGNING_KEY_SIGNATURE_CONTEXT: &[u8] = b"oasis-core/keymanager: signing pk signature";
+
 /// Maximum age of a signed ephemeral public key in the number of epochs.
 const MAX_SIGNED_EPHEMERAL_PUBLIC_KEY_AGE: EpochTime = 10;
 
@@ -65,6 +68,17 @@ impl AsRef<[u8]> for Secret {
     }
 }
 
+/// Type of a key derived by the key manager.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, cbor::Encode, cbor::Decode)]
+#[repr(u8)]
+pub enum KeyType {
+    /// X25519 encryption key.
+    #[default]
+    X25519 = 0,
+    /// Ed25519 signing key.
+    Ed25519 = 1,
+}
+
 /// A key pair managed by the key manager.
 #[derive(Clone, Default, cbor::Encode, cbor::Decode)]
 pub struct KeyPair {
@@ -124,6 +138,52 @@ pub struct InputKeyPair {
     pub sk: x25519::PrivateKey,
 }
 
+/// A signing key pair managed by the key manager.
+#[derive(Clone, Default, cbor::Encode, cbor::Decode)]
+pub struct SigningKeyPair {
+    /// Key type.
+    pub key_type: KeyType,
+    /// Public key.
+    pub pk: signature::PublicKey,
+    /// Private key seed.
+    pub sk: Secret,
+    /// Checksum of the key manager state.
+    pub checksum: Vec<u8>,
+}
+
+impl SigningKeyPair {
+    /// Generate a new random Ed25519 key (for testing).
+    pub fn generate_mock() -> Self {
+        let sk = signature::PrivateKey::generate();
+        let pk = sk.public_key();
+        let sk = Secret(sk.to_bytes().try_into().unwrap());
+
+        SigningKeyPair {
+            key_type: KeyType::Ed25519,
+            pk,
+            sk,
+            checksum: vec![],
+        }
+    }
+
+    /// Create an Ed25519 `SigningKeyPair` from the given private key seed.
+    pub fn new_ed25519(sk: Secret, checksum: Vec<u8>) -> Self {
+        let pk = signature::PrivateKey::from_bytes(sk.0.to_vec()).public_key();
+
+        Self {
+            key_type: KeyType::Ed25519,
+            pk,
+            sk,
+            checksum,
+        }
+    }
+
+    /// Signer backed by the private key.
+    pub fn signer(&self) -> Arc<dyn Signer> {
+        Arc::new(signature::PrivateKey::from_bytes(self.sk.0.to_vec()))
+    }
+}
+
 /// Signed public key error.
 #[derive(Error, Debug)]
 enum SignedPublicKeyError {
@@ -142,24 +202,30 @@ enum SignedPublicKeyError {
 pub struct SignedPublicKey {
     /// Public key.
     pub key: x25519::PublicKey,
-    /// Checksum of the key manager state.
+    /// Checksum of the master secret generation the key is derived from.
     pub checksum: Vec<u8>,
-    /// Sign(sk, (key || checksum || runtime id || key pair id || epoch || expiration epoch)) from
-    /// the key manager.
+    /// Sign(sk, (key || checksum || runtime id || key pair id || epoch || expiration epoch ||
+    /// generation)) from the key manager.
     pub signature: Signature,
     /// Expiration epoch.
     #[cbor(optional)]
     pub expiration: Option<EpochTime>,
+    /// Master secret generation the key is derived from.
+    #[cbor(optional)]
+    pub generation: u64,
 }
 
 impl SignedPublicKey {
     /// Create a new signed public key.
+    ///
+    /// The checksum must belong to the master secret generation the key is derived from.
     pub fn new(
         key: x25519::PublicKey,
         checksum: Vec<u8>,
         runtime_id: Namespace,
         key_pair_id: KeyPairId,
         epoch: Option<EpochTime>,
+        generation: u64,
         signer: &Arc<dyn Signer>,
     ) -> Result<Self> {
         if checksum.len() != CHECKSUM_SIZE {
@@ -167,7 +233,15 @@ impl SignedPublicKey {
         }
 
         let expiration = epoch.map(|epoch| epoch + MAX_SIGNED_EPHEMERAL_PUBLIC_KEY_AGE);
-        let body = Self::body(key, &checksum, runtime_id, key_pair_id, epoch, expiration);
+        let body = Self::body(
+            key,
+            &checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            expiration,
+            generation,
+        );
         let signature = signer.sign(PUBLIC_KEY_SIGNATURE_CONTEXT, &body)?;
 
         Ok(SignedPublicKey {
@@ -175,6 +249,7 @@ impl SignedPublicKey {
             checksum,
             signature,
             expiration,
+            generation,
         })
     }
 
@@ -187,58 +262,211 @@ impl SignedPublicKey {
         now: Option<EpochTime>,
         pk: &signature::PublicKey,
     ) -> Result<()> {
-        // Checksum validation.
-        if self.checksum.len() != CHECKSUM_SIZE {
+        validate_signed_key(&self.checksum, epoch, self.expiration, now)?;
+
+        let body = Self::body(
+            self.key,
+            &self.checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            self.expiration,
+            self.generation,
+        );
+
+        self.signature
+            .verify(pk, PUBLIC_KEY_SIGNATURE_CONTEXT, &body)
+    }
+
+    fn body(
+        key: x25519::PublicKey,
+        checksum: &[u8],
+        runtime_id: Namespace,
+        key_pair_id: KeyPairId,
+        epoch: Option<EpochTime>,
+        expiration: Option<EpochTime>,
+        generation: u64,
+    ) -> Vec<u8> {
+        signed_key_body(
+            key.0.as_bytes(),
+            checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            expiration,
+            generation,
+        )
+    }
+}
+
+/// Signed public signing key.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct SignedPublicSigningKey {
+    /// Key type.
+    pub key_type: KeyType,
+    /// Public key.
+    pub key: signature::PublicKey,
+    /// Checksum of the master secret generation the key is derived from.
+    pub checksum: Vec<u8>,
+    /// Sign(sk, (key type || key || checksum || runtime id || key pair id || epoch ||
+    /// expiration epoch || generation)) from the key manager.
+    pub signature: Signature,
+    /// Expiration epoch.
+    #[cbor(optional)]
+    pub expiration: Option<EpochTime>,
+    /// Master secret generation the key is derived from.
+    #[cbor(optional)]
+    pub generation: u64,
+}
+
+impl SignedPublicSigningKey {
+    /// Create a new signed public signing key.
+    ///
+    /// The checksum must belong to the master secret generation the key is derived from.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        key_type: KeyType,
+        key: signature::PublicKey,
+        checksum: Vec<u8>,
+        runtime_id: Namespace,
+        key_pair_id: KeyPairId,
+        epoch: Option<EpochTime>,
+        generation: u64,
+        signer: &Arc<dyn Signer>,
+    ) -> Result<Self> {
+        if checksum.len() != CHECKSUM_SIZE {
             return Err(SignedPublicKeyError::InvalidChecksum.into());
         }
 
-        // Cache validation for ephemeral keys.
-        if let Some(epoch) = epoch {
-            let now = now.ok_or(SignedPublicKeyError::CurrentEpochRequired)?;
-            if now < epoch {
-                return Err(SignedPublicKeyError::SignatureFromFuture.into());
-            }
-        }
-        if let Some(expiration) = self.expiration {
-            let now = now.ok_or(SignedPublicKeyError::CurrentEpochRequired)?;
-            if now > expiration {
-                return Err(SignedPublicKeyError::SignatureExpired.into());
-            }
-        }
+        let expiration = epoch.map(|epoch| epoch + MAX_SIGNED_EPHEMERAL_PUBLIC_KEY_AGE);
+        let body = Self::body(
+            key_type,
+            key,
+            &checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            expiration,
+            generation,
+        );
+        let signature = signer.sign(PUBLIC_SIGNING_KEY_SIGNATURE_CONTEXT, &body)?;
+
+        Ok(SignedPublicSigningKey {
+            key_type,
+            key,
+            checksum,
+            signature,
+            expiration,
+            generation,
+        })
+    }
+
+    /// Verify the signature.
+    pub fn verify(
+        &self,
+        runtime_id: Namespace,
+        key_pair_id: KeyPairId,
+        epoch: Option<EpochTime>,
+        now: Option<EpochTime>,
+        pk: &signature::PublicKey,
+    ) -> Result<()> {
+        validate_signed_key(&self.checksum, epoch, self.expiration, now)?;
 
         let body = Self::body(
+            self.key_type,
             self.key,
             &self.checksum,
             runtime_id,
             key_pair_id,
             epoch,
             self.expiration,
+            self.generation,
         );
 
         self.signature
-            .verify(pk, PUBLIC_KEY_SIGNATURE_CONTEXT, &body)
+            .verify(pk, PUBLIC_SIGNING_KEY_SIGNATURE_CONTEXT, &body)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn body(
-        key: x25519::PublicKey,
+        key_type: KeyType,
+        key: signature::PublicKey,
         checksum: &[u8],
         runtime_id: Namespace,
         key_pair_id: KeyPairId,
         epoch: Option<EpochTime>,
         expiration: Option<EpochTime>,
+        generation: u64,
     ) -> Vec<u8> {
-        let mut body = key.0.as_bytes().to_vec();
-        body.extend_from_slice(checksum);
-        body.extend_from_slice(runtime_id.as_ref());
-        body.extend_from_slice(key_pair_id.as_ref());
-        if let Some(epoch) = epoch {
-            body.extend_from_slice(&epoch.to_be_bytes());
+        let mut typed_key = vec![key_type as u8];
+        typed_key.extend_from_slice(key.as_ref());
+
+        signed_key_body(
+            &typed_key,
+            checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            expiration,
+            generation,
+        )
+    }
+}
+
+/// Validate the checksum and the freshness of a signed key.
+fn validate_signed_key(
+    checksum: &[u8],
+    epoch: Option<EpochTime>,
+    expiration: Option<EpochTime>,
+    now: Option<EpochTime>,
+) -> Result<()> {
+    // Checksum validation.
+    if checksum.len() != CHECKSUM_SIZE {
+        return Err(SignedPublicKeyError::InvalidChecksum.into());
+    }
+
+    // Cache validation for ephemeral keys.
+    if let Some(epoch) = epoch {
+        let now = now.ok_or(SignedPublicKeyError::CurrentEpochRequired)?;
+        if now < epoch {
+            return Err(SignedPublicKeyError::SignatureFromFuture.into());
         }
-        if let Some(expiration) = expiration {
-            body.extend_from_slice(&expiration.to_be_bytes());
+    }
+    if let Some(expiration) = expiration {
+        let now = now.ok_or(SignedPublicKeyError::CurrentEpochRequired)?;
+        if now > expiration {
+            return Err(SignedPublicKeyError::SignatureExpired.into());
         }
-        body
     }
+
+    Ok(())
+}
+
+fn signed_key_body(
+    key: &[u8],
+    checksum: &[u8],
+    runtime_id: Namespace,
+    key_pair_id: KeyPairId,
+    epoch: Option<EpochTime>,
+    expiration: Option<EpochTime>,
+    generation: u64,
+) -> Vec<u8> {
+    let mut body = key.to_vec();
+    body.extend_from_slice(checksum);
+    body.extend_from_slice(runtime_id.as_ref());
+    body.extend_from_slice(key_pair_id.as_ref());
+    if let Some(epoch) = epoch {
+        body.extend_from_slice(&epoch.to_be_bytes());
+    }
+    if let Some(expiration) = expiration {
+        body.extend_from_slice(&expiration.to_be_bytes());
+    }
+    // Only later generations are bound so that signatures of keys derived from the first
+    // generation stay compatible. Ephemeral keys are always derived from the first one.
+    if generation > 0 {
+        body.extend_from_slice(&generation.to_be_bytes());
+    }
+    body
 }
 
 #[cfg(test)]
@@ -256,19 +484,90 @@ mod test {
         consensus::beacon::EpochTime,
     };
 
-    use crate::crypto::{types::MAX_SIGNED_EPHEMERAL_PUBLIC_KEY_AGE, KeyPairId, SignedPublicKey};
+    use crate::crypto::{
+        types::MAX_SIGNED_EPHEMERAL_PUBLIC_KEY_AGE, KeyPairId, KeyType, SignedPublicKey,
+        SignedPublicSigningKey, SigningKeyPair,
+    };
 
     #[test]
     fn test_signed_public_key_with_epoch() {
-        test_signed_public_key(Some(10), Some(15))
+        test_signed_public_key(Some(10), Some(15), 0)
     }
 
     #[test]
     fn test_signed_public_key_without_epoch() {
-        test_signed_public_key(None, None)
+        test_signed_public_key(None, None, 0)
+    }
+
+    #[test]
+    fn test_signed_public_key_with_generation() {
+        test_signed_public_key(None, None, 2)
+    }
+
+    #[test]
+    fn test_signed_public_signing_key() {
+        let sk = Arc::new(signature::PrivateKey::from_test_seed("seed".to_string()));
+        let pk = sk.public_key();
+        let signer: Arc<dyn Signer> = sk;
+
+        let key = SigningKeyPair::generate_mock().pk;
+        let checksum = [1u8; 32].to_vec();
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let key_pair_id = KeyPairId::from(vec![1u8; 32]);
+        let epoch = Some(10);
+        let now = Some(15);
+
+        let signed_pk = SignedPublicSigningKey::new(
+            KeyType::Ed25519,
+            key,
+            checksum.clone(),
+            runtime_id,
+            key_pair_id,
+            epoch,
+            0,
+            &signer,
+        )
+        .expect("signing public key should work");
+        signed_pk
+            .verify(runtime_id, key_pair_id, epoch, now, &pk)
+            .expect("verification should succeed");
+
+        // Verify the signature with different key type.
+        let invalid_signed_pk = SignedPublicSigningKey {
+            key_type: KeyType::X25519,
+            ..signed_pk.clone()
+        };
+        let result = invalid_signed_pk.verify(runtime_id, key_pair_id, epoch, now, &pk);
+        assert!(
+            result.is_err(),
+            "verification with different key type should fail"
+        );
+        assert_eq!(result.unwrap_err().to_string(), "invalid signature");
+
+        // Signatures of encryption keys should not be valid for signing keys.
+        let encryption_pk = SignedPublicKey::new(
+            x25519::PublicKey::from(key.0),
+            checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            0,
+            &signer,
+        )
+        .expect("signing public key should work");
+        let invalid_signed_pk = SignedPublicSigningKey {
+            signature: encryption_pk.signature,
+            ..signed_pk
+        };
+        let result = invalid_signed_pk.verify(runtime_id, key_pair_id, epoch, now, &pk);
+        assert!(
+            result.is_err(),
+            "verification with encryption key signature should fail"
+        );
+        assert_eq!(result.unwrap_err().to_string(), "invalid signature");
     }
 
-    fn test_signed_public_key(epoch: Option<EpochTime>, now: Option<EpochTime>) {
+    fn test_signed_public_key(epoch: Option<EpochTime>, now: Option<EpochTime>, generation: u64) {
         let sk = Arc::new(signature::PrivateKey::from_test_seed("seed".to_string()));
         let pk = sk.public_key();
 
@@ -285,6 +584,7 @@ mod test {
             runtime_id,
             key_pair_id,
             epoch,
+            generation,
             &signer,
         );
         assert!(
@@ -294,7 +594,15 @@ mod test {
         assert_eq!(result.unwrap_err().to_string(), "invalid checksum");
 
         // Create a signature.
-        let result = SignedPublicKey::new(key, checksum, runtime_id, key_pair_id, epoch, &signer);
+        let result = SignedPublicKey::new(
+            key,
+            checksum,
+            runtime_id,
+            key_pair_id,
+            epoch,
+            generation,
+            &signer,
+        );
         assert!(result.is_ok(), "signing public key should work");
         let signed_pk = result.unwrap();
 
@@ -379,6 +687,7 @@ mod test {
             checksum: signed_pk.checksum.clone(),
             signature: signed_pk.signature.clone(),
             expiration: signed_pk.expiration,
+            generation: signed_pk.generation,
         };
         let result = invalid_signed_pk.verify(runtime_id, key_pair_id, epoch, now, &pk);
         assert!(
@@ -393,6 +702,7 @@ mod test {
             checksum: [2u8; 32].to_vec(),
             signature: signed_pk.signature.clone(),
             expiration: signed_pk.expiration,
+            generation: signed_pk.generation,
         };
         let result = invalid_signed_pk.verify(runtime_id, key_pair_id, epoch, now, &pk);
         assert!(
@@ -407,6 +717,7 @@ mod test {
             checksum: [1u8; 30].to_vec(),
             signature: signed_pk.signature.clone(),
             expiration: signed_pk.expiration,
+            generation: signed_pk.generation,
         };
         let result = invalid_signed_pk.verify(runtime_id, key_pair_id, epoch, now, &pk);
         assert!(
@@ -421,6 +732,7 @@ mod test {
             checksum: signed_pk.checksum.clone(),
             signature: signed_pk.signature.clone(),
             expiration: Some(100),
+            generation: signed_pk.generation,
         };
         let result = invalid_signed_pk.verify(runtime_id, key_pair_id, epoch, Some(15), &pk);
         assert!(
@@ -428,5 +740,17 @@ mod test {
             "verification with different expiration epoch should fail"
         );
         assert_eq!(result.unwrap_err().to_string(), "invalid signature");
+
+        // Verify the signature with different generation.
+        let invalid_signed_pk = SignedPublicKey {
+            generation: generation + 1,
+            ..signed_pk.clone()
+        };
+        let result = invalid_signed_pk.verify(runtime_id, key_pair_id, epoch, now, &pk);
+        assert!(
+            result.is_err(),
+            "verification with different generation should fail"
+        );
+        assert_eq!(result.unwrap_err().to_string(), "invalid signature");
     }
 }
@@ -1,4 +1,5 @@
 pub mod api;
+pub mod audit;
 pub mod client;
 pub mod crypto;
 pub mod policy;
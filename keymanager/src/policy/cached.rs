@@ -18,8 +18,11 @@ use oasis_core_runtime::{
             EnclaveIdentity,
         },
     },
-    consensus::{beacon::EpochTime, keymanager::SignedPolicySGX},
-    enclave_rpc::Context as RpcContext,
+    consensus::{
+        beacon::EpochTime,
+        keymanager::{RuntimeRulesSGX, SignedPolicySGX},
+    },
+    enclave_rpc::{access::AccessPolicy, Context as RpcContext},
     policy::PolicyVerifier,
     runtime_context,
     storage::KeyValue,
@@ -43,12 +46,30 @@ pub struct Policy {
 
 struct Inner {
     policy: Option<CachedPolicy>,
+    /// Number of calls of private key methods per runtime, counted since the start
+    /// of the given epoch.
+    ///
+    /// Usage is only known to this enclave, so quotas are enforced per key manager node.
+    usage: HashMap<(Namespace, String), (EpochTime, u64)>,
+}
+
+impl Inner {
+    /// Forget the usage of runtimes whose rules differ between the given policies,
+    /// calls counted under the old rules no longer count.
+    fn reset_usage(&mut self, old_policy: &CachedPolicy, new_policy: &CachedPolicy) {
+        self.usage.retain(|(runtime_id, _), _| {
+            old_policy.runtime_rules.get(runtime_id) == new_policy.runtime_rules.get(runtime_id)
+        });
+    }
 }
 
 impl Policy {
     fn new() -> Self {
         Self {
-            inner: RwLock::new(Inner { policy: None }),
+            inner: RwLock::new(Inner {
+                policy: None,
+                usage: HashMap::new(),
+            }),
         }
     }
 
@@ -105,6 +126,8 @@ impl Policy {
                 Ok(old_policy.checksum)
             }
             Ordering::Less => {
+                inner.reset_usage(&old_policy, &new_policy);
+
                 // Persist then apply the new policy.
                 Self::save_raw_policy(ctx.untrusted_local_storage, policy_raw);
                 let new_checksum = new_policy.checksum.clone();
@@ -116,6 +139,46 @@ impl Policy {
         }
     }
 
+    /// Access policy of the methods returning private keys.
+    ///
+    /// Only enclaves which may query the keys of at least one runtime are allowed to call them,
+    /// the methods check the runtime the keys are requested for.
+    pub fn key_query_access() -> AccessPolicy {
+        if Self::unsafe_skip() {
+            return AccessPolicy::default(); // Authorize unsafe builds always.
+        }
+        AccessPolicy::default()
+            .authorizer(|remote_enclave| Self::global().may_query_keys(remote_enclave).is_ok())
+    }
+
+    /// Access policy of the methods replicating secrets to other key manager enclaves.
+    pub fn replication_access() -> AccessPolicy {
+        if Self::unsafe_skip() {
+            return AccessPolicy::default(); // Authorize unsafe builds always.
+        }
+        AccessPolicy::default().authorizer(|remote_enclave| {
+            Self::global().may_replicate_secret(remote_enclave).is_ok()
+        })
+    }
+
+    /// Check if the MRSIGNER/MRENCLAVE may query keys for any runtime ID.
+    pub fn may_query_keys(&self, remote_enclave: &EnclaveIdentity) -> Result<()> {
+        let inner = self.inner.read().unwrap();
+        let policy = inner
+            .policy
+            .as_ref()
+            .ok_or(KeyManagerError::NotAuthorized)?;
+
+        match policy
+            .may_query
+            .values()
+            .any(|may_query| may_query.contains(remote_enclave))
+        {
+            true => Ok(()),
+            false => Err(KeyManagerError::NotAuthorized.into()),
+        }
+    }
+
     /// Check if the MRSIGNER/MRENCLAVE may query keys for the given
     /// runtime ID/contract ID.
     pub fn may_get_or_create_keys(
@@ -135,6 +198,97 @@ impl Policy {
         }
     }
 
+    /// Check if the enclaves of the given runtime may call the given private key method
+    /// and have not exhausted the runtime's quota for the given epoch.
+    ///
+    /// Quotas are enforced by every key manager node on its own, so a runtime may call
+    /// the method up to its quota on each node.
+    pub fn may_call_key_method(
+        &self,
+        runtime_id: &Namespace,
+        method: &str,
+        epoch: EpochTime,
+    ) -> Result<()> {
+        self.check_key_method(runtime_id, method, epoch, false)
+    }
+
+    /// Charge a successful call of the given private key method against the runtime's
+    /// quota of this key manager node for the given epoch.
+    ///
+    /// Fails if the quota was exhausted since the call was checked.
+    pub fn charge_key_method(
+        &self,
+        runtime_id: &Namespace,
+        method: &str,
+        epoch: EpochTime,
+    ) -> Result<()> {
+        self.check_key_method(runtime_id, method, epoch, true)
+    }
+
+    /// Handle a call of the given private key method made by the enclaves of the given runtime,
+    /// charging the runtime's quota for the given epoch only if the handler succeeds.
+    ///
+    /// Failed calls, e.g. those failing with retryable errors, don't use up the quota.
+    pub fn call_key_method<T, F>(
+        &self,
+        runtime_id: &Namespace,
+        method: &str,
+        epoch: EpochTime,
+        handler: F,
+    ) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        self.may_call_key_method(runtime_id, method, epoch)?;
+        let result = handler()?;
+        self.charge_key_method(runtime_id, method, epoch)?;
+        Ok(result)
+    }
+
+    fn check_key_method(
+        &self,
+        runtime_id: &Namespace,
+        method: &str,
+        epoch: EpochTime,
+        charge: bool,
+    ) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+        let inner = &mut *inner;
+        let policy = inner
+            .policy
+            .as_ref()
+            .ok_or(KeyManagerError::NotAuthorized)?;
+
+        let rules = match policy.runtime_rules.get(runtime_id) {
+            Some(rules) => rules,
+            None => return Ok(()), // No rules for the runtime.
+        };
+        if !rules.allowed_methods.is_empty() && !rules.allowed_methods.iter().any(|m| m == method) {
+            return Err(KeyManagerError::MethodNotAllowed.into());
+        }
+        let quota = match rules.quotas.get(method) {
+            Some(quota) => *quota,
+            None => return Ok(()), // No quota for the method.
+        };
+
+        let (usage_epoch, calls) = inner
+            .usage
+            .entry((*runtime_id, method.to_string()))
+            .or_insert((epoch, 0));
+        if *usage_epoch < epoch {
+            *usage_epoch = epoch;
+            *calls = 0;
+        }
+        if *calls >= quota {
+            return Err(KeyManagerError::QuotaExceeded.into());
+        }
+        if charge {
+            *calls += 1;
+        }
+
+        Ok(())
+    }
+
     /// Check if the MRENCLAVE/MRSIGNER may replicate.
     pub fn may_replicate_secret(&self, remote_enclave: &EnclaveIdentity) -> Result<()> {
         // Always allow replication to ourselves, if it is possible to do so in
@@ -205,6 +359,7 @@ struct CachedPolicy {
     pub may_query: HashMap<Namespace, HashSet<EnclaveIdentity>>,
     pub may_replicate: HashSet<EnclaveIdentity>,
     pub may_replicate_from: HashSet<EnclaveIdentity>,
+    pub runtime_rules: HashMap<Namespace, RuntimeRulesSGX>,
     pub max_ephemeral_secret_age: EpochTime,
 }
 
@@ -249,6 +404,7 @@ impl CachedPolicy {
         for e_id in &enclave_policy.may_replicate {
             cached_policy.may_replicate.insert(e_id.clone());
         }
+        cached_policy.runtime_rules = enclave_policy.runtime_rules.clone();
         for (e_id, other_policy) in &policy.enclaves {
             if other_policy.may_replicate.contains(&enclave_identity) {
                 cached_policy.may_replicate_from.insert(e_id.clone());
@@ -276,3 +432,136 @@ impl CachedPolicy {
         self.may_replicate.contains(remote_enclave)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oasis_core_runtime::{
+        common::namespace::Namespace, consensus::keymanager::RuntimeRulesSGX,
+    };
+
+    use crate::api::KeyManagerError;
+
+    use super::{CachedPolicy, Policy};
+
+    #[test]
+    fn test_may_call_key_method() {
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let other_runtime_id = Namespace::from(vec![2u8; 32]);
+        let policy = Policy::new();
+
+        // Calls are not authorized without a policy.
+        assert!(policy
+            .may_call_key_method(&runtime_id, "get_or_create_keys", 1)
+            .is_err());
+
+        policy.inner.write().unwrap().policy = Some(CachedPolicy {
+            runtime_rules: HashMap::from([(
+                runtime_id,
+                RuntimeRulesSGX {
+                    allowed_methods: vec!["get_or_create_keys".to_string()],
+                    quotas: HashMap::from([("get_or_create_keys".to_string(), 2)]),
+                },
+            )]),
+            ..Default::default()
+        });
+
+        // Runtimes without rules are not restricted.
+        for _ in 0..5 {
+            policy
+                .may_call_key_method(&other_runtime_id, "get_or_create_ephemeral_keys", 1)
+                .expect("runtime should not be restricted");
+        }
+
+        // Methods which are not allowed are rejected.
+        let err = policy
+            .may_call_key_method(&runtime_id, "get_or_create_ephemeral_keys", 1)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<KeyManagerError>(),
+            Some(KeyManagerError::MethodNotAllowed)
+        ));
+
+        // Quotas are enforced per epoch, only charged calls count.
+        for epoch in 1..3 {
+            for _ in 0..2 {
+                policy
+                    .may_call_key_method(&runtime_id, "get_or_create_keys", epoch)
+                    .expect("call should be within quota");
+                policy
+                    .may_call_key_method(&runtime_id, "get_or_create_keys", epoch)
+                    .expect("uncharged calls should not count");
+                policy
+                    .charge_key_method(&runtime_id, "get_or_create_keys", epoch)
+                    .expect("call should be within quota");
+            }
+            let err = policy
+                .may_call_key_method(&runtime_id, "get_or_create_keys", epoch)
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<KeyManagerError>(),
+                Some(KeyManagerError::QuotaExceeded)
+            ));
+            let err = policy
+                .charge_key_method(&runtime_id, "get_or_create_keys", epoch)
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<KeyManagerError>(),
+                Some(KeyManagerError::QuotaExceeded)
+            ));
+        }
+
+        // Failed calls are not charged.
+        for _ in 0..5 {
+            let err = policy
+                .call_key_method(&runtime_id, "get_or_create_keys", 3, || -> Result<()> {
+                    Err(KeyManagerError::AuditLogUnavailable.into())
+                })
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<KeyManagerError>(),
+                Some(KeyManagerError::AuditLogUnavailable)
+            ));
+        }
+        for _ in 0..2 {
+            policy
+                .call_key_method(&runtime_id, "get_or_create_keys", 3, || Ok(()))
+                .expect("call should be within quota");
+        }
+        let err = policy
+            .call_key_method(&runtime_id, "get_or_create_keys", 3, || Ok(()))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<KeyManagerError>(),
+            Some(KeyManagerError::QuotaExceeded)
+        ));
+
+        // Usage is reset when the rules of the runtime change.
+        let mut inner = policy.inner.write().unwrap();
+        inner
+            .usage
+            .insert((other_runtime_id, "get_or_create_keys".to_string()), (2, 1));
+        let old_policy = inner.policy.clone().unwrap();
+        let mut new_policy = old_policy.clone();
+        inner.reset_usage(&old_policy, &new_policy);
+        assert_eq!(
+            inner.usage.len(),
+            2,
+            "usage should be kept if rules are unchanged"
+        );
+
+        new_policy
+            .runtime_rules
+            .get_mut(&runtime_id)
+            .unwrap()
+            .quotas
+            .insert("get_or_create_keys".to_string(), 3);
+        inner.reset_usage(&old_policy, &new_policy);
+        assert_eq!(
+            inner.usage.keys().collect::<Vec<_>>(),
+            vec![&(other_runtime_id, "get_or_create_keys".to_string())],
+            "usage should be reset if rules change"
+        );
+    }
+}
@@ -15,11 +15,10 @@ use oasis_core_runtime::{
                 deoxysii::{self, Opener, TAG_SIZE},
                 nonce::{Nonce, NONCE_SIZE},
             },
-            signature::{self, Signer},
+            signature::{self, SignatureBundle, Signer},
             x25519,
         },
         namespace::Namespace,
-        sgx::EnclaveIdentity,
     },
     consensus::{
         beacon::EpochTime,
@@ -29,19 +28,31 @@ use oasis_core_runtime::{
             registry::ImmutableState as RegistryState,
         },
     },
-    enclave_rpc::Context as RpcContext,
+    enclave_rpc::{dispatcher::Chunks, types::Error as RpcError, Context as RpcContext},
     runtime_context,
 };
 
 use crate::{
     api::{
-        EphemeralKeyRequest, GenerateEphemeralSecretRequest, GenerateEphemeralSecretResponse,
-        InitRequest, KeyManagerError, LoadEphemeralSecretRequest, LongTermKeyRequest,
-        ReplicateEphemeralSecretRequest, ReplicateEphemeralSecretResponse,
-        ReplicateMasterSecretRequest, ReplicateMasterSecretResponse, SignedInitResponse,
+        CommitMasterSecretContributionRequest, ConfirmMasterSecretRequest, EphemeralKeyRequest,
+        ExportAuditLogRequest, GenerateEphemeralSecretRequest, GenerateEphemeralSecretResponse,
+        InitRequest, KeyManagerError, KeyManagerService, LoadEphemeralSecretRequest,
+        LongTermKeyRequest, ReplicateEphemeralSecretRequest, ReplicateEphemeralSecretResponse,
+        ReplicateMasterSecretRequest, ReplicateMasterSecretResponse, ReplicateMasterSecretsRequest,
+        RevealMasterSecretContributionRequest, RevealMasterSecretContributionResponse,
+        SignedInitResponse, METHOD_GET_OR_CREATE_EPHEMERAL_KEYS,
+        METHOD_GET_OR_CREATE_EPHEMERAL_SIGNING_KEYS, METHOD_GET_OR_CREATE_KEYS,
+        METHOD_GET_OR_CREATE_SIGNING_KEYS, METHOD_REPLICATE_EPHEMERAL_SECRET,
+        METHOD_REPLICATE_MASTER_SECRET, METHOD_REPLICATE_MASTER_SECRETS,
     },
+    audit::{AuditLog, AuditRecord, SignedAuditLogExport},
     client::{KeyManagerClient, RemoteClient},
-    crypto::{kdf::Kdf, KeyPair, Secret, SignedPublicKey, SECRET_SIZE},
+    crypto::{
+        contribution::{ContributionCommitment, Contributions},
+        kdf::Kdf,
+        KeyPair, KeyType, Secret, SignedPublicKey, SignedPublicSigningKey, SigningKeyPair,
+        SECRET_SIZE,
+    },
     policy::Policy,
     runtime::context::Context as KmContext,
 };
@@ -57,6 +68,134 @@ const MAX_FRESH_HEIGHT_AGE: u64 = 50;
 /// The size of an encrypted ephemeral secret.
 const EPHEMERAL_SECRET_STORAGE_SIZE: usize = 32 + TAG_SIZE + NONCE_SIZE;
 
+/// Key manager EnclaveRPC service.
+pub struct KeyManager;
+
+impl KeyManagerService for KeyManager {
+    fn get_or_create_keys(ctx: &mut RpcContext, req: &LongTermKeyRequest) -> Result<KeyPair> {
+        coded(get_or_create_keys(ctx, req))
+    }
+
+    fn get_public_key(ctx: &mut RpcContext, req: &LongTermKeyRequest) -> Result<SignedPublicKey> {
+        coded(get_public_key(ctx, req))
+    }
+
+    fn get_or_create_ephemeral_keys(
+        ctx: &mut RpcContext,
+        req: &EphemeralKeyRequest,
+    ) -> Result<KeyPair> {
+        coded(get_or_create_ephemeral_keys(ctx, req))
+    }
+
+    fn get_public_ephemeral_key(
+        ctx: &mut RpcContext,
+        req: &EphemeralKeyRequest,
+    ) -> Result<SignedPublicKey> {
+        coded(get_public_ephemeral_key(ctx, req))
+    }
+
+    fn get_or_create_signing_keys(
+        ctx: &mut RpcContext,
+        req: &LongTermKeyRequest,
+    ) -> Result<SigningKeyPair> {
+        coded(get_or_create_signing_keys(ctx, req))
+    }
+
+    fn get_public_signing_key(
+        ctx: &mut RpcContext,
+        req: &LongTermKeyRequest,
+    ) -> Result<SignedPublicSigningKey> {
+        coded(get_public_signing_key(ctx, req))
+    }
+
+    fn get_or_create_ephemeral_signing_keys(
+        ctx: &mut RpcContext,
+        req: &EphemeralKeyRequest,
+    ) -> Result<SigningKeyPair> {
+        coded(get_or_create_ephemeral_signing_keys(ctx, req))
+    }
+
+    fn get_public_ephemeral_signing_key(
+        ctx: &mut RpcContext,
+        req: &EphemeralKeyRequest,
+    ) -> Result<SignedPublicSigningKey> {
+        coded(get_public_ephemeral_signing_key(ctx, req))
+    }
+
+    fn replicate_master_secret(
+        ctx: &mut RpcContext,
+        req: &ReplicateMasterSecretRequest,
+    ) -> Result<ReplicateMasterSecretResponse> {
+        coded(replicate_master_secret(ctx, req))
+    }
+
+    fn replicate_master_secrets(
+        ctx: &mut RpcContext,
+        req: &ReplicateMasterSecretsRequest,
+    ) -> Result<Chunks<ReplicateMasterSecretResponse>> {
+        let chunks = coded(replicate_master_secrets(ctx, req))?;
+        Ok(Box::new(chunks.map(coded)))
+    }
+
+    fn replicate_ephemeral_secret(
+        ctx: &mut RpcContext,
+        req: &ReplicateEphemeralSecretRequest,
+    ) -> Result<ReplicateEphemeralSecretResponse> {
+        coded(replicate_ephemeral_secret(ctx, req))
+    }
+
+    fn commit_master_secret_contribution(
+        ctx: &mut RpcContext,
+        req: &CommitMasterSecretContributionRequest,
+    ) -> Result<ContributionCommitment> {
+        coded(commit_master_secret_contribution(ctx, req))
+    }
+
+    fn reveal_master_secret_contribution(
+        ctx: &mut RpcContext,
+        req: &RevealMasterSecretContributionRequest,
+    ) -> Result<RevealMasterSecretContributionResponse> {
+        coded(reveal_master_secret_contribution(ctx, req))
+    }
+
+    fn confirm_master_secret(
+        ctx: &mut RpcContext,
+        req: &ConfirmMasterSecretRequest,
+    ) -> Result<SignatureBundle> {
+        coded(confirm_master_secret(ctx, req))
+    }
+
+    fn init(ctx: &mut RpcContext, req: &InitRequest) -> Result<SignedInitResponse> {
+        coded(init_kdf(ctx, req))
+    }
+
+    fn generate_ephemeral_secret(
+        ctx: &mut RpcContext,
+        req: &GenerateEphemeralSecretRequest,
+    ) -> Result<GenerateEphemeralSecretResponse> {
+        coded(generate_ephemeral_secret(ctx, req))
+    }
+
+    fn load_ephemeral_secret(ctx: &mut RpcContext, req: &LoadEphemeralSecretRequest) -> Result<()> {
+        coded(load_ephemeral_secret(ctx, req))
+    }
+
+    fn export_audit_log(
+        ctx: &mut RpcContext,
+        req: &ExportAuditLogRequest,
+    ) -> Result<SignedAuditLogExport> {
+        coded(export_audit_log(ctx, req))
+    }
+}
+
+/// Convert key manager errors into coded EnclaveRPC errors, so that clients can tell them apart.
+fn coded<T>(result: Result<T>) -> Result<T> {
+    result.map_err(|err| match err.downcast::<KeyManagerError>() {
+        Ok(err) => RpcError::from(err).into(),
+        Err(err) => err,
+    })
+}
+
 /// Initialize the Kdf.
 pub fn init_kdf(ctx: &mut RpcContext, req: &InitRequest) -> Result<SignedInitResponse> {
     let policy_checksum = Policy::global().init(ctx, &req.policy)?;
@@ -65,20 +204,36 @@ pub fn init_kdf(ctx: &mut RpcContext, req: &InitRequest) -> Result<SignedInitRes
 
 /// See `Kdf::get_or_create_keys`.
 pub fn get_or_create_keys(ctx: &mut RpcContext, req: &LongTermKeyRequest) -> Result<KeyPair> {
+    validate_encryption_key_type(req.key_type)?;
     authorize_private_key_generation(ctx, &req.runtime_id)?;
     validate_height_freshness(ctx, req.height)?;
 
-    Kdf::global().get_or_create_keys(req.runtime_id, req.key_pair_id, None)
+    enforce_runtime_rules(ctx, &req.runtime_id, METHOD_GET_OR_CREATE_KEYS, || {
+        let keys = Kdf::global().get_or_create_keys(
+            req.runtime_id,
+            req.key_pair_id,
+            req.generation,
+            None,
+        )?;
+        AuditLog::global().append(
+            ctx,
+            AuditRecord::new(METHOD_GET_OR_CREATE_KEYS, req.runtime_id)
+                .with_key_pair_id(req.key_pair_id)
+                .with_generation(req.generation),
+        )?;
+        Ok(keys)
+    })
 }
 
 /// See `Kdf::get_public_key`.
 pub fn get_public_key(_ctx: &mut RpcContext, req: &LongTermKeyRequest) -> Result<SignedPublicKey> {
     // No authentication or authorization.
     // Absolutely anyone is allowed to query public long-term keys.
+    validate_encryption_key_type(req.key_type)?;
 
     let kdf = Kdf::global();
-    let pk = kdf.get_public_key(req.runtime_id, req.key_pair_id, None)?;
-    let sig = kdf.sign_public_key(pk, req.runtime_id, req.key_pair_id, None)?;
+    let pk = kdf.get_public_key(req.runtime_id, req.key_pair_id, req.generation, None)?;
+    let sig = kdf.sign_public_key(pk, req.runtime_id, req.key_pair_id, req.generation, None)?;
     Ok(sig)
 }
 
@@ -87,11 +242,31 @@ pub fn get_or_create_ephemeral_keys(
     ctx: &mut RpcContext,
     req: &EphemeralKeyRequest,
 ) -> Result<KeyPair> {
+    validate_encryption_key_type(req.key_type)?;
     authorize_private_key_generation(ctx, &req.runtime_id)?;
     validate_ephemeral_key_epoch(ctx, req.epoch)?;
     validate_height_freshness(ctx, req.height)?;
 
-    Kdf::global().get_or_create_keys(req.runtime_id, req.key_pair_id, Some(req.epoch))
+    enforce_runtime_rules(
+        ctx,
+        &req.runtime_id,
+        METHOD_GET_OR_CREATE_EPHEMERAL_KEYS,
+        || {
+            let keys = Kdf::global().get_or_create_keys(
+                req.runtime_id,
+                req.key_pair_id,
+                0,
+                Some(req.epoch),
+            )?;
+            AuditLog::global().append(
+                ctx,
+                AuditRecord::new(METHOD_GET_OR_CREATE_EPHEMERAL_KEYS, req.runtime_id)
+                    .with_key_pair_id(req.key_pair_id)
+                    .with_epoch(req.epoch),
+            )?;
+            Ok(keys)
+        },
+    )
 }
 
 /// See `Kdf::get_public_key`.
@@ -101,11 +276,12 @@ pub fn get_public_ephemeral_key(
 ) -> Result<SignedPublicKey> {
     // No authentication or authorization.
     // Absolutely anyone is allowed to query public ephemeral keys.
+    validate_encryption_key_type(req.key_type)?;
     validate_ephemeral_key_epoch(ctx, req.epoch)?;
 
     let kdf = Kdf::global();
-    let pk = kdf.get_public_key(req.runtime_id, req.key_pair_id, Some(req.epoch))?;
-    let mut sig = kdf.sign_public_key(pk, req.runtime_id, req.key_pair_id, Some(req.epoch))?;
+    let pk = kdf.get_public_key(req.runtime_id, req.key_pair_id, 0, Some(req.epoch))?;
+    let mut sig = kdf.sign_public_key(pk, req.runtime_id, req.key_pair_id, 0, Some(req.epoch))?;
 
     // Outdated key manager clients request public ephemeral keys via secure RPC calls,
     // they never verify their signatures and are not aware that signed ephemeral keys expire.
@@ -118,30 +294,221 @@ pub fn get_public_ephemeral_key(
     Ok(sig)
 }
 
+/// See `Kdf::get_or_create_signing_keys`.
+pub fn get_or_create_signing_keys(
+    ctx: &mut RpcContext,
+    req: &LongTermKeyRequest,
+) -> Result<SigningKeyPair> {
+    authorize_private_key_generation(ctx, &req.runtime_id)?;
+    validate_height_freshness(ctx, req.height)?;
+
+    enforce_runtime_rules(
+        ctx,
+        &req.runtime_id,
+        METHOD_GET_OR_CREATE_SIGNING_KEYS,
+        || {
+            let keys = Kdf::global().get_or_create_signing_keys(
+                req.runtime_id,
+                req.key_pair_id,
+                req.key_type,
+                req.generation,
+                None,
+            )?;
+            AuditLog::global().append(
+                ctx,
+                AuditRecord::new(METHOD_GET_OR_CREATE_SIGNING_KEYS, req.runtime_id)
+                    .with_key_pair_id(req.key_pair_id)
+                    .with_generation(req.generation),
+            )?;
+            Ok(keys)
+        },
+    )
+}
+
+/// See `Kdf::get_public_signing_key`.
+pub fn get_public_signing_key(
+    _ctx: &mut RpcContext,
+    req: &LongTermKeyRequest,
+) -> Result<SignedPublicSigningKey> {
+    // No authentication or authorization.
+    // Absolutely anyone is allowed to query public long-term signing keys.
+
+    let kdf = Kdf::global();
+    let pk = kdf.get_public_signing_key(
+        req.runtime_id,
+        req.key_pair_id,
+        req.key_type,
+        req.generation,
+        None,
+    )?;
+    let sig = kdf.sign_public_signing_key(
+        req.key_type,
+        pk,
+        req.runtime_id,
+        req.key_pair_id,
+        req.generation,
+        None,
+    )?;
+    Ok(sig)
+}
+
+/// See `Kdf::get_or_create_signing_keys`.
+pub fn get_or_create_ephemeral_signing_keys(
+    ctx: &mut RpcContext,
+    req: &EphemeralKeyRequest,
+) -> Result<SigningKeyPair> {
+    authorize_private_key_generation(ctx, &req.runtime_id)?;
+    validate_ephemeral_key_epoch(ctx, req.epoch)?;
+    validate_height_freshness(ctx, req.height)?;
+
+    enforce_runtime_rules(
+        ctx,
+        &req.runtime_id,
+        METHOD_GET_OR_CREATE_EPHEMERAL_SIGNING_KEYS,
+        || {
+            let keys = Kdf::global().get_or_create_signing_keys(
+                req.runtime_id,
+                req.key_pair_id,
+                req.key_type,
+                0,
+                Some(req.epoch),
+            )?;
+            AuditLog::global().append(
+                ctx,
+                AuditRecord::new(METHOD_GET_OR_CREATE_EPHEMERAL_SIGNING_KEYS, req.runtime_id)
+                    .with_key_pair_id(req.key_pair_id)
+                    .with_epoch(req.epoch),
+            )?;
+            Ok(keys)
+        },
+    )
+}
+
+/// See `Kdf::get_public_signing_key`.
+pub fn get_public_ephemeral_signing_key(
+    ctx: &mut RpcContext,
+    req: &EphemeralKeyRequest,
+) -> Result<SignedPublicSigningKey> {
+    // No authentication or authorization.
+    // Absolutely anyone is allowed to query public ephemeral signing keys.
+    validate_ephemeral_key_epoch(ctx, req.epoch)?;
+
+    let kdf = Kdf::global();
+    let pk = kdf.get_public_signing_key(
+        req.runtime_id,
+        req.key_pair_id,
+        req.key_type,
+        0,
+        Some(req.epoch),
+    )?;
+    let sig = kdf.sign_public_signing_key(
+        req.key_type,
+        pk,
+        req.runtime_id,
+        req.key_pair_id,
+        0,
+        Some(req.epoch),
+    )?;
+    Ok(sig)
+}
+
 /// See `Kdf::replicate_master_secret`.
 pub fn replicate_master_secret(
     ctx: &mut RpcContext,
     req: &ReplicateMasterSecretRequest,
 ) -> Result<ReplicateMasterSecretResponse> {
-    authorize_secret_replication(ctx)?;
     validate_height_freshness(ctx, req.height)?;
 
-    let master_secret = Kdf::global().replicate_master_secret()?;
+    let master_secret = Kdf::global().replicate_master_secret(req.generation)?;
+    let runtime_id = runtime_context!(ctx, KmContext).runtime_id;
+    AuditLog::global().append(
+        ctx,
+        AuditRecord::new(METHOD_REPLICATE_MASTER_SECRET, runtime_id)
+            .with_generation(req.generation),
+    )?;
     Ok(ReplicateMasterSecretResponse { master_secret })
 }
 
+/// See `Kdf::replicate_master_secrets`.
+pub fn replicate_master_secrets(
+    ctx: &mut RpcContext,
+    req: &ReplicateMasterSecretsRequest,
+) -> Result<Chunks<ReplicateMasterSecretResponse>> {
+    validate_height_freshness(ctx, req.height)?;
+
+    let master_secrets = Kdf::global().replicate_master_secrets(req.from_generation)?;
+    let runtime_id = runtime_context!(ctx, KmContext).runtime_id;
+    AuditLog::global().append(
+        ctx,
+        AuditRecord::new(METHOD_REPLICATE_MASTER_SECRETS, runtime_id)
+            .with_generation(req.from_generation),
+    )?;
+    Ok(Box::new(master_secrets.map(|master_secret| {
+        master_secret.map(|master_secret| ReplicateMasterSecretResponse { master_secret })
+    })))
+}
+
 /// See `Kdf::replicate_ephemeral_secret`.
 pub fn replicate_ephemeral_secret(
     ctx: &mut RpcContext,
     req: &ReplicateEphemeralSecretRequest,
 ) -> Result<ReplicateEphemeralSecretResponse> {
-    authorize_secret_replication(ctx)?;
     validate_height_freshness(ctx, req.height)?;
 
     let ephemeral_secret = Kdf::global().replicate_ephemeral_secret(req.epoch)?;
+    let runtime_id = runtime_context!(ctx, KmContext).runtime_id;
+    AuditLog::global().append(
+        ctx,
+        AuditRecord::new(METHOD_REPLICATE_EPHEMERAL_SECRET, runtime_id).with_epoch(req.epoch),
+    )?;
     Ok(ReplicateEphemeralSecretResponse { ephemeral_secret })
 }
 
+/// See `Contributions::commit`.
+pub fn commit_master_secret_contribution(
+    ctx: &mut RpcContext,
+    req: &CommitMasterSecretContributionRequest,
+) -> Result<ContributionCommitment> {
+    let commitment = Contributions::global().commit(req.round);
+    Ok(ContributionCommitment {
+        rak: ctx.identity.public_rak(),
+        commitment,
+    })
+}
+
+/// See `Contributions::reveal`.
+pub fn reveal_master_secret_contribution(
+    ctx: &mut RpcContext,
+    req: &RevealMasterSecretContributionRequest,
+) -> Result<RevealMasterSecretContributionResponse> {
+    let contribution = Contributions::global().reveal(
+        req.round,
+        ctx.identity.public_rak(),
+        req.commitments.clone(),
+    )?;
+    Ok(RevealMasterSecretContributionResponse { contribution })
+}
+
+/// See `Contributions::confirm`.
+pub fn confirm_master_secret(
+    ctx: &mut RpcContext,
+    req: &ConfirmMasterSecretRequest,
+) -> Result<SignatureBundle> {
+    let runtime_id = runtime_context!(ctx, KmContext).runtime_id;
+    let transcript =
+        Contributions::global().confirm(req.round, runtime_id, req.contributions.clone())?;
+    transcript.sign(ctx)
+}
+
+/// See `AuditLog::export`.
+pub fn export_audit_log(
+    ctx: &mut RpcContext,
+    req: &ExportAuditLogRequest,
+) -> Result<SignedAuditLogExport> {
+    let runtime_id = runtime_context!(ctx, KmContext).runtime_id;
+    AuditLog::global().export(ctx, runtime_id, req.from_index)
+}
+
 /// Generate an ephemeral secret and encrypt it with key manager REK keys.
 pub fn generate_ephemeral_secret(
     ctx: &mut RpcContext,
@@ -291,29 +658,35 @@ fn fetch_ephemeral_secret(
 }
 
 /// Authorize the remote enclave so that the private keys are never released to an incorrect enclave.
+///
+/// The access policy of the calling method has already authenticated the remote enclave, this
+/// only checks that it may query the keys of the given runtime.
 fn authorize_private_key_generation(ctx: &RpcContext, runtime_id: &Namespace) -> Result<()> {
     if Policy::unsafe_skip() {
         return Ok(()); // Authorize unsafe builds always.
     }
-    let remote_enclave = authenticate(ctx)?;
-    Policy::global().may_get_or_create_keys(remote_enclave, runtime_id)
+    let si = ctx.session_info.as_ref();
+    let si = si.ok_or(KeyManagerError::NotAuthenticated)?;
+    Policy::global().may_get_or_create_keys(&si.verified_quote.identity, runtime_id)
 }
 
-/// Authorize the remote enclave so that the master and ephemeral secrets are never replicated
-/// to an incorrect enclave.
-fn authorize_secret_replication(ctx: &RpcContext) -> Result<()> {
+/// Enforce the key usage rules which the policy sets for the given runtime on the handling
+/// of private key requests, charging the runtime's quota only if the handler succeeds,
+/// i.e. once the keys have been derived and the call has been audited.
+fn enforce_runtime_rules<T, F>(
+    ctx: &RpcContext,
+    runtime_id: &Namespace,
+    method: &str,
+    handler: F,
+) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
     if Policy::unsafe_skip() {
-        return Ok(()); // Authorize unsafe builds always.
+        return handler(); // Unsafe builds have no rules.
     }
-    let remote_enclave = authenticate(ctx)?;
-    Policy::global().may_replicate_secret(remote_enclave)
-}
-
-/// Authenticate the remote enclave based on the MRSIGNER/MRENCLAVE/request.
-fn authenticate<'a>(ctx: &'a RpcContext) -> Result<&'a EnclaveIdentity> {
-    let si = ctx.session_info.as_ref();
-    let si = si.ok_or(KeyManagerError::NotAuthenticated)?;
-    Ok(&si.verified_quote.identity)
+    let epoch = consensus_epoch(ctx)?;
+    Policy::global().call_key_method(runtime_id, method, epoch, handler)
 }
 
 /// Fetch current epoch from the consensus layer.
@@ -335,6 +708,14 @@ fn validate_ephemeral_key_epoch(ctx: &RpcContext, epoch: EpochTime) -> Result<()
     Ok(())
 }
 
+/// Validate that the requested key type can be used for encryption.
+fn validate_encryption_key_type(key_type: KeyType) -> Result<()> {
+    match key_type {
+        KeyType::X25519 => Ok(()),
+        _ => Err(KeyManagerError::UnsupportedKeyType.into()),
+    }
+}
+
 /// Validate that given height is fresh, i.e. the height is not more than
 /// predefined number of blocks lower than the height of the latest trust root.
 ///
@@ -6,10 +6,15 @@ use std::{
     sync::{Arc, Mutex, MutexGuard},
 };
 
+use anyhow::Result;
+
 use crate::{
     common::crypto::hash::Hash,
     protocol::Protocol,
-    storage::mkvs::{sync::HostReadSyncer, Root, Tree},
+    storage::mkvs::{
+        sync::{HostReadSyncer, SyncOperation},
+        Root, Tree, TreeFork,
+    },
     types::HostStorageEndpoint,
 };
 
@@ -35,8 +40,8 @@ impl CacheSet {
     /// Create a new empty cache set.
     pub fn new(protocol: Arc<Protocol>) -> Self {
         Self {
-            execute: Arc::new(Mutex::new(Cache::new(&protocol))),
-            check: Arc::new(Mutex::new(Cache::new(&protocol))),
+            execute: Arc::new(Mutex::new(Cache::new(&protocol, SyncOperation::Execute))),
+            check: Arc::new(Mutex::new(Cache::new(&protocol, SyncOperation::Check))),
             protocol,
         }
     }
@@ -63,7 +68,10 @@ impl CacheSet {
                 return cache.clone();
             }
 
-            let cache = Rc::new(RefCell::new(Cache::new(&self.protocol)));
+            let cache = Rc::new(RefCell::new(Cache::new(
+                &self.protocol,
+                SyncOperation::Query,
+            )));
             caches.put(root.version, cache.clone());
             cache
         });
@@ -74,27 +82,34 @@ impl CacheSet {
 
 /// Cached storage tree with an associated root.
 pub struct Cache {
+    protocol: Arc<Protocol>,
+    operation: SyncOperation,
     root: Root,
     tree: Tree,
 }
 
 impl Cache {
-    fn new(protocol: &Arc<Protocol>) -> Self {
+    fn new(protocol: &Arc<Protocol>, operation: SyncOperation) -> Self {
         Self {
+            protocol: protocol.clone(),
+            operation,
             root: Default::default(),
-            tree: Self::build(protocol, Default::default()),
+            tree: Self::build(protocol, operation, Default::default()),
         }
     }
 
-    fn build(protocol: &Arc<Protocol>, root: Root) -> Tree {
+    fn build(protocol: &Arc<Protocol>, operation: SyncOperation, root: Root) -> Tree {
         let config = protocol.get_config();
-        let read_syncer = HostReadSyncer::new(protocol.clone(), HostStorageEndpoint::Runtime);
+        let read_syncer = HostReadSyncer::new(protocol.clone(), HostStorageEndpoint::Runtime)
+            .with_operation(operation);
         Tree::builder()
             .with_capacity(
                 config.storage.cache_node_capacity,
                 config.storage.cache_value_capacity,
             )
+            .with_eviction_policy(config.storage.cache_eviction_policy)
             .with_root(root)
+            .with_cache_metrics(operation)
             .build(Box::new(read_syncer))
     }
 
@@ -103,7 +118,7 @@ impl Cache {
             return;
         }
 
-        self.tree = Self::build(protocol, root);
+        self.tree = Self::build(protocol, self.operation, root);
         self.root = root;
     }
 
@@ -117,6 +132,19 @@ impl Cache {
         &mut self.tree
     }
 
+    /// Create a copy-on-write fork of the cached tree at the last committed root.
+    ///
+    /// The fork shares all unchanged nodes with the cached tree, so it can be used for
+    /// speculative execution without rebuilding the tree. It can either be dropped to discard
+    /// any changes or merged back using `TreeFork::merge`. In case the fork has been committed,
+    /// `commit` must be called after merging to record the new version and root as for any
+    /// other change to the cached tree.
+    pub fn fork(&self) -> Result<TreeFork<'_>> {
+        let read_syncer = HostReadSyncer::new(self.protocol.clone(), HostStorageEndpoint::Runtime)
+            .with_operation(self.operation);
+        self.tree.fork(Box::new(read_syncer))
+    }
+
     /// Commits a specific version and root as being stored by the tree.
     pub fn commit(&mut self, version: u64, hash: Hash) {
         self.root.version = version;
@@ -1,4 +1,5 @@
 //! Wrappers for sealing secrets to the enclave in cold storage.
+use anyhow::{anyhow, Result};
 use rand::{rngs::OsRng, Rng};
 use sgx_isa::Keypolicy;
 use zeroize::Zeroize;
@@ -33,14 +34,24 @@ pub fn seal(key_policy: Keypolicy, context: &[u8], data: &[u8]) -> Vec<u8> {
 /// All parsing and authentication errors of the ciphertext are fatal and
 /// will result in a panic.
 pub fn unseal(key_policy: Keypolicy, context: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    try_unseal(key_policy, context, ciphertext).unwrap()
+}
+
+/// Unseal a previously sealed secret to the enclave, failing if the ciphertext is corrupted.
+///
+/// The `context` field is a domain separation tag.
+pub fn try_unseal(
+    key_policy: Keypolicy,
+    context: &[u8],
+    ciphertext: &[u8],
+) -> Result<Option<Vec<u8>>> {
     let ct_len = ciphertext.len();
     if ct_len == 0 {
-        return None;
+        return Ok(None);
+    }
+    if ct_len < TAG_SIZE + NONCE_SIZE {
+        return Err(anyhow!("ciphertext is corrupted, invalid size"));
     }
-    assert!(
-        ct_len >= TAG_SIZE + NONCE_SIZE,
-        "ciphertext is corrupted, invalid size"
-    );
     let ct_len = ct_len - NONCE_SIZE;
 
     // Split the ciphertext || tag || nonce.
@@ -51,9 +62,9 @@ pub fn unseal(key_policy: Keypolicy, context: &[u8], ciphertext: &[u8]) -> Optio
     let d2 = new_d2(key_policy, context);
     let plaintext = d2
         .open(&nonce, ciphertext.to_vec(), vec![])
-        .expect("ciphertext is corrupted");
+        .map_err(|_| anyhow!("ciphertext is corrupted"))?;
 
-    Some(plaintext)
+    Ok(Some(plaintext))
 }
 
 fn new_d2(key_policy: Keypolicy, context: &[u8]) -> DeoxysII {
@@ -106,4 +117,25 @@ mod tests {
         sealed_b[0] = sealed_b[0].wrapping_add(1);
         unseal(Keypolicy::MRENCLAVE, b"MRENCLAVE", &sealed_b);
     }
+
+    #[test]
+    fn test_try_unseal() {
+        let sealed = seal(Keypolicy::MRENCLAVE, b"MRENCLAVE", b"Mr. Enclave");
+        let unsealed = try_unseal(Keypolicy::MRENCLAVE, b"MRENCLAVE", &sealed).unwrap();
+        assert_eq!(unsealed, Some(b"Mr. Enclave".to_vec()));
+        assert_eq!(
+            try_unseal(Keypolicy::MRENCLAVE, b"MRENCLAVE", b"").unwrap(),
+            None
+        );
+
+        // Corrupted ciphertexts should be rejected without panicking.
+        try_unseal(Keypolicy::MRENCLAVE, b"MRENCLAVE", &sealed[..2])
+            .expect_err("truncated ciphertext should be rejected");
+        let mut corrupted = sealed.clone();
+        corrupted[0] = corrupted[0].wrapping_add(1);
+        try_unseal(Keypolicy::MRENCLAVE, b"MRENCLAVE", &corrupted)
+            .expect_err("altered ciphertext should be rejected");
+        try_unseal(Keypolicy::MRENCLAVE, b"MRENCLAVE2", &sealed)
+            .expect_err("ciphertext with incorrect context should be rejected");
+    }
 }
@@ -1,5 +1,8 @@
 //! Runtime configuration.
-use crate::{common::version::Version, consensus::verifier::TrustRoot, types::Features};
+use crate::{
+    common::version::Version, consensus::verifier::TrustRoot, storage::mkvs::EvictionPolicy,
+    types::Features,
+};
 
 /// Global runtime configuration.
 #[derive(Clone, Debug, Default)]
@@ -28,6 +31,8 @@ pub struct Storage {
     /// The total size, in bytes, of values held by the cache before eviction.
     /// A zero value denotes unlimited capacity.
     pub cache_value_capacity: usize,
+    /// The policy used to select nodes to evict from the cache.
+    pub cache_eviction_policy: EvictionPolicy,
 }
 
 impl Default for Storage {
@@ -35,6 +40,7 @@ impl Default for Storage {
         Self {
             cache_node_capacity: 100_000,
             cache_value_capacity: 32 * 1024 * 1024, // 32 MiB
+            cache_eviction_policy: EvictionPolicy::LRU,
         }
     }
 }
@@ -36,6 +36,9 @@ pub struct PolicySGX {
     pub enclaves: HashMap<EnclaveIdentity, EnclavePolicySGX>,
     #[cbor(optional)]
     pub max_ephemeral_secret_age: EpochTime,
+    /// Minimum number of epochs between master secret rotations. Zero disables rotations.
+    #[cbor(optional)]
+    pub master_secret_rotation_interval: EpochTime,
 }
 
 /// Per enclave key manager access control policy.
@@ -43,6 +46,20 @@ pub struct PolicySGX {
 pub struct EnclavePolicySGX {
     pub may_query: HashMap<Namespace, Vec<EnclaveIdentity>>,
     pub may_replicate: Vec<EnclaveIdentity>,
+    #[cbor(optional)]
+    pub runtime_rules: HashMap<Namespace, RuntimeRulesSGX>,
+}
+
+/// Per runtime key manager key usage rules.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct RuntimeRulesSGX {
+    /// Private key methods the runtime's enclaves may call. All methods are allowed if empty.
+    #[cbor(optional)]
+    pub allowed_methods: Vec<String>,
+    /// Maximum number of calls per epoch of the given private key methods, enforced by each
+    /// key manager node separately.
+    #[cbor(optional)]
+    pub quotas: HashMap<String, u64>,
 }
 
 /// Signed key manager access control policy.
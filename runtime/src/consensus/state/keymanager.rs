@@ -43,6 +43,12 @@ pub struct Status {
     pub is_secure: bool,
     /// Key manager master secret verification checksum.
     pub checksum: Vec<u8>,
+    /// Latest master secret generation, which the checksum belongs to.
+    #[cbor(optional)]
+    pub generation: u64,
+    /// Epoch in which the latest master secret generation was accepted.
+    #[cbor(optional)]
+    pub rotation_epoch: EpochTime,
     /// List of currently active key manager node IDs.
     pub nodes: Vec<PublicKey>,
     /// Key manager policy.
@@ -200,6 +206,8 @@ mod test {
                 is_initialized: false,
                 is_secure: false,
                 checksum: vec![],
+                generation: 0,
+                rotation_epoch: 0,
                 nodes: vec![],
                 policy: None,
                 rsk: None,
@@ -209,6 +217,8 @@ mod test {
                 is_initialized: true,
                 is_secure: true,
                 checksum: checksum,
+                generation: 0,
+                rotation_epoch: 0,
                 nodes: vec![signer1, signer2],
                 policy: Some(SignedPolicySGX {
                     policy: PolicySGX {
@@ -219,9 +229,11 @@ mod test {
                             EnclavePolicySGX {
                                 may_query: HashMap::from([(runtime, vec![runtime_enclave])]),
                                 may_replicate: vec![keymanager_enclave2],
+                                runtime_rules: HashMap::new(),
                             },
                         )]),
                         max_ephemeral_secret_age: 10,
+                        master_secret_rotation_interval: 0,
                     },
                     signatures: vec![
                         SignatureBundle {
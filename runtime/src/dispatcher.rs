@@ -30,11 +30,12 @@ use crate::{
     },
     enclave_rpc::{
         demux::Demux as RpcDemux,
-        dispatcher::Dispatcher as RpcDispatcher,
+        dispatcher::{Dispatcher as RpcDispatcher, ResponseStream as RpcResponseStream},
         session::SessionInfo,
         types::{
-            Kind, Kind as RpcKind, Message as RpcMessage, Request as RpcRequest,
-            Response as RpcResponse,
+            Body as RpcBody, Kind, Kind as RpcKind, LegacyMessage as RpcLegacyMessage,
+            LegacyResponse as RpcLegacyResponse, Message as RpcMessage, Request as RpcRequest,
+            Response as RpcResponse, SessionID,
         },
         Context as RpcContext,
     },
@@ -105,6 +106,39 @@ impl Drop for AbortOnPanic {
     }
 }
 
+/// Request tracked by the RPC demultiplexer, which is released in case no response is written.
+///
+/// This makes sure that requests failing before a response could be written do not count
+/// towards the in-flight limit of their session forever.
+struct InFlightRpcRequest<'a> {
+    demux: &'a Mutex<RpcDemux>,
+    session_id: SessionID,
+    request_id: u64,
+    responded: bool,
+}
+
+impl<'a> InFlightRpcRequest<'a> {
+    fn new(demux: &'a Mutex<RpcDemux>, session_id: SessionID, request_id: u64) -> Self {
+        Self {
+            demux,
+            session_id,
+            request_id,
+            responded: false,
+        }
+    }
+}
+
+impl<'a> Drop for InFlightRpcRequest<'a> {
+    fn drop(&mut self) {
+        if !self.responded {
+            self.demux
+                .lock()
+                .unwrap()
+                .release_request(self.session_id, self.request_id);
+        }
+    }
+}
+
 impl From<tokio::task::JoinError> for Error {
     fn from(e: tokio::task::JoinError) -> Self {
         Error::new(
@@ -174,6 +208,8 @@ impl Dispatcher {
         tokio::runtime::Builder::new_current_thread()
             .max_blocking_threads(2) // Limited in SGX.
             .thread_keep_alive(std::time::Duration::from_secs(120))
+            // Timers are driven by the host-controlled clock, so timeouts are only advisory.
+            .enable_time()
             .build()
             .unwrap()
     }
@@ -848,6 +884,8 @@ impl Dispatcher {
 
             match message {
                 RpcMessage::Request(req) => {
+                    let request = InFlightRpcRequest::new(&state.rpc_demux, session_id, req.id);
+
                     // First make sure that the untrusted_plaintext matches
                     // the request's method!
                     if untrusted_plaintext != req.method {
@@ -862,11 +900,22 @@ impl Dispatcher {
                         ));
                     }
 
-                    // Request, dispatch.
-                    let response = self
-                        .dispatch_rpc(ctx, req, RpcKind::NoiseSession, session_info, &state)
+                    // Request, dispatch. Responses are only streamed to peers which support it.
+                    let stream = state
+                        .rpc_demux
+                        .lock()
+                        .unwrap()
+                        .supports_pipelining(session_id);
+                    let (response, stream) = self
+                        .dispatch_rpc(
+                            ctx,
+                            req,
+                            RpcKind::NoiseSession,
+                            session_info,
+                            stream,
+                            &state,
+                        )
                         .await?;
-                    let response = RpcMessage::Response(response);
 
                     // Note: MKVS commit is omitted, this MUST be global side-effect free.
 
@@ -874,17 +923,26 @@ impl Dispatcher {
                         "kind" => ?Kind::NoiseSession,
                     );
 
-                    let mut buffer = vec![];
-                    state
+                    self.write_rpc_response(request, response, stream)
+                }
+                RpcMessage::NextChunk(request_id) => {
+                    // Next chunk of a streamed response.
+                    let request = InFlightRpcRequest::new(&state.rpc_demux, session_id, request_id);
+                    let mut stream = state
                         .rpc_demux
                         .lock()
                         .unwrap()
-                        .write_message(session_id, response, &mut buffer)
+                        .take_stream(session_id, request_id)
                         .map_err(|err| {
-                            error!(self.logger, "Error while writing response"; "err" => %err);
+                            error!(self.logger, "Error while resuming stream"; "err" => %err);
                             Error::new("rhp/dispatcher", 1, &format!("{err}"))
-                        })
-                        .map(|_| Body::RuntimeRPCCallResponse { response: buffer })
+                        })?;
+                    let (response, stream) = tokio::task::spawn_blocking(move || {
+                        (stream.next_response(request_id), stream)
+                    })
+                    .await?;
+
+                    self.write_rpc_response(request, response, Some(stream))
                 }
                 RpcMessage::Close => {
                     // Session close.
@@ -911,6 +969,41 @@ impl Dispatcher {
         }
     }
 
+    fn write_rpc_response(
+        &self,
+        mut request: InFlightRpcRequest<'_>,
+        mut response: RpcResponse,
+        stream: Option<RpcResponseStream>,
+    ) -> Result<Body, Error> {
+        let mut demux = request.demux.lock().unwrap();
+
+        // Keep any remaining chunks of a streamed response until they are requested.
+        if let Some(mut stream) = stream {
+            if !stream.is_done() {
+                if let Err(err) = demux.add_stream(request.session_id, response.id, stream) {
+                    // Fail the call instead of leaving the peer waiting for the remaining chunks.
+                    warn!(self.logger, "Error while registering stream"; "err" => %err);
+                    response.body = RpcBody::Error(err.into());
+                }
+            }
+        }
+
+        // The request is no longer in flight once the response is written, even if writing fails.
+        request.responded = true;
+        let mut buffer = vec![];
+        demux
+            .write_message(
+                request.session_id,
+                RpcMessage::Response(response),
+                &mut buffer,
+            )
+            .map_err(|err| {
+                error!(self.logger, "Error while writing response"; "err" => %err);
+                Error::new("rhp/dispatcher", 1, &format!("{err}"))
+            })
+            .map(|_| Body::RuntimeRPCCallResponse { response: buffer })
+    }
+
     async fn dispatch_insecure_rpc(
         &self,
         ctx: Context,
@@ -925,10 +1018,11 @@ impl Dispatcher {
             .map_err(|_| Error::new("rhp/dispatcher", 1, "malformed request"))?;
 
         // Request, dispatch.
-        let response = self
-            .dispatch_rpc(ctx, request, RpcKind::InsecureQuery, None, &state)
+        let (response, _) = self
+            .dispatch_rpc(ctx, request, RpcKind::InsecureQuery, None, false, &state)
             .await?;
-        let response = cbor::to_vec(response);
+        // Insecure queries don't negotiate support for coded errors.
+        let response = cbor::to_vec(RpcLegacyResponse::from(response));
 
         // Note: MKVS commit is omitted, this MUST be global side-effect free.
 
@@ -953,10 +1047,11 @@ impl Dispatcher {
             .map_err(|_| Error::new("rhp/dispatcher", 1, "malformed request"))?;
 
         // Request, dispatch.
-        let response = self
-            .dispatch_rpc(ctx, request, RpcKind::LocalQuery, None, &state)
+        let (response, _) = self
+            .dispatch_rpc(ctx, request, RpcKind::LocalQuery, None, false, &state)
             .await?;
-        let response = RpcMessage::Response(response);
+        // Local queries don't negotiate support for coded errors.
+        let response = RpcLegacyMessage::Response(response.into());
         let response = cbor::to_vec(response);
 
         debug!(self.logger, "RPC call dispatch complete";
@@ -972,8 +1067,9 @@ impl Dispatcher {
         request: RpcRequest,
         kind: RpcKind,
         session_info: Option<Arc<SessionInfo>>,
+        stream: bool,
         state: &State,
-    ) -> Result<RpcResponse, Error> {
+    ) -> Result<(RpcResponse, Option<RpcResponseStream>), Error> {
         let ctx = ctx.freeze();
         let identity = self.identity.clone();
         let protocol = state.protocol.clone();
@@ -993,7 +1089,12 @@ impl Dispatcher {
                 &untrusted_local,
             );
 
-            rpc_dispatcher.dispatch(rpc_ctx, request, kind)
+            // Only Noise sessions can hold streamed responses.
+            if stream {
+                rpc_dispatcher.dispatch_stream(rpc_ctx, request, kind)
+            } else {
+                (rpc_dispatcher.dispatch(rpc_ctx, request, kind), None)
+            }
         })
         .await?;
 
@@ -0,0 +1,340 @@
+//! Access policies of RPC methods.
+use std::{collections::HashSet, fmt};
+
+use io_context::Context as IoContext;
+use thiserror::Error;
+
+use crate::{
+    common::{crypto::signature::PublicKey, namespace::Namespace, sgx::EnclaveIdentity},
+    consensus::{
+        registry::{Node, RolesMask},
+        state::registry::ImmutableState as RegistryState,
+    },
+};
+
+use super::context::Context;
+
+/// Access policy error.
+#[derive(Error, Debug)]
+pub enum AccessError {
+    #[error("caller not authenticated")]
+    NotAuthenticated,
+    #[error("enclave identity not allowed")]
+    EnclaveNotAllowed,
+    #[error("runtime not allowed")]
+    RuntimeNotAllowed,
+    #[error("node roles not allowed")]
+    RolesNotAllowed,
+    #[error("registry unavailable: {0}")]
+    RegistryUnavailable(#[source] anyhow::Error),
+}
+
+/// Function deciding whether the given enclave identity is allowed to call a RPC method.
+pub type EnclaveAuthorizer = fn(&EnclaveIdentity) -> bool;
+
+/// Rules restricting which callers may invoke a RPC method.
+///
+/// Rules which are not set do not restrict access. As soon as any rule is set, the caller must
+/// be an enclave authenticated over a Noise session and must satisfy all of the rules.
+#[derive(Clone, Default)]
+pub struct AccessPolicy {
+    /// Enclave identities allowed to call the method.
+    pub enclaves: Option<HashSet<EnclaveIdentity>>,
+    /// Function deciding which enclave identities are allowed to call the method, for rules
+    /// which may change at runtime.
+    pub authorizer: Option<EnclaveAuthorizer>,
+    /// Runtimes allowed to call the method.
+    ///
+    /// The caller must use the RAK of a node registered for one of the runtimes.
+    pub runtime_ids: Option<HashSet<Namespace>>,
+    /// Node roles allowed to call the method.
+    ///
+    /// The caller must use the RAK of a node registered with at least one of the roles.
+    pub roles: Option<RolesMask>,
+}
+
+impl AccessPolicy {
+    /// Restrict access to the given enclave identities.
+    pub fn enclaves(mut self, enclaves: HashSet<EnclaveIdentity>) -> Self {
+        self.enclaves = Some(enclaves);
+        self
+    }
+
+    /// Restrict access to enclave identities accepted by the given function.
+    pub fn authorizer(mut self, authorizer: EnclaveAuthorizer) -> Self {
+        self.authorizer = Some(authorizer);
+        self
+    }
+
+    /// Restrict access to enclaves of the given runtimes.
+    pub fn runtime_ids(mut self, runtime_ids: HashSet<Namespace>) -> Self {
+        self.runtime_ids = Some(runtime_ids);
+        self
+    }
+
+    /// Restrict access to enclaves of nodes with at least one of the given roles.
+    pub fn roles(mut self, roles: RolesMask) -> Self {
+        self.roles = Some(roles);
+        self
+    }
+
+    /// Whether the policy allows access to everyone.
+    pub fn is_empty(&self) -> bool {
+        self.enclaves.is_none()
+            && self.authorizer.is_none()
+            && self.runtime_ids.is_none()
+            && self.roles.is_none()
+    }
+
+    /// Check whether the caller is allowed access.
+    pub fn check(&self, ctx: &Context) -> Result<(), AccessError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let si = ctx
+            .session_info
+            .as_ref()
+            .ok_or(AccessError::NotAuthenticated)?;
+
+        self.check_caller(
+            &si.verified_quote.identity,
+            si.rak_binding.rak_pub(),
+            || Self::registered_nodes(ctx),
+        )
+    }
+
+    /// Check whether the enclave with the given identity and RAK is allowed access.
+    ///
+    /// The registered nodes are only fetched if the policy has rules about them.
+    fn check_caller<F>(
+        &self,
+        identity: &EnclaveIdentity,
+        rak: PublicKey,
+        registered_nodes: F,
+    ) -> Result<(), AccessError>
+    where
+        F: FnOnce() -> Result<Vec<Node>, AccessError>,
+    {
+        if let Some(ref enclaves) = self.enclaves {
+            if !enclaves.contains(identity) {
+                return Err(AccessError::EnclaveNotAllowed);
+            }
+        }
+
+        if let Some(authorizer) = self.authorizer {
+            if !authorizer(identity) {
+                return Err(AccessError::EnclaveNotAllowed);
+            }
+        }
+
+        if self.runtime_ids.is_none() && self.roles.is_none() {
+            return Ok(());
+        }
+
+        let nodes = self.caller_nodes(registered_nodes()?, rak);
+        if self.runtime_ids.is_some() && nodes.is_empty() {
+            return Err(AccessError::RuntimeNotAllowed);
+        }
+
+        if let Some(ref roles) = self.roles {
+            if !nodes.iter().any(|node| node.roles.0 & roles.0 != 0) {
+                return Err(AccessError::RolesNotAllowed);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return all nodes registered in the latest consensus state.
+    fn registered_nodes(ctx: &Context) -> Result<Vec<Node>, AccessError> {
+        let consensus_state = ctx
+            .consensus_verifier
+            .latest_state()
+            .map_err(|err| AccessError::RegistryUnavailable(err.into()))?;
+        let registry_state = RegistryState::new(&consensus_state);
+        registry_state
+            .nodes(IoContext::create_child(&ctx.io_ctx))
+            .map_err(|err| AccessError::RegistryUnavailable(err.into()))
+    }
+
+    /// Return the given nodes using the given RAK for one of the allowed runtimes.
+    fn caller_nodes(&self, nodes: Vec<Node>, rak: PublicKey) -> Vec<Node> {
+        nodes
+            .into_iter()
+            .filter(|node| {
+                node.runtimes
+                    .as_ref()
+                    .map(|runtimes| {
+                        runtimes
+                            .iter()
+                            .filter(|rt| match self.runtime_ids {
+                                Some(ref ids) => ids.contains(&rt.id),
+                                None => true,
+                            })
+                            .flat_map(|rt| &rt.capabilities.tee)
+                            .any(|tee| tee.rak == rak)
+                    })
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for AccessPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessPolicy")
+            .field("enclaves", &self.enclaves)
+            .field("authorizer", &self.authorizer.is_some())
+            .field("runtime_ids", &self.runtime_ids)
+            .field("roles", &self.roles)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use crate::{
+        common::{
+            crypto::signature::PublicKey,
+            namespace::Namespace,
+            sgx::{EnclaveIdentity, MrEnclave, MrSigner},
+        },
+        consensus::registry::{Capabilities, CapabilityTEE, Node, NodeRuntime, RolesMask},
+    };
+
+    use super::{AccessError, AccessPolicy};
+
+    fn enclave(id: u8) -> EnclaveIdentity {
+        EnclaveIdentity {
+            mr_enclave: MrEnclave::from(vec![id; 32]),
+            mr_signer: MrSigner::from(vec![id; 32]),
+        }
+    }
+
+    fn node(runtime_id: Namespace, rak: PublicKey, roles: RolesMask) -> Node {
+        Node {
+            runtimes: Some(vec![NodeRuntime {
+                id: runtime_id,
+                capabilities: Capabilities {
+                    tee: Some(CapabilityTEE {
+                        rak,
+                        ..Default::default()
+                    }),
+                },
+                ..Default::default()
+            }]),
+            roles,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_access_policy_enclaves() {
+        let rak = PublicKey::from(vec![1u8; 32]);
+        let no_nodes =
+            || -> Result<Vec<Node>, AccessError> { panic!("nodes should not be needed") };
+
+        // An empty policy allows everyone.
+        let policy = AccessPolicy::default();
+        assert!(policy.is_empty());
+        policy
+            .check_caller(&enclave(1), rak, no_nodes)
+            .expect("empty policy should allow access");
+
+        let policy = AccessPolicy::default().enclaves(HashSet::from([enclave(1)]));
+        assert!(!policy.is_empty());
+        policy
+            .check_caller(&enclave(1), rak, no_nodes)
+            .expect("allowed enclave should have access");
+        assert!(matches!(
+            policy.check_caller(&enclave(2), rak, no_nodes),
+            Err(AccessError::EnclaveNotAllowed)
+        ));
+
+        let policy = AccessPolicy::default().authorizer(|identity| *identity == enclave(2));
+        policy
+            .check_caller(&enclave(2), rak, no_nodes)
+            .expect("authorized enclave should have access");
+        assert!(matches!(
+            policy.check_caller(&enclave(1), rak, no_nodes),
+            Err(AccessError::EnclaveNotAllowed)
+        ));
+    }
+
+    #[test]
+    fn test_access_policy_runtime_ids() {
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let other_runtime_id = Namespace::from(vec![2u8; 32]);
+        let rak = PublicKey::from(vec![1u8; 32]);
+        let other_rak = PublicKey::from(vec![2u8; 32]);
+        let nodes = || {
+            Ok(vec![
+                node(runtime_id, rak, RolesMask::ROLE_COMPUTE_WORKER),
+                node(other_runtime_id, other_rak, RolesMask::ROLE_COMPUTE_WORKER),
+            ])
+        };
+
+        let policy = AccessPolicy::default().runtime_ids(HashSet::from([runtime_id]));
+        policy
+            .check_caller(&enclave(1), rak, nodes)
+            .expect("node of an allowed runtime should have access");
+        assert!(matches!(
+            policy.check_caller(&enclave(1), other_rak, nodes),
+            Err(AccessError::RuntimeNotAllowed)
+        ));
+
+        // Unregistered RAKs should be denied.
+        assert!(matches!(
+            policy.check_caller(&enclave(1), PublicKey::from(vec![3u8; 32]), nodes),
+            Err(AccessError::RuntimeNotAllowed)
+        ));
+
+        // Registry failures should deny access.
+        assert!(matches!(
+            policy.check_caller(&enclave(1), rak, || Err(AccessError::RegistryUnavailable(
+                anyhow::anyhow!("unavailable")
+            ))),
+            Err(AccessError::RegistryUnavailable(_))
+        ));
+    }
+
+    #[test]
+    fn test_access_policy_roles() {
+        let runtime_id = Namespace::from(vec![1u8; 32]);
+        let other_runtime_id = Namespace::from(vec![2u8; 32]);
+        let km_rak = PublicKey::from(vec![1u8; 32]);
+        let compute_rak = PublicKey::from(vec![2u8; 32]);
+        let nodes = || {
+            Ok(vec![
+                node(runtime_id, km_rak, RolesMask::ROLE_KEY_MANAGER),
+                node(runtime_id, compute_rak, RolesMask::ROLE_COMPUTE_WORKER),
+            ])
+        };
+
+        let policy = AccessPolicy::default().roles(RolesMask::ROLE_KEY_MANAGER);
+        policy
+            .check_caller(&enclave(1), km_rak, nodes)
+            .expect("node with an allowed role should have access");
+        assert!(matches!(
+            policy.check_caller(&enclave(1), compute_rak, nodes),
+            Err(AccessError::RolesNotAllowed)
+        ));
+
+        // All rules should need to be satisfied.
+        let policy = AccessPolicy::default()
+            .enclaves(HashSet::from([enclave(1)]))
+            .runtime_ids(HashSet::from([other_runtime_id]))
+            .roles(RolesMask::ROLE_KEY_MANAGER);
+        assert!(matches!(
+            policy.check_caller(&enclave(1), km_rak, nodes),
+            Err(AccessError::RuntimeNotAllowed)
+        ));
+        assert!(matches!(
+            policy.check_caller(&enclave(2), km_rak, nodes),
+            Err(AccessError::EnclaveNotAllowed)
+        ));
+    }
+}
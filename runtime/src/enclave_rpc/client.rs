@@ -3,9 +3,10 @@ use std::{
     collections::HashSet,
     mem,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, Mutex,
     },
+    time::Duration,
 };
 
 use anyhow;
@@ -15,7 +16,11 @@ use futures::{
 };
 use io_context::Context;
 use thiserror::Error;
-use tokio;
+use tokio::{
+    self,
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
 
 use crate::{
     cbor,
@@ -25,7 +30,7 @@ use crate::{
         sgx::{EnclaveIdentity, QuotePolicy},
     },
     enclave_rpc::{
-        session::{Builder, Session},
+        session::{Builder, Session, VerificationCache},
         types,
     },
     protocol::Protocol,
@@ -35,22 +40,44 @@ use super::transport::{RuntimeTransport, Transport};
 
 /// Internal send queue backlog.
 const SENDQ_BACKLOG: usize = 10;
+/// Default maximum number of concurrent sessions.
+const DEFAULT_MAX_SESSIONS: usize = 1;
+/// Maximum number of calls in flight on a single session.
+const MAX_IN_FLIGHT_CALLS: usize = 16;
+/// Default maximum number of call retries.
+const DEFAULT_MAX_RETRIES: usize = 3;
+/// Default deadline of a call, including all of its retries.
+const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Default timeout of each step of a session handshake.
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default delay before the first retry of a call.
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// Default maximum delay between retries of a call.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+/// Default maximum size of a reassembled streamed response.
+const DEFAULT_MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
 
 /// RPC client error.
 #[derive(Error, Debug)]
 pub enum RpcClientError {
     #[error("call failed: {0}")]
-    CallFailed(String),
+    CallFailed(types::Error),
     #[error("expected response message, received: {0:?}")]
     ExpectedResponseMessage(types::Message),
     #[error("expected close message, received: {0:?}")]
     ExpectedCloseMessage(types::Message),
+    #[error("unexpected response to request {0}")]
+    UnexpectedResponse(u64),
     #[error("transport error")]
     Transport,
     #[error("unsupported RPC kind")]
     UnsupportedRpcKind,
     #[error("client dropped")]
     Dropped,
+    #[error("call timed out")]
+    Timeout,
+    #[error("response too large")]
+    ResponseTooLarge,
     #[error("decode error: {0}")]
     DecodeError(#[from] cbor::DecodeError),
     #[error("unknown error: {0}")]
@@ -61,10 +88,69 @@ type SendqRequest = (
     Arc<Context>,
     types::Request,
     types::Kind,
-    oneshot::Sender<Result<(u64, types::Response), RpcClientError>>,
+    oneshot::Sender<
+        Result<(types::SessionID, signature::PublicKey, types::Response), RpcClientError>,
+    >,
     usize,
+    Option<Instant>,
 );
 
+/// Timeouts and retry policy of RPC calls.
+///
+/// Inside an enclave the clock is provided by the untrusted host, so all timeouts are advisory:
+/// they keep calls from waiting forever on an unresponsive remote node, but the host can make
+/// them expire early or never. They must not be relied upon for anything security-relevant.
+#[derive(Clone, Debug)]
+pub struct CallConfig {
+    /// Deadline of a call, including all of its retries.
+    ///
+    /// Calls without a deadline may wait forever on an unresponsive remote node.
+    pub call_timeout: Option<Duration>,
+    /// Timeout of each step of a session handshake.
+    pub handshake_timeout: Option<Duration>,
+    /// Maximum number of call retries.
+    pub max_retries: usize,
+    /// Delay before the first retry of a call, doubled for every subsequent retry.
+    pub initial_backoff: Duration,
+    /// Maximum delay between retries of a call.
+    pub max_backoff: Duration,
+    /// Maximum total size of the chunks of a streamed response, in bytes.
+    pub max_response_size: usize,
+}
+
+impl CallConfig {
+    /// Delay before the given retry of a call.
+    fn backoff(&self, retry: usize) -> Duration {
+        let factor = 1u32.checked_shl(retry as u32).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for CallConfig {
+    fn default() -> Self {
+        Self {
+            call_timeout: Some(DEFAULT_CALL_TIMEOUT),
+            handshake_timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+        }
+    }
+}
+
+/// Time left until the given deadline, if any.
+fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Whether the given deadline, if any, has passed.
+fn is_expired(deadline: Option<Instant>) -> bool {
+    deadline.map_or(false, |deadline| Instant::now() >= deadline)
+}
+
 struct MultiplexedSession {
     /// Session builder for resetting sessions.
     builder: Builder,
@@ -72,6 +158,10 @@ struct MultiplexedSession {
     id: types::SessionID,
     /// Current underlying protocol session.
     inner: Session,
+    /// Remote node the session is pinned to, if any.
+    node: Option<signature::PublicKey>,
+    /// Identifier of the next request sent over the session.
+    next_request_id: u64,
 }
 
 impl MultiplexedSession {
@@ -80,22 +170,32 @@ impl MultiplexedSession {
             builder: builder.clone(),
             id: types::SessionID::random(),
             inner: builder.build_initiator(),
+            node: None,
+            next_request_id: 0,
         }
     }
 
     fn reset(&mut self) {
         self.id = types::SessionID::random();
         self.inner = self.builder.clone().build_initiator();
+        self.next_request_id = 0;
+    }
+
+    /// Reset the session and unpin it from its remote node so that the next connection can be
+    /// established with a different node.
+    fn failover(&mut self) {
+        self.node = None;
+        self.reset();
     }
 }
 
-struct Inner {
-    /// Allowed nodes.
-    nodes: Mutex<Vec<signature::PublicKey>>,
+/// A session from the client's session pool.
+///
+/// Calls using the same session go through its send queue, which serializes connection
+/// establishment, after which up to `MAX_IN_FLIGHT_CALLS` calls can be in flight at once.
+struct PooledSession {
     /// Multiplexed session.
     session: Mutex<MultiplexedSession>,
-    /// Used transport.
-    transport: Box<dyn Transport>,
     /// Internal send queue receiver, only available until the controller
     /// is spawned (is None later).
     recvq: Mutex<Option<mpsc::Receiver<SendqRequest>>>,
@@ -103,8 +203,136 @@ struct Inner {
     sendq: mpsc::Sender<SendqRequest>,
     /// Flag indicating whether the controller has been spawned.
     has_controller: AtomicBool,
-    /// Maximum number of call retries.
-    max_retries: usize,
+    /// Number of calls which are queued or in progress.
+    pending: AtomicUsize,
+    /// Number of consecutive calls which failed.
+    failures: AtomicUsize,
+    /// Permits for calls in flight.
+    in_flight: Arc<Semaphore>,
+}
+
+impl PooledSession {
+    fn new(builder: Builder) -> Self {
+        let (tx, rx) = mpsc::channel(SENDQ_BACKLOG);
+
+        Self {
+            session: Mutex::new(MultiplexedSession::new(builder)),
+            recvq: Mutex::new(Some(rx)),
+            sendq: tx,
+            has_controller: AtomicBool::new(false),
+            pending: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            in_flight: Arc::new(Semaphore::new(MAX_IN_FLIGHT_CALLS)),
+        }
+    }
+
+    /// Whether the last call using this session succeeded.
+    fn is_healthy(&self) -> bool {
+        self.failures.load(Ordering::SeqCst) == 0
+    }
+
+    /// Update session health based on feedback on the peer that handled a call made using the
+    /// session with the given identifier.
+    ///
+    /// Negative feedback unpins the session from its node so that subsequent calls fail over
+    /// to another node.
+    fn record_peer_feedback(
+        &self,
+        session_id: types::SessionID,
+        peer_feedback: types::PeerFeedback,
+    ) {
+        match peer_feedback {
+            types::PeerFeedback::Success => {
+                // Success of a call made using a replaced session says nothing about the health
+                // of the current one.
+                if self.session.lock().unwrap().id == session_id {
+                    self.failures.store(0, Ordering::SeqCst);
+                }
+            }
+            types::PeerFeedback::Failure | types::PeerFeedback::BadPeer => {
+                self.record_failure(session_id);
+            }
+        }
+    }
+
+    /// Record a failed call made using the session with the given identifier.
+    ///
+    /// Returns the node the session was pinned to, if it has been unpinned.
+    fn record_failure(&self, session_id: types::SessionID) -> Option<signature::PublicKey> {
+        // Other calls in flight may have already caused the session to be replaced.
+        let mut session = self.session.lock().unwrap();
+        if session.id != session_id {
+            return None;
+        }
+        self.failures.fetch_add(1, Ordering::SeqCst);
+        let node = session.node;
+        session.failover();
+        node
+    }
+}
+
+struct Inner {
+    /// Allowed nodes.
+    nodes: Mutex<Vec<signature::PublicKey>>,
+    /// Session pool.
+    sessions: Vec<PooledSession>,
+    /// Index used to rotate between equally suitable sessions.
+    next_session: AtomicUsize,
+    /// Used transport.
+    transport: Box<dyn Transport>,
+    /// Call timeouts and retry policy.
+    config: Mutex<CallConfig>,
+}
+
+impl Inner {
+    /// Select the session to use for the next call, preferring healthy sessions with the least
+    /// number of pending calls.
+    fn select_session(&self) -> usize {
+        let start = self.next_session.fetch_add(1, Ordering::SeqCst);
+        (0..self.sessions.len())
+            .map(|offset| (start + offset) % self.sessions.len())
+            .min_by_key(|&index| {
+                let session = &self.sessions[index];
+                (
+                    !session.is_healthy(),
+                    session.pending.load(Ordering::SeqCst),
+                )
+            })
+            .expect("session pool must not be empty")
+    }
+
+    /// Nodes the given session may connect to.
+    ///
+    /// A pinned session keeps using its node as long as the node is allowed. Otherwise nodes
+    /// which are not used by other sessions are preferred so that sessions are spread across
+    /// nodes.
+    fn session_nodes(&self, index: usize) -> Vec<signature::PublicKey> {
+        let nodes = self.nodes.lock().unwrap().clone();
+
+        let pinned = self.sessions[index].session.lock().unwrap().node;
+        if let Some(node) = pinned {
+            if nodes.is_empty() || nodes.contains(&node) {
+                return vec![node];
+            }
+        }
+
+        let used: HashSet<_> = self
+            .sessions
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .filter_map(|(_, session)| session.session.lock().unwrap().node)
+            .collect();
+        let unused: Vec<_> = nodes
+            .iter()
+            .filter(|node| !used.contains(*node))
+            .cloned()
+            .collect();
+        if unused.is_empty() {
+            return nodes;
+        }
+        unused
+    }
 }
 
 /// RPC client.
@@ -113,22 +341,40 @@ pub struct RpcClient {
 }
 
 impl RpcClient {
-    fn new(
+    /// Construct an unconnected RPC client with the given transport.
+    pub fn new(
         transport: Box<dyn Transport>,
         builder: Builder,
         nodes: Vec<signature::PublicKey>,
     ) -> Self {
-        let (tx, rx) = mpsc::channel(SENDQ_BACKLOG);
+        Self::new_with_sessions(transport, builder, nodes, DEFAULT_MAX_SESSIONS)
+    }
+
+    /// Construct an unconnected RPC client with the given transport which uses a pool of
+    /// up to `max_sessions` concurrent sessions.
+    pub fn new_with_sessions(
+        transport: Box<dyn Transport>,
+        builder: Builder,
+        nodes: Vec<signature::PublicKey>,
+        max_sessions: usize,
+    ) -> Self {
+        // Share verified quotes among all sessions, so that re-establishing a session with an
+        // already verified remote enclave does not require verifying its quote again.
+        let builder = match builder.get_verification_cache() {
+            Some(_) => builder,
+            None => builder.verification_cache(Some(VerificationCache::default())),
+        };
+        let sessions = (0..max_sessions.max(1))
+            .map(|_| PooledSession::new(builder.clone()))
+            .collect();
 
         Self {
             inner: Arc::new(Inner {
                 nodes: Mutex::new(nodes),
-                session: Mutex::new(MultiplexedSession::new(builder)),
+                sessions,
+                next_session: AtomicUsize::new(0),
                 transport,
-                recvq: Mutex::new(Some(rx)),
-                sendq: tx,
-                has_controller: AtomicBool::new(false),
-                max_retries: 3,
+                config: Mutex::new(CallConfig::default()),
             }),
         }
     }
@@ -140,13 +386,34 @@ impl RpcClient {
         endpoint: &str,
         nodes: Vec<signature::PublicKey>,
     ) -> Self {
-        Self::new(
+        Self::new_runtime_with_sessions(builder, protocol, endpoint, nodes, DEFAULT_MAX_SESSIONS)
+    }
+
+    /// Construct an unconnected RPC client with runtime-internal transport which uses a pool of
+    /// up to `max_sessions` concurrent sessions.
+    ///
+    /// Each session is pinned to a different remote node where possible and calls are spread
+    /// across sessions, so a single slow node does not block all calls.
+    pub fn new_runtime_with_sessions(
+        builder: Builder,
+        protocol: Arc<Protocol>,
+        endpoint: &str,
+        nodes: Vec<signature::PublicKey>,
+        max_sessions: usize,
+    ) -> Self {
+        Self::new_with_sessions(
             Box::new(RuntimeTransport::new(protocol, endpoint)),
             builder,
             nodes,
+            max_sessions,
         )
     }
 
+    /// Configure call timeouts and retry policy.
+    pub fn set_call_config(&self, config: CallConfig) {
+        *self.inner.config.lock().unwrap() = config;
+    }
+
     /// Call a remote method using an encrypted and authenticated Noise session.
     pub async fn secure_call<C, O>(
         &self,
@@ -189,138 +456,308 @@ impl RpcClient {
         O: cbor::Decode + Send + 'static,
     {
         let request = types::Request {
+            id: 0,
             method: method.to_owned(),
             args: cbor::to_value(args),
         };
 
-        let (pfid, response) = self.execute_call(ctx, request, kind).await?;
-        let result = match response.body {
-            types::Body::Success(value) => cbor::from_value(value).map_err(Into::into),
-            types::Body::Error(error) => Err(RpcClientError::CallFailed(error)),
+        let ctx = ctx.freeze();
+        let call_timeout = self.inner.config.lock().unwrap().call_timeout;
+        let deadline = call_timeout.map(|timeout| Instant::now() + timeout);
+        let index = self.inner.select_session();
+        let (session_id, node, response) = self
+            .execute_call(Context::create_child(&ctx), index, request, kind, deadline)
+            .await?;
+        let result = match self.response_value(&ctx, index, response, deadline).await {
+            Ok(value) => cbor::from_value(value).map_err(Into::into),
+            Err(err) => Err(err),
         };
 
-        // Report peer feedback based on whether call was successful.
+        // Report peer feedback on the node and session which handled the call based on whether
+        // the call was successful. Other calls may have used other nodes in the meantime.
         let pf = match result {
             Ok(_) => types::PeerFeedback::Success,
             Err(_) => types::PeerFeedback::Failure,
         };
-        self.inner.transport.set_peer_feedback(pfid, Some(pf));
+        self.inner.transport.set_peer_feedback(Some(node), pf);
+        if kind == types::Kind::NoiseSession {
+            self.inner.sessions[index].record_peer_feedback(session_id, pf);
+        }
 
         result
     }
 
+    /// Return the value of a successful response.
+    ///
+    /// Streamed responses are reassembled by requesting all remaining chunks, which are returned
+    /// as an array. Reassembly fails once the chunks exceed the configured maximum response size.
+    async fn response_value(
+        &self,
+        ctx: &Arc<Context>,
+        index: usize,
+        mut response: types::Response,
+        deadline: Option<Instant>,
+    ) -> Result<cbor::Value, RpcClientError> {
+        let max_response_size = self.inner.config.lock().unwrap().max_response_size;
+        let mut chunks = vec![];
+        let mut size = 0;
+        loop {
+            match response.body {
+                types::Body::Success(value) => return Ok(value),
+                types::Body::Error(error) => return Err(RpcClientError::CallFailed(error)),
+                types::Body::Chunk(chunk) => {
+                    if let Some(data) = chunk.data {
+                        size += cbor::to_vec(data.clone()).len();
+                        if size > max_response_size {
+                            return Err(RpcClientError::ResponseTooLarge);
+                        }
+                        chunks.push(data);
+                    }
+                    if chunk.last {
+                        return Ok(cbor::Value::Array(chunks));
+                    }
+                }
+            }
+
+            response = Self::next_chunk_raw(
+                self.inner.clone(),
+                index,
+                Context::create_child(ctx),
+                response.id,
+                remaining(deadline),
+            )
+            .await
+            .map_err(|err| {
+                if is_expired(deadline) {
+                    RpcClientError::Timeout
+                } else {
+                    err
+                }
+            })?;
+        }
+    }
+
     async fn execute_call(
         &self,
         ctx: Context,
+        index: usize,
         request: types::Request,
         kind: types::Kind,
-    ) -> Result<(u64, types::Response), RpcClientError> {
+        deadline: Option<Instant>,
+    ) -> Result<(types::SessionID, signature::PublicKey, types::Response), RpcClientError> {
+        let session = &self.inner.sessions[index];
+
         // Spawn a new controller if we haven't spawned one yet.
-        if self
-            .inner
+        if session
             .has_controller
             .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
             .is_ok()
         {
-            let mut rx = self
-                .inner
+            let rx = session
                 .recvq
                 .lock()
                 .unwrap()
                 .take()
                 .expect("has_controller was false");
-            let inner = self.inner.clone();
-
-            tokio::spawn(async move {
-                while let Some((ctx, request, kind, rsp_tx, retries)) = rx.next().await {
-                    let result = async {
-                        match kind {
-                            types::Kind::NoiseSession => {
-                                // Attempt to establish a connection. This will not do anything in case the
-                                // session has already been established.
-                                Self::connect(inner.clone(), Context::create_child(&ctx)).await?;
-
-                                // Perform the call.
-                                Self::secure_call_raw(
-                                    inner.clone(),
-                                    Context::create_child(&ctx),
-                                    request.clone(),
-                                )
-                                .await
-                            }
-                            types::Kind::InsecureQuery => {
-                                // Perform the call.
-                                Self::insecure_call_raw(
-                                    inner.clone(),
-                                    Context::create_child(&ctx),
-                                    request.clone(),
-                                )
-                                .await
-                            }
-                            _ => Err(RpcClientError::UnsupportedRpcKind),
-                        }
-                    }
-                    .await;
-
-                    // Update peer feedback for next request.
-                    let pfid = inner.transport.get_peer_feedback_id();
-                    if result.is_err() {
-                        // In case there was a transport error we need to reset the session
-                        // immediately as no progress is possible.
-                        let mut session = inner.session.lock().unwrap();
-                        session.reset();
-                        // Set peer feedback immediately so retries can try new peers.
-                        inner
-                            .transport
-                            .set_peer_feedback(pfid, Some(types::PeerFeedback::Failure));
-                    }
 
-                    match result {
-                        ref r if r.is_ok() || retries >= inner.max_retries => {
-                            // Request was successful or number of retries has been exceeded.
-                            let _ = rsp_tx.send(result.map(|rsp| (pfid, rsp)));
-                        }
+            tokio::spawn(Self::run_controller(self.inner.clone(), index, rx));
+        }
 
-                        _ => {
-                            // Attempt retry if number of retries is not exceeded. Retry is
-                            // performed by queueing another request.
-                            let _ = inner
-                                .sendq
-                                .clone()
-                                .send((ctx, request, kind, rsp_tx, retries + 1))
-                                .await;
-                        }
+        // Send request to controller.
+        session.pending.fetch_add(1, Ordering::SeqCst);
+        let result = async {
+            let (rsp_tx, rsp_rx) = oneshot::channel();
+            session
+                .sendq
+                .clone()
+                .send((ctx.freeze(), request, kind, rsp_tx, 0, deadline))
+                .await
+                .map_err(|_| RpcClientError::Dropped)?;
+
+            rsp_rx.await.map_err(|_| RpcClientError::Dropped)?
+        };
+        // Stop waiting once the deadline passes, even if the call is still queued behind others.
+        let result = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, result)
+                .await
+                .unwrap_or(Err(RpcClientError::Timeout)),
+            None => result.await,
+        };
+        session.pending.fetch_sub(1, Ordering::SeqCst);
+
+        result
+    }
+
+    async fn run_controller(inner: Arc<Inner>, index: usize, mut rx: mpsc::Receiver<SendqRequest>) {
+        while let Some(request) = rx.next().await {
+            // Limit the number of calls in flight on the session.
+            let permit = inner.sessions[index]
+                .in_flight
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+
+            // Attempt to establish a connection before pipelining the call. This will not do
+            // anything in case the session has already been established. Calls which are past
+            // their deadline are not attempted at all.
+            let (ctx, _, kind, _, _, deadline) = &request;
+            let result = match kind {
+                _ if is_expired(*deadline) => Err(RpcClientError::Timeout),
+                types::Kind::NoiseSession => {
+                    Self::connect(inner.clone(), index, Context::create_child(ctx), *deadline).await
+                }
+                _ => Ok(()),
+            };
+
+            match result {
+                Ok(_) => {
+                    // Peers using the legacy session protocol process messages in order, so
+                    // calls are made one at a time.
+                    let pipelined = *kind != types::Kind::NoiseSession
+                        || inner.sessions[index]
+                            .session
+                            .lock()
+                            .unwrap()
+                            .inner
+                            .supports_pipelining();
+                    let task = Self::execute_request(inner.clone(), index, request, permit);
+                    if pipelined {
+                        tokio::spawn(task);
+                    } else {
+                        task.await;
                     }
                 }
+                Err(err) => {
+                    drop(permit);
+                    let session_id = inner.sessions[index].session.lock().unwrap().id;
+                    Self::handle_result(inner.clone(), index, session_id, request, Err(err)).await;
+                }
+            }
+        }
+
+        // Close stream after the client is dropped.
+        let _ = Self::close(inner, index).await;
+    }
+
+    async fn execute_request(
+        inner: Arc<Inner>,
+        index: usize,
+        request: SendqRequest,
+        permit: OwnedSemaphorePermit,
+    ) {
+        let session_id = inner.sessions[index].session.lock().unwrap().id;
+        let (ctx, rq, kind, _, _, deadline) = &request;
+        let timeout = remaining(*deadline);
+        let result = match kind {
+            types::Kind::NoiseSession => {
+                // Perform the call.
+                Self::secure_call_raw(
+                    inner.clone(),
+                    index,
+                    Context::create_child(ctx),
+                    rq.clone(),
+                    timeout,
+                )
+                .await
+            }
+            types::Kind::InsecureQuery => {
+                // Perform the call.
+                Self::insecure_call_raw(
+                    inner.clone(),
+                    Context::create_child(ctx),
+                    rq.clone(),
+                    timeout,
+                )
+                .await
+            }
+            _ => Err(RpcClientError::UnsupportedRpcKind),
+        };
+
+        // Release the permit before a potential retry is queued.
+        drop(permit);
+        Self::handle_result(inner, index, session_id, request, result).await;
+    }
 
-                // Close stream after the client is dropped.
-                let _ = Self::close(inner).await;
-            });
+    async fn handle_result(
+        inner: Arc<Inner>,
+        index: usize,
+        session_id: types::SessionID,
+        request: SendqRequest,
+        result: Result<(signature::PublicKey, types::Response), RpcClientError>,
+    ) {
+        let (ctx, request, kind, rsp_tx, retries, deadline) = request;
+        let config = inner.config.lock().unwrap().clone();
+
+        if result.is_err() && kind == types::Kind::NoiseSession {
+            // In case there was a transport error we need to reset the session
+            // immediately as no progress is possible. The session is also unpinned so
+            // that retries can fail over to another node. Insecure queries are not bound
+            // to the session, so they leave it intact.
+            //
+            // Set peer feedback immediately so retries can try new peers. Feedback is only
+            // sent when the failed node is known, as feedback without a node would be
+            // attributed to whichever peer handled the last call.
+            if let Some(node) = inner.sessions[index].record_failure(session_id) {
+                inner
+                    .transport
+                    .set_peer_feedback(Some(node), types::PeerFeedback::Failure);
+            }
         }
 
-        // Send request to controller.
-        let (rsp_tx, rsp_rx) = oneshot::channel();
-        self.inner
-            .sendq
-            .clone()
-            .send((ctx.freeze(), request, kind, rsp_tx, 0))
-            .await
-            .map_err(|_| RpcClientError::Dropped)?;
+        match result {
+            Err(_) if is_expired(deadline) => {
+                // Deadline has passed, so there is no time left for retries.
+                let _ = rsp_tx.send(Err(RpcClientError::Timeout));
+            }
 
-        rsp_rx.await.map_err(|_| RpcClientError::Dropped)?
+            ref r if r.is_ok() || retries >= config.max_retries => {
+                // Request was successful or number of retries has been exceeded.
+                let _ = rsp_tx.send(result.map(|(node, rsp)| (session_id, node, rsp)));
+            }
+
+            _ => {
+                // Attempt retry if number of retries is not exceeded. Retry is
+                // performed by queueing another request after a backoff delay, which
+                // is waited out in a separate task so that other calls can proceed.
+                let backoff = config.backoff(retries);
+                let backoff = remaining(deadline).map_or(backoff, |left| backoff.min(left));
+                let mut sendq = inner.sessions[index].sendq.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(backoff).await;
+                    let _ = sendq
+                        .send((ctx, request, kind, rsp_tx, retries + 1, deadline))
+                        .await;
+                });
+            }
+        }
     }
 
-    async fn connect(inner: Arc<Inner>, ctx: Context) -> Result<(), RpcClientError> {
+    async fn connect(
+        inner: Arc<Inner>,
+        index: usize,
+        ctx: Context,
+        deadline: Option<Instant>,
+    ) -> Result<(), RpcClientError> {
         let mut buffer = vec![];
         let session_id;
+        let nodes = inner.session_nodes(index);
+        let handshake_timeout = inner.config.lock().unwrap().handshake_timeout;
+        // Each handshake step must complete within the handshake timeout and the call deadline.
+        let timeout = || {
+            handshake_timeout
+                .into_iter()
+                .chain(remaining(deadline))
+                .min()
+        };
 
         {
-            let mut session = inner.session.lock().unwrap();
-            let nodes = inner.nodes.lock().unwrap();
+            let mut session = inner.sessions[index].session.lock().unwrap();
+            let allowed_nodes = inner.nodes.lock().unwrap();
 
             // No need to create a new session if we are connected to one of the nodes.
             if session.inner.is_connected()
-                && (nodes.is_empty() || session.inner.is_connected_to(&nodes))
+                && (allowed_nodes.is_empty() || session.inner.is_connected_to(&allowed_nodes))
             {
                 return Ok(());
             }
@@ -338,16 +775,15 @@ impl RpcClient {
         let fctx = ctx.freeze();
         let ctx = Context::create_child(&fctx);
 
-        let nodes = inner.nodes.lock().unwrap().to_vec();
         let (data, node) = inner
             .transport
-            .write_noise_session(ctx, session_id, buffer, String::new(), nodes)
+            .write_noise_session(ctx, session_id, buffer, String::new(), nodes, timeout())
             .await
             .map_err(|_| RpcClientError::Transport)?;
 
         let mut buffer = vec![];
         {
-            let mut session = inner.session.lock().unwrap();
+            let mut session = inner.sessions[index].session.lock().unwrap();
             // Update the session with the identity of the remote node. The latter still needs
             // to be verified using the RAK from the consensus layer.
             session.inner.set_remote_node(node)?;
@@ -357,24 +793,34 @@ impl RpcClient {
                 .inner
                 .process_data(data, &mut buffer)
                 .map_err(|_| RpcClientError::Transport)?;
+
+            // Pin the session to the node so that it keeps using it while the node is healthy.
+            session.node = Some(node);
         }
 
         let ctx = Context::create_child(&fctx);
         inner
             .transport
-            .write_noise_session(ctx, session_id, buffer, String::new(), vec![node])
+            .write_noise_session(
+                ctx,
+                session_id,
+                buffer,
+                String::new(),
+                vec![node],
+                timeout(),
+            )
             .await
             .map_err(|_| RpcClientError::Transport)?;
 
         Ok(())
     }
 
-    async fn close(inner: Arc<Inner>) -> Result<(), RpcClientError> {
+    async fn close(inner: Arc<Inner>, index: usize) -> Result<(), RpcClientError> {
         let mut buffer = vec![];
         let session_id;
         let node;
         {
-            let mut session = inner.session.lock().unwrap();
+            let mut session = inner.sessions[index].session.lock().unwrap();
             if !session.inner.is_connected() {
                 return Ok(());
             }
@@ -387,14 +833,15 @@ impl RpcClient {
         }
 
         let ctx = Context::background();
+        let timeout = inner.config.lock().unwrap().handshake_timeout;
         let (data, _) = inner
             .transport
-            .write_noise_session(ctx, session_id, buffer, String::new(), vec![node])
+            .write_noise_session(ctx, session_id, buffer, String::new(), vec![node], timeout)
             .await
             .map_err(|_| RpcClientError::Transport)?;
 
         // Verify that session is closed.
-        let mut session = inner.session.lock().unwrap();
+        let mut session = inner.sessions[index].session.lock().unwrap();
         let msg = session
             .inner
             .process_data(data, vec![])?
@@ -411,16 +858,61 @@ impl RpcClient {
 
     async fn secure_call_raw(
         inner: Arc<Inner>,
+        index: usize,
         ctx: Context,
-        request: types::Request,
-    ) -> Result<types::Response, RpcClientError> {
+        mut request: types::Request,
+        timeout: Option<Duration>,
+    ) -> Result<(signature::PublicKey, types::Response), RpcClientError> {
         let method = request.method.clone();
-        let msg = types::Message::Request(request);
+        Self::secure_message_raw(inner, index, ctx, method, timeout, move |session| {
+            // Peers using the legacy session protocol don't know about request identifiers.
+            if session.inner.supports_pipelining() {
+                request.id = session.next_request_id;
+                session.next_request_id += 1;
+            }
+            (request.id, types::Message::Request(request))
+        })
+        .await
+    }
+
+    async fn next_chunk_raw(
+        inner: Arc<Inner>,
+        index: usize,
+        ctx: Context,
+        request_id: u64,
+        timeout: Option<Duration>,
+    ) -> Result<types::Response, RpcClientError> {
+        Self::secure_message_raw(inner, index, ctx, String::new(), timeout, move |_| {
+            (request_id, types::Message::NextChunk(request_id))
+        })
+        .await
+        .map(|(_, rsp)| rsp)
+    }
+
+    /// Send a message built by `build` over the session and wait for the response to the request
+    /// with the identifier returned by `build`.
+    ///
+    /// Returns the node which handled the request together with the response.
+    async fn secure_message_raw<F>(
+        inner: Arc<Inner>,
+        index: usize,
+        ctx: Context,
+        untrusted_plaintext: String,
+        timeout: Option<Duration>,
+        build: F,
+    ) -> Result<(signature::PublicKey, types::Response), RpcClientError>
+    where
+        F: FnOnce(&mut MultiplexedSession) -> (u64, types::Message),
+    {
+        let request_id;
         let session_id;
         let node;
         let mut buffer = vec![];
         {
-            let mut session = inner.session.lock().unwrap();
+            let mut session = inner.sessions[index].session.lock().unwrap();
+            let (id, msg) = build(&mut session);
+            request_id = id;
+
             session
                 .inner
                 .write_message(msg, &mut buffer)
@@ -429,20 +921,30 @@ impl RpcClient {
             node = session.inner.get_node()?;
         }
 
-        let (data, _) = inner
+        let (data, node) = inner
             .transport
-            .write_noise_session(ctx, session_id, buffer, method, vec![node])
+            .write_noise_session(
+                ctx,
+                session_id,
+                buffer,
+                untrusted_plaintext,
+                vec![node],
+                timeout,
+            )
             .await
             .map_err(|_| RpcClientError::Transport)?;
 
-        let mut session = inner.session.lock().unwrap();
+        let mut session = inner.sessions[index].session.lock().unwrap();
         let msg = session
             .inner
             .process_data(data, vec![])?
             .expect("message must be decoded if there is no error");
 
+        // Responses may arrive in a different order than the requests were sent, so make sure
+        // that this is the response to our request.
         match msg {
-            types::Message::Response(rsp) => Ok(rsp),
+            types::Message::Response(rsp) if rsp.id == request_id => Ok((node, rsp)),
+            types::Message::Response(rsp) => Err(RpcClientError::UnexpectedResponse(rsp.id)),
             msg => Err(RpcClientError::ExpectedResponseMessage(msg)),
         }
     }
@@ -451,48 +953,56 @@ impl RpcClient {
         inner: Arc<Inner>,
         ctx: Context,
         request: types::Request,
-    ) -> Result<types::Response, RpcClientError> {
+        timeout: Option<Duration>,
+    ) -> Result<(signature::PublicKey, types::Response), RpcClientError> {
         let nodes = inner.nodes.lock().unwrap().to_vec();
-        let (data, _) = inner
+        let (data, node) = inner
             .transport
-            .write_insecure_query(ctx, cbor::to_vec(request), nodes)
+            .write_insecure_query(ctx, cbor::to_vec(request), nodes, timeout)
             .await
             .map_err(|_| RpcClientError::Transport)?;
 
-        cbor::from_slice(&data).map_err(RpcClientError::DecodeError)
+        let rsp = cbor::from_slice(&data).map_err(RpcClientError::DecodeError)?;
+        Ok((node, rsp))
     }
 
     /// Update allowed remote enclave identities.
     ///
     /// Useful if the key manager's policy has changed.
     pub fn update_enclaves(&self, enclaves: Option<HashSet<EnclaveIdentity>>) {
-        let mut session = self.inner.session.lock().unwrap();
-        if session.builder.get_remote_enclaves() == &enclaves {
-            return;
+        for pooled in &self.inner.sessions {
+            let mut session = pooled.session.lock().unwrap();
+            if session.builder.get_remote_enclaves() == &enclaves {
+                continue;
+            }
+            session.builder = mem::take(&mut session.builder).remote_enclaves(enclaves.clone());
+            session.reset();
         }
-        session.builder = mem::take(&mut session.builder).remote_enclaves(enclaves);
-        session.reset();
     }
 
     /// Update key manager's quote policy.
     pub fn update_quote_policy(&self, policy: QuotePolicy) {
         let policy = Some(Arc::new(policy));
-        let mut session = self.inner.session.lock().unwrap();
-        if session.builder.get_quote_policy() == &policy {
-            return;
+        for pooled in &self.inner.sessions {
+            let mut session = pooled.session.lock().unwrap();
+            if session.builder.get_quote_policy() == &policy {
+                continue;
+            }
+            session.builder = mem::take(&mut session.builder).quote_policy(policy.clone());
+            session.reset();
         }
-        session.builder = mem::take(&mut session.builder).quote_policy(policy);
-        session.reset();
     }
 
     /// Update remote runtime id.
     pub fn update_runtime_id(&self, id: Option<Namespace>) {
-        let mut session = self.inner.session.lock().unwrap();
-        if session.builder.get_remote_runtime_id() == &id {
-            return;
+        for pooled in &self.inner.sessions {
+            let mut session = pooled.session.lock().unwrap();
+            if session.builder.get_remote_runtime_id() == &id {
+                continue;
+            }
+            session.builder = mem::take(&mut session.builder).remote_runtime_id(id);
+            session.reset();
         }
-        session.builder = mem::take(&mut session.builder).remote_runtime_id(id);
-        session.reset();
     }
 
     /// Update allowed nodes.
@@ -504,9 +1014,13 @@ impl RpcClient {
 
 #[cfg(test)]
 mod test {
-    use std::sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+    use std::{
+        collections::VecDeque,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
     };
 
     use anyhow::anyhow;
@@ -515,21 +1029,24 @@ mod test {
 
     use crate::{
         common::crypto::signature,
-        enclave_rpc::{demux::Demux, session, types},
+        enclave_rpc::{demux::Demux, dispatcher::ResponseStream, session, types},
         identity::Identity,
     };
 
-    use super::{super::transport::Transport, RpcClient};
+    use super::{super::transport::Transport, CallConfig, RpcClient, RpcClientError};
 
     #[derive(Clone)]
     struct MockTransport {
         identity: Arc<Identity>,
         demux: Arc<Mutex<Demux>>,
         next_error: Arc<AtomicBool>,
-        peer_feedback: Arc<Mutex<(u64, Option<types::PeerFeedback>)>>,
-        peer_feedback_history: Arc<Mutex<Vec<(u64, Option<types::PeerFeedback>)>>>,
+        unavailable: Arc<AtomicBool>,
+        peer_feedback: Arc<Mutex<VecDeque<PeerFeedback>>>,
+        peer_feedback_history: Arc<Mutex<Vec<Option<PeerFeedback>>>>,
     }
 
+    type PeerFeedback = (Option<signature::PublicKey>, types::PeerFeedback);
+
     impl MockTransport {
         fn new() -> Self {
             let identity = Arc::new(Identity::new());
@@ -538,7 +1055,8 @@ mod test {
                 identity: identity.clone(),
                 demux: Arc::new(Mutex::new(Demux::new(identity))),
                 next_error: Arc::new(AtomicBool::new(false)),
-                peer_feedback: Arc::new(Mutex::new((0, None))),
+                unavailable: Arc::new(AtomicBool::new(false)),
+                peer_feedback: Arc::new(Mutex::new(VecDeque::new())),
                 peer_feedback_history: Arc::new(Mutex::new(Vec::new())),
             }
         }
@@ -552,14 +1070,18 @@ mod test {
             self.next_error.store(true, Ordering::SeqCst);
         }
 
-        fn take_peer_feedback_history(&self) -> Vec<(u64, Option<types::PeerFeedback>)> {
+        fn set_unavailable(&self, unavailable: bool) {
+            self.unavailable.store(unavailable, Ordering::SeqCst);
+        }
+
+        fn take_peer_feedback_history(&self) -> Vec<Option<PeerFeedback>> {
             let mut pfh: Vec<_> = {
                 let mut pfh = self.peer_feedback_history.lock().unwrap();
                 std::mem::take(&mut pfh)
             };
             // Also add the pending feedback.
             let pf = self.peer_feedback.lock().unwrap();
-            pfh.push(pf.clone());
+            pfh.extend(pf.iter().cloned().map(Some));
             pfh
         }
     }
@@ -570,30 +1092,25 @@ mod test {
             _ctx: Context,
             request: Vec<u8>,
             kind: types::Kind,
-            _nodes: Vec<signature::PublicKey>,
+            nodes: Vec<signature::PublicKey>,
+            _timeout: Option<Duration>,
         ) -> BoxFuture<Result<(Vec<u8>, signature::PublicKey), anyhow::Error>> {
-            let pf = {
-                let mut pf = self.peer_feedback.lock().unwrap();
-                let peer_feedback = pf.1.take();
-
-                if !matches!(peer_feedback, None | Some(types::PeerFeedback::Success)) {
-                    pf.0 += 1;
-                }
-
-                (pf.0, peer_feedback)
-            };
+            let pf = self.peer_feedback.lock().unwrap().pop_front();
             self.peer_feedback_history.lock().unwrap().push(pf);
 
             // Induce error when configured to do so.
-            if self
-                .next_error
-                .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
-                .is_ok()
+            if self.unavailable.load(Ordering::SeqCst)
+                || self
+                    .next_error
+                    .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
             {
                 return Box::pin(future::err(anyhow!("transport error")));
             }
 
             let mut demux = self.demux.lock().unwrap();
+            // Pretend that the first given node handled the request.
+            let node = nodes.first().cloned().unwrap_or_default();
 
             match kind {
                 types::Kind::NoiseSession => {
@@ -603,24 +1120,49 @@ mod test {
                         Err(err) => Box::pin(future::err(err)),
                         Ok(Some((session_id, _session_info, message, _untrusted_plaintext))) => {
                             // Message, process and write reply.
-                            let body = match message {
+                            let (response, stream) = match message {
+                                types::Message::Request(rq) if rq.method == "stream" => {
+                                    // Stream back the given values one by one.
+                                    let values: Vec<cbor::Value> =
+                                        cbor::from_value(rq.args).unwrap();
+                                    let mut stream =
+                                        ResponseStream::new(Box::new(values.into_iter().map(Ok)));
+                                    (stream.next_response(rq.id), Some((rq.id, stream)))
+                                }
+                                types::Message::Request(rq) if rq.method == "error" => {
+                                    // Fail with the given code.
+                                    let code: u32 = cbor::from_value(rq.args).unwrap();
+                                    let error = types::Error::new("test", code, "failure");
+                                    let body = types::Body::Error(error);
+                                    (types::Response { id: rq.id, body }, None)
+                                }
                                 types::Message::Request(rq) => {
                                     // Just echo back what was given.
-                                    types::Body::Success(rq.args)
+                                    let body = types::Body::Success(rq.args);
+                                    (types::Response { id: rq.id, body }, None)
+                                }
+                                types::Message::NextChunk(id) => {
+                                    let mut stream = demux.take_stream(session_id, id).unwrap();
+                                    (stream.next_response(id), Some((id, stream)))
                                 }
                                 _ => panic!("unhandled message type"),
                             };
-                            let response = types::Message::Response(types::Response { body });
+                            if let Some((id, mut stream)) = stream {
+                                if !stream.is_done() {
+                                    demux.add_stream(session_id, id, stream).unwrap();
+                                }
+                            }
+                            let response = types::Message::Response(response);
 
                             let mut buffer = Vec::new();
                             match demux.write_message(session_id, response, &mut buffer) {
-                                Ok(_) => Box::pin(future::ok((buffer, Default::default()))),
+                                Ok(_) => Box::pin(future::ok((buffer, node))),
                                 Err(error) => Box::pin(future::err(error)),
                             }
                         }
                         Ok(None) => {
                             // Handshake.
-                            Box::pin(future::ok((buffer, Default::default())))
+                            Box::pin(future::ok((buffer, node)))
                         }
                     }
                 }
@@ -628,7 +1170,7 @@ mod test {
                     // Just echo back what was given.
                     let rq: types::Request = cbor::from_slice(&request).unwrap();
                     let body = types::Body::Success(rq.args);
-                    let response = types::Response { body };
+                    let response = types::Response { id: rq.id, body };
                     return Box::pin(future::ok((cbor::to_vec(response), Default::default())));
                 }
                 types::Kind::LocalQuery => {
@@ -637,28 +1179,30 @@ mod test {
             }
         }
 
-        fn set_peer_feedback(&self, pfid: u64, peer_feedback: Option<types::PeerFeedback>) {
+        fn set_peer_feedback(
+            &self,
+            node: Option<signature::PublicKey>,
+            peer_feedback: types::PeerFeedback,
+        ) {
             let mut pf = self.peer_feedback.lock().unwrap();
-            if pf.0 != pfid {
-                return;
-            }
-
-            pf.1 = peer_feedback;
-        }
-
-        fn get_peer_feedback_id(&self) -> u64 {
-            self.peer_feedback.lock().unwrap().0
+            pf.retain(|(n, _)| *n != node);
+            pf.push_back((node, peer_feedback));
         }
     }
 
     #[test]
     fn test_rpc_client() {
         let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
             .build()
             .unwrap();
         let transport = MockTransport::new();
         let builder = session::Builder::default();
-        let client = RpcClient::new(Box::new(transport.clone()), builder, vec![]);
+        let client = RpcClient::new_with_sessions(Box::new(transport.clone()), builder, vec![], 1);
+        // Without allowed nodes, the mock transport reports the default node as handling calls.
+        let node = Some(signature::PublicKey::default());
+        let success = types::PeerFeedback::Success;
+        let failure = types::PeerFeedback::Failure;
 
         // Basic secure call.
         let result: u64 = rt
@@ -668,10 +1212,10 @@ mod test {
         assert_eq!(
             transport.take_peer_feedback_history(),
             vec![
-                (0, None),                               // Handshake.
-                (0, None),                               // Handshake.
-                (0, None),                               // Call.
-                (0, Some(types::PeerFeedback::Success)), // Handled call.
+                None,                  // Handshake.
+                None,                  // Handshake.
+                None,                  // Call.
+                Some((node, success)), // Handled call.
             ]
         );
 
@@ -685,11 +1229,11 @@ mod test {
         assert_eq!(
             transport.take_peer_feedback_history(),
             vec![
-                (0, Some(types::PeerFeedback::Success)), // Previous handled call.
-                (1, Some(types::PeerFeedback::Failure)), // Failed call due to session reset.
-                (1, None),                               // New handshake.
-                (1, None),                               // New handshake.
-                (1, Some(types::PeerFeedback::Success)), // Handled call.
+                Some((node, success)), // Previous handled call.
+                Some((node, failure)), // Failed call due to session reset.
+                None,                  // New handshake.
+                None,                  // New handshake.
+                Some((node, success)), // Handled call.
             ]
         );
 
@@ -704,11 +1248,11 @@ mod test {
         assert_eq!(
             transport.take_peer_feedback_history(),
             vec![
-                (1, Some(types::PeerFeedback::Success)), // Previous handled call.
-                (2, Some(types::PeerFeedback::Failure)), // Failed call due to induced error.
-                (2, None),                               // New handshake.
-                (2, None),                               // New handshake.
-                (2, Some(types::PeerFeedback::Success)), // Handled call.
+                Some((node, success)), // Previous handled call.
+                Some((node, failure)), // Failed call due to induced error.
+                None,                  // New handshake.
+                None,                  // New handshake.
+                Some((node, success)), // Handled call.
             ]
         );
 
@@ -720,8 +1264,8 @@ mod test {
         assert_eq!(
             transport.take_peer_feedback_history(),
             vec![
-                (2, Some(types::PeerFeedback::Success)), // Previous handled call.
-                (2, Some(types::PeerFeedback::Success)), // Handled call.
+                Some((node, success)), // Previous handled call.
+                Some((node, success)), // Handled call.
             ]
         );
 
@@ -735,10 +1279,234 @@ mod test {
         assert_eq!(
             transport.take_peer_feedback_history(),
             vec![
-                (2, Some(types::PeerFeedback::Success)), // Previous handled call.
-                (3, Some(types::PeerFeedback::Failure)), // Failed call due to induced error.
-                (3, Some(types::PeerFeedback::Success)), // Handled call.
+                Some((node, success)), // Previous handled call.
+                None,                  // Failed call due to induced error, node unknown.
+                Some((node, success)), // Handled call.
             ]
         );
     }
+
+    #[test]
+    fn test_rpc_client_session_pool() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let transport = MockTransport::new();
+        let builder = session::Builder::default();
+        let nodes = vec![
+            signature::PublicKey::from(
+                "0000000000000000000000000000000000000000000000000000000000000001",
+            ),
+            signature::PublicKey::from(
+                "0000000000000000000000000000000000000000000000000000000000000002",
+            ),
+        ];
+        let client =
+            RpcClient::new_with_sessions(Box::new(transport.clone()), builder, nodes.clone(), 2);
+
+        let pinned_nodes = || -> Vec<Option<signature::PublicKey>> {
+            client
+                .inner
+                .sessions
+                .iter()
+                .map(|pooled| pooled.session.lock().unwrap().node)
+                .collect()
+        };
+
+        // Concurrent calls should be spread across sessions pinned to different nodes.
+        let (result1, result2) = rt.block_on(future::join(
+            client.secure_call::<_, u64>(Context::background(), "test", 42),
+            client.secure_call::<_, u64>(Context::background(), "test", 43),
+        ));
+        assert_eq!(result1.unwrap(), 42, "secure call should work");
+        assert_eq!(result2.unwrap(), 43, "secure call should work");
+        assert_eq!(pinned_nodes(), vec![Some(nodes[0]), Some(nodes[1])]);
+
+        // Peer feedback should be reported on the node which handled each call.
+        let history = transport.take_peer_feedback_history();
+        for node in &nodes {
+            assert!(history.contains(&Some((Some(*node), types::PeerFeedback::Success))));
+        }
+
+        // A transport error should mark the session as failed and unpin it, the retry should
+        // reconnect and succeed.
+        transport.induce_transport_error();
+
+        let result: u64 = rt
+            .block_on(client.secure_call(Context::background(), "test", 44))
+            .unwrap();
+        assert_eq!(result, 44, "secure call should work");
+        assert!(
+            client
+                .inner
+                .sessions
+                .iter()
+                .all(|pooled| pooled.is_healthy()),
+            "sessions should be healthy after a successful retry"
+        );
+        let pinned = pinned_nodes();
+        assert!(pinned.contains(&Some(nodes[0])) && pinned.contains(&Some(nodes[1])));
+
+        // Negative peer feedback should fail over to another session.
+        let session_id = client.inner.sessions[0].session.lock().unwrap().id;
+        client.inner.sessions[0].record_peer_feedback(session_id, types::PeerFeedback::Failure);
+        assert!(!client.inner.sessions[0].is_healthy());
+        assert_eq!(pinned_nodes()[0], None);
+
+        // Feedback on calls made using a replaced session should not affect the new one.
+        client.inner.sessions[0].record_peer_feedback(session_id, types::PeerFeedback::Success);
+        assert!(!client.inner.sessions[0].is_healthy());
+        client.inner.sessions[1].record_peer_feedback(session_id, types::PeerFeedback::Failure);
+        assert_eq!(pinned_nodes()[1], Some(nodes[1]));
+        assert_eq!(client.inner.select_session(), 1);
+        assert_eq!(client.inner.select_session(), 1);
+    }
+
+    #[test]
+    fn test_rpc_client_pipelining() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let transport = MockTransport::new();
+        let builder = session::Builder::default();
+        let client = RpcClient::new_with_sessions(Box::new(transport.clone()), builder, vec![], 1);
+
+        // Multiple calls can be in flight on the same session.
+        let results =
+            rt.block_on(future::join_all((0..5u64).map(|i| {
+                client.secure_call::<_, u64>(Context::background(), "test", i)
+            })));
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap(), i as u64, "secure call should work");
+        }
+        assert_eq!(
+            client.inner.sessions[0]
+                .session
+                .lock()
+                .unwrap()
+                .next_request_id,
+            5,
+            "all calls should use the same session"
+        );
+    }
+
+    #[test]
+    fn test_rpc_client_streaming() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let transport = MockTransport::new();
+        let builder = session::Builder::default();
+        let client = RpcClient::new_with_sessions(Box::new(transport.clone()), builder, vec![], 1);
+
+        // Streamed responses should be reassembled from all chunks.
+        let values: Vec<u64> = (0..10).collect();
+        let result: Vec<u64> = rt
+            .block_on(client.secure_call(Context::background(), "stream", values.clone()))
+            .unwrap();
+        assert_eq!(result, values, "streamed call should work");
+
+        // An empty stream should produce an empty result.
+        let result: Vec<u64> = rt
+            .block_on(client.secure_call(Context::background(), "stream", Vec::<u64>::new()))
+            .unwrap();
+        assert!(result.is_empty(), "empty streamed call should work");
+
+        // Regular calls should still work on the same session.
+        let result: u64 = rt
+            .block_on(client.secure_call(Context::background(), "test", 42))
+            .unwrap();
+        assert_eq!(result, 42, "secure call should work");
+
+        // Reassembly should stop once the response grows too large.
+        client.set_call_config(CallConfig {
+            max_response_size: 8,
+            ..Default::default()
+        });
+        let result: Result<Vec<u64>, _> =
+            rt.block_on(client.secure_call(Context::background(), "stream", values));
+        assert!(matches!(result, Err(RpcClientError::ResponseTooLarge)));
+    }
+
+    #[test]
+    fn test_rpc_client_call_failed() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let transport = MockTransport::new();
+        let builder = session::Builder::default();
+        let client = RpcClient::new_with_sessions(Box::new(transport.clone()), builder, vec![], 1);
+
+        // Errors should retain their module and code.
+        let result: Result<u64, _> =
+            rt.block_on(client.secure_call(Context::background(), "error", 42u32));
+        match result {
+            Err(RpcClientError::CallFailed(err)) => {
+                assert_eq!(err.module, "test");
+                assert_eq!(err.code, 42);
+                assert_eq!(err.message, "failure");
+            }
+            result => panic!("expected failed call, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rpc_client_timeout() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let transport = MockTransport::new();
+        let builder = session::Builder::default();
+        let client = RpcClient::new_with_sessions(Box::new(transport.clone()), builder, vec![], 1);
+        transport.set_unavailable(true);
+
+        // Calls should give up after the configured number of retries.
+        client.set_call_config(CallConfig {
+            call_timeout: None,
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let result: Result<u64, _> =
+            rt.block_on(client.secure_call(Context::background(), "test", 42));
+        assert!(matches!(result, Err(RpcClientError::Transport)));
+
+        // Calls should give up once their deadline passes, even if retries remain.
+        client.set_call_config(CallConfig {
+            call_timeout: Some(Duration::from_millis(100)),
+            max_retries: usize::MAX,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+            ..Default::default()
+        });
+        let result: Result<u64, _> =
+            rt.block_on(client.secure_call(Context::background(), "test", 42));
+        assert!(matches!(result, Err(RpcClientError::Timeout)));
+
+        // Calls should succeed once the remote node becomes available again.
+        transport.set_unavailable(false);
+        let result: u64 = rt
+            .block_on(client.secure_call(Context::background(), "test", 42))
+            .unwrap();
+        assert_eq!(result, 42, "secure call should work");
+    }
+
+    #[test]
+    fn test_call_config_backoff() {
+        let config = CallConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..Default::default()
+        };
+        assert_eq!(config.backoff(0), Duration::from_millis(100));
+        assert_eq!(config.backoff(1), Duration::from_millis(200));
+        assert_eq!(config.backoff(3), Duration::from_millis(800));
+        assert_eq!(config.backoff(4), Duration::from_secs(1));
+        assert_eq!(config.backoff(usize::MAX), Duration::from_secs(1));
+    }
 }
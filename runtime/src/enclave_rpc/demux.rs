@@ -1,24 +1,52 @@
 //! Session demultiplexer.
-use std::{collections::HashMap, io::Write, sync::Arc, time::SystemTime};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    sync::Arc,
+    time::SystemTime,
+};
 
 use anyhow::Result;
 use thiserror::Error;
 
 use super::{
-    session::{Builder, Session, SessionInfo},
-    types::{Frame, Message, SessionID},
+    dispatcher::ResponseStream,
+    session::{Builder, Session, SessionInfo, VerificationCache},
+    types::{Body, Error as RpcError, Frame, Message, Response, SessionID},
+};
+use crate::{
+    common::{
+        crypto::signature::PublicKey,
+        time::{insecure_posix_system_time, insecure_posix_time},
+    },
+    identity::Identity,
 };
-use crate::{common::time::insecure_posix_system_time, identity::Identity};
 
 /// Maximum concurrent EnclaveRPC sessions.
 const DEFAULT_MAX_CONCURRENT_SESSIONS: usize = 100;
+/// Maximum concurrent EnclaveRPC sessions with a single remote peer.
+const DEFAULT_MAX_SESSIONS_PER_PEER: usize = 8;
+/// Number of requests per second which can be made on a single session in the long run.
+const DEFAULT_REQUEST_RATE: u64 = 16;
+/// Number of requests which can be made on a single session in a burst.
+const DEFAULT_REQUEST_BURST: u64 = 64;
+/// Maximum number of requests in flight on a single session.
+const MAX_IN_FLIGHT_REQUESTS: usize = 32;
+/// Maximum number of unfinished streamed responses on a single session.
+const MAX_STREAMS: usize = 4;
 /// Sessions without any processed frame for more than STALE_SESSION_TIMEOUT_SECS seconds
 /// can be purged.
 const DEFAULT_STALE_SESSION_TIMEOUT_SECS: u64 = 60;
+/// Authenticated sessions can only be evicted to make room for new sessions if they haven't
+/// processed any frame for more than MIN_EVICTION_IDLE_TIME_SECS seconds.
+const MIN_EVICTION_IDLE_TIME_SECS: u64 = 10;
 /// Stale session check will be performed on any new incoming connection with at minimum
 /// STALE_SESSIONS_CHECK_TIMEOUT_SECS seconds between checks.
 const STALE_SESSIONS_CHECK_TIMEOUT_SECS: u64 = 10;
 
+/// Name of the module used for errors produced by the demultiplexer.
+const MODULE_NAME: &str = "enclave_rpc/demux";
+
 /// Demux error.
 #[derive(Error, Debug)]
 enum DemuxError {
@@ -26,6 +54,50 @@ enum DemuxError {
     SessionNotFound { session: SessionID },
     #[error("max concurrent sessions reached")]
     MaxConcurrentSessions,
+    #[error("max concurrent sessions with peer reached")]
+    MaxPeerSessions,
+    #[error("request rate limit exceeded")]
+    RateLimited,
+    #[error("request {id} already in flight")]
+    DuplicateRequest { id: u64 },
+    #[error("max in-flight requests reached")]
+    MaxInFlightRequests,
+    #[error("no request {id} in flight")]
+    RequestNotFound { id: u64 },
+    #[error("no streamed response to request {id}")]
+    StreamNotFound { id: u64 },
+    #[error("max streamed responses reached")]
+    MaxStreams,
+}
+
+impl DemuxError {
+    fn code(&self) -> u32 {
+        match self {
+            DemuxError::SessionNotFound { .. } => 1,
+            DemuxError::MaxConcurrentSessions => 2,
+            DemuxError::MaxPeerSessions => 3,
+            DemuxError::RateLimited => 4,
+            DemuxError::DuplicateRequest { .. } => 5,
+            DemuxError::MaxInFlightRequests => 6,
+            DemuxError::RequestNotFound { .. } => 7,
+            DemuxError::StreamNotFound { .. } => 8,
+            DemuxError::MaxStreams => 9,
+        }
+    }
+
+    /// Response rejecting the request with the given identifier.
+    fn into_response(self, id: u64) -> Response {
+        Response {
+            id,
+            body: Body::Error(self.into()),
+        }
+    }
+}
+
+impl From<DemuxError> for RpcError {
+    fn from(err: DemuxError) -> Self {
+        RpcError::new(MODULE_NAME, err.code(), &err.to_string())
+    }
 }
 
 pub type SessionMessage = (SessionID, Option<Arc<SessionInfo>>, Message, String);
@@ -35,13 +107,141 @@ pub struct Demux {
     identity: Arc<Identity>,
     sessions: HashMap<SessionID, EnrichedSession>,
     max_concurrent_sessions: usize,
+    max_sessions_per_peer: usize,
+    request_rate: u64,
+    request_burst: u64,
     stale_session_timeout: u64,
     last_stale_sessions_purge: SystemTime,
+    /// Counter used to order sessions by their last activity.
+    activity: u64,
+    /// Verified quotes of remote peers, reused when peers re-establish sessions.
+    verification_cache: VerificationCache,
 }
 
 struct EnrichedSession {
     session: Session,
     last_process_frame_time: SystemTime,
+    /// Value of the activity counter when a frame was last processed.
+    last_activity: u64,
+    /// RAK of the remote peer, once authenticated.
+    peer: Option<PublicKey>,
+    /// Identifiers of requests which have not been responded to yet.
+    in_flight: HashSet<u64>,
+    /// Unfinished streamed responses by request identifier.
+    streams: HashMap<u64, ResponseStream>,
+    /// Request rate limiter.
+    requests: TokenBucket,
+}
+
+impl EnrichedSession {
+    fn new(session: Session, activity: u64, request_rate: u64, request_burst: u64) -> Self {
+        Self {
+            session,
+            last_process_frame_time: insecure_posix_system_time(),
+            last_activity: activity,
+            peer: None,
+            in_flight: HashSet::new(),
+            streams: HashMap::new(),
+            requests: TokenBucket::new(request_rate, request_burst),
+        }
+    }
+
+    /// Whether the session has no requests being processed.
+    fn is_idle(&self) -> bool {
+        self.in_flight.is_empty() && self.streams.is_empty()
+    }
+
+    /// Whether the session can be evicted to make room for a session with another peer.
+    ///
+    /// Only idle sessions which are either still unauthenticated or haven't been used for
+    /// a while can be evicted, so that new connections cannot push out active peers.
+    fn is_evictable(&self, now: SystemTime) -> bool {
+        if !self.is_idle() {
+            return false;
+        }
+        if self.peer.is_none() {
+            return true;
+        }
+        now.duration_since(self.last_process_frame_time)
+            .map(|idle| idle.as_secs() >= MIN_EVICTION_IDLE_TIME_SECS)
+            .unwrap_or(false)
+    }
+
+    /// Start tracking a received request so that its response can be matched.
+    ///
+    /// Requests exceeding the limits of the session are not tracked, instead the response
+    /// rejecting them is returned so that the peer can retry them later.
+    fn track_request(&mut self, message: &Message) -> Result<Option<Response>> {
+        let id = match message {
+            Message::Request(request) => request.id,
+            Message::NextChunk(id) => {
+                if !self.streams.contains_key(id) {
+                    return Err(DemuxError::StreamNotFound { id: *id }.into());
+                }
+                *id
+            }
+            _ => return Ok(None),
+        };
+
+        if self.in_flight.contains(&id) {
+            return Err(DemuxError::DuplicateRequest { id }.into());
+        }
+        if let Message::Request(_) = message {
+            // Chunks of streamed responses are limited by the number of streams instead.
+            if self.in_flight.len() >= MAX_IN_FLIGHT_REQUESTS {
+                return Ok(Some(DemuxError::MaxInFlightRequests.into_response(id)));
+            }
+            if !self.requests.take(insecure_posix_time() as u64) {
+                return Ok(Some(DemuxError::RateLimited.into_response(id)));
+            }
+        }
+        self.in_flight.insert(id);
+        Ok(None)
+    }
+}
+
+/// Token bucket limiting the rate of requests.
+struct TokenBucket {
+    /// Number of tokens added per second, zero if unlimited.
+    rate: u64,
+    /// Maximum number of tokens.
+    burst: u64,
+    tokens: u64,
+    /// Time of the last refill, in seconds since the UNIX epoch.
+    last_refill: u64,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, burst: u64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: insecure_posix_time() as u64,
+        }
+    }
+
+    /// Take a token, returning false if there are none left.
+    fn take(&mut self, now: u64) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        let elapsed = now.saturating_sub(self.last_refill);
+        if elapsed > 0 {
+            self.tokens = self
+                .tokens
+                .saturating_add(elapsed.saturating_mul(self.rate))
+                .min(self.burst);
+            self.last_refill = now;
+        }
+
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
 }
 
 impl Demux {
@@ -51,8 +251,13 @@ impl Demux {
             identity,
             sessions: HashMap::new(),
             max_concurrent_sessions: DEFAULT_MAX_CONCURRENT_SESSIONS,
+            max_sessions_per_peer: DEFAULT_MAX_SESSIONS_PER_PEER,
+            request_rate: DEFAULT_REQUEST_RATE,
+            request_burst: DEFAULT_REQUEST_BURST,
             stale_session_timeout: DEFAULT_STALE_SESSION_TIMEOUT_SECS,
             last_stale_sessions_purge: insecure_posix_system_time(),
+            activity: 0,
+            verification_cache: VerificationCache::default(),
         }
     }
 
@@ -61,6 +266,21 @@ impl Demux {
         self.max_concurrent_sessions = max_concurrent_sessions;
     }
 
+    /// Configures the maximum number of concurrent sessions with a single remote peer.
+    ///
+    /// Peers are identified by their RAK, so the limit only applies to authenticated sessions.
+    pub fn set_max_sessions_per_peer(&mut self, max_sessions_per_peer: usize) {
+        self.max_sessions_per_peer = max_sessions_per_peer;
+    }
+
+    /// Configures the request rate limit of new sessions, as the number of requests per second
+    /// and the number of requests allowed in a burst.
+    /// If the rate is 0, requests are not rate limited.
+    pub fn set_request_rate_limit(&mut self, rate: u64, burst: u64) {
+        self.request_rate = rate;
+        self.request_burst = burst;
+    }
+
     /// Configures stale session timeout.
     /// If 0, sessions are never considered stale.
     pub fn set_stale_session_timeout(&mut self, stale_session_timeout: u64) {
@@ -83,20 +303,81 @@ impl Demux {
         self.last_stale_sessions_purge = now;
     }
 
+    fn next_activity(&mut self) -> u64 {
+        self.activity += 1;
+        self.activity
+    }
+
+    /// Evict the least recently active idle session matching the given predicate.
+    fn evict_idle_session<P>(&mut self, predicate: P) -> bool
+    where
+        P: Fn(&SessionID, &EnrichedSession) -> bool,
+    {
+        let lru = self
+            .sessions
+            .iter()
+            .filter(|(id, session)| session.is_idle() && predicate(id, session))
+            .min_by_key(|(_, session)| session.last_activity)
+            .map(|(id, _)| *id);
+
+        match lru {
+            Some(id) => {
+                self.sessions.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Associate the session with its remote peer once authenticated, making room for it within
+    /// the peer's quota by evicting one of the peer's idle sessions if needed.
+    fn assign_peer(&mut self, id: SessionID) -> Result<()> {
+        let peer = match self.sessions.get(&id) {
+            Some(enriched_session) if enriched_session.peer.is_none() => {
+                match enriched_session.session.session_info() {
+                    Some(si) => si.rak_binding.rak_pub(),
+                    None => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        };
+
+        let peer_sessions = self
+            .sessions
+            .values()
+            .filter(|session| session.peer == Some(peer))
+            .count();
+        if peer_sessions >= self.max_sessions_per_peer
+            && !self.evict_idle_session(|sid, session| *sid != id && session.peer == Some(peer))
+        {
+            self.sessions.remove(&id);
+            return Err(DemuxError::MaxPeerSessions.into());
+        }
+
+        if let Some(enriched_session) = self.sessions.get_mut(&id) {
+            enriched_session.peer = Some(peer);
+        }
+        Ok(())
+    }
+
     /// Process an incoming frame.
+    ///
+    /// Requests rejected because they exceed the limits of their session are not returned,
+    /// instead the response rejecting them is written.
     pub fn process_frame<W: Write>(
         &mut self,
         data: Vec<u8>,
-        writer: W,
+        mut writer: W,
     ) -> Result<Option<SessionMessage>> {
         let frame: Frame = cbor::from_slice(&data)?;
         let id = frame.session;
         let untrusted_plaintext = frame.untrusted_plaintext.clone();
+        let activity = self.next_activity();
 
         if let Some(enriched_session) = self.sessions.get_mut(&id) {
             match enriched_session
                 .session
-                .process_data(frame.payload, writer)
+                .process_data(frame.payload, &mut writer)
                 .map(|m| {
                     m.map(|msg| {
                         (
@@ -107,8 +388,20 @@ impl Demux {
                         )
                     })
                 }) {
-                Ok(result) => {
+                Ok(mut result) => {
                     enriched_session.last_process_frame_time = insecure_posix_system_time();
+                    enriched_session.last_activity = activity;
+                    let rejection = match result {
+                        Some((_, _, ref message, _)) => enriched_session.track_request(message)?,
+                        None => None,
+                    };
+                    if let Some(rejection) = rejection {
+                        enriched_session
+                            .session
+                            .write_message(Message::Response(rejection), &mut writer)?;
+                        result = None;
+                    }
+                    self.assign_peer(id)?;
                     Ok(result)
                 }
                 // In case there is an error, drop the session.
@@ -132,11 +425,18 @@ impl Demux {
                 self.purge_stale_sessions()
             }
 
+            // Make room for the new session by evicting the least recently active evictable
+            // session.
+            while self.sessions.len() >= self.max_concurrent_sessions
+                && self.evict_idle_session(|_, session| session.is_evictable(now))
+            {}
+
             // Create a new session.
             if self.sessions.len() < self.max_concurrent_sessions {
                 let mut session = Builder::default()
                     .quote_policy(self.identity.quote_policy())
                     .local_identity(self.identity.clone())
+                    .verification_cache(Some(self.verification_cache.clone()))
                     .build_responder();
                 let result = match session.process_data(frame.payload, writer).map(|m| {
                     m.map(|msg| (id, session.session_info(), msg, untrusted_plaintext.clone()))
@@ -147,10 +447,7 @@ impl Demux {
                 };
                 self.sessions.insert(
                     id,
-                    EnrichedSession {
-                        session,
-                        last_process_frame_time: insecure_posix_system_time(),
-                    },
+                    EnrichedSession::new(session, activity, self.request_rate, self.request_burst),
                 );
 
                 Ok(result)
@@ -161,6 +458,9 @@ impl Demux {
     }
 
     /// Write message to session and generate a response.
+    ///
+    /// Responses must be for a request received on the same session which has not been
+    /// responded to yet, but they may be written in any order.
     pub fn write_message<W: Write>(
         &mut self,
         id: SessionID,
@@ -169,6 +469,12 @@ impl Demux {
     ) -> Result<()> {
         match self.sessions.get_mut(&id) {
             Some(enriched_session) => {
+                if let Message::Response(ref response) = msg {
+                    if !enriched_session.in_flight.remove(&response.id) {
+                        return Err(DemuxError::RequestNotFound { id: response.id }.into());
+                    }
+                }
+
                 // Responses don't need framing as they are linked at the
                 // runtime IPC protocol.
                 enriched_session.session.write_message(msg, &mut writer)?;
@@ -178,6 +484,56 @@ impl Demux {
         }
     }
 
+    /// Stop tracking a request which will not be responded to, e.g. because its processing
+    /// failed.
+    pub fn release_request(&mut self, id: SessionID, request_id: u64) {
+        if let Some(enriched_session) = self.sessions.get_mut(&id) {
+            enriched_session.in_flight.remove(&request_id);
+        }
+    }
+
+    /// Register the remaining chunks of the streamed response to the given request.
+    ///
+    /// Errors are coded, so that they can be returned to the peer in place of the response.
+    pub fn add_stream(
+        &mut self,
+        id: SessionID,
+        request_id: u64,
+        stream: ResponseStream,
+    ) -> Result<()> {
+        let enriched_session = self
+            .sessions
+            .get_mut(&id)
+            .ok_or_else(|| RpcError::from(DemuxError::SessionNotFound { session: id }))?;
+        if enriched_session.streams.len() >= MAX_STREAMS {
+            return Err(RpcError::from(DemuxError::MaxStreams).into());
+        }
+        enriched_session.streams.insert(request_id, stream);
+        Ok(())
+    }
+
+    /// Whether the given session supports multiple requests in flight and streamed responses.
+    pub fn supports_pipelining(&self, id: SessionID) -> bool {
+        self.sessions.get(&id).map_or(false, |enriched_session| {
+            enriched_session.session.supports_pipelining()
+        })
+    }
+
+    /// Take the remaining chunks of the streamed response to the given request.
+    ///
+    /// In case not all chunks are consumed, the stream should be registered again using
+    /// `add_stream`.
+    pub fn take_stream(&mut self, id: SessionID, request_id: u64) -> Result<ResponseStream> {
+        let enriched_session = self
+            .sessions
+            .get_mut(&id)
+            .ok_or(DemuxError::SessionNotFound { session: id })?;
+        enriched_session
+            .streams
+            .remove(&request_id)
+            .ok_or_else(|| DemuxError::StreamNotFound { id: request_id }.into())
+    }
+
     /// Close the session and generate a response.
     pub fn close<W: Write>(&mut self, id: SessionID, mut writer: W) -> Result<()> {
         match self.sessions.remove(&id) {
@@ -193,3 +549,186 @@ impl Demux {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::enclave_rpc::types::Request;
+
+    fn frame(id: SessionID, payload: Vec<u8>) -> Vec<u8> {
+        cbor::to_vec(Frame {
+            session: id,
+            untrusted_plaintext: String::new(),
+            payload,
+        })
+    }
+
+    fn connect(demux: &mut Demux, id: SessionID) -> Result<Session> {
+        let mut initiator = Builder::default().build_initiator();
+
+        let mut buffer = vec![];
+        initiator.process_data(vec![], &mut buffer)?;
+        let mut reply = vec![];
+        demux.process_frame(frame(id, buffer), &mut reply)?;
+        let mut buffer = vec![];
+        initiator.process_data(reply, &mut buffer)?;
+        demux.process_frame(frame(id, buffer), vec![])?;
+
+        Ok(initiator)
+    }
+
+    fn request(
+        demux: &mut Demux,
+        initiator: &mut Session,
+        id: SessionID,
+        request_id: u64,
+    ) -> Result<Option<Message>> {
+        let mut buffer = vec![];
+        initiator.write_message(
+            Message::Request(Request {
+                id: request_id,
+                method: "test".to_string(),
+                args: cbor::to_value(request_id),
+            }),
+            &mut buffer,
+        )?;
+        let mut reply = vec![];
+        match demux.process_frame(frame(id, buffer), &mut reply)? {
+            Some((_, _, message, _)) => Ok(Some(message)),
+            // Rejected requests are responded to right away.
+            None => initiator.process_data(reply, vec![]),
+        }
+    }
+
+    fn is_rejected(message: &Message, request_id: u64, error: DemuxError) -> bool {
+        match message {
+            Message::Response(Response {
+                id,
+                body: Body::Error(err),
+            }) => *id == request_id && err.module == MODULE_NAME && err.code == error.code(),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket {
+            rate: 2,
+            burst: 3,
+            tokens: 3,
+            last_refill: 0,
+        };
+        assert!(bucket.take(0) && bucket.take(0) && bucket.take(0));
+        assert!(!bucket.take(0), "bucket should be empty");
+
+        // Tokens are refilled over time, but never above the burst size.
+        assert!(bucket.take(1) && bucket.take(1));
+        assert!(!bucket.take(1), "bucket should be empty");
+        assert!(bucket.take(100) && bucket.take(100) && bucket.take(100));
+        assert!(!bucket.take(100), "bucket should be empty");
+
+        let mut unlimited = TokenBucket::new(0, 0);
+        assert!(unlimited.take(0), "unlimited bucket should never be empty");
+    }
+
+    #[test]
+    fn test_idle_session_eviction() {
+        let mut demux = Demux::new(Arc::new(Identity::new()));
+        demux.set_max_concurrent_sessions(2);
+
+        let (a, b, c, d) = (
+            SessionID::random(),
+            SessionID::random(),
+            SessionID::random(),
+            SessionID::random(),
+        );
+        let mut initiator_a = connect(&mut demux, a).unwrap();
+        connect(&mut demux, b).unwrap();
+        request(&mut demux, &mut initiator_a, a, 0).unwrap();
+
+        // The idle session should be evicted to make room for a new one.
+        connect(&mut demux, c).unwrap();
+        assert!(demux.sessions.contains_key(&a) && demux.sessions.contains_key(&c));
+        assert!(!demux.sessions.contains_key(&b));
+
+        // Recently active authenticated sessions should not be evicted.
+        demux.sessions.get_mut(&c).unwrap().peer = Some(PublicKey::default());
+        connect(&mut demux, d).expect_err("session should be rejected when no session is idle");
+        assert!(demux.sessions.contains_key(&a) && demux.sessions.contains_key(&c));
+        assert!(!demux.sessions.contains_key(&d));
+
+        // Authenticated sessions should be evicted once they have been idle long enough.
+        demux.sessions.get_mut(&c).unwrap().last_process_frame_time -=
+            Duration::from_secs(MIN_EVICTION_IDLE_TIME_SECS);
+        connect(&mut demux, d).unwrap();
+        assert!(demux.sessions.contains_key(&a) && demux.sessions.contains_key(&d));
+        assert!(!demux.sessions.contains_key(&c));
+
+        // Sessions with requests in flight should never be evicted.
+        demux.set_max_concurrent_sessions(1);
+        let e = SessionID::random();
+        connect(&mut demux, e).expect_err("session should be rejected when no session is idle");
+        assert!(demux.sessions.contains_key(&a));
+        assert!(!demux.sessions.contains_key(&d) && !demux.sessions.contains_key(&e));
+    }
+
+    #[test]
+    fn test_request_rate_limit() {
+        let mut demux = Demux::new(Arc::new(Identity::new()));
+        demux.set_request_rate_limit(1, 2);
+
+        let id = SessionID::random();
+        let mut initiator = connect(&mut demux, id).unwrap();
+        // Prevent tokens from being refilled while the requests are made.
+        demux.sessions.get_mut(&id).unwrap().requests.last_refill = u64::MAX;
+
+        let results: Vec<_> = (0..5)
+            .map(|request_id| request(&mut demux, &mut initiator, id, request_id).unwrap())
+            .collect();
+        assert!(matches!(results[0], Some(Message::Request(_))));
+        assert!(matches!(results[1], Some(Message::Request(_))));
+        for (request_id, result) in results.iter().enumerate().skip(2) {
+            let rejected = result
+                .as_ref()
+                .expect("rejected request should be responded to");
+            assert!(
+                is_rejected(rejected, request_id as u64, DemuxError::RateLimited),
+                "requests above the burst should be rate limited"
+            );
+        }
+
+        // Rejected requests should not be in flight and the session should remain usable.
+        assert!((2..5).all(|request_id| !demux.sessions[&id].in_flight.contains(&request_id)));
+        assert!(demux.sessions.contains_key(&id));
+    }
+
+    #[test]
+    fn test_max_in_flight_requests() {
+        let mut demux = Demux::new(Arc::new(Identity::new()));
+
+        let id = SessionID::random();
+        let mut initiator = connect(&mut demux, id).unwrap();
+        // Requests should be rate limited by default.
+        assert_ne!(demux.sessions[&id].requests.rate, 0);
+        for request_id in 0..MAX_IN_FLIGHT_REQUESTS as u64 {
+            let result = request(&mut demux, &mut initiator, id, request_id).unwrap();
+            assert!(matches!(result, Some(Message::Request(_))));
+        }
+
+        // Requests above the in-flight limit should be rejected.
+        let request_id = MAX_IN_FLIGHT_REQUESTS as u64;
+        let result = request(&mut demux, &mut initiator, id, request_id).unwrap();
+        assert!(is_rejected(
+            result.as_ref().unwrap(),
+            request_id,
+            DemuxError::MaxInFlightRequests
+        ));
+
+        // Released requests should make room for new ones.
+        demux.release_request(id, 0);
+        let result = request(&mut demux, &mut initiator, id, request_id).unwrap();
+        assert!(matches!(result, Some(Message::Request(_))));
+    }
+}
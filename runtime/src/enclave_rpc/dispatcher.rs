@@ -1,16 +1,30 @@
 //! RPC dispatcher.
-use std::collections::HashMap;
+use std::{collections::HashMap, iter::Peekable};
 
 use anyhow::{bail, Result};
 use thiserror::Error;
 
-use crate::{common::sgx::QuotePolicy, consensus::state::keymanager::Status as KeyManagerStatus};
+use crate::{
+    common::sgx::QuotePolicy,
+    consensus::state::keymanager::Status as KeyManagerStatus,
+    storage::mkvs::sync::{sync_metrics, SyncMetrics},
+};
 
 use super::{
+    access::{AccessError, AccessPolicy},
     context::Context,
-    types::{Body, Kind, Request, Response},
+    types::{Body, Chunk, Error as RpcError, Kind, Request, Response},
 };
 
+/// Name of the module used for errors produced by the dispatcher.
+pub const MODULE_NAME: &str = "enclave_rpc";
+
+/// Code of the error returned when the caller is denied by the access policy of the method.
+pub const CODE_ACCESS_DENIED: u32 = 3;
+
+/// Name of the local RPC method returning a snapshot of the storage read syncer metrics.
+pub const LOCAL_METHOD_SYNC_METRICS: &str = "storage_sync_metrics";
+
 /// Dispatch error.
 #[derive(Error, Debug)]
 enum DispatchError {
@@ -18,6 +32,28 @@ enum DispatchError {
     MethodNotFound { method: String },
     #[error("invalid RPC kind: {method:?} ({kind:?})")]
     InvalidRpcKind { method: String, kind: Kind },
+    #[error("access denied: {method:?}: {source}")]
+    AccessDenied {
+        method: String,
+        #[source]
+        source: AccessError,
+    },
+}
+
+impl DispatchError {
+    fn code(&self) -> u32 {
+        match self {
+            DispatchError::MethodNotFound { .. } => 1,
+            DispatchError::InvalidRpcKind { .. } => 2,
+            DispatchError::AccessDenied { .. } => CODE_ACCESS_DENIED,
+        }
+    }
+}
+
+impl From<DispatchError> for RpcError {
+    fn from(err: DispatchError) -> Self {
+        RpcError::new(MODULE_NAME, err.code(), &err.to_string())
+    }
 }
 
 /// Custom context initializer.
@@ -42,6 +78,25 @@ pub struct MethodDescriptor {
     pub name: String,
     /// Specifies which kind of RPC is allowed to call the method.
     pub kind: Kind,
+    /// Specifies which callers are allowed to call the method.
+    pub access: AccessPolicy,
+}
+
+impl MethodDescriptor {
+    /// Create a new method descriptor which allows all callers.
+    pub fn new(name: &str, kind: Kind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            access: AccessPolicy::default(),
+        }
+    }
+
+    /// Restrict callers of the method with the given access policy.
+    pub fn with_access(mut self, access: AccessPolicy) -> Self {
+        self.access = access;
+        self
+    }
 }
 
 /// Handler for a RPC method.
@@ -61,6 +116,69 @@ where
     }
 }
 
+/// Chunks of a streamed response.
+pub type Chunks<Rsp> = Box<dyn Iterator<Item = Result<Rsp>> + Send>;
+
+/// Handler for a RPC method which streams its response in chunks.
+///
+/// Chunks are only produced as the client requests them, so the whole response never needs to
+/// be held in memory at once.
+pub trait StreamingMethodHandler<Rq, Rsp> {
+    /// Invoke the method implementation and return the response chunks.
+    fn handle(&self, ctx: &mut Context, request: &Rq) -> Result<Chunks<Rsp>>;
+}
+
+impl<Rq, Rsp, F> StreamingMethodHandler<Rq, Rsp> for F
+where
+    Rq: 'static,
+    Rsp: 'static,
+    F: Fn(&mut Context, &Rq) -> Result<Chunks<Rsp>> + 'static,
+{
+    fn handle(&self, ctx: &mut Context, request: &Rq) -> Result<Chunks<Rsp>> {
+        (*self)(ctx, request)
+    }
+}
+
+/// Remaining chunks of a streamed response.
+pub struct ResponseStream {
+    chunks: Peekable<Chunks<cbor::Value>>,
+}
+
+impl ResponseStream {
+    pub(crate) fn new(chunks: Chunks<cbor::Value>) -> Self {
+        Self {
+            chunks: chunks.peekable(),
+        }
+    }
+
+    /// Produce the next chunk of the response to the request with the given identifier.
+    ///
+    /// A failure to produce a chunk ends the stream with an error response.
+    pub fn next_response(&mut self, id: u64) -> Response {
+        let body = match self.chunks.next() {
+            Some(Ok(data)) => Body::Chunk(Chunk {
+                data: Some(data),
+                last: self.chunks.peek().is_none(),
+            }),
+            Some(Err(error)) => {
+                self.chunks = (Box::new(std::iter::empty()) as Chunks<_>).peekable();
+                Body::Error(error.into())
+            }
+            None => Body::Chunk(Chunk {
+                data: None,
+                last: true,
+            }),
+        };
+
+        Response { id, body }
+    }
+
+    /// Whether all chunks have been produced.
+    pub fn is_done(&mut self) -> bool {
+        self.chunks.peek().is_none()
+    }
+}
+
 /// Dispatcher for a RPC method.
 pub trait MethodHandlerDispatch {
     /// Get method descriptor.
@@ -68,6 +186,15 @@ pub trait MethodHandlerDispatch {
 
     /// Dispatch request.
     fn dispatch(&self, ctx: &mut Context, request: Request) -> Result<Response>;
+
+    /// Dispatch request, returning the remaining chunks in case the response is streamed.
+    fn dispatch_stream(
+        &self,
+        ctx: &mut Context,
+        request: Request,
+    ) -> Result<(Response, Option<ResponseStream>)> {
+        Ok((self.dispatch(ctx, request)?, None))
+    }
 }
 
 struct MethodHandlerDispatchImpl<Rq, Rsp> {
@@ -91,11 +218,66 @@ where
         let response = self.handler.handle(ctx, &request)?;
 
         Ok(Response {
+            id: 0,
             body: Body::Success(cbor::to_value(response)),
         })
     }
 }
 
+struct StreamingMethodHandlerDispatchImpl<Rq, Rsp> {
+    /// Method descriptor.
+    descriptor: MethodDescriptor,
+    /// Method handler.
+    handler: Box<dyn StreamingMethodHandler<Rq, Rsp> + Send + Sync>,
+}
+
+impl<Rq, Rsp> StreamingMethodHandlerDispatchImpl<Rq, Rsp>
+where
+    Rq: cbor::Decode + 'static,
+    Rsp: cbor::Encode + 'static,
+{
+    fn handle(&self, ctx: &mut Context, request: Request) -> Result<Chunks<cbor::Value>> {
+        let request = cbor::from_value(request.args)?;
+        let chunks = self.handler.handle(ctx, &request)?;
+
+        Ok(Box::new(chunks.map(|chunk| chunk.map(cbor::to_value))))
+    }
+}
+
+impl<Rq, Rsp> MethodHandlerDispatch for StreamingMethodHandlerDispatchImpl<Rq, Rsp>
+where
+    Rq: cbor::Decode + 'static,
+    Rsp: cbor::Encode + 'static,
+{
+    fn get_descriptor(&self) -> &MethodDescriptor {
+        &self.descriptor
+    }
+
+    fn dispatch(&self, ctx: &mut Context, request: Request) -> Result<Response> {
+        // Without a session to hold the stream the whole response needs to be collected.
+        let chunks = self.handle(ctx, request)?.collect::<Result<Vec<_>>>()?;
+
+        Ok(Response {
+            id: 0,
+            body: Body::Success(cbor::Value::Array(chunks)),
+        })
+    }
+
+    fn dispatch_stream(
+        &self,
+        ctx: &mut Context,
+        request: Request,
+    ) -> Result<(Response, Option<ResponseStream>)> {
+        let mut stream = ResponseStream::new(self.handle(ctx, request)?);
+        let response = stream.next_response(0);
+        if stream.is_done() {
+            return Ok((response, None));
+        }
+
+        Ok((response, Some(stream)))
+    }
+}
+
 /// RPC method dispatcher implementation.
 pub struct Method {
     /// Method dispatcher.
@@ -118,6 +300,25 @@ impl Method {
         }
     }
 
+    /// Create a new enclave method descriptor for a method which streams its response.
+    ///
+    /// Streamed responses are only supported in Noise sessions, for other kinds of RPC calls all
+    /// chunks are collected and returned as a single array. In both cases `RpcClient` returns
+    /// the chunks as an array.
+    pub fn new_streaming<Rq, Rsp, Handler>(method: MethodDescriptor, handler: Handler) -> Self
+    where
+        Rq: cbor::Decode + 'static,
+        Rsp: cbor::Encode + 'static,
+        Handler: StreamingMethodHandler<Rq, Rsp> + Send + Sync + 'static,
+    {
+        Method {
+            dispatcher: Box::new(StreamingMethodHandlerDispatchImpl {
+                descriptor: method,
+                handler: Box::new(handler),
+            }),
+        }
+    }
+
     /// Return method name.
     fn get_name(&self) -> &String {
         &self.dispatcher.get_descriptor().name
@@ -128,10 +329,24 @@ impl Method {
         self.dispatcher.get_descriptor().kind
     }
 
+    /// Return access policy.
+    fn get_access(&self) -> &AccessPolicy {
+        &self.dispatcher.get_descriptor().access
+    }
+
     /// Dispatch a request.
     fn dispatch(&self, ctx: &mut Context, request: Request) -> Result<Response> {
         self.dispatcher.dispatch(ctx, request)
     }
+
+    /// Dispatch a request, returning the remaining chunks in case the response is streamed.
+    fn dispatch_stream(
+        &self,
+        ctx: &mut Context,
+        request: Request,
+    ) -> Result<(Response, Option<ResponseStream>)> {
+        self.dispatcher.dispatch_stream(ctx, request)
+    }
 }
 
 /// Key manager status update handler callback.
@@ -153,6 +368,12 @@ pub struct Dispatcher {
 }
 
 impl Dispatcher {
+    /// Register the local `storage_sync_metrics` method which exports a snapshot of the storage
+    /// read syncer metrics to the host.
+    pub fn enable_sync_metrics(&mut self) {
+        self.add_method(sync_metrics_method());
+    }
+
     /// Register a new method in the dispatcher.
     pub fn add_method(&mut self, method: Method) {
         self.methods.insert(method.get_name().clone(), method);
@@ -167,17 +388,49 @@ impl Dispatcher {
     }
 
     /// Dispatch request.
-    pub fn dispatch(&self, mut ctx: Context, request: Request, kind: Kind) -> Response {
+    ///
+    /// The returned response carries the identifier of the request.
+    pub fn dispatch(&self, ctx: Context, request: Request, kind: Kind) -> Response {
+        self.dispatch_impl(ctx, request, kind, false).0
+    }
+
+    /// Dispatch request, returning the remaining chunks in case the response is streamed.
+    ///
+    /// The first chunk is returned in the response, the remaining ones should be produced using
+    /// the returned stream as the client requests them.
+    pub fn dispatch_stream(
+        &self,
+        ctx: Context,
+        request: Request,
+        kind: Kind,
+    ) -> (Response, Option<ResponseStream>) {
+        self.dispatch_impl(ctx, request, kind, true)
+    }
+
+    fn dispatch_impl(
+        &self,
+        mut ctx: Context,
+        request: Request,
+        kind: Kind,
+        stream: bool,
+    ) -> (Response, Option<ResponseStream>) {
         if let Some(ref ctx_init) = self.ctx_initializer {
             ctx_init.init(&mut ctx);
         }
 
-        match self.dispatch_fallible(&mut ctx, request, kind) {
-            Ok(response) => response,
-            Err(error) => Response {
-                body: Body::Error(format!("{error}")),
-            },
-        }
+        let id = request.id;
+        let (mut response, stream) = match self.dispatch_fallible(&mut ctx, request, kind, stream) {
+            Ok(result) => result,
+            Err(error) => (
+                Response {
+                    id: 0,
+                    body: Body::Error(error.into()),
+                },
+                None,
+            ),
+        };
+        response.id = id;
+        (response, stream)
     }
 
     fn dispatch_fallible(
@@ -185,12 +438,13 @@ impl Dispatcher {
         ctx: &mut Context,
         request: Request,
         kind: Kind,
-    ) -> Result<Response> {
+        stream: bool,
+    ) -> Result<(Response, Option<ResponseStream>)> {
         let method = match self.methods.get(&request.method) {
             Some(method) => method,
-            None => bail!(DispatchError::MethodNotFound {
+            None => bail!(RpcError::from(DispatchError::MethodNotFound {
                 method: request.method,
-            }),
+            })),
         };
 
         match (method.get_kind(), kind) {
@@ -198,13 +452,24 @@ impl Dispatcher {
             (Kind::InsecureQuery, Kind::InsecureQuery) => {}
             (Kind::InsecureQuery, Kind::NoiseSession) => {}
             (Kind::LocalQuery, Kind::LocalQuery) => {}
-            _ => bail!(DispatchError::InvalidRpcKind {
+            _ => bail!(RpcError::from(DispatchError::InvalidRpcKind {
                 method: request.method,
                 kind,
-            }),
+            })),
         };
 
-        method.dispatch(ctx, request)
+        // Enforce the access policy before the handler runs.
+        if let Err(source) = method.get_access().check(ctx) {
+            bail!(RpcError::from(DispatchError::AccessDenied {
+                method: request.method,
+                source,
+            }));
+        }
+
+        if stream {
+            return method.dispatch_stream(ctx, request);
+        }
+        Ok((method.dispatch(ctx, request)?, None))
     }
 
     /// Handle key manager status update.
@@ -237,3 +502,252 @@ impl Dispatcher {
         self.km_quote_policy_handler = f;
     }
 }
+
+/// Local RPC method returning a snapshot of all storage read syncer metrics.
+fn sync_metrics_method() -> Method {
+    Method::new(
+        MethodDescriptor::new(LOCAL_METHOD_SYNC_METRICS, Kind::LocalQuery),
+        |_ctx: &mut Context, _args: &()| -> Result<SyncMetrics> { Ok(sync_metrics()) },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use io_context::Context as IoContext;
+
+    use crate::{
+        consensus::{
+            beacon::EpochTime,
+            registry::RolesMask,
+            roothash::{ComputeResultsHeader, Header},
+            state::ConsensusState,
+            verifier::{Error as VerifierError, Verifier},
+            Event, LightBlock,
+        },
+        identity::Identity,
+        storage::KeyValue,
+        types::{Error as StorageError, EventKind},
+    };
+
+    use super::{
+        super::{
+            access::AccessPolicy,
+            context::Context,
+            types::{Body, Kind, Request},
+        },
+        Chunks, Dispatcher, Method, MethodDescriptor, CODE_ACCESS_DENIED, MODULE_NAME,
+    };
+
+    struct NoopVerifier;
+
+    impl Verifier for NoopVerifier {
+        fn sync(&self, _height: u64) -> Result<(), VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn verify(
+            &self,
+            _consensus_block: LightBlock,
+            _runtime_header: Header,
+            _epoch: EpochTime,
+        ) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn verify_for_query(
+            &self,
+            _consensus_block: LightBlock,
+            _runtime_header: Header,
+            _epoch: EpochTime,
+        ) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn unverified_state(
+            &self,
+            _consensus_block: LightBlock,
+        ) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn latest_state(&self) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn state_at(&self, _height: u64) -> Result<ConsensusState, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn events_at(&self, _height: u64, _kind: EventKind) -> Result<Vec<Event>, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn latest_height(&self) -> Result<u64, VerifierError> {
+            Err(VerifierError::Internal)
+        }
+
+        fn trust(&self, _header: &ComputeResultsHeader) -> Result<(), VerifierError> {
+            Err(VerifierError::Internal)
+        }
+    }
+
+    struct NoopStorage;
+
+    impl KeyValue for NoopStorage {
+        fn get(&self, _key: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+            Ok(vec![])
+        }
+
+        fn insert(&self, _key: Vec<u8>, _value: Vec<u8>) -> Result<(), StorageError> {
+            Ok(())
+        }
+    }
+
+    // The generated client is not exercised by the tests.
+    #[allow(dead_code)]
+    mod service {
+        use super::{AccessPolicy, RolesMask};
+
+        crate::enclave_rpc_service! {
+            /// Test service.
+            pub trait TestService, client TestServiceClient {
+                /// Echo the given value.
+                secure fn echo(u64) -> u64 = "echo";
+                /// Echo the given value, only for key manager nodes.
+                secure fn km_echo(u64) -> u64 = "km_echo",
+                    access = AccessPolicy::default().roles(RolesMask::ROLE_KEY_MANAGER);
+                /// Count up to the given value, one chunk per number.
+                secure(stream) fn count(u64) -> u64 = "count";
+            }
+        }
+    }
+
+    use service::TestService;
+
+    struct TestServiceImpl;
+
+    impl TestService for TestServiceImpl {
+        fn echo(_ctx: &mut Context, request: &u64) -> Result<u64> {
+            Ok(*request)
+        }
+
+        fn km_echo(_ctx: &mut Context, request: &u64) -> Result<u64> {
+            Ok(*request)
+        }
+
+        fn count(_ctx: &mut Context, request: &u64) -> Result<Chunks<u64>> {
+            Ok(Box::new((0..*request).map(Ok)))
+        }
+    }
+
+    fn echo_method(name: &str, access: AccessPolicy) -> Method {
+        Method::new(
+            MethodDescriptor::new(name, Kind::NoiseSession).with_access(access),
+            |_ctx: &mut Context, args: &u64| -> Result<u64> { Ok(*args) },
+        )
+    }
+
+    #[test]
+    fn test_dispatch_access_denied() {
+        let mut dispatcher = Dispatcher::default();
+        dispatcher.add_method(echo_method("echo", AccessPolicy::default()));
+        dispatcher.add_method(echo_method(
+            "km_echo",
+            AccessPolicy::default().roles(RolesMask::ROLE_KEY_MANAGER),
+        ));
+
+        let storage = NoopStorage;
+        let dispatch = |method: &str| {
+            let ctx = Context::new(
+                IoContext::background().freeze(),
+                Arc::new(Identity::new()),
+                None,
+                Arc::new(NoopVerifier),
+                &storage,
+            );
+            let request = Request {
+                id: 7,
+                method: method.to_string(),
+                args: cbor::to_value(42u64),
+            };
+            dispatcher.dispatch(ctx, request, Kind::NoiseSession)
+        };
+
+        // Methods without an access policy should be open to everyone.
+        let response = dispatch("echo");
+        assert_eq!(response.id, 7);
+        assert!(matches!(response.body, Body::Success(_)));
+
+        // Unauthenticated callers should be denied before the handler runs.
+        let response = dispatch("km_echo");
+        assert_eq!(response.id, 7);
+        match response.body {
+            Body::Error(err) => {
+                assert_eq!(err.module, MODULE_NAME);
+                assert_eq!(err.code, CODE_ACCESS_DENIED);
+                assert!(err.message.contains("caller not authenticated"));
+            }
+            _ => panic!("access should be denied"),
+        }
+    }
+
+    #[test]
+    fn test_enclave_rpc_service() {
+        let mut dispatcher = Dispatcher::default();
+        TestServiceImpl::register(&mut dispatcher);
+
+        let storage = NoopStorage;
+        let ctx = || {
+            Context::new(
+                IoContext::background().freeze(),
+                Arc::new(Identity::new()),
+                None,
+                Arc::new(NoopVerifier),
+                &storage,
+            )
+        };
+        let request = |method: &str| Request {
+            id: 7,
+            method: method.to_string(),
+            args: cbor::to_value(3u64),
+        };
+
+        // Unary methods should return their response directly.
+        let response = dispatcher.dispatch(ctx(), request("echo"), Kind::NoiseSession);
+        match response.body {
+            Body::Success(value) => assert_eq!(cbor::from_value::<u64>(value).unwrap(), 3),
+            _ => panic!("echo should succeed"),
+        }
+
+        // Methods should only be callable with the declared kind.
+        let response = dispatcher.dispatch(ctx(), request("echo"), Kind::InsecureQuery);
+        assert!(matches!(response.body, Body::Error(_)));
+
+        // Access policies should be enforced.
+        let response = dispatcher.dispatch(ctx(), request("km_echo"), Kind::NoiseSession);
+        match response.body {
+            Body::Error(err) => assert!(err.message.contains("caller not authenticated")),
+            _ => panic!("access should be denied"),
+        }
+
+        // Streaming methods should return their response in chunks.
+        let (response, stream) =
+            dispatcher.dispatch_stream(ctx(), request("count"), Kind::NoiseSession);
+        let mut chunks = vec![response];
+        let mut stream = stream.expect("response should be streamed");
+        while !stream.is_done() {
+            chunks.push(stream.next_response(7));
+        }
+        let chunks: Vec<u64> = chunks
+            .into_iter()
+            .map(|response| match response.body {
+                Body::Chunk(chunk) => cbor::from_value(chunk.data.unwrap()).unwrap(),
+                _ => panic!("response should be a chunk"),
+            })
+            .collect();
+        assert_eq!(chunks, vec![0, 1, 2]);
+    }
+}
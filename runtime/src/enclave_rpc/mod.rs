@@ -1,11 +1,12 @@
 //! Secure inter-enclave RPC.
 
+pub mod access;
 pub mod client;
 pub mod context;
 pub mod demux;
 pub mod dispatcher;
 pub mod session;
-mod transport;
+pub mod transport;
 pub mod types;
 
 // Re-exports.
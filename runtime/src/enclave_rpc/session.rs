@@ -1,17 +1,28 @@
 //! Secure channel session.
-use std::{collections::HashSet, io::Write, mem, sync::Arc};
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    io::Write,
+    mem,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::Result;
 use io_context::Context;
 use snow;
 use thiserror::Error;
 
-use super::types::Message;
+use super::types::{LegacyMessage, Message};
 use crate::{
     common::{
-        crypto::signature::{self, PublicKey, Signature, Signer},
+        crypto::{
+            hash::Hash,
+            signature::{self, PublicKey, Signature, Signer},
+        },
         namespace::Namespace,
         sgx::{ias, EnclaveIdentity, Quote, QuotePolicy, VerifiedQuote},
+        time::insecure_posix_time,
     },
     consensus::{state::registry::ImmutableState as RegistryState, verifier::Verifier},
     identity::Identity,
@@ -21,6 +32,23 @@ use crate::{
 const NOISE_PATTERN: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
 /// RAK signature session binding context.
 const RAK_SESSION_BINDING_CONTEXT: [u8; 8] = *b"EkRakRpc";
+/// Size of the explicit nonce prepended to each transport message.
+const NONCE_SIZE: usize = 8;
+/// Session protocol version of peers which don't negotiate a version during the handshake.
+///
+/// Transport messages carry implicit nonces and must be processed in order, so only a single
+/// request can be in flight and responses are never streamed.
+pub const SESSION_VERSION_LEGACY: u16 = 0;
+/// Session protocol version where transport messages carry explicit nonces, which allows
+/// multiple requests in flight and streamed responses, and where errors are coded.
+pub const SESSION_VERSION_EXPLICIT_NONCES: u16 = 1;
+/// Highest supported session protocol version.
+pub const SESSION_VERSION: u16 = SESSION_VERSION_EXPLICIT_NONCES;
+/// Number of most recent nonces tracked for replay protection. Messages may be received out of
+/// order as long as they are not older than this window.
+const NONCE_WINDOW: u64 = 64;
+/// Default number of verified quotes kept in a verification cache.
+pub const DEFAULT_VERIFICATION_CACHE_SIZE: usize = 128;
 
 /// Session-related error.
 #[derive(Error, Debug)]
@@ -45,6 +73,21 @@ enum SessionError {
     RAKNotFound,
     #[error("runtime id not set")]
     RuntimeNotSet,
+    #[error("replayed or stale message")]
+    ReplayedMessage,
+}
+
+/// Payload of handshake messages exchanged by peers which negotiate the session protocol version.
+///
+/// Peers which don't negotiate a version ignore the payload of the first handshake message and
+/// send a bare RAK binding in the others, which never decodes as a handshake payload.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+struct HandshakePayload {
+    /// Highest session protocol version supported by the sender.
+    version: u16,
+    /// Serialized RAK binding, if any.
+    #[cbor(optional)]
+    rak_binding: Vec<u8>,
 }
 
 /// Information about a session.
@@ -56,10 +99,117 @@ pub struct SessionInfo {
 enum State {
     Handshake1(snow::HandshakeState),
     Handshake2(snow::HandshakeState),
-    Transport(snow::TransportState),
+    Transport(snow::StatelessTransportState),
     Closed,
 }
 
+/// Replay protection for transport messages with explicit nonces.
+///
+/// Tracks the highest received nonce and which of the preceding `NONCE_WINDOW` nonces have been
+/// seen, so that messages can be processed out of order but each one at most once.
+#[derive(Default)]
+struct NonceWindow {
+    /// Highest received nonce plus one (zero if no message has been received yet).
+    next: u64,
+    /// Bit `i` is set iff nonce `next - 1 - i` has been received.
+    seen: u64,
+}
+
+impl NonceWindow {
+    /// Whether a message with the given nonce may be accepted.
+    fn check(&self, nonce: u64) -> bool {
+        if nonce >= self.next {
+            return true;
+        }
+        let offset = self.next - 1 - nonce;
+        offset < NONCE_WINDOW && self.seen & (1 << offset) == 0
+    }
+
+    /// Mark the given nonce as received.
+    fn mark(&mut self, nonce: u64) {
+        if nonce >= self.next {
+            let shift = nonce - self.next + 1;
+            self.seen = if shift >= NONCE_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.next = nonce + 1;
+        } else {
+            self.seen |= 1 << (self.next - 1 - nonce);
+        }
+    }
+}
+
+/// A quote verified under a given quote policy.
+struct CachedVerification {
+    policy: QuotePolicy,
+    verified_quote: VerifiedQuote,
+}
+
+/// Cache of verified remote attestation quotes.
+///
+/// Sessions sharing a verification cache only verify the quote of a remote enclave during the
+/// first handshake. Later handshakes with the same enclave, e.g. when a session is re-established
+/// after a transient failure, reuse the verified quote for as long as it remains fresh under the
+/// quote policy and only verify that the RAK binds the new session key.
+#[derive(Clone)]
+pub struct VerificationCache {
+    entries: Arc<Mutex<lru::LruCache<Hash, CachedVerification>>>,
+}
+
+impl VerificationCache {
+    /// Create a new verification cache holding up to `capacity` verified quotes.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).expect("capacity should be non-zero");
+        Self {
+            entries: Arc::new(Mutex::new(lru::LruCache::new(capacity))),
+        }
+    }
+
+    /// Return the cached verification of the given quote, if it was verified under the same
+    /// policy and is still fresh.
+    fn get(&self, quote: &Quote, policy: &QuotePolicy) -> Option<VerifiedQuote> {
+        let key = Self::key(quote);
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(&key)?;
+        if &entry.policy != policy
+            || !quote.is_fresh(
+                insecure_posix_time(),
+                entry.verified_quote.timestamp,
+                policy,
+            )
+        {
+            entries.pop(&key);
+            return None;
+        }
+        Some(entry.verified_quote.clone())
+    }
+
+    /// Cache the verification of the given quote under the given policy.
+    fn put(&self, quote: &Quote, policy: &QuotePolicy, verified_quote: VerifiedQuote) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.put(
+            Self::key(quote),
+            CachedVerification {
+                policy: policy.clone(),
+                verified_quote,
+            },
+        );
+    }
+
+    fn key(quote: &Quote) -> Hash {
+        Hash::digest_bytes(&cbor::to_vec(quote.clone()))
+    }
+}
+
+impl Default for VerificationCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_VERIFICATION_CACHE_SIZE)
+    }
+}
+
 /// An encrypted and authenticated RPC session.
 pub struct Session {
     consensus_verifier: Option<Arc<dyn Verifier>>,
@@ -69,12 +219,18 @@ pub struct Session {
     remote_node: Option<signature::PublicKey>,
     remote_runtime_id: Option<Namespace>,
     policy: Option<Arc<QuotePolicy>>,
+    verification_cache: Option<VerificationCache>,
     info: Option<Arc<SessionInfo>>,
     state: State,
+    /// Highest session protocol version supported by the remote peer, if it negotiates one.
+    remote_version: Option<u16>,
+    send_nonce: u64,
+    recv_nonces: NonceWindow,
     buf: Vec<u8>,
 }
 
 impl Session {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         consensus_verifier: Option<Arc<dyn Verifier>>,
         handshake_state: snow::HandshakeState,
@@ -83,6 +239,7 @@ impl Session {
         remote_enclaves: Option<HashSet<EnclaveIdentity>>,
         remote_runtime_id: Option<Namespace>,
         policy: Option<Arc<QuotePolicy>>,
+        verification_cache: Option<VerificationCache>,
     ) -> Self {
         Self {
             consensus_verifier,
@@ -92,8 +249,12 @@ impl Session {
             remote_node: None,
             remote_runtime_id,
             policy,
+            verification_cache,
             info: None,
             state: State::Handshake1(handshake_state),
+            remote_version: None,
+            send_nonce: 0,
+            recv_nonces: NonceWindow::default(),
             buf: vec![0u8; 65535],
         }
     }
@@ -103,6 +264,11 @@ impl Session {
     /// In case the session is in transport mode the returned result will
     /// contained a parsed message. The `writer` will be used in case any
     /// protocol replies need to be generated.
+    ///
+    /// Unless the remote peer only supports the legacy session protocol version,
+    /// transport messages carry explicit nonces, so they can be processed in
+    /// a different order than they were written, e.g. when several requests
+    /// are in flight at the same time.
     pub fn process_data<W: Write>(
         &mut self,
         data: Vec<u8>,
@@ -119,14 +285,22 @@ impl Session {
                     }
 
                     // -> e
-                    let len = state.write_message(&[], &mut self.buf)?;
+                    let payload = cbor::to_vec(HandshakePayload {
+                        version: SESSION_VERSION,
+                        ..Default::default()
+                    });
+                    let len = state.write_message(&payload, &mut self.buf)?;
                     writer.write_all(&self.buf[..len])?;
                 } else {
                     // <- e
-                    state.read_message(&data, &mut self.buf)?;
+                    let len = state.read_message(&data, &mut self.buf)?;
+                    self.remote_version = cbor::from_slice::<HandshakePayload>(&self.buf[..len])
+                        .ok()
+                        .map(|payload| payload.version);
 
                     // -> e, ee, s, es
-                    let len = state.write_message(&self.get_rak_binding(), &mut self.buf)?;
+                    let payload = self.get_handshake_payload();
+                    let len = state.write_message(&payload, &mut self.buf)?;
                     writer.write_all(&self.buf[..len])?;
                 }
 
@@ -136,29 +310,60 @@ impl Session {
                 if state.is_initiator() {
                     // <- e, ee, s, es
                     let len = state.read_message(&data, &mut self.buf)?;
+                    let rak_binding = match cbor::from_slice::<HandshakePayload>(&self.buf[..len]) {
+                        Ok(payload) => {
+                            self.remote_version = Some(payload.version);
+                            payload.rak_binding
+                        }
+                        // The responder doesn't negotiate a version.
+                        Err(_) => self.buf[..len].to_vec(),
+                    };
                     let remote_static = state
                         .get_remote_static()
                         .expect("dh exchange just happened");
-                    self.info = self.verify_rak_binding(&self.buf[..len], remote_static)?;
+                    self.info = self.verify_rak_binding(&rak_binding, remote_static)?;
 
                     // -> s, se
-                    let len = state.write_message(&self.get_rak_binding(), &mut self.buf)?;
+                    let payload = self.get_handshake_payload();
+                    let len = state.write_message(&payload, &mut self.buf)?;
                     writer.write_all(&self.buf[..len])?;
                 } else {
                     // <- s, se
                     let len = state.read_message(&data, &mut self.buf)?;
+                    let rak_binding = match self.remote_version {
+                        Some(_) => {
+                            cbor::from_slice::<HandshakePayload>(&self.buf[..len])?.rak_binding
+                        }
+                        None => self.buf[..len].to_vec(),
+                    };
                     let remote_static = state
                         .get_remote_static()
                         .expect("dh exchange just happened");
-                    self.info = self.verify_rak_binding(&self.buf[..len], remote_static)?;
+                    self.info = self.verify_rak_binding(&rak_binding, remote_static)?;
                 }
 
                 // Move into transport mode.
-                self.state = State::Transport(state.into_transport_mode()?);
+                self.state = State::Transport(state.into_stateless_transport_mode()?);
             }
-            State::Transport(mut state) => {
+            State::Transport(state) => {
                 // TODO: Restore session in case of errors.
-                let len = state.read_message(&data, &mut self.buf)?;
+                let (nonce, data) = if self.version() >= SESSION_VERSION_EXPLICIT_NONCES {
+                    if data.len() < NONCE_SIZE {
+                        return Err(SessionError::InvalidInput.into());
+                    }
+                    let (nonce, data) = data.split_at(NONCE_SIZE);
+                    let nonce = u64::from_be_bytes(nonce.try_into().unwrap());
+                    if !self.recv_nonces.check(nonce) {
+                        return Err(SessionError::ReplayedMessage.into());
+                    }
+                    (nonce, data)
+                } else {
+                    // Messages with implicit nonces must be processed in order.
+                    (self.recv_nonces.next, &data[..])
+                };
+
+                let len = state.read_message(nonce, data, &mut self.buf)?;
+                self.recv_nonces.mark(nonce);
                 let msg = cbor::from_slice(&self.buf[..len])?;
 
                 self.state = State::Transport(state);
@@ -177,9 +382,20 @@ impl Session {
     /// The `writer` will be used for protocol message output which should
     /// be transmitted to the remote session counterpart.
     pub fn write_message<W: Write>(&mut self, msg: Message, mut writer: W) -> Result<()> {
-        if let State::Transport(ref mut state) = self.state {
-            let msg = cbor::to_vec(msg);
-            let len = state.write_message(&msg, &mut self.buf)?;
+        if let State::Transport(ref state) = self.state {
+            let nonce = self.send_nonce;
+            let msg = match msg {
+                Message::Response(response) if !self.supports_coded_errors() => {
+                    cbor::to_vec(LegacyMessage::Response(response.into()))
+                }
+                msg => cbor::to_vec(msg),
+            };
+            let len = state.write_message(nonce, &msg, &mut self.buf)?;
+            self.send_nonce += 1;
+
+            if self.version() >= SESSION_VERSION_EXPLICIT_NONCES {
+                writer.write_all(&nonce.to_be_bytes())?;
+            }
             writer.write_all(&self.buf[..len])?;
 
             Ok(())
@@ -196,6 +412,37 @@ impl Session {
         self.state = State::Closed;
     }
 
+    /// Session protocol version negotiated during the handshake.
+    pub fn version(&self) -> u16 {
+        self.remote_version
+            .map_or(SESSION_VERSION_LEGACY, |version| {
+                version.min(SESSION_VERSION)
+            })
+    }
+
+    /// Whether multiple requests can be in flight and responses can be streamed.
+    pub fn supports_pipelining(&self) -> bool {
+        self.version() >= SESSION_VERSION_EXPLICIT_NONCES
+    }
+
+    /// Whether errors in responses are coded, see `types::Error`.
+    pub fn supports_coded_errors(&self) -> bool {
+        self.version() >= SESSION_VERSION_EXPLICIT_NONCES
+    }
+
+    /// Payload of the handshake message carrying the RAK binding, which is wrapped only in
+    /// case the remote peer negotiates the session protocol version.
+    fn get_handshake_payload(&self) -> Vec<u8> {
+        let rak_binding = self.get_rak_binding();
+        match self.remote_version {
+            Some(_) => cbor::to_vec(HandshakePayload {
+                version: SESSION_VERSION,
+                rak_binding,
+            }),
+            None => rak_binding,
+        }
+    }
+
     fn get_rak_binding(&self) -> Vec<u8> {
         match self.identity {
             Some(ref identity) => {
@@ -250,7 +497,12 @@ impl Session {
             .ok_or(SessionError::MissingQuotePolicy)?;
 
         let rak_binding: RAKBinding = cbor::from_slice(rak_binding)?;
-        let verified_quote = rak_binding.verify(remote_static, &self.remote_enclaves, policy)?;
+        let verified_quote = match self.verification_cache {
+            Some(ref cache) => {
+                rak_binding.verify_cached(remote_static, &self.remote_enclaves, policy, cache)?
+            }
+            None => rak_binding.verify(remote_static, &self.remote_enclaves, policy)?,
+        };
 
         // Verify node identity if verification is enabled.
         if self.consensus_verifier.is_some() {
@@ -384,7 +636,38 @@ impl RAKBinding {
         policy: &QuotePolicy,
     ) -> Result<VerifiedQuote> {
         let verified_quote = self.verify_quote(policy)?;
+        self.verify_session_binding(verified_quote, remote_static, remote_enclaves)
+    }
+
+    /// Verify the RAK binding, reusing the verification of the quote from the given cache in
+    /// case the same quote has already been verified under the same policy.
+    pub fn verify_cached(
+        &self,
+        remote_static: &[u8],
+        remote_enclaves: &Option<HashSet<EnclaveIdentity>>,
+        policy: &QuotePolicy,
+        cache: &VerificationCache,
+    ) -> Result<VerifiedQuote> {
+        let quote = self.quote();
+        let verified_quote = match cache.get(&quote, policy) {
+            Some(verified_quote) => verified_quote,
+            None => {
+                let verified_quote = self.verify_quote(policy)?;
+                cache.put(&quote, policy, verified_quote.clone());
+                verified_quote
+            }
+        };
+        self.verify_session_binding(verified_quote, remote_static, remote_enclaves)
+    }
 
+    /// Verify that the verified quote binds the RAK and that the RAK binds the session's static
+    /// public key.
+    fn verify_session_binding(
+        &self,
+        verified_quote: VerifiedQuote,
+        remote_static: &[u8],
+        remote_enclaves: &Option<HashSet<EnclaveIdentity>>,
+    ) -> Result<VerifiedQuote> {
         // Verify MRENCLAVE/MRSIGNER.
         if let Some(ref remote_enclaves) = remote_enclaves {
             if !remote_enclaves.contains(&verified_quote.identity) {
@@ -402,6 +685,14 @@ impl RAKBinding {
         Ok(verified_quote)
     }
 
+    /// Quote that is part of the RAK binding.
+    fn quote(&self) -> Quote {
+        match self {
+            Self::V0 { ref avr, .. } => Quote::Ias(avr.clone()),
+            Self::V1 { ref quote, .. } => quote.clone(),
+        }
+    }
+
     /// Verify the quote that is part of the RAK binding.
     pub fn verify_quote(&self, policy: &QuotePolicy) -> Result<VerifiedQuote> {
         match self {
@@ -419,6 +710,7 @@ pub struct Builder {
     remote_enclaves: Option<HashSet<EnclaveIdentity>>,
     remote_runtime_id: Option<Namespace>,
     policy: Option<Arc<QuotePolicy>>,
+    verification_cache: Option<VerificationCache>,
 }
 
 impl Builder {
@@ -461,6 +753,20 @@ impl Builder {
         self
     }
 
+    /// Return verification cache if configured in the builder.
+    pub fn get_verification_cache(&self) -> &Option<VerificationCache> {
+        &self.verification_cache
+    }
+
+    /// Configure a cache of verified quotes, shared by all sessions built using the cache.
+    ///
+    /// Handshakes with remote enclaves whose quotes are found in the cache skip quote
+    /// verification, which makes re-establishing sessions considerably cheaper.
+    pub fn verification_cache(mut self, cache: Option<VerificationCache>) -> Self {
+        self.verification_cache = cache;
+        self
+    }
+
     /// Enable RAK binding.
     pub fn local_identity(mut self, identity: Arc<Identity>) -> Self {
         self.identity = Some(identity);
@@ -478,6 +784,7 @@ impl Builder {
         Option<Arc<Identity>>,
         Option<HashSet<EnclaveIdentity>>,
         Option<Arc<QuotePolicy>>,
+        Option<VerificationCache>,
     ) {
         let noise_builder = snow::Builder::new(NOISE_PATTERN.parse().unwrap());
         let verifier = self.consensus_verifier.take();
@@ -485,6 +792,7 @@ impl Builder {
         let remote_enclaves = self.remote_enclaves.take();
         let remote_runtime_id = self.remote_runtime_id.take();
         let quote_policy = self.policy.take();
+        let verification_cache = self.verification_cache.take();
         let keypair = noise_builder.generate_keypair().unwrap();
 
         (
@@ -495,12 +803,14 @@ impl Builder {
             identity,
             remote_enclaves,
             quote_policy,
+            verification_cache,
         )
     }
 
     /// Build initiator session.
     pub fn build_initiator(self) -> Session {
-        let (builder, keypair, runtime_id, verifier, identity, enclaves, policy) = self.build();
+        let (builder, keypair, runtime_id, verifier, identity, enclaves, policy, cache) =
+            self.build();
         let session = builder
             .local_private_key(&keypair.private)
             .build_initiator()
@@ -513,12 +823,14 @@ impl Builder {
             enclaves,
             runtime_id,
             policy,
+            cache,
         )
     }
 
     /// Build responder session.
     pub fn build_responder(self) -> Session {
-        let (builder, keypair, runtime_id, verifier, identity, enclaves, policy) = self.build();
+        let (builder, keypair, runtime_id, verifier, identity, enclaves, policy, cache) =
+            self.build();
         let session = builder
             .local_private_key(&keypair.private)
             .build_responder()
@@ -531,6 +843,207 @@ impl Builder {
             enclaves,
             runtime_id,
             policy,
+            cache,
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        common::sgx::MAX_QUOTE_AGE,
+        enclave_rpc::types::{Body, Response},
+    };
+
+    fn connect() -> (Session, Session) {
+        let mut initiator = Builder::default().build_initiator();
+        let mut responder = Builder::default().build_responder();
+
+        let mut buffer = vec![];
+        initiator.process_data(vec![], &mut buffer).unwrap();
+        let mut reply = vec![];
+        responder.process_data(buffer, &mut reply).unwrap();
+        let mut buffer = vec![];
+        initiator.process_data(reply, &mut buffer).unwrap();
+        responder.process_data(buffer, vec![]).unwrap();
+        assert!(initiator.is_connected() && responder.is_connected());
+
+        (initiator, responder)
+    }
+
+    fn response(id: u64) -> Message {
+        Message::Response(Response {
+            id,
+            body: Body::Success(cbor::to_value(id)),
+        })
+    }
+
+    fn response_id(msg: Option<Message>) -> u64 {
+        match msg {
+            Some(Message::Response(rsp)) => rsp.id,
+            msg => panic!("expected response, got {msg:?}"),
+        }
+    }
+
+    #[test]
+    fn test_out_of_order_messages() {
+        let (mut initiator, mut responder) = connect();
+
+        let mut messages = vec![];
+        for id in 0..3 {
+            let mut buffer = vec![];
+            responder.write_message(response(id), &mut buffer).unwrap();
+            messages.push(buffer);
+        }
+
+        // Messages can be processed in any order.
+        for id in [2, 0, 1] {
+            let msg = initiator
+                .process_data(messages[id].clone(), vec![])
+                .unwrap();
+            assert_eq!(response_id(msg), id as u64);
+        }
+
+        // Replayed messages are rejected.
+        initiator
+            .process_data(messages[1].clone(), vec![])
+            .expect_err("replayed message should be rejected");
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        let (initiator, responder) = connect();
+        assert_eq!(initiator.version(), SESSION_VERSION);
+        assert_eq!(responder.version(), SESSION_VERSION);
+
+        let noise_builder = || snow::Builder::new(NOISE_PATTERN.parse().unwrap());
+        let keypair = noise_builder().generate_keypair().unwrap();
+        let mut buf = vec![0u8; 65535];
+
+        // Legacy initiator.
+        let mut responder = Builder::default().build_responder();
+        let mut legacy = noise_builder()
+            .local_private_key(&keypair.private)
+            .build_initiator()
+            .unwrap();
+        let len = legacy.write_message(&[], &mut buf).unwrap();
+        let mut reply = vec![];
+        responder
+            .process_data(buf[..len].to_vec(), &mut reply)
+            .unwrap();
+        legacy.read_message(&reply, &mut buf).unwrap();
+        let len = legacy.write_message(&[], &mut buf).unwrap();
+        responder.process_data(buf[..len].to_vec(), vec![]).unwrap();
+        let mut legacy = legacy.into_transport_mode().unwrap();
+        assert_eq!(responder.version(), SESSION_VERSION_LEGACY);
+        assert!(!responder.supports_pipelining());
+
+        // Messages should use implicit nonces.
+        for id in 0..2 {
+            let mut buffer = vec![];
+            responder.write_message(response(id), &mut buffer).unwrap();
+            let len = legacy.read_message(&buffer, &mut buf).unwrap();
+            let msg: Message = cbor::from_slice(&buf[..len]).unwrap();
+            assert_eq!(response_id(Some(msg)), id);
+
+            let len = legacy
+                .write_message(&cbor::to_vec(response(id)), &mut buf)
+                .unwrap();
+            let msg = responder.process_data(buf[..len].to_vec(), vec![]).unwrap();
+            assert_eq!(response_id(msg), id);
+        }
+
+        // Legacy responder.
+        let mut initiator = Builder::default().build_initiator();
+        let mut legacy = noise_builder()
+            .local_private_key(&keypair.private)
+            .build_responder()
+            .unwrap();
+        let mut buffer = vec![];
+        initiator.process_data(vec![], &mut buffer).unwrap();
+        legacy.read_message(&buffer, &mut buf).unwrap();
+        let len = legacy.write_message(&[], &mut buf).unwrap();
+        let mut buffer = vec![];
+        initiator
+            .process_data(buf[..len].to_vec(), &mut buffer)
+            .unwrap();
+        let len = legacy.read_message(&buffer, &mut buf).unwrap();
+        assert_eq!(len, 0, "RAK binding should not be wrapped");
+        let mut legacy = legacy.into_transport_mode().unwrap();
+        assert!(initiator.is_connected());
+        assert_eq!(initiator.version(), SESSION_VERSION_LEGACY);
+
+        let mut buffer = vec![];
+        initiator.write_message(response(0), &mut buffer).unwrap();
+        let len = legacy.read_message(&buffer, &mut buf).unwrap();
+        let msg: Message = cbor::from_slice(&buf[..len]).unwrap();
+        assert_eq!(response_id(Some(msg)), 0);
+    }
+
+    #[test]
+    fn test_nonce_window() {
+        let mut window = NonceWindow::default();
+        assert!(window.check(0));
+        window.mark(5);
+        assert!(!window.check(5));
+        assert!(window.check(0));
+        window.mark(0);
+        assert!(!window.check(0));
+
+        window.mark(5 + NONCE_WINDOW);
+        assert!(
+            !window.check(4),
+            "nonces before the window should be rejected"
+        );
+        assert!(window.check(6));
+        assert!(!window.check(5 + NONCE_WINDOW));
+    }
+
+    #[test]
+    fn test_verification_cache() {
+        let cache = VerificationCache::new(1);
+        let policy = QuotePolicy::default();
+        let quote = Quote::Ias(ias::AVR::default());
+        let verified_quote = VerifiedQuote {
+            timestamp: insecure_posix_time(),
+            ..Default::default()
+        };
+
+        assert!(cache.get(&quote, &policy).is_none());
+        cache.put(&quote, &policy, verified_quote.clone());
+        assert!(cache.get(&quote, &policy).is_some());
+
+        // Verifications under a different policy are not reused.
+        let other_policy = QuotePolicy {
+            ias: Some(ias::QuotePolicy {
+                disabled: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(cache.get(&quote, &other_policy).is_none());
+        assert!(
+            cache.get(&quote, &policy).is_none(),
+            "mismatched entries should be evicted"
+        );
+
+        // Stale verifications are not reused.
+        let stale_quote = VerifiedQuote {
+            timestamp: verified_quote.timestamp - MAX_QUOTE_AGE - 1,
+            ..Default::default()
+        };
+        cache.put(&quote, &policy, stale_quote);
+        assert!(cache.get(&quote, &policy).is_none());
+
+        // Only the most recently used verifications are kept.
+        let other_quote = Quote::Ias(ias::AVR {
+            body: b"other".to_vec(),
+            ..Default::default()
+        });
+        cache.put(&quote, &policy, verified_quote.clone());
+        cache.put(&other_quote, &policy, verified_quote);
+        assert!(cache.get(&quote, &policy).is_none());
+        assert!(cache.get(&other_quote, &policy).is_some());
+    }
+}
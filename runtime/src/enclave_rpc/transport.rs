@@ -1,4 +1,8 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::{anyhow, Error as AnyError};
 use futures::future::{self, BoxFuture};
@@ -9,6 +13,8 @@ use crate::{common::crypto::signature, types::Body, Protocol};
 use super::types;
 
 /// An EnclaveRPC transport.
+///
+/// Writes fail in case no response has been received within the given timeout, if any.
 pub trait Transport: Send + Sync {
     fn write_noise_session(
         &self,
@@ -17,6 +23,7 @@ pub trait Transport: Send + Sync {
         data: Vec<u8>,
         untrusted_plaintext: String,
         nodes: Vec<signature::PublicKey>,
+        timeout: Option<Duration>,
     ) -> BoxFuture<Result<(Vec<u8>, signature::PublicKey), AnyError>> {
         // Frame message.
         let frame = types::Frame {
@@ -25,7 +32,13 @@ pub trait Transport: Send + Sync {
             payload: data,
         };
 
-        self.write_message_impl(ctx, cbor::to_vec(frame), types::Kind::NoiseSession, nodes)
+        self.write_message_impl(
+            ctx,
+            cbor::to_vec(frame),
+            types::Kind::NoiseSession,
+            nodes,
+            timeout,
+        )
     }
 
     fn write_insecure_query(
@@ -33,8 +46,9 @@ pub trait Transport: Send + Sync {
         ctx: Context,
         data: Vec<u8>,
         nodes: Vec<signature::PublicKey>,
+        timeout: Option<Duration>,
     ) -> BoxFuture<Result<(Vec<u8>, signature::PublicKey), AnyError>> {
-        self.write_message_impl(ctx, data, types::Kind::InsecureQuery, nodes)
+        self.write_message_impl(ctx, data, types::Kind::InsecureQuery, nodes, timeout)
     }
 
     fn write_message_impl(
@@ -43,15 +57,20 @@ pub trait Transport: Send + Sync {
         data: Vec<u8>,
         kind: types::Kind,
         nodes: Vec<signature::PublicKey>,
+        timeout: Option<Duration>,
     ) -> BoxFuture<Result<(Vec<u8>, signature::PublicKey), AnyError>>;
 
-    fn set_peer_feedback(&self, _pfid: u64, _peer_feedback: Option<types::PeerFeedback>) {
-        // Default implementation doesn't do anything.
-    }
-
-    fn get_peer_feedback_id(&self) -> u64 {
+    /// Set feedback on the peer that handled a call, which is propagated together with one of
+    /// the subsequent writes.
+    ///
+    /// The feedback refers to the given node or, if no node is given, to the peer that handled
+    /// the last write.
+    fn set_peer_feedback(
+        &self,
+        _node: Option<signature::PublicKey>,
+        _peer_feedback: types::PeerFeedback,
+    ) {
         // Default implementation doesn't do anything.
-        0
     }
 }
 
@@ -61,7 +80,8 @@ pub struct RuntimeTransport {
     pub protocol: Arc<Protocol>,
     pub endpoint: String,
 
-    peer_feedback: Mutex<(u64, Option<types::PeerFeedback>)>,
+    /// Pending peer feedback, at most one per node.
+    peer_feedback: Mutex<VecDeque<(Option<signature::PublicKey>, types::PeerFeedback)>>,
 }
 
 impl RuntimeTransport {
@@ -69,7 +89,7 @@ impl RuntimeTransport {
         Self {
             protocol,
             endpoint: endpoint.to_string(),
-            peer_feedback: Mutex::new((0, None)),
+            peer_feedback: Mutex::new(VecDeque::new()),
         }
     }
 }
@@ -81,20 +101,15 @@ impl Transport for RuntimeTransport {
         data: Vec<u8>,
         kind: types::Kind,
         nodes: Vec<signature::PublicKey>,
+        timeout: Option<Duration>,
     ) -> BoxFuture<Result<(Vec<u8>, signature::PublicKey), AnyError>> {
-        let peer_feedback = {
-            let mut pf = self.peer_feedback.lock().unwrap();
-            let peer_feedback = pf.1.take();
-
-            // If non-success feedback was propagated this means that the peer will be changed for
-            // subsequent requests. Increment pfid to make sure that we don't incorporate stale
-            // feedback.
-            if !matches!(peer_feedback, None | Some(types::PeerFeedback::Success)) {
-                pf.0 += 1;
-            }
-
-            peer_feedback
-        };
+        // Each request carries at most one feedback, any remaining ones go with the next
+        // requests.
+        let (peer_feedback_node, peer_feedback) =
+            match self.peer_feedback.lock().unwrap().pop_front() {
+                Some((node, pf)) => (node, Some(pf)),
+                None => (None, None),
+            };
 
         // NOTE: This is not actually async in SGX, but futures should be
         //       dispatched on the current thread anyway.
@@ -106,6 +121,8 @@ impl Transport for RuntimeTransport {
                 kind,
                 nodes,
                 peer_feedback,
+                peer_feedback_node,
+                timeout: timeout.map(|timeout| timeout.as_millis() as u64),
             },
         );
 
@@ -118,16 +135,14 @@ impl Transport for RuntimeTransport {
         }
     }
 
-    fn set_peer_feedback(&self, pfid: u64, peer_feedback: Option<types::PeerFeedback>) {
-        let mut pf = self.peer_feedback.lock().unwrap();
-        if pf.0 != pfid {
-            return;
-        }
-
-        pf.1 = peer_feedback;
-    }
-
-    fn get_peer_feedback_id(&self) -> u64 {
-        self.peer_feedback.lock().unwrap().0
+    fn set_peer_feedback(
+        &self,
+        node: Option<signature::PublicKey>,
+        peer_feedback: types::PeerFeedback,
+    ) {
+        // Only the most recent feedback on a node is relevant.
+        let mut pending = self.peer_feedback.lock().unwrap();
+        pending.retain(|(n, _)| *n != node);
+        pending.push_back((node, peer_feedback));
     }
 }
@@ -1,5 +1,6 @@
 //! RPC protocol types.
 use rand::{rngs::OsRng, Rng};
+use thiserror::Error;
 
 impl_bytes!(
     SessionID,
@@ -54,33 +55,172 @@ pub struct Frame {
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 #[cbor(no_default)]
 pub struct Request {
+    /// Request identifier, unique among the requests in flight on a session.
+    #[cbor(optional)]
+    pub id: u64,
     pub method: String,
     pub args: cbor::Value,
 }
 
-#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+/// An error returned by a RPC method.
+///
+/// Errors are identified by the module they originate from and a code unique within the
+/// module, so that clients can tell them apart without parsing the message. Peers which don't
+/// support coded errors only send the message, see `LegacyResponse`.
+#[derive(Clone, Debug, Default, Error, cbor::Encode)]
+#[error("module: {module} code: {code} message: {message}")]
 pub struct Error {
+    #[cbor(optional)]
+    pub module: String,
+
+    #[cbor(optional)]
+    pub code: u32,
+
+    #[cbor(optional)]
     pub message: String,
 }
 
+impl Error {
+    /// Create a new error.
+    pub fn new(module: &str, code: u32, msg: &str) -> Self {
+        Self {
+            module: module.to_owned(),
+            code,
+            message: msg.to_owned(),
+        }
+    }
+}
+
+impl cbor::Decode for Error {
+    fn try_default() -> Result<Self, cbor::DecodeError> {
+        Ok(Default::default())
+    }
+
+    fn try_from_cbor_value(value: cbor::Value) -> Result<Self, cbor::DecodeError> {
+        match value {
+            // Peers which don't support coded errors only send the message.
+            cbor::Value::TextString(message) => Ok(Self {
+                message,
+                ..Default::default()
+            }),
+            value => {
+                let error: CodedError = cbor::Decode::try_from_cbor_value(value)?;
+                Ok(Self {
+                    module: error.module,
+                    code: error.code,
+                    message: error.message,
+                })
+            }
+        }
+    }
+}
+
+/// Encoding of coded errors.
+#[derive(cbor::Decode)]
+struct CodedError {
+    #[cbor(optional)]
+    module: String,
+
+    #[cbor(optional)]
+    code: u32,
+
+    #[cbor(optional)]
+    message: String,
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) => Self {
+                module: "unknown".to_string(),
+                code: 1,
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 pub enum Body {
     Success(cbor::Value),
-    Error(String),
+    Error(Error),
+    /// A chunk of a streamed response.
+    Chunk(Chunk),
+}
+
+/// A chunk of a streamed response.
+///
+/// Further chunks can be requested using `Message::NextChunk` until the last chunk has been
+/// received.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct Chunk {
+    /// Chunk data, missing in case the stream is empty.
+    #[cbor(optional)]
+    pub data: Option<cbor::Value>,
+    /// Whether this is the last chunk of the stream.
+    #[cbor(optional)]
+    pub last: bool,
 }
 
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 #[cbor(no_default)]
 pub struct Response {
+    /// Identifier of the request this is a response to.
+    #[cbor(optional)]
+    pub id: u64,
     pub body: Body,
 }
 
+/// Response in the format understood by peers which don't support coded errors.
+///
+/// Such peers are those which did not negotiate a session protocol version and all peers making
+/// insecure or local queries, errors sent to them only consist of the message.
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct LegacyResponse {
+    #[cbor(optional)]
+    pub id: u64,
+    pub body: LegacyBody,
+}
+
+/// Response body in the format understood by peers which don't support coded errors.
+#[derive(Clone, Debug, cbor::Encode)]
+pub enum LegacyBody {
+    Success(cbor::Value),
+    Error(String),
+    Chunk(Chunk),
+}
+
+impl From<Response> for LegacyResponse {
+    fn from(response: Response) -> Self {
+        let body = match response.body {
+            Body::Success(value) => LegacyBody::Success(value),
+            Body::Error(error) => LegacyBody::Error(error.message),
+            Body::Chunk(chunk) => LegacyBody::Chunk(chunk),
+        };
+
+        Self {
+            id: response.id,
+            body,
+        }
+    }
+}
+
+/// Protocol message in the format understood by peers which don't support coded errors.
+#[derive(Clone, Debug, cbor::Encode)]
+pub enum LegacyMessage {
+    Response(LegacyResponse),
+}
+
 /// Protocol message.
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 pub enum Message {
     Request(Request),
     Response(Response),
     Close,
+    /// Request for the next chunk of the streamed response to the request with the given
+    /// identifier.
+    NextChunk(u64),
 }
 
 /// Feedback on the peer that handled the last EnclaveRPC call.
@@ -90,3 +230,43 @@ pub enum PeerFeedback {
     Failure = 1,
     BadPeer = 2,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_legacy_encoding() {
+        // Coded errors should round trip.
+        let error = Error::new("module", 42, "failed");
+        let decoded: Error = cbor::from_slice(&cbor::to_vec(error)).unwrap();
+        assert_eq!(decoded.module, "module");
+        assert_eq!(decoded.code, 42);
+        assert_eq!(decoded.message, "failed");
+
+        // Errors of peers which don't support coded errors should be accepted.
+        let decoded: Error = cbor::from_slice(&cbor::to_vec("failed".to_string())).unwrap();
+        assert_eq!(decoded.module, "");
+        assert_eq!(decoded.code, 0);
+        assert_eq!(decoded.message, "failed");
+
+        // Legacy responses should only carry the message.
+        let response = LegacyResponse::from(Response {
+            id: 0,
+            body: Body::Error(Error::new("module", 42, "failed")),
+        });
+        assert!(matches!(response.body, LegacyBody::Error(ref message) if message == "failed"));
+        let legacy = cbor::to_vec(LegacyMessage::Response(response));
+        let decoded: Message = cbor::from_slice(&legacy).unwrap();
+        match decoded {
+            Message::Response(Response {
+                body: Body::Error(error),
+                ..
+            }) => {
+                assert_eq!(error.code, 0);
+                assert_eq!(error.message, "failed");
+            }
+            _ => panic!("legacy response should decode as an error response"),
+        }
+    }
+}
@@ -17,3 +17,233 @@ macro_rules! runtime_context {
             .expect("invalid runtime context")
     };
 }
+
+/// Define an EnclaveRPC service.
+///
+/// This generates a trait with one handler per method, which registers all methods with an
+/// RPC dispatcher via `register`, and a typed client wrapping `RpcClient` with one call per
+/// secure (Noise session) or insecure method. Local methods can only be called by the host and
+/// are not part of the client. Methods can optionally restrict their callers with an access
+/// policy.
+///
+/// Methods marked with `(stream)` return their response in chunks, see
+/// `Method::new_streaming`. Their handlers return an iterator of chunks and their client calls
+/// return all chunks reassembled into a vector.
+///
+/// Users of the macro need to depend on the `anyhow` and `io-context` crates.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// enclave_rpc_service! {
+///     /// My service.
+///     pub trait MyService, client MyServiceClient {
+///         /// Echo the given value.
+///         secure fn echo(u64) -> u64 = "echo";
+///         /// Echo the given value, only for key manager nodes.
+///         secure fn km_echo(u64) -> u64 = "km_echo",
+///             access = AccessPolicy::default().roles(RolesMask::ROLE_KEY_MANAGER);
+///         /// Return the status.
+///         insecure fn status(()) -> Status = "status";
+///         /// Return all entries, one chunk per entry.
+///         secure(stream) fn entries(()) -> Entry = "entries";
+///         /// Initialize the service.
+///         local fn init(InitRequest) -> () = "init";
+///     }
+/// }
+///
+/// struct MyServiceImpl;
+///
+/// impl MyService for MyServiceImpl {
+///     // ...
+/// }
+///
+/// MyServiceImpl::register(&mut dispatcher);
+///
+/// let client = MyServiceClient::new(rpc_client);
+/// let value = client.echo(ctx, 42).await?;
+/// ```
+#[macro_export]
+macro_rules! enclave_rpc_service {
+    (@kind secure) => { $crate::enclave_rpc::types::Kind::NoiseSession };
+    (@kind insecure) => { $crate::enclave_rpc::types::Kind::InsecureQuery };
+    (@kind local) => { $crate::enclave_rpc::types::Kind::LocalQuery };
+
+    (@access) => { $crate::enclave_rpc::access::AccessPolicy::default() };
+    (@access $access:expr) => { $access };
+
+    (@handler_result $response:ty, []) => { ::anyhow::Result<$response> };
+    (@handler_result $response:ty, [stream]) => {
+        ::anyhow::Result<$crate::enclave_rpc::dispatcher::Chunks<$response>>
+    };
+
+    (@method $descriptor:expr, $handler:expr, []) => {
+        $crate::enclave_rpc::dispatcher::Method::new($descriptor, $handler)
+    };
+    (@method $descriptor:expr, $handler:expr, [stream]) => {
+        $crate::enclave_rpc::dispatcher::Method::new_streaming($descriptor, $handler)
+    };
+
+    (@call_result $response:ty, []) => { $response };
+    (@call_result $response:ty, [stream]) => { ::std::vec::Vec<$response> };
+
+    (
+        @call secure $(#[$attr:meta])* $method:ident($request:ty) -> $response:ty = $name:expr,
+            $stream:tt
+    ) => {
+        $(#[$attr])*
+        pub async fn $method(
+            &self,
+            ctx: ::io_context::Context,
+            request: $request,
+        ) -> Result<
+            $crate::enclave_rpc_service!(@call_result $response, $stream),
+            $crate::enclave_rpc::client::RpcClientError,
+        > {
+            self.rpc_client.secure_call(ctx, $name, request).await
+        }
+    };
+    (
+        @call insecure $(#[$attr:meta])* $method:ident($request:ty) -> $response:ty = $name:expr,
+            $stream:tt
+    ) => {
+        $(#[$attr])*
+        pub async fn $method(
+            &self,
+            ctx: ::io_context::Context,
+            request: $request,
+        ) -> Result<
+            $crate::enclave_rpc_service!(@call_result $response, $stream),
+            $crate::enclave_rpc::client::RpcClientError,
+        > {
+            self.rpc_client.insecure_call(ctx, $name, request).await
+        }
+    };
+    (@call local $($rest:tt)*) => {};
+
+    // Parse the methods one by one, normalizing them so that streaming methods carry `[stream]`
+    // and all other methods carry `[]`.
+    (
+        @parse $header:tt [$($methods:tt)*]
+        $(#[$method_attr:meta])*
+        $kind:ident(stream) fn $method:ident($request:ty) -> $response:ty
+            = $name:expr $(, access = $access:expr)?;
+        $($rest:tt)*
+    ) => {
+        $crate::enclave_rpc_service!(
+            @parse $header [
+                $($methods)*
+                [
+                    $(#[$method_attr])* $kind [stream] $method($request) -> $response
+                        = [$($access)?] $name
+                ]
+            ]
+            $($rest)*
+        );
+    };
+    (
+        @parse $header:tt [$($methods:tt)*]
+        $(#[$method_attr:meta])*
+        $kind:ident fn $method:ident($request:ty) -> $response:ty
+            = $name:expr $(, access = $access:expr)?;
+        $($rest:tt)*
+    ) => {
+        $crate::enclave_rpc_service!(
+            @parse $header [
+                $($methods)*
+                [
+                    $(#[$method_attr])* $kind [] $method($request) -> $response
+                        = [$($access)?] $name
+                ]
+            ]
+            $($rest)*
+        );
+    };
+    (
+        @parse [
+            $(#[$attr:meta])*
+            $vis:vis trait $service:ident, client $client:ident
+        ] [
+            $([
+                $(#[$method_attr:meta])* $kind:ident $stream:tt $method:ident($request:ty)
+                    -> $response:ty = [$($access:expr)?] $name:expr
+            ])*
+        ]
+    ) => {
+        $(#[$attr])*
+        $vis trait $service {
+            $(
+                $(#[$method_attr])*
+                fn $method(
+                    ctx: &mut $crate::enclave_rpc::Context,
+                    request: &$request,
+                ) -> $crate::enclave_rpc_service!(@handler_result $response, $stream);
+            )*
+
+            /// Register all methods of the service with the given dispatcher.
+            fn register(dispatcher: &mut $crate::enclave_rpc::dispatcher::Dispatcher)
+            where
+                Self: Sized + 'static,
+            {
+                $(
+                    dispatcher.add_method($crate::enclave_rpc_service!(
+                        @method
+                        $crate::enclave_rpc::dispatcher::MethodDescriptor::new(
+                            $name,
+                            $crate::enclave_rpc_service!(@kind $kind),
+                        )
+                        .with_access($crate::enclave_rpc_service!(@access $($access)?)),
+                        Self::$method,
+                        $stream
+                    ));
+                )*
+            }
+        }
+
+        #[doc = concat!("Typed client for the [`", stringify!($service), "`] service.")]
+        $vis struct $client {
+            rpc_client: $crate::enclave_rpc::client::RpcClient,
+        }
+
+        impl $client {
+            /// Create a new service client using the given RPC client.
+            pub fn new(rpc_client: $crate::enclave_rpc::client::RpcClient) -> Self {
+                Self { rpc_client }
+            }
+
+            $(
+                $crate::enclave_rpc_service!(
+                    @call $kind $(#[$method_attr])* $method($request) -> $response = $name,
+                        $stream
+                );
+            )*
+        }
+
+        impl ::std::ops::Deref for $client {
+            type Target = $crate::enclave_rpc::client::RpcClient;
+
+            fn deref(&self) -> &Self::Target {
+                &self.rpc_client
+            }
+        }
+    };
+
+    (@parse $header:tt [$($methods:tt)*] $($rest:tt)+) => {
+        compile_error!(
+            "invalid method definition, expected `secure`, `insecure` or `local`, optionally \
+             followed by `(stream)`"
+        );
+    };
+
+    (
+        $(#[$attr:meta])*
+        $vis:vis trait $service:ident, client $client:ident {
+            $($methods:tt)*
+        }
+    ) => {
+        $crate::enclave_rpc_service!(
+            @parse [$(#[$attr])* $vis trait $service, client $client] []
+            $($methods)*
+        );
+    };
+}
@@ -1,20 +1,49 @@
-use std::{any::Any, cell::RefCell, pin::Pin, ptr::NonNull, rc::Rc, sync::Arc};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::{HashSet, VecDeque},
+    mem,
+    pin::Pin,
+    ptr::NonNull,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::{anyhow, Result};
 use intrusive_collections::{intrusive_adapter, LinkedList, LinkedListLink};
 use io_context::Context;
 use thiserror::Error;
 
-use crate::storage::mkvs::{cache::*, sync::*, tree::*};
+use crate::{
+    common::crypto::hash::Hash,
+    storage::mkvs::{cache::*, sync::*, tree::*},
+};
 
 #[derive(Error, Debug)]
 #[error("mkvs: tried to remove locked node")]
 struct RemoveLockedError;
 
+/// Queue of the cache an item is in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Queue {
+    /// Items which have been needed again after being evicted. With the LRU policy this holds
+    /// all items.
+    #[default]
+    Frequent,
+    /// Items which have been cached for the first time.
+    Recent,
+}
+
 #[derive(Clone, Default)]
 pub struct CacheItemBox<Item: CacheItem + Default> {
     item: Rc<RefCell<Item>>,
     link: LinkedListLink,
+    queue: Cell<Queue>,
+    /// Identifier of the cache whose lists the item is in.
+    owner: Cell<usize>,
 }
 
 intrusive_adapter!(
@@ -29,7 +58,8 @@ where
 {
     pub list: LinkedList<CacheItemAdapter<V>>,
     pub size: usize,
-    pub capacity: usize,
+    pub queue: Queue,
+    pub owner: usize,
     pub mark: CacheExtra<V>,
 }
 
@@ -37,11 +67,12 @@ impl<V> LRUList<V>
 where
     V: CacheItem + Default,
 {
-    pub fn new(capacity: usize) -> LRUList<V> {
+    pub fn new(queue: Queue, owner: usize) -> LRUList<V> {
         LRUList {
             list: LinkedList::new(CacheItemAdapter::new()),
             size: 0,
-            capacity,
+            queue,
+            owner,
             mark: None,
         }
     }
@@ -63,6 +94,8 @@ where
             let mut item_box = Box::pin(CacheItemBox {
                 item: val.clone(),
                 link: LinkedListLink::new(),
+                queue: Cell::new(self.queue),
+                owner: Cell::new(self.owner),
             });
             val_ref.set_cache_extra(NonNull::new(&mut *item_box));
             if let Some(non_null_pos) = &self.mark {
@@ -115,22 +148,262 @@ where
         }
     }
 
+    /// Move all items of the other list to the front of this list, keeping their order.
+    fn append(&mut self, other: &mut LRUList<V>) {
+        other.mark = None;
+        while let Some(item_box) = other.list.pop_back() {
+            item_box.owner.set(self.owner);
+            self.list.push_front(item_box);
+        }
+        self.size += other.size;
+        other.size = 0;
+    }
+}
+
+/// Identifier of the next cache to be created, used to tell which cache an item belongs to.
+static NEXT_CACHE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Identifier of the cache tracking the given node, if any.
+fn owner_of(val: &NodePtrRef) -> Option<usize> {
+    val.borrow()
+        .get_cache_extra()
+        .map(|non_null| unsafe { non_null.as_ref() }.owner.get())
+}
+
+/// Percentage of the capacity given to recently cached items before frequently used items get
+/// evicted (2Q only).
+const RECENT_SHARE: usize = 25;
+
+/// Number of evicted items that are remembered, as a percentage of the capacity (2Q only).
+///
+/// Only hashes are remembered, so the history can be as large as the cache itself.
+const HISTORY_SHARE: usize = 100;
+
+/// Maximum number of nodes visited when looking for frequently used nodes below a node which
+/// is about to be evicted (2Q only).
+///
+/// Larger subtrees are assumed to contain frequently used nodes, so that eviction stays cheap
+/// for nodes close to the root.
+const FREQUENT_DESCENDANT_SEARCH_LIMIT: usize = 64;
+
+/// Eviction queues for a single kind of node.
+struct Queues {
+    policy: EvictionPolicy,
+    capacity: usize,
+    owner: usize,
+    frequent: LRUList<NodePointer>,
+    recent: LRUList<NodePointer>,
+    /// Hashes of recently evicted nodes, oldest first.
+    ///
+    /// May contain hashes which have since been removed from `history_hashes`.
+    history: VecDeque<Hash>,
+    history_hashes: HashSet<Hash>,
+    evicted: u64,
+}
+
+impl Queues {
+    fn new(policy: EvictionPolicy, capacity: usize, owner: usize) -> Queues {
+        Queues {
+            policy,
+            capacity,
+            owner,
+            frequent: LRUList::new(Queue::Frequent, owner),
+            recent: LRUList::new(Queue::Recent, owner),
+            history: VecDeque::new(),
+            history_hashes: HashSet::new(),
+            evicted: 0,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.frequent.size + self.recent.size
+    }
+
+    /// Queue the given node is in, if it is tracked by this cache.
+    fn queue_of(&self, val: &NodePtrRef) -> Option<Queue> {
+        let non_null = val.borrow().get_cache_extra()?;
+        let item = unsafe { non_null.as_ref() };
+        if item.owner.get() != self.owner {
+            return None;
+        }
+        Some(item.queue.get())
+    }
+
+    /// Whether the given node is tracked by another cache sharing it.
+    fn is_foreign(&self, val: &NodePtrRef) -> bool {
+        owner_of(val).map_or(false, |owner| owner != self.owner)
+    }
+
+    fn mark(&mut self) {
+        self.frequent.mark();
+        self.recent.mark();
+    }
+
+    fn add(&mut self, val: NodePtrRef) {
+        if self.use_val(val.clone()) {
+            return;
+        }
+        match self.policy {
+            EvictionPolicy::LRU => self.frequent.add(val),
+            EvictionPolicy::TwoQueue => {
+                // Nodes which were evicted and are needed again are considered to be used
+                // frequently.
+                let hash = val.borrow().hash;
+                if self.history_hashes.remove(&hash) {
+                    self.frequent.add(val);
+                } else {
+                    self.recent.add(val);
+                }
+            }
+        }
+    }
+
+    fn use_val(&mut self, val: NodePtrRef) -> bool {
+        match self.queue_of(&val) {
+            None => false,
+            Some(Queue::Frequent) => self.frequent.use_val(val),
+            // Uses of recently cached nodes are usually correlated (e.g. a traversal visiting
+            // the node again), so they only keep the node in the recent queue. Keeping the
+            // order makes sure that nodes on the path being traversed are not evicted.
+            Some(Queue::Recent) => self.recent.use_val(val),
+        }
+    }
+
+    /// Move a node from the recent to the frequent queue.
+    ///
+    /// The item box is moved as-is, so this does not need to mutably borrow the node.
+    fn promote(&mut self, val: &NodePtrRef) {
+        let val_ref = val.borrow();
+        let non_null = val_ref.get_cache_extra().unwrap();
+        if let Some(non_null_mark) = self.recent.mark {
+            if non_null.as_ptr() == non_null_mark.as_ptr() {
+                self.recent.mark = None;
+            }
+        }
+
+        let mut item_cursor = unsafe { self.recent.list.cursor_mut_from_ptr(non_null.as_ptr()) };
+        let item_box = item_cursor.remove().unwrap();
+        item_box.queue.set(Queue::Frequent);
+        let size = val_ref.get_cached_size();
+        self.recent.size -= size;
+        self.frequent.size += size;
+        self.frequent.list.push_front(item_box);
+    }
+
+    /// Whether any node in the subtree below the given node is in the frequent queue.
+    ///
+    /// At most `FREQUENT_DESCENDANT_SEARCH_LIMIT` nodes are visited; if the subtree is larger,
+    /// it is assumed to contain a frequently used node. Subtrees tracked by other caches are
+    /// skipped as they are not dropped on eviction.
+    fn has_frequent_descendant(&self, ptr: &NodePtrRef) -> bool {
+        let mut stack = vec![ptr.clone()];
+        let mut visited = 0;
+        while let Some(ptr) = stack.pop() {
+            visited += 1;
+            if visited > FREQUENT_DESCENDANT_SEARCH_LIMIT {
+                return true;
+            }
+            let node_ref = match ptr.borrow().node {
+                Some(ref node_ref) => node_ref.clone(),
+                None => continue,
+            };
+            if let NodeBox::Internal(ref n) = *node_ref.borrow() {
+                for child in &[&n.leaf_node, &n.left, &n.right] {
+                    if self.queue_of(child) == Some(Queue::Frequent) {
+                        return true;
+                    }
+                    if !self.is_foreign(child) {
+                        stack.push((*child).clone());
+                    }
+                }
+            };
+        }
+        false
+    }
+
+    fn remove(&mut self, val: NodePtrRef) -> bool {
+        match self.queue_of(&val) {
+            None => false,
+            Some(Queue::Frequent) => self.frequent.remove(val),
+            Some(Queue::Recent) => self.recent.remove(val),
+        }
+    }
+
+    fn remember(&mut self, hash: Hash) {
+        if self.policy != EvictionPolicy::TwoQueue {
+            return;
+        }
+        if self.history_hashes.insert(hash) {
+            self.history.push_back(hash);
+        }
+
+        let history_capacity = self.capacity * HISTORY_SHARE / 100;
+        while self.history.len() > history_capacity {
+            if let Some(hash) = self.history.pop_front() {
+                self.history_hashes.remove(&hash);
+            }
+        }
+    }
+
+    /// Take over all nodes tracked by the other queues.
+    fn append(&mut self, other: &mut Queues) {
+        self.frequent.append(&mut other.frequent);
+        self.recent.append(&mut other.recent);
+    }
+
+    /// Stop tracking all nodes and drop them from their pointers.
+    fn release(&mut self) {
+        for list in &mut [&mut self.frequent, &mut self.recent] {
+            list.mark = None;
+            list.size = 0;
+            while let Some(item_box) = list.list.pop_front() {
+                let mut ptr = item_box.item.borrow_mut();
+                ptr.set_cache_extra(None);
+                ptr.node = None;
+            }
+        }
+    }
+
     fn evict_for_val(
         &mut self,
-        val: Rc<RefCell<V>>,
-        locked_val: Option<&Rc<RefCell<V>>>,
-    ) -> Result<Vec<Rc<RefCell<V>>>, RemoveLockedError> {
-        let mut evicted: Vec<Rc<RefCell<V>>> = Vec::new();
+        val: NodePtrRef,
+        locked_val: Option<&NodePtrRef>,
+    ) -> Result<Vec<NodePtrRef>, RemoveLockedError> {
+        let mut evicted: Vec<NodePtrRef> = Vec::new();
         if self.capacity > 0 {
             let target_size = val.borrow().get_cached_size();
-            while !self.list.is_empty() && self.size + target_size > self.capacity {
-                let back = self.list.back().get().unwrap().item.clone();
+            let recent_capacity = self.capacity * RECENT_SHARE / 100;
+            while self.size() + target_size > self.capacity {
+                let from_recent = !self.recent.list.is_empty()
+                    && (self.recent.size > recent_capacity || self.frequent.list.is_empty());
+                let list = if from_recent {
+                    &self.recent
+                } else {
+                    &self.frequent
+                };
+                let back = match list.list.back().get() {
+                    Some(back) => back.item.clone(),
+                    None => break,
+                };
                 if let Some(locked_val) = locked_val {
                     if back.as_ptr() == locked_val.as_ptr() {
                         return Err(RemoveLockedError);
                     }
                 }
-                if self.remove(back.clone()) {
+                // Evicting a node also drops its subtree, so keep nodes which are above nodes
+                // that are used frequently.
+                if from_recent && self.has_frequent_descendant(&back) {
+                    self.promote(&back);
+                    continue;
+                }
+                let list = if from_recent {
+                    &mut self.recent
+                } else {
+                    &mut self.frequent
+                };
+                if list.remove(back.clone()) {
+                    self.remember(back.borrow().hash);
+                    self.evicted += 1;
                     evicted.push(back);
                 }
             }
@@ -139,15 +412,51 @@ where
     }
 }
 
-/// Cache implementation with a simple LRU eviction strategy.
+/// Eviction lists of a single cache.
+///
+/// Nodes shared with forks are tracked by at most one cache, the one which cached them first.
+/// Other caches may use such nodes, but never evict them.
+struct LRULists {
+    id: usize,
+    leaf: Queues,
+    internal: Queues,
+    hits: u64,
+    misses: u64,
+}
+
+impl LRULists {
+    fn new(node_capacity: usize, value_capacity: usize, policy: EvictionPolicy) -> LRULists {
+        let id = NEXT_CACHE_ID.fetch_add(1, Ordering::Relaxed);
+        LRULists {
+            id,
+            leaf: Queues::new(policy, value_capacity, id),
+            internal: Queues::new(policy, node_capacity, id),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Whether the given node is tracked by another cache sharing it.
+    fn is_foreign(&self, val: &NodePtrRef) -> bool {
+        owner_of(val).map_or(false, |owner| owner != self.id)
+    }
+}
+
+/// Cache implementation with a configurable LRU-based eviction strategy.
 pub struct LRUCache {
     read_syncer: Box<dyn ReadSync>,
 
     pending_root: NodePtrRef,
     sync_root: Root,
 
-    lru_leaf: LRUList<NodePointer>,
-    lru_internal: LRUList<NodePointer>,
+    lru: RefCell<LRULists>,
+    /// Handle shared by a cache and all of its forks. As long as more than one of them exists,
+    /// clean nodes may be shared.
+    shared: Rc<()>,
+    /// Nodes committed by the tree which are kept from being evicted, if pinning is enabled.
+    pinned: Option<Vec<NodePtrRef>>,
+    /// Read syncer metrics counters to record hits and misses in, if any.
+    metrics: Option<Arc<CacheCounters>>,
 }
 
 impl LRUCache {
@@ -157,10 +466,12 @@ impl LRUCache {
     ///   cache before eviction.
     /// * `value_capacity` is the total size, in bytes, of values held
     ///   by the cache before eviction.
+    /// * `policy` is the policy used to select nodes to evict.
     /// * `read_syncer` is the read syncer used as backing for the cache.
     pub fn new(
         node_capacity: usize,
         value_capacity: usize,
+        policy: EvictionPolicy,
         read_syncer: Box<dyn ReadSync>,
         root_type: RootType,
     ) -> Box<LRUCache> {
@@ -176,11 +487,95 @@ impl LRUCache {
                 ..Default::default()
             },
 
-            lru_leaf: LRUList::new(value_capacity),
-            lru_internal: LRUList::new(node_capacity),
+            lru: RefCell::new(LRULists::new(node_capacity, value_capacity, policy)),
+            shared: Rc::new(()),
+            pinned: None,
+            metrics: None,
+        })
+    }
+
+    /// Construct a new cache instance sharing all nodes with this cache.
+    ///
+    /// The new cache starts at the same pending and sync roots and uses the given read
+    /// syncer for fetching nodes. It has the same capacity as this cache, but keeps its own
+    /// eviction lists, so it never evicts nodes cached by this cache and vice versa. Clean
+    /// nodes must not be modified in place by either of them afterwards.
+    pub fn fork(&self, read_syncer: Box<dyn ReadSync>) -> Box<LRUCache> {
+        let lru = self.lru.borrow();
+
+        Box::new(LRUCache {
+            read_syncer,
+
+            pending_root: self.pending_root.clone(),
+            sync_root: self.sync_root,
+
+            lru: RefCell::new(LRULists::new(
+                lru.internal.capacity,
+                lru.leaf.capacity,
+                lru.internal.policy,
+            )),
+            shared: self.shared.clone(),
+            pinned: None,
+            metrics: self.metrics.clone(),
         })
     }
 
+    /// Record hits and misses in the read syncer metrics, tagged by the given operation.
+    pub fn set_metrics_operation(&mut self, operation: SyncOperation) {
+        self.metrics = Some(cache_counters(self.sync_root.root_type, operation));
+    }
+
+    /// Record a node dereference which was either served from the cache or not.
+    fn record_lookup(&self, hit: bool) {
+        let mut lru = self.lru.borrow_mut();
+        if hit {
+            lru.hits += 1;
+        } else {
+            lru.misses += 1;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_lookup(hit);
+        }
+    }
+
+    /// Whether clean nodes in this cache may be shared with a fork.
+    pub fn is_forked(&self) -> bool {
+        Rc::strong_count(&self.shared) > 1
+    }
+
+    /// Keep nodes committed by the tree from being evicted until `release_pinned_nodes` is
+    /// called, e.g., so that new nodes can still be persisted after the tree is committed.
+    pub fn pin_committed_nodes(&mut self) {
+        if self.pinned.is_none() {
+            self.pinned = Some(Vec::new());
+        }
+    }
+
+    /// Allow all nodes pinned so far to be evicted. Nodes committed afterwards are pinned
+    /// again.
+    pub fn release_pinned_nodes(&mut self) {
+        let pinned = match self.pinned {
+            Some(ref mut pinned) => mem::take(pinned),
+            None => return,
+        };
+        for ptr in pinned {
+            // Nodes may have been modified again in the meantime.
+            if ptr.borrow().clean {
+                self.try_commit_node(ptr, None)
+                    .expect("no locked pointer passed, cannot fail");
+            }
+        }
+    }
+
+    /// Take over all nodes cached by the given fork of this cache, e.g., when the state of
+    /// the fork replaces the state of this cache.
+    pub fn adopt(&mut self, fork: &mut LRUCache) {
+        let mut lru = self.lru.borrow_mut();
+        let mut fork_lru = fork.lru.borrow_mut();
+        lru.internal.append(&mut fork_lru.internal);
+        lru.leaf.append(&mut fork_lru.leaf);
+    }
+
     fn new_internal_node_ptr(&mut self, node: Option<NodeRef>) -> NodePtrRef {
         Rc::new(RefCell::new(NodePointer {
             node,
@@ -195,13 +590,72 @@ impl LRUCache {
         }))
     }
 
+    /// Fetch nodes of a tree which is not the tree held by this cache, merging them below the
+    /// given pointer without adding them to the cache.
+    ///
+    /// * `root` is the root of the other tree.
+    /// * `root_ptr` is the pointer to the root node of the other tree.
+    pub fn sync_detached<F: ReadSyncFetcher>(
+        &mut self,
+        ctx: &Arc<Context>,
+        root: Root,
+        root_ptr: NodePtrRef,
+        ptr: NodePtrRef,
+        fetcher: F,
+    ) -> Result<()> {
+        self.fetch_subtree(ctx, root, root_ptr, ptr, fetcher)?;
+        Ok(())
+    }
+
+    /// Fetch and verify a proof for the node behind the given pointer and merge the resulting
+    /// nodes into the tree, returning the pointers of all merged subtrees.
+    fn fetch_subtree<F: ReadSyncFetcher>(
+        &mut self,
+        ctx: &Arc<Context>,
+        root: Root,
+        root_ptr: NodePtrRef,
+        ptr: NodePtrRef,
+        fetcher: F,
+    ) -> Result<Vec<NodePtrRef>> {
+        let proof = fetcher.fetch(
+            Context::create_child(ctx),
+            root,
+            ptr.clone(),
+            &mut self.read_syncer,
+        )?;
+
+        // The proof can be for one of two hashes: i) it is either for ptr.Hash in case
+        // all the nodes are only contained in the subtree below ptr, or ii) it is for
+        // the root hash in case it contains nodes outside the subtree.
+        let ptr_hash = ptr.borrow().hash;
+        let (dst_ptr, expected_root) = if proof.untrusted_root == ptr_hash {
+            (ptr, ptr_hash)
+        } else if proof.untrusted_root == root.hash {
+            (root_ptr, root.hash)
+        } else {
+            return Err(anyhow!(
+                "mkvs: got proof for unexpected root ({:?})",
+                proof.untrusted_root
+            ));
+        };
+
+        // Verify proof.
+        let pv = ProofVerifier;
+        let subtree = pv.verify_proof(Context::create_child(ctx), expected_root, &proof)?;
+
+        // Merge resulting nodes.
+        let mut merged_nodes: Vec<NodePtrRef> = Vec::new();
+        merge_verified_subtree(dst_ptr, subtree, &mut merged_nodes)?;
+        Ok(merged_nodes)
+    }
+
     fn try_commit_node(
         &mut self,
         ptr: NodePtrRef,
         locked_ptr: Option<&NodePtrRef>,
     ) -> Result<(), RemoveLockedError> {
         assert!(ptr.borrow().clean, "mkvs: commit_node called on dirty node");
-        if ptr.borrow().node.is_none() {
+        if ptr.borrow().node.is_none() || self.lru.borrow().is_foreign(&ptr) {
             return Ok(());
         }
         if self.use_node(ptr.clone()) {
@@ -210,18 +664,26 @@ impl LRUCache {
 
         match classify_noderef!(? ptr.borrow().node) {
             NodeKind::Internal => {
-                let evicted = self.lru_internal.evict_for_val(ptr.clone(), locked_ptr)?;
+                let evicted = self
+                    .lru
+                    .borrow_mut()
+                    .internal
+                    .evict_for_val(ptr.clone(), locked_ptr)?;
                 for node in evicted {
-                    self.try_remove_node(node.clone(), locked_ptr)?;
+                    self.try_remove_node(node.clone(), locked_ptr, true)?;
                 }
-                self.lru_internal.add(ptr);
+                self.lru.borrow_mut().internal.add(ptr);
             }
             NodeKind::Leaf => {
-                let evicted = self.lru_leaf.evict_for_val(ptr.clone(), locked_ptr)?;
+                let evicted = self
+                    .lru
+                    .borrow_mut()
+                    .leaf
+                    .evict_for_val(ptr.clone(), locked_ptr)?;
                 for node in evicted {
-                    self.try_remove_node(node.clone(), locked_ptr)?;
+                    self.try_remove_node(node.clone(), locked_ptr, true)?;
                 }
-                self.lru_leaf.add(ptr);
+                self.lru.borrow_mut().leaf.add(ptr);
             }
             NodeKind::None => return Ok(()),
         };
@@ -229,10 +691,15 @@ impl LRUCache {
         Ok(())
     }
 
+    /// Remove the node and its subtree from the cache.
+    ///
+    /// In case the node is being evicted, the removed nodes are remembered by the eviction
+    /// policy.
     fn try_remove_node(
         &mut self,
         ptr: NodePtrRef,
         locked_ptr: Option<&NodePtrRef>,
+        evicting: bool,
     ) -> Result<(), RemoveLockedError> {
         #[derive(Clone, Copy)]
         enum VisitState {
@@ -255,6 +722,12 @@ impl LRUCache {
                 }
             }
 
+            // Subtrees tracked by other caches are left for them to evict.
+            if self.lru.borrow().is_foreign(&top.0) {
+                stack.pop();
+                continue 'stack;
+            }
+
             // Perform removal in depth-first order. We do not remove the node from
             // the stack until all of its subtrees have been fully removed.
             if let Some(ref node_ref) = top.0.borrow().node {
@@ -284,17 +757,16 @@ impl LRUCache {
 
             stack.pop();
 
-            match classify_noderef!(? top.0.borrow().node) {
-                NodeKind::Internal => {
-                    self.lru_internal.remove(top.0.clone());
-                    top.0.borrow_mut().node = None;
-                }
-                NodeKind::Leaf => {
-                    self.lru_leaf.remove(top.0.clone());
-                    top.0.borrow_mut().node = None;
-                }
-                NodeKind::None => {}
+            let mut lru = self.lru.borrow_mut();
+            let queues = match classify_noderef!(? top.0.borrow().node) {
+                NodeKind::Internal => &mut lru.internal,
+                NodeKind::Leaf => &mut lru.leaf,
+                NodeKind::None => continue,
+            };
+            if queues.remove(top.0.clone()) && evicting {
+                queues.remember(top.0.borrow().hash);
             }
+            top.0.borrow_mut().node = None;
         }
 
         Ok(())
@@ -328,15 +800,31 @@ impl LRUCache {
     }
 }
 
+impl Drop for LRUCache {
+    fn drop(&mut self) {
+        // Nodes tracked by this cache may still be reachable from other caches, which must
+        // not be left pointing to the removed eviction lists.
+        if self.is_forked() {
+            let mut lru = self.lru.borrow_mut();
+            lru.internal.release();
+            lru.leaf.release();
+        }
+    }
+}
+
 impl Cache for LRUCache {
     fn as_any(&self) -> &dyn Any {
         self
     }
 
     fn stats(&self) -> CacheStats {
+        let lru = self.lru.borrow();
         CacheStats {
-            internal_node_count: self.lru_internal.size,
-            leaf_value_size: self.lru_leaf.size,
+            internal_node_count: lru.internal.size(),
+            leaf_value_size: lru.leaf.size(),
+            hit_count: lru.hits,
+            miss_count: lru.misses,
+            eviction_count: lru.internal.evicted + lru.leaf.evicted,
         }
     }
 
@@ -389,7 +877,7 @@ impl Cache for LRUCache {
     }
 
     fn remove_node(&mut self, ptr: NodePtrRef) {
-        self.try_remove_node(ptr, None)
+        self.try_remove_node(ptr, None, false)
             .expect("no locked pointer passed, cannot fail");
     }
 
@@ -419,6 +907,7 @@ impl Cache for LRUCache {
                 drop(ptr);
                 self.remove_node(ptr_ref.clone());
             } else {
+                self.record_lookup(true);
                 return Ok(Some(node.clone()));
             }
         } else {
@@ -430,6 +919,7 @@ impl Cache for LRUCache {
 
         // Node not available locally, fetch from read syncer.
         if let Some(fetcher) = fetcher {
+            self.record_lookup(false);
             self.remote_sync(ctx, ptr_ref.clone(), fetcher)?;
         } else {
             return Err(anyhow!(
@@ -452,35 +942,13 @@ impl Cache for LRUCache {
         ptr: NodePtrRef,
         fetcher: F,
     ) -> Result<()> {
-        let proof = fetcher.fetch(
-            Context::create_child(ctx),
+        let merged_nodes = self.fetch_subtree(
+            ctx,
             self.sync_root,
+            self.pending_root.clone(),
             ptr.clone(),
-            &mut self.read_syncer,
+            fetcher,
         )?;
-
-        // The proof can be for one of two hashes: i) it is either for ptr.Hash in case
-        // all the nodes are only contained in the subtree below ptr, or ii) it is for
-        // the c.syncRoot.Hash in case it contains nodes outside the subtree.
-        let ptr_hash = ptr.borrow().hash;
-        let (dst_ptr, expected_root) = if proof.untrusted_root == ptr_hash {
-            (ptr.clone(), ptr_hash)
-        } else if proof.untrusted_root == self.sync_root.hash {
-            (self.pending_root.clone(), self.sync_root.hash)
-        } else {
-            return Err(anyhow!(
-                "mkvs: got proof for unexpected root ({:?})",
-                proof.untrusted_root
-            ));
-        };
-
-        // Verify proof.
-        let pv = ProofVerifier;
-        let subtree = pv.verify_proof(Context::create_child(ctx), expected_root, &proof)?;
-
-        // Merge resulting nodes.
-        let mut merged_nodes: Vec<NodePtrRef> = Vec::new();
-        merge_verified_subtree(dst_ptr, subtree, &mut merged_nodes)?;
         let mut remove = false;
         for node_ref in merged_nodes {
             if remove {
@@ -499,35 +967,59 @@ impl Cache for LRUCache {
 
     fn use_node(&mut self, ptr: NodePtrRef) -> bool {
         match classify_noderef!(? ptr.borrow().node) {
-            NodeKind::Internal => self.lru_internal.use_val(ptr),
-            NodeKind::Leaf => self.lru_leaf.use_val(ptr),
+            NodeKind::Internal => self.lru.borrow_mut().internal.use_val(ptr),
+            NodeKind::Leaf => self.lru.borrow_mut().leaf.use_val(ptr),
             NodeKind::None => false,
         }
     }
 
     fn commit_node(&mut self, ptr: NodePtrRef) {
+        if let Some(ref mut pinned) = self.pinned {
+            pinned.push(ptr);
+            return;
+        }
         self.try_commit_node(ptr, None)
             .expect("no locked pointer passed, cannot fail");
     }
 
     fn rollback_node(&mut self, ptr: NodePtrRef, kind: NodeKind) {
+        // The leaf node is serialized together with the internal node, so it must not be
+        // evicted while the internal node is dirty either. It is committed again together
+        // with the internal node.
+        if let NodeKind::Internal = kind {
+            let leaf_node = match ptr.borrow().node {
+                Some(ref node_ref) => noderef_as!(node_ref, Internal).leaf_node.clone(),
+                None => NodePointer::null_ptr(),
+            };
+            if leaf_node.borrow().has_node() {
+                self.rollback_node(leaf_node, NodeKind::Leaf);
+            }
+        }
+
         if ptr.borrow().get_cache_extra().is_none() {
             // Node has not yet been committed to cache.
             return;
         }
+        if self.lru.borrow().is_foreign(&ptr) {
+            // Node is tracked by another cache.
+            return;
+        }
 
-        let lru = match kind {
-            NodeKind::Internal => &mut self.lru_internal,
-            NodeKind::Leaf => &mut self.lru_leaf,
+        let mut lru = self.lru.borrow_mut();
+        let list = match kind {
+            NodeKind::Internal => &mut lru.internal,
+            NodeKind::Leaf => &mut lru.leaf,
             NodeKind::None => panic!("lru_cache: rollback works only for Internal and Leaf nodes!"),
         };
-        lru.remove(ptr.clone());
+        list.remove(ptr.clone());
+        drop(lru);
 
         ptr.borrow_mut().set_cache_extra(None);
     }
 
     fn mark_position(&mut self) {
-        self.lru_internal.mark();
-        self.lru_leaf.mark();
+        let mut lru = self.lru.borrow_mut();
+        lru.internal.mark();
+        lru.leaf.mark();
     }
 }
@@ -16,6 +16,29 @@ pub struct CacheStats {
     pub internal_node_count: usize,
     /// Total size of values held by the cache.
     pub leaf_value_size: usize,
+    /// Number of node dereferences served from the cache.
+    pub hit_count: u64,
+    /// Number of node dereferences which required fetching nodes from the read syncer.
+    pub miss_count: u64,
+    /// Number of nodes evicted to make space for other nodes.
+    ///
+    /// Evicting an internal node also drops its subtree, which is not counted separately.
+    pub eviction_count: u64,
+}
+
+/// Policy used to select which nodes get evicted once the cache is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used nodes first.
+    #[default]
+    LRU,
+    /// Scan-resistant 2Q policy.
+    ///
+    /// Nodes cached for the first time are kept in a separate LRU queue, which is evicted from
+    /// first once it holds more than a quarter of the capacity. Nodes which are needed again
+    /// after being evicted are moved to the main LRU queue, so a one-shot scan over many nodes
+    /// does not flush the nodes which are used repeatedly.
+    TwoQueue,
 }
 
 /// Used to fetch proofs from a remote tree via the ReadSyncer interface.
@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+use crate::common::crypto::hash::Hash;
+
+#[derive(Error, Debug)]
+pub enum DbError {
+    #[error("mkvs/db: root not found")]
+    RootNotFound,
+    #[error("mkvs/db: node not found ({0:?})")]
+    NodeNotFound(Hash),
+    #[error("mkvs/db: corrupted node ({0:?})")]
+    CorruptedNode(Hash),
+    #[error("mkvs/db: invalid root type")]
+    InvalidRootType,
+    #[error("mkvs/db: root is dirty")]
+    DirtyRoot,
+}
@@ -0,0 +1,383 @@
+use std::{
+    any::Any,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Result;
+use io_context::Context;
+
+use crate::{
+    common::{crypto::hash::Hash, namespace::Namespace},
+    storage::mkvs::{cache::Cache, db::DbError, marshal::Marshal, sync::*, tree::*, WriteLog},
+};
+
+/// Name of the directory holding serialized nodes.
+const NODES_DIR: &str = "nodes";
+/// Name of the directory holding the root index.
+const ROOTS_DIR: &str = "roots";
+
+/// Counter used to generate unique names for temporary files.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A persistent node database backed by a directory on the local filesystem.
+///
+/// Nodes are content-addressed by their hash so nodes shared between versions are only stored
+/// once. Roots are indexed by namespace, version and root type so any state that has been
+/// committed can later be opened as a `Tree` or served to remote trees using `read_syncer`.
+#[derive(Clone, Debug)]
+pub struct FileNodeDB {
+    path: PathBuf,
+}
+
+impl FileNodeDB {
+    /// Open the node database in the given directory, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(path.join(NODES_DIR))?;
+        fs::create_dir_all(path.join(ROOTS_DIR))?;
+
+        Ok(Self { path })
+    }
+
+    /// Check whether the given root is stored in the database.
+    ///
+    /// Empty roots are always considered to exist.
+    pub fn has_root(&self, root: &Root) -> bool {
+        root.hash.is_empty() || self.root_path(root).exists()
+    }
+
+    /// Return all roots stored under the given namespace and version.
+    pub fn get_roots_for_version(&self, namespace: &Namespace, version: u64) -> Result<Vec<Root>> {
+        let roots = read_dir_names(&self.version_path(namespace, version))?
+            .iter()
+            .filter_map(|name| parse_root_name(name))
+            .map(|(root_type, hash)| Root {
+                namespace: *namespace,
+                version,
+                root_type,
+                hash,
+            })
+            .collect();
+
+        Ok(roots)
+    }
+
+    /// Return the earliest version stored under the given namespace.
+    pub fn get_earliest_version(&self, namespace: &Namespace) -> Result<Option<u64>> {
+        Ok(self.get_versions(namespace)?.into_iter().min())
+    }
+
+    /// Return the latest version stored under the given namespace.
+    pub fn get_latest_version(&self, namespace: &Namespace) -> Result<Option<u64>> {
+        Ok(self.get_versions(namespace)?.into_iter().max())
+    }
+
+    /// Construct a new tree for the given root, backed by this database.
+    pub fn open_tree(&self, root: Root) -> Result<Tree> {
+        self.open_tree_with_builder(Tree::builder(), root)
+    }
+
+    /// Construct a new tree for the given root using the given builder, backed by this
+    /// database.
+    ///
+    /// Nodes committed by the tree are not evicted from its cache until they are stored using
+    /// `commit_tree`.
+    pub fn open_tree_with_builder(&self, builder: Builder, root: Root) -> Result<Tree> {
+        if !self.has_root(&root) {
+            return Err(DbError::RootNotFound.into());
+        }
+
+        let tree = builder
+            .with_root(root)
+            .build(Box::new(NodeReader { db: self.clone() }));
+        tree.cache.borrow_mut().pin_committed_nodes();
+
+        Ok(tree)
+    }
+
+    /// Construct a read syncer serving proofs for roots stored in this database.
+    pub fn read_syncer(&self) -> DbReadSyncer {
+        DbReadSyncer {
+            db: self.clone(),
+            tree: None,
+        }
+    }
+
+    /// Store the nodes of a committed tree and index its root.
+    ///
+    /// All nodes which are not yet in the database must still be held in the tree's cache.
+    /// This is always the case for trees opened from this database, other trees should be
+    /// stored right after `Tree::commit`.
+    pub fn commit_tree(&self, _ctx: Context, tree: &Tree) -> Result<Root> {
+        let (root, pending_root) = {
+            let cache = tree.cache.borrow();
+            (cache.get_sync_root(), cache.get_pending_root())
+        };
+        if root.root_type == RootType::Invalid {
+            return Err(DbError::InvalidRootType.into());
+        }
+        if !pending_root.borrow().clean || pending_root.borrow().hash != root.hash {
+            return Err(DbError::DirtyRoot.into());
+        }
+
+        // Store all nodes before the root so an indexed root is always complete.
+        self.store_subtree(&pending_root)?;
+        write_file(&self.root_path(&root), &[])?;
+        tree.cache.borrow_mut().release_pinned_nodes();
+
+        Ok(root)
+    }
+
+    /// Apply the write log to the source root, check that it results in the destination root
+    /// and store the resulting nodes.
+    pub fn apply_write_log(
+        &self,
+        ctx: Context,
+        src_root: Root,
+        dst_root: Root,
+        write_log: &WriteLog,
+    ) -> Result<()> {
+        let ctx = ctx.freeze();
+        if src_root.root_type != dst_root.root_type {
+            return Err(DbError::InvalidRootType.into());
+        }
+
+        // Use an unbounded cache so no new nodes are evicted before being stored.
+        let mut tree =
+            self.open_tree_with_builder(Tree::builder().with_capacity(0, 0), src_root)?;
+        tree.apply_write_log(Context::create_child(&ctx), write_log, dst_root)?;
+        self.commit_tree(Context::create_child(&ctx), &tree)?;
+
+        Ok(())
+    }
+
+    fn store_subtree(&self, ptr: &NodePtrRef) -> Result<()> {
+        let ptr = ptr.borrow();
+        if ptr.is_null() {
+            return Ok(());
+        }
+
+        // Nodes are only stored after their subtrees, so the whole subtree is already there.
+        let path = self.node_path(&ptr.hash);
+        if path.exists() {
+            return Ok(());
+        }
+
+        let node_ref = match ptr.node {
+            Some(ref node_ref) => node_ref.clone(),
+            None => return Err(DbError::NodeNotFound(ptr.hash).into()),
+        };
+        let node = node_ref.borrow();
+        if let NodeBox::Internal(ref n) = *node {
+            // The leaf node is serialized together with the internal node.
+            let leaf_node = n.leaf_node.borrow();
+            if !leaf_node.is_null() && !leaf_node.has_node() {
+                return Err(DbError::NodeNotFound(leaf_node.hash).into());
+            }
+
+            self.store_subtree(&n.left)?;
+            self.store_subtree(&n.right)?;
+        }
+
+        write_file(&path, &node.marshal_binary()?)
+    }
+
+    fn read_node(&self, hash: &Hash) -> Result<NodeBox> {
+        let data = match fs::read(self.node_path(hash)) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(DbError::NodeNotFound(*hash).into())
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut node = NodeBox::default();
+        node.unmarshal_binary(&data)?;
+        if node.get_hash() != *hash {
+            return Err(DbError::CorruptedNode(*hash).into());
+        }
+
+        Ok(node)
+    }
+
+    fn get_versions(&self, namespace: &Namespace) -> Result<Vec<u64>> {
+        let versions = read_dir_names(&self.namespace_path(namespace))?
+            .iter()
+            .filter_map(|name| name.parse().ok())
+            .collect();
+
+        Ok(versions)
+    }
+
+    fn node_path(&self, hash: &Hash) -> PathBuf {
+        // Shard nodes by the first byte of their hash to keep directories small.
+        let name = format!("{:x}", hash);
+        self.path.join(NODES_DIR).join(&name[..2]).join(name)
+    }
+
+    fn namespace_path(&self, namespace: &Namespace) -> PathBuf {
+        self.path.join(ROOTS_DIR).join(format!("{:x}", namespace))
+    }
+
+    fn version_path(&self, namespace: &Namespace, version: u64) -> PathBuf {
+        self.namespace_path(namespace).join(version.to_string())
+    }
+
+    fn root_path(&self, root: &Root) -> PathBuf {
+        self.version_path(&root.namespace, root.version)
+            .join(format!("{}.{:x}", root.root_type as u8, root.hash))
+    }
+}
+
+/// A read syncer serving proofs for roots stored in the node database.
+///
+/// The tree opened for the most recently requested root is kept, so consecutive requests for
+/// the same root reuse the nodes it has already read.
+pub struct DbReadSyncer {
+    db: FileNodeDB,
+    tree: Option<(Root, Tree)>,
+}
+
+impl DbReadSyncer {
+    fn tree(&mut self, root: Root) -> Result<&mut Tree> {
+        match self.tree {
+            Some((tree_root, _)) if tree_root == root => {}
+            _ => self.tree = Some((root, self.db.open_tree(root)?)),
+        }
+
+        Ok(&mut self.tree.as_mut().unwrap().1)
+    }
+}
+
+impl ReadSync for DbReadSyncer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, ctx: Context, request: GetRequest) -> Result<ProofResponse> {
+        self.tree(request.tree.root)?.sync_get(ctx, request)
+    }
+
+    fn sync_get_many(&mut self, ctx: Context, request: GetManyRequest) -> Result<ProofResponse> {
+        self.tree(request.tree.root)?.sync_get_many(ctx, request)
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        ctx: Context,
+        request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        self.tree(request.tree.root)?
+            .sync_get_prefixes(ctx, request)
+    }
+
+    fn sync_iterate(&mut self, ctx: Context, request: IterateRequest) -> Result<ProofResponse> {
+        self.tree(request.tree.root)?.sync_iterate(ctx, request)
+    }
+}
+
+/// A read syncer used by trees opened from the node database.
+///
+/// Each request is answered with a proof containing only the node at the requested position
+/// as the node database is local and fetching more nodes than needed is not useful.
+struct NodeReader {
+    db: FileNodeDB,
+}
+
+impl NodeReader {
+    fn get_node(&self, position: Hash) -> Result<ProofResponse> {
+        let node = self.db.read_node(&position)?;
+        let mut pb = ProofBuilder::new(position, position);
+        pb.include(&node);
+        let proof = pb.build(Context::background())?;
+
+        Ok(ProofResponse { proof })
+    }
+}
+
+impl ReadSync for NodeReader {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, _ctx: Context, request: GetRequest) -> Result<ProofResponse> {
+        self.get_node(request.tree.position)
+    }
+
+    fn sync_get_many(&mut self, _ctx: Context, request: GetManyRequest) -> Result<ProofResponse> {
+        self.get_node(request.tree.position)
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        _ctx: Context,
+        request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        self.get_node(request.tree.position)
+    }
+
+    fn sync_iterate(&mut self, _ctx: Context, request: IterateRequest) -> Result<ProofResponse> {
+        self.get_node(request.tree.position)
+    }
+}
+
+/// Parse a root index file name into its root type and hash.
+fn parse_root_name(name: &str) -> Option<(RootType, Hash)> {
+    let (root_type, hash) = name.split_once('.')?;
+    let root_type = match root_type.parse::<u8>().ok()? {
+        t if t == RootType::State as u8 => RootType::State,
+        t if t == RootType::IO as u8 => RootType::IO,
+        _ => return None,
+    };
+
+    Some((root_type, hash.parse().ok()?))
+}
+
+/// Return the names of all entries in the given directory, treating missing directories as
+/// empty.
+fn read_dir_names(path: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        if let Ok(name) = entry?.file_name().into_string() {
+            names.push(name);
+        }
+    }
+
+    Ok(names)
+}
+
+/// Atomically write a file by writing to a temporary file first and then renaming it.
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp_path = OsString::from(path);
+    tmp_path.push(format!(
+        ".tmp-{}-{}",
+        process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(data)?;
+    // Make sure the data is durable before it becomes visible under the final name.
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    // Make sure the rename itself is durable.
+    if let Some(parent) = path.parent() {
+        fs::File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,9 @@
+//! Persistent node database.
+mod errors;
+mod file;
+
+pub use errors::*;
+pub use file::*;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,304 @@
+use io_context::Context;
+
+use crate::{
+    common::{crypto::hash::Hash, namespace::Namespace},
+    storage::mkvs::{cache::Cache, db::*, sync::*, tree::*, LogEntry},
+};
+
+fn new_db() -> (tempfile::TempDir, FileNodeDB) {
+    let datadir = tempfile::Builder::default()
+        .prefix("oasis-test-mkvs-db")
+        .tempdir()
+        .expect("failed to create temporary data directory");
+    let db = FileNodeDB::open(datadir.path()).expect("open");
+    (datadir, db)
+}
+
+fn items(version: u64) -> Vec<(Vec<u8>, Vec<u8>)> {
+    (0..100)
+        .map(|i| {
+            (
+                format!("key {}", i).into_bytes(),
+                format!("value {} at {}", i, version).into_bytes(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_commit_tree() {
+    let (datadir, db) = new_db();
+    let namespace = Namespace::default();
+
+    let mut tree = Tree::builder()
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+    for (key, value) in items(1) {
+        tree.insert(Context::background(), &key, &value)
+            .expect("insert");
+    }
+    let hash = tree
+        .commit(Context::background(), namespace, 1)
+        .expect("commit");
+    let root = db
+        .commit_tree(Context::background(), &tree)
+        .expect("commit_tree");
+    assert_eq!(
+        root,
+        Root {
+            namespace,
+            version: 1,
+            root_type: RootType::State,
+            hash,
+        }
+    );
+
+    // Committing the same tree again should be a no-op.
+    db.commit_tree(Context::background(), &tree)
+        .expect("commit_tree");
+
+    // Data should be available after reopening the database.
+    let db = FileNodeDB::open(datadir.path()).expect("open");
+    assert!(db.has_root(&root));
+    assert_eq!(
+        db.get_roots_for_version(&namespace, 1).expect("get_roots"),
+        vec![root]
+    );
+    assert_eq!(
+        db.get_earliest_version(&namespace).expect("version"),
+        Some(1)
+    );
+    assert_eq!(db.get_latest_version(&namespace).expect("version"), Some(1));
+
+    let tree = db.open_tree(root).expect("open_tree");
+    for (key, value) in items(1) {
+        assert_eq!(
+            tree.get(Context::background(), &key).expect("get"),
+            Some(value)
+        );
+    }
+    assert_eq!(
+        tree.get(Context::background(), b"missing").expect("get"),
+        None
+    );
+
+    // Unknown roots should be rejected.
+    let bogus_root = Root {
+        hash: Hash::digest_bytes(b"i am a bogus hash"),
+        ..root
+    };
+    assert!(!db.has_root(&bogus_root));
+    assert!(db.open_tree(bogus_root).is_err());
+
+    // Dirty trees should be rejected.
+    let mut tree = db.open_tree(root).expect("open_tree");
+    tree.insert(Context::background(), b"dirty", b"value")
+        .expect("insert");
+    assert!(db.commit_tree(Context::background(), &tree).is_err());
+}
+
+#[test]
+fn test_commit_tree_evicted() {
+    let (_datadir, db) = new_db();
+    let namespace = Namespace::default();
+
+    let mut tree = Tree::builder()
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+    for (key, value) in items(1) {
+        tree.insert(Context::background(), &key, &value)
+            .expect("insert");
+    }
+    tree.commit(Context::background(), namespace, 1)
+        .expect("commit");
+    let root = db
+        .commit_tree(Context::background(), &tree)
+        .expect("commit_tree");
+
+    // Update keys through a tree with a tiny cache, so that new nodes would get evicted before
+    // the tree is stored.
+    let mut tree = db
+        .open_tree_with_builder(Tree::builder().with_capacity(16, 16), root)
+        .expect("open_tree");
+    for (key, value) in items(2) {
+        tree.insert(Context::background(), &key, &value)
+            .expect("insert");
+    }
+    tree.commit(Context::background(), namespace, 2)
+        .expect("commit");
+    db.commit_tree(Context::background(), &tree)
+        .expect("commit_tree");
+
+    // Key 5 is a prefix of other keys, so its leaf node is serialized together with the
+    // internal node which gets updated here. Reading other keys afterwards must not evict it.
+    tree.insert(Context::background(), b"key 5", b"updated")
+        .expect("insert");
+    tree.commit(Context::background(), namespace, 3)
+        .expect("commit");
+    db.commit_tree(Context::background(), &tree)
+        .expect("commit_tree");
+    tree.insert(Context::background(), b"key 5a", b"new")
+        .expect("insert");
+    for (key, _) in items(2) {
+        tree.get(Context::background(), &key).expect("get");
+    }
+    tree.commit(Context::background(), namespace, 4)
+        .expect("commit");
+    let root = db
+        .commit_tree(Context::background(), &tree)
+        .expect("commit_tree");
+
+    let tree = db.open_tree(root).expect("open_tree");
+    for (i, (key, value)) in items(2).into_iter().enumerate() {
+        let expected = match i {
+            5 => b"updated".to_vec(),
+            _ => value,
+        };
+        assert_eq!(
+            tree.get(Context::background(), &key).expect("get"),
+            Some(expected)
+        );
+    }
+    assert_eq!(
+        tree.get(Context::background(), b"key 5a").expect("get"),
+        Some(b"new".to_vec())
+    );
+}
+
+#[test]
+fn test_apply_write_log() {
+    let (_datadir, db) = new_db();
+    let namespace = Namespace::default();
+
+    // Compute the expected roots using an in-memory tree.
+    let mut tree = Tree::builder()
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+    let mut roots = vec![Root {
+        namespace,
+        version: 0,
+        root_type: RootType::State,
+        hash: Hash::empty_hash(),
+    }];
+    let mut write_logs = vec![];
+    for version in 1..=3 {
+        let mut write_log: Vec<LogEntry> = items(version)
+            .iter()
+            .map(|(key, value)| LogEntry::new(key, value))
+            .collect();
+        if version == 3 {
+            // Also remove some keys.
+            write_log.truncate(50);
+            write_log.push(LogEntry {
+                key: b"key 99".to_vec(),
+                value: None,
+            });
+        }
+        for entry in write_log.iter() {
+            match entry.value {
+                Some(ref value) => tree.insert(Context::background(), &entry.key, value),
+                None => tree.remove(Context::background(), &entry.key),
+            }
+            .expect("update");
+        }
+        let hash = tree
+            .commit(Context::background(), namespace, version)
+            .expect("commit");
+
+        roots.push(Root {
+            namespace,
+            version,
+            root_type: RootType::State,
+            hash,
+        });
+        write_logs.push(write_log);
+    }
+
+    for (i, write_log) in write_logs.iter().enumerate() {
+        db.apply_write_log(Context::background(), roots[i], roots[i + 1], write_log)
+            .expect("apply_write_log");
+    }
+    assert_eq!(
+        db.get_earliest_version(&namespace).expect("version"),
+        Some(1)
+    );
+    assert_eq!(db.get_latest_version(&namespace).expect("version"), Some(3));
+
+    // All versions should remain available.
+    for root in &roots[1..3] {
+        let tree = db.open_tree(*root).expect("open_tree");
+        for (key, value) in items(root.version) {
+            assert_eq!(
+                tree.get(Context::background(), &key).expect("get"),
+                Some(value)
+            );
+        }
+    }
+    let tree = db.open_tree(roots[3]).expect("open_tree");
+    for (i, ((key, v2), (_, v3))) in items(2).into_iter().zip(items(3)).enumerate() {
+        let expected = match i {
+            0..=49 => Some(v3),
+            99 => None,
+            _ => Some(v2),
+        };
+        assert_eq!(
+            tree.get(Context::background(), &key).expect("get"),
+            expected
+        );
+    }
+
+    // Write logs resulting in an unexpected root should be rejected.
+    let bogus_root = Root {
+        hash: Hash::digest_bytes(b"i am a bogus hash"),
+        version: 4,
+        ..roots[3]
+    };
+    let result = db.apply_write_log(
+        Context::background(),
+        roots[3],
+        bogus_root,
+        &vec![LogEntry::new(b"foo", b"bar")],
+    );
+    assert!(result.is_err());
+    assert!(!db.has_root(&bogus_root));
+}
+
+#[test]
+fn test_read_sync() {
+    let (_datadir, db) = new_db();
+
+    let mut tree = Tree::builder()
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+    for (key, value) in items(1) {
+        tree.insert(Context::background(), &key, &value)
+            .expect("insert");
+    }
+    tree.commit(Context::background(), Default::default(), 1)
+        .expect("commit");
+    let root = db
+        .commit_tree(Context::background(), &tree)
+        .expect("commit_tree");
+
+    // A remote tree should be able to sync from the database.
+    let stats = StatsCollector::new(Box::new(db.read_syncer()));
+    let remote_tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root(root)
+        .build(Box::new(stats));
+
+    for (key, value) in items(1) {
+        assert_eq!(
+            remote_tree.get(Context::background(), &key).expect("get"),
+            Some(value)
+        );
+    }
+
+    let cache = remote_tree.cache.borrow();
+    let stats = cache
+        .get_read_syncer()
+        .as_any()
+        .downcast_ref::<StatsCollector>()
+        .expect("stats");
+    assert!(stats.sync_get_count > 0, "sync_get count");
+}
@@ -1,6 +1,9 @@
 //! A ReadSync implementation that can be used to test interoperability
 //! with a storage server over the gRPC protocol.
 //!
+//! When no external protocol server binary is configured, the built-in
+//! Rust storage server is used instead.
+//!
 //! This should only be used for testing.
 use std::{
     any::Any,
@@ -16,7 +19,13 @@ use tempfile::{self, TempDir};
 use super::{rpc, Driver};
 use crate::{
     common::{crypto::hash::Hash, namespace::Namespace},
-    storage::mkvs::{sync::*, tree::RootType, WriteLog},
+    storage::mkvs::{
+        db::FileNodeDB,
+        server::{ApplyRequest, Server, ServerHandle},
+        sync::*,
+        tree::RootType,
+        WriteLog,
+    },
 };
 
 /// Location of the protocol server binary.
@@ -25,12 +34,20 @@ static PROTOCOL_SERVER_BINARY: Option<&'static str> =
 
 /// Interoperability protocol server for testing storage.
 pub struct ProtocolServer {
-    server_process: Child,
+    server: ServerInstance,
     client: rpc::StorageClient,
     #[allow(unused)]
     datadir: TempDir,
 }
 
+/// A running protocol server instance.
+enum ServerInstance {
+    /// External protocol server process.
+    Process(Child),
+    /// Built-in storage server.
+    Local(#[allow(unused)] ServerHandle),
+}
+
 struct ProtocolServerReadSyncer {
     client: rpc::StorageClient,
 }
@@ -60,30 +77,50 @@ impl ProtocolServer {
         let socket_path = datadir.path().join("socket");
 
         // Start protocol server.
-        let server_binary = PROTOCOL_SERVER_BINARY.expect("no server binary configured");
+        let fixture = fixture.unwrap_or(Fixture::None);
+        let server = match (PROTOCOL_SERVER_BINARY, fixture) {
+            (Some(server_binary), fixture) => {
+                ServerInstance::Process(Self::spawn_process(server_binary, &datadir, fixture))
+            }
+            (None, Fixture::None) => {
+                let db = FileNodeDB::open(datadir.path().join("db")).expect("failed to open db");
+                let handle = Server::new(db)
+                    .with_writes(true)
+                    .start(&socket_path)
+                    .expect("storage server failed to start");
+                ServerInstance::Local(handle)
+            }
+            (None, _) => panic!("no server binary configured"),
+        };
+
+        // Create connection with the protocol server.
+        let client = rpc::StorageClient::new(socket_path);
+
+        Self {
+            server,
+            client,
+            datadir,
+        }
+    }
+
+    fn spawn_process(server_binary: &str, datadir: &TempDir, fixture: Fixture) -> Child {
+        let socket_path = datadir.path().join("socket");
         let mut server_cmd = Command::new(server_binary);
         server_cmd
             .arg("proto-server")
             .arg("--datadir")
             .arg(datadir.path())
             .arg("--socket")
-            .arg(socket_path.clone())
+            .arg(socket_path)
             .arg("--fixture")
-            .arg(fixture.unwrap_or(Fixture::None).to_string());
+            .arg(fixture.to_string());
         let server_process = server_cmd.spawn().expect("protocol server failed to start");
 
         // Wait for the server to initialize, because the client is too
         // stupid to attempt to reconnect if it can't the first time around.
         thread::sleep(time::Duration::from_secs(5));
 
-        // Create connection with the protocol server.
-        let client = rpc::StorageClient::new(socket_path.clone());
-
-        Self {
-            server_process,
-            client,
-            datadir,
-        }
+        server_process
     }
 
     /// Return a ReadSync backed by the protocol server
@@ -97,8 +134,10 @@ impl ProtocolServer {
 impl Drop for ProtocolServer {
     fn drop(&mut self) {
         // Stop protocol server.
-        drop(self.server_process.kill());
-        drop(self.server_process.wait());
+        if let ServerInstance::Process(ref mut server_process) = self.server {
+            drop(server_process.kill());
+            drop(server_process.wait());
+        }
     }
 }
 
@@ -116,7 +155,7 @@ impl Driver for ProtocolServer {
         version: u64,
     ) {
         self.client
-            .apply(&rpc::ApplyRequest {
+            .apply(&ApplyRequest {
                 namespace,
                 root_type: RootType::State, // Doesn't matter for tests.
                 src_round: version,
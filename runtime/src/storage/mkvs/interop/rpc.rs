@@ -1,46 +1,19 @@
 use std::{path::PathBuf, time::Duration};
 
 use anyhow::Result;
-use base64::STANDARD;
 use jsonrpc::{simple_uds::UdsTransport, Client};
-use serde::{Deserialize, Serialize};
 
-use crate::{
-    common::{crypto::hash::Hash, namespace::Namespace},
-    storage::mkvs::{sync, tree::RootType, WriteLog},
+use crate::storage::mkvs::{
+    server::{
+        ApplyRequest, ApplyResponse, RPCRequest, RPCResponse, METHOD_APPLY, METHOD_SYNC_GET,
+        METHOD_SYNC_GET_PREFIXES, METHOD_SYNC_ITERATE,
+    },
+    sync,
 };
 
 // Calls should still have a timeout to handle the case where the interop server exits prematurely.
 const CALL_TIMEOUT: Duration = Duration::from_secs(30);
 
-#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
-pub struct ApplyRequest {
-    pub namespace: Namespace,
-    pub root_type: RootType,
-    pub src_round: u64,
-    pub src_root: Hash,
-    pub dst_round: u64,
-    pub dst_root: Hash,
-    pub writelog: WriteLog,
-}
-
-base64_serde_type!(Base64Standard, STANDARD);
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct RPCRequest {
-    #[serde(with = "Base64Standard")]
-    pub payload: Vec<u8>,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct RPCResponse {
-    #[serde(with = "Base64Standard")]
-    pub payload: Vec<u8>,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct ApplyResponse {}
-
 /// A (simplified) storage gRPC service client.
 ///
 /// # Note
@@ -70,7 +43,7 @@ impl StorageClient {
         };
         match self
             .client
-            .call::<ApplyResponse>("Database.Apply", &[jsonrpc::arg(req)])
+            .call::<ApplyResponse>(METHOD_APPLY, &[jsonrpc::arg(req)])
         {
             Ok(_) => Ok(()),
             Err(err) => Err(err.into()),
@@ -83,7 +56,7 @@ impl StorageClient {
         };
         match self
             .client
-            .call::<RPCResponse>("Database.SyncGet", &[jsonrpc::arg(req)])
+            .call::<RPCResponse>(METHOD_SYNC_GET, &[jsonrpc::arg(req)])
         {
             Ok(resp) => match cbor::from_slice::<sync::ProofResponse>(&resp.payload) {
                 Ok(proof) => Ok(proof),
@@ -102,7 +75,7 @@ impl StorageClient {
         };
         match self
             .client
-            .call::<RPCResponse>("Database.SyncGetPrefixes", &[jsonrpc::arg(req)])
+            .call::<RPCResponse>(METHOD_SYNC_GET_PREFIXES, &[jsonrpc::arg(req)])
         {
             Ok(resp) => match cbor::from_slice::<sync::ProofResponse>(&resp.payload) {
                 Ok(proof) => Ok(proof),
@@ -118,7 +91,7 @@ impl StorageClient {
         };
         match self
             .client
-            .call::<RPCResponse>("Database.SyncIterate", &[jsonrpc::arg(req)])
+            .call::<RPCResponse>(METHOD_SYNC_ITERATE, &[jsonrpc::arg(req)])
         {
             Ok(resp) => match cbor::from_slice::<sync::ProofResponse>(&resp.payload) {
                 Ok(proof) => Ok(proof),
@@ -4,7 +4,7 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Context as _, Error, Result};
 use io_context::Context;
 
 use crate::common::{crypto::hash::Hash, namespace::Namespace};
@@ -12,14 +12,21 @@ use crate::common::{crypto::hash::Hash, namespace::Namespace};
 #[macro_use]
 mod tree;
 mod cache;
+#[cfg(not(target_env = "sgx"))]
+pub mod db;
 #[cfg(test)]
 pub mod interop;
 pub mod marshal;
+mod range;
+#[cfg(not(target_env = "sgx"))]
+pub mod server;
 pub mod sync;
 #[cfg(test)]
 mod tests;
 
-pub use tree::{Depth, Key, NodeBox, OverlayTree, Root, RootType, Tree};
+pub use cache::{CacheStats, EvictionPolicy};
+pub use range::RangeIterator;
+pub use tree::{Depth, Key, NodeBox, OverlayTree, Root, RootType, Tree, TreeFork};
 
 /// The type of entry in the log.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -122,9 +129,31 @@ pub trait MKVS {
     /// in the database.
     fn remove(&mut self, ctx: Context, key: &[u8]) -> Option<Vec<u8>>;
 
+    /// Remove all entries with keys starting with the given prefix, returning a write log
+    /// with a delete entry for each removed key.
+    ///
+    /// The default implementation iterates over the keys and removes them one by one.
+    fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> WriteLog {
+        let ctx = ctx.freeze();
+        let keys = keys_with_prefix(MKVS::iter(self, Context::create_child(&ctx)), prefix)
+            .expect("iteration over keys to remove failed");
+        let mut log = WriteLog::with_capacity(keys.len());
+        for key in keys {
+            if MKVS::remove(self, Context::create_child(&ctx), &key).is_some() {
+                log.push(LogEntry { key, value: None });
+            }
+        }
+        log
+    }
+
     /// Populate the in-memory tree with nodes for keys starting with given prefixes.
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &[Prefix], limit: u16);
 
+    /// Populate the in-memory tree with nodes for the given keys.
+    ///
+    /// Prefetching is only an optimization, so the default implementation does nothing.
+    fn prefetch_keys(&self, _ctx: Context, _keys: &[&[u8]]) {}
+
     /// Returns an iterator over the tree.
     fn iter(&self, ctx: Context) -> Box<dyn Iterator + '_>;
 
@@ -163,9 +192,36 @@ pub trait FallibleMKVS {
     /// in the database.
     fn remove(&mut self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>>;
 
+    /// Remove all entries with keys starting with the given prefix, returning a write log
+    /// with a delete entry for each removed key.
+    ///
+    /// The default implementation iterates over the keys and removes them one by one.
+    fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> Result<WriteLog> {
+        let ctx = ctx.freeze();
+        let keys = keys_with_prefix(
+            FallibleMKVS::iter(self, Context::create_child(&ctx)),
+            prefix,
+        )
+        .context("failed to iterate over keys to remove")?;
+        let mut log = WriteLog::with_capacity(keys.len());
+        for key in keys {
+            if FallibleMKVS::remove(self, Context::create_child(&ctx), &key)?.is_some() {
+                log.push(LogEntry { key, value: None });
+            }
+        }
+        Ok(log)
+    }
+
     /// Populate the in-memory tree with nodes for keys starting with given prefixes.
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &[Prefix], limit: u16) -> Result<()>;
 
+    /// Populate the in-memory tree with nodes for the given keys.
+    ///
+    /// Prefetching is only an optimization, so the default implementation does nothing.
+    fn prefetch_keys(&self, _ctx: Context, _keys: &[&[u8]]) -> Result<()> {
+        Ok(())
+    }
+
     /// Returns an iterator over the tree.
     fn iter(&self, ctx: Context) -> Box<dyn Iterator + '_>;
 
@@ -213,12 +269,50 @@ pub trait Iterator: iter::Iterator<Item = (Vec<u8>, Vec<u8>)> {
     /// Return the error that occurred during iteration if any.
     fn error(&self) -> &Option<Error>;
 
+    /// Take the error that occurred during iteration if any.
+    ///
+    /// The default implementation can only return a copy of the error message, iterators
+    /// should override it to return the original error.
+    fn take_error(&mut self) -> Result<()> {
+        match self.error() {
+            Some(error) => Err(anyhow!("{:#}", error)),
+            None => Ok(()),
+        }
+    }
+
     /// Moves the iterator to the first key in the tree.
     fn rewind(&mut self);
 
     /// Moves the iterator either at the given key or at the next larger key.
     fn seek(&mut self, key: &[u8]);
 
+    /// Moves the iterator to the last key in the tree.
+    ///
+    /// The default implementation scans the whole tree in forward order. Iterators which can
+    /// iterate in reverse should override it.
+    fn seek_last(&mut self) {
+        self.rewind();
+        let mut last = None;
+        while let Some(key) = self.get_key() {
+            last = Some(key.clone());
+            Iterator::next(self);
+        }
+        if let Some(key) = last {
+            self.seek(&key);
+        }
+    }
+
+    /// Moves the iterator either at the given key or at the previous smaller key.
+    ///
+    /// The default implementation scans the tree in forward order up to the given key.
+    /// Iterators which can iterate in reverse should override it.
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        self.seek(key);
+        if self.get_key().as_deref() != Some(key) {
+            seek_before(self, key);
+        }
+    }
+
     /// The key under the iterator.
     fn get_key(&self) -> &Option<Key>;
 
@@ -227,6 +321,54 @@ pub trait Iterator: iter::Iterator<Item = (Vec<u8>, Vec<u8>)> {
 
     /// Advance the iterator to the next key.
     fn next(&mut self);
+
+    /// Move the iterator back to the previous key.
+    ///
+    /// The default implementation scans the tree in forward order up to the current key.
+    /// Iterators which can iterate in reverse should override it.
+    fn prev(&mut self) {
+        if let Some(key) = self.get_key().clone() {
+            seek_before(self, &key);
+        }
+    }
+}
+
+/// Collect all keys starting with the given prefix.
+fn keys_with_prefix(mut it: Box<dyn Iterator + '_>, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut keys = Vec::new();
+    it.seek(prefix);
+    while let Some(key) = it.get_key() {
+        if !key.starts_with(prefix) {
+            break;
+        }
+        keys.push(key.clone());
+        Iterator::next(&mut *it);
+    }
+    it.take_error()?;
+    Ok(keys)
+}
+
+/// Move the iterator to the largest key smaller than the given key by scanning the tree in
+/// forward order, invalidating the iterator if there is no such key.
+fn seek_before<I: Iterator + ?Sized>(it: &mut I, key: &[u8]) {
+    it.rewind();
+    let mut last = None;
+    while let Some(current) = it.get_key() {
+        if current.as_slice() >= key {
+            break;
+        }
+        last = Some(current.clone());
+        Iterator::next(it);
+    }
+    match last {
+        Some(last) => it.seek(&last),
+        None => {
+            // Move past the end of the tree.
+            while it.is_valid() {
+                Iterator::next(it);
+            }
+        }
+    }
 }
 
 impl<T: MKVS + ?Sized> MKVS for &mut T {
@@ -246,10 +388,18 @@ impl<T: MKVS + ?Sized> MKVS for &mut T {
         T::remove(self, ctx, key)
     }
 
+    fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> WriteLog {
+        T::remove_prefix(self, ctx, prefix)
+    }
+
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &[Prefix], limit: u16) {
         T::prefetch_prefixes(self, ctx, prefixes, limit)
     }
 
+    fn prefetch_keys(&self, ctx: Context, keys: &[&[u8]]) {
+        T::prefetch_keys(self, ctx, keys)
+    }
+
     fn iter(&self, ctx: Context) -> Box<dyn Iterator + '_> {
         T::iter(self, ctx)
     }
@@ -281,10 +431,18 @@ impl<T: FallibleMKVS + ?Sized> FallibleMKVS for &mut T {
         T::remove(self, ctx, key)
     }
 
+    fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> Result<WriteLog> {
+        T::remove_prefix(self, ctx, prefix)
+    }
+
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &[Prefix], limit: u16) -> Result<()> {
         T::prefetch_prefixes(self, ctx, prefixes, limit)
     }
 
+    fn prefetch_keys(&self, ctx: Context, keys: &[&[u8]]) -> Result<()> {
+        T::prefetch_keys(self, ctx, keys)
+    }
+
     fn iter(&self, ctx: Context) -> Box<dyn Iterator + '_> {
         T::iter(self, ctx)
     }
@@ -297,6 +455,87 @@ impl<T: FallibleMKVS + ?Sized> FallibleMKVS for &mut T {
 #[cfg(test)]
 mod _tests {
     use super::*;
+    use crate::storage::mkvs::sync::NoopReadSyncer;
+
+    /// Tree which only implements the required methods of the MKVS traits.
+    struct MinimalTree(Tree);
+
+    impl FallibleMKVS for MinimalTree {
+        fn get(&self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            FallibleMKVS::get(&self.0, ctx, key)
+        }
+
+        fn cache_contains_key(&self, ctx: Context, key: &[u8]) -> bool {
+            FallibleMKVS::cache_contains_key(&self.0, ctx, key)
+        }
+
+        fn insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+            FallibleMKVS::insert(&mut self.0, ctx, key, value)
+        }
+
+        fn remove(&mut self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            FallibleMKVS::remove(&mut self.0, ctx, key)
+        }
+
+        fn prefetch_prefixes(&self, ctx: Context, prefixes: &[Prefix], limit: u16) -> Result<()> {
+            FallibleMKVS::prefetch_prefixes(&self.0, ctx, prefixes, limit)
+        }
+
+        fn iter(&self, ctx: Context) -> Box<dyn Iterator + '_> {
+            FallibleMKVS::iter(&self.0, ctx)
+        }
+
+        fn commit(&mut self, ctx: Context, namespace: Namespace, version: u64) -> Result<Hash> {
+            FallibleMKVS::commit(&mut self.0, ctx, namespace, version)
+        }
+    }
+
+    #[test]
+    fn test_default_prefetch_keys() {
+        let tree = MinimalTree(
+            Tree::builder()
+                .with_root_type(RootType::State)
+                .build(Box::new(NoopReadSyncer)),
+        );
+        FallibleMKVS::prefetch_keys(&tree, Context::background(), &[b"foo", b"bar"])
+            .expect("prefetch_keys should succeed");
+    }
+
+    #[test]
+    fn test_default_remove_prefix() {
+        let mut tree = MinimalTree(
+            Tree::builder()
+                .with_root_type(RootType::State)
+                .build(Box::new(NoopReadSyncer)),
+        );
+        for key in [&b"foo"[..], b"foo 1", b"foo 2", b"fop", b"bar"] {
+            FallibleMKVS::insert(&mut tree, Context::background(), key, b"value").unwrap();
+        }
+
+        let log = FallibleMKVS::remove_prefix(&mut tree, Context::background(), b"foo").unwrap();
+        assert_eq!(
+            log,
+            vec![
+                LogEntry {
+                    key: b"foo".to_vec(),
+                    value: None
+                },
+                LogEntry {
+                    key: b"foo 1".to_vec(),
+                    value: None
+                },
+                LogEntry {
+                    key: b"foo 2".to_vec(),
+                    value: None
+                },
+            ]
+        );
+
+        let mut it = FallibleMKVS::iter(&tree, Context::background());
+        it.rewind();
+        let keys: Vec<_> = it.map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![b"bar".to_vec(), b"fop".to_vec()]);
+    }
 
     #[test]
     fn test_write_log_serialization() {
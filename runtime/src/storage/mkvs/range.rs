@@ -0,0 +1,179 @@
+//! Bounded range iteration.
+use std::ops::{Bound, RangeBounds};
+
+use anyhow::Error;
+
+use super::Iterator;
+
+/// An iterator over all items whose keys are within a given range.
+///
+/// Items can be consumed from the front in ascending key order and from the back in descending
+/// key order (e.g., by using `rev`). Each end is driven by its own MKVS iterator.
+pub struct RangeIterator<I: Iterator> {
+    front: I,
+    back: I,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    front_started: bool,
+    back_started: bool,
+    /// Last key returned from the front.
+    front_key: Option<Vec<u8>>,
+    /// Last key returned from the back.
+    back_key: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<I: Iterator> RangeIterator<I> {
+    /// Create a new range iterator using the given MKVS iterators for the two ends.
+    pub fn new<K, R>(front: I, back: I, range: R) -> Self
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let to_owned = |bound: Bound<&K>| match bound {
+            Bound::Included(key) => Bound::Included(key.as_ref().to_vec()),
+            Bound::Excluded(key) => Bound::Excluded(key.as_ref().to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        Self {
+            front,
+            back,
+            start: to_owned(range.start_bound()),
+            end: to_owned(range.end_bound()),
+            front_started: false,
+            back_started: false,
+            front_key: None,
+            back_key: None,
+            done: false,
+        }
+    }
+
+    /// Sets the number of elements to prefetch at each end.
+    pub fn set_prefetch(&mut self, prefetch: usize) {
+        self.front.set_prefetch(prefetch);
+        self.back.set_prefetch(prefetch);
+    }
+
+    /// Return the error that occurred during iteration if any.
+    pub fn error(&self) -> &Option<Error> {
+        match self.front.error() {
+            Some(_) => self.front.error(),
+            None => self.back.error(),
+        }
+    }
+
+    fn is_after_start(&self, key: &[u8]) -> bool {
+        match self.start {
+            Bound::Included(ref start) => key >= &start[..],
+            Bound::Excluded(ref start) => key > &start[..],
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn is_before_end(&self, key: &[u8]) -> bool {
+        match self.end {
+            Bound::Included(ref end) => key <= &end[..],
+            Bound::Excluded(ref end) => key < &end[..],
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn advance_front(&mut self) {
+        if self.front_started {
+            Iterator::next(&mut self.front);
+            return;
+        }
+        self.front_started = true;
+
+        match self.start {
+            Bound::Included(ref start) => self.front.seek(start),
+            Bound::Excluded(ref start) => {
+                self.front.seek(start);
+                if self.front.get_key().as_ref() == Some(start) {
+                    Iterator::next(&mut self.front);
+                }
+            }
+            Bound::Unbounded => self.front.rewind(),
+        }
+    }
+
+    fn advance_back(&mut self) {
+        if self.back_started {
+            self.back.prev();
+            return;
+        }
+        self.back_started = true;
+
+        match self.end {
+            Bound::Included(ref end) => self.back.seek_for_prev(end),
+            Bound::Excluded(ref end) => {
+                self.back.seek_for_prev(end);
+                if self.back.get_key().as_ref() == Some(end) {
+                    self.back.prev();
+                }
+            }
+            Bound::Unbounded => self.back.seek_last(),
+        }
+    }
+}
+
+impl<I: Iterator> std::iter::Iterator for RangeIterator<I> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        self.advance_front();
+        let key = match self.front.get_key() {
+            Some(key) => key.clone(),
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+
+        // Stop at the end of the range or when meeting the items returned from the back.
+        let met_back = matches!(self.back_key, Some(ref back_key) if key >= *back_key);
+        if !self.is_before_end(&key) || met_back {
+            self.done = true;
+            return None;
+        }
+
+        let value = self.front.get_value().clone().expect("iterator is valid");
+        self.front_key = Some(key.clone());
+
+        Some((key, value))
+    }
+}
+
+impl<I: Iterator> DoubleEndedIterator for RangeIterator<I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        self.advance_back();
+        let key = match self.back.get_key() {
+            Some(key) => key.clone(),
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+
+        // Stop at the start of the range or when meeting the items returned from the front.
+        let met_front = matches!(self.front_key, Some(ref front_key) if key <= *front_key);
+        if !self.is_after_start(&key) || met_front {
+            self.done = true;
+            return None;
+        }
+
+        let value = self.back.get_value().clone().expect("iterator is valid");
+        self.back_key = Some(key.clone());
+
+        Some((key, value))
+    }
+}
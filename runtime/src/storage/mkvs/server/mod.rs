@@ -0,0 +1,391 @@
+//! A standalone MKVS storage server.
+//!
+//! The server exposes the read syncer interface of a node database over a Unix socket. It
+//! speaks JSON-RPC 2.0 with CBOR-encoded payloads, the same protocol as the storage
+//! interoperability protocol server, so existing clients can use either one.
+//!
+//! The server is read-only unless writes are explicitly enabled.
+use std::{
+    fs,
+    io::{BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use base64::STANDARD;
+use base64_serde::base64_serde_type;
+use io_context::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{crypto::hash::Hash, namespace::Namespace},
+    storage::mkvs::{
+        db::{DbReadSyncer, FileNodeDB},
+        sync::*,
+        tree::*,
+        WriteLog,
+    },
+};
+
+#[cfg(test)]
+mod test;
+
+/// Method name for the Apply operation.
+pub const METHOD_APPLY: &str = "Database.Apply";
+/// Method name for the SyncGet operation.
+pub const METHOD_SYNC_GET: &str = "Database.SyncGet";
+/// Method name for the SyncGetMany operation.
+pub const METHOD_SYNC_GET_MANY: &str = "Database.SyncGetMany";
+/// Method name for the SyncGetPrefixes operation.
+pub const METHOD_SYNC_GET_PREFIXES: &str = "Database.SyncGetPrefixes";
+/// Method name for the SyncIterate operation.
+pub const METHOD_SYNC_ITERATE: &str = "Database.SyncIterate";
+
+/// JSON-RPC error code for methods that do not exist.
+const ERROR_METHOD_NOT_FOUND: i32 = -32601;
+/// JSON-RPC error code for invalid method parameters.
+const ERROR_INVALID_PARAMS: i32 = -32602;
+/// JSON-RPC error code for failed method calls.
+const ERROR_SERVER: i32 = -32000;
+/// JSON-RPC error code for methods that are not allowed.
+const ERROR_METHOD_NOT_ALLOWED: i32 = -32001;
+
+/// Default maximum number of concurrently served connections.
+const DEFAULT_MAX_CONNECTIONS: usize = 16;
+/// Permissions of the socket of a server which accepts writes, restricting access to
+/// the owner.
+const WRITABLE_SOCKET_MODE: u32 = 0o600;
+
+/// Request for the Apply operation.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct ApplyRequest {
+    pub namespace: Namespace,
+    pub root_type: RootType,
+    pub src_round: u64,
+    pub src_root: Hash,
+    pub dst_round: u64,
+    pub dst_root: Hash,
+    pub writelog: WriteLog,
+}
+
+base64_serde_type!(Base64Standard, STANDARD);
+
+/// JSON-RPC request parameters carrying a CBOR-encoded request.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RPCRequest {
+    #[serde(with = "Base64Standard")]
+    pub payload: Vec<u8>,
+}
+
+/// JSON-RPC result carrying a CBOR-encoded response.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RPCResponse {
+    #[serde(with = "Base64Standard")]
+    pub payload: Vec<u8>,
+}
+
+/// JSON-RPC result of the Apply operation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApplyResponse {}
+
+/// A JSON-RPC request envelope.
+#[derive(Deserialize)]
+struct Request {
+    method: String,
+    #[serde(default)]
+    params: serde_json::Value,
+    #[serde(default)]
+    id: serde_json::Value,
+}
+
+/// A JSON-RPC response envelope.
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+/// A JSON-RPC error.
+#[derive(Serialize)]
+struct ResponseError {
+    code: i32,
+    message: String,
+}
+
+/// MKVS storage server.
+#[derive(Clone)]
+pub struct Server {
+    db: FileNodeDB,
+    writable: bool,
+    max_connections: usize,
+    connections: Arc<AtomicUsize>,
+}
+
+impl Server {
+    /// Create a new read-only storage server serving from the given node database.
+    pub fn new(db: FileNodeDB) -> Self {
+        Self {
+            db,
+            writable: false,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Allow writes to the node database via the Apply operation.
+    ///
+    /// As anyone who can connect can then modify the database, the socket of a writable server
+    /// is only accessible to its owner.
+    pub fn with_writes(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+
+    /// Configure the maximum number of concurrently served connections.
+    ///
+    /// Connections above the limit are closed right away.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Start serving requests on the given Unix socket in a background thread.
+    ///
+    /// The server stops when the returned handle is dropped.
+    pub fn start<P: AsRef<Path>>(self, socket_path: P) -> Result<ServerHandle> {
+        let socket_path = socket_path.as_ref().to_path_buf();
+        let listener = UnixListener::bind(&socket_path)?;
+        if self.writable {
+            fs::set_permissions(
+                &socket_path,
+                fs::Permissions::from_mode(WRITABLE_SOCKET_MODE),
+            )?;
+        }
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(_) => continue,
+                    };
+                    let guard =
+                        match ConnectionGuard::acquire(&self.connections, self.max_connections) {
+                            Some(guard) => guard,
+                            // Dropping the stream closes the connection.
+                            None => continue,
+                        };
+
+                    let server = self.clone();
+                    thread::spawn(move || {
+                        // Errors only affect the given connection.
+                        let _ = server.handle_connection(stream);
+                        drop(guard);
+                    });
+                }
+            })
+        };
+
+        Ok(ServerHandle {
+            socket_path,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    fn handle_connection(&self, stream: UnixStream) -> Result<()> {
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        // Requests on the same connection usually refer to the same root, so they share a
+        // read syncer.
+        let mut read_syncer = self.db.read_syncer();
+        let requests = serde_json::Deserializer::from_reader(reader).into_iter::<Request>();
+        for request in requests {
+            let request = request?;
+            let (result, error) =
+                match self.dispatch(&mut read_syncer, &request.method, request.params) {
+                    Ok(result) => (Some(result), None),
+                    Err(error) => (None, Some(error)),
+                };
+            let response = Response {
+                jsonrpc: "2.0",
+                id: request.id,
+                result,
+                error,
+            };
+
+            serde_json::to_writer(&mut writer, &response)?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn dispatch(
+        &self,
+        read_syncer: &mut DbReadSyncer,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, ResponseError> {
+        match method {
+            METHOD_APPLY
+            | METHOD_SYNC_GET
+            | METHOD_SYNC_GET_MANY
+            | METHOD_SYNC_GET_PREFIXES
+            | METHOD_SYNC_ITERATE => {}
+            _ => {
+                return Err(ResponseError {
+                    code: ERROR_METHOD_NOT_FOUND,
+                    message: format!("unknown method: {}", method),
+                })
+            }
+        }
+        if method == METHOD_APPLY && !self.writable {
+            return Err(ResponseError {
+                code: ERROR_METHOD_NOT_ALLOWED,
+                message: format!("{}: server is read-only", method),
+            });
+        }
+
+        // Requests are always sent as an array with a single element.
+        let payload = match serde_json::from_value::<Vec<RPCRequest>>(params) {
+            Ok(mut requests) if requests.len() == 1 => requests.remove(0).payload,
+            Ok(requests) => {
+                return Err(ResponseError {
+                    code: ERROR_INVALID_PARAMS,
+                    message: format!("{}: invalid number of requests: {}", method, requests.len()),
+                })
+            }
+            Err(err) => {
+                return Err(ResponseError {
+                    code: ERROR_INVALID_PARAMS,
+                    message: format!("{}: invalid request: {}", method, err),
+                })
+            }
+        };
+
+        self.call(read_syncer, method, &payload)
+            .map_err(|err| ResponseError {
+                code: ERROR_SERVER,
+                message: format!("{}: {}", method, err),
+            })
+    }
+
+    fn call(
+        &self,
+        read_syncer: &mut DbReadSyncer,
+        method: &str,
+        payload: &[u8],
+    ) -> Result<serde_json::Value> {
+        let ctx = Context::background();
+
+        let response = match method {
+            METHOD_APPLY => {
+                let request: ApplyRequest = cbor::from_slice(payload)?;
+                self.apply(ctx, request)?;
+                return Ok(serde_json::to_value(ApplyResponse {})?);
+            }
+            METHOD_SYNC_GET => read_syncer.sync_get(ctx, cbor::from_slice(payload)?)?,
+            METHOD_SYNC_GET_MANY => read_syncer.sync_get_many(ctx, cbor::from_slice(payload)?)?,
+            METHOD_SYNC_GET_PREFIXES => {
+                read_syncer.sync_get_prefixes(ctx, cbor::from_slice(payload)?)?
+            }
+            METHOD_SYNC_ITERATE => read_syncer.sync_iterate(ctx, cbor::from_slice(payload)?)?,
+            _ => return Err(anyhow!("unknown method")),
+        };
+
+        Ok(serde_json::to_value(RPCResponse {
+            payload: cbor::to_vec(response),
+        })?)
+    }
+
+    fn apply(&self, ctx: Context, request: ApplyRequest) -> Result<()> {
+        let src_root = Root {
+            namespace: request.namespace,
+            version: request.src_round,
+            root_type: request.root_type,
+            hash: request.src_root,
+        };
+        let dst_root = Root {
+            namespace: request.namespace,
+            version: request.dst_round,
+            root_type: request.root_type,
+            hash: request.dst_root,
+        };
+
+        self.db
+            .apply_write_log(ctx, src_root, dst_root, &request.writelog)
+    }
+}
+
+/// A slot of a served connection, released when dropped.
+struct ConnectionGuard {
+    connections: Arc<AtomicUsize>,
+}
+
+impl ConnectionGuard {
+    /// Acquire a connection slot, unless all of them are taken.
+    fn acquire(connections: &Arc<AtomicUsize>, max_connections: usize) -> Option<Self> {
+        connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max_connections).then(|| n + 1)
+            })
+            .ok()?;
+
+        Some(Self {
+            connections: connections.clone(),
+        })
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A handle to a running storage server.
+pub struct ServerHandle {
+    socket_path: PathBuf,
+    stopped: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    /// Path to the Unix socket the server is listening on.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        // Wake up the listener so it notices that it should stop.
+        drop(UnixStream::connect(&self.socket_path));
+        if let Some(thread) = self.thread.take() {
+            drop(thread.join());
+        }
+        drop(fs::remove_file(&self.socket_path));
+    }
+}
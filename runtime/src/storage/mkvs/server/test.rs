@@ -0,0 +1,183 @@
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    thread,
+    time::Duration,
+};
+
+use super::*;
+
+fn call(socket_path: &Path, request: serde_json::Value) -> serde_json::Value {
+    let mut stream = UnixStream::connect(socket_path).expect("connect");
+    serde_json::to_writer(&mut stream, &request).expect("write request");
+    stream.flush().expect("flush");
+
+    serde_json::Deserializer::from_reader(stream)
+        .into_iter::<serde_json::Value>()
+        .next()
+        .expect("response")
+        .expect("valid response")
+}
+
+#[test]
+fn test_server_errors() {
+    let datadir = tempfile::Builder::default()
+        .prefix("oasis-test-mkvs-server")
+        .tempdir()
+        .expect("failed to create temporary data directory");
+    let db = FileNodeDB::open(datadir.path().join("db")).expect("open");
+    let server = Server::new(db)
+        .start(datadir.path().join("socket"))
+        .expect("start");
+
+    // Unknown methods should be rejected.
+    let response = call(
+        server.socket_path(),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "Database.Unknown",
+            "params": [],
+            "id": 1,
+        }),
+    );
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["error"]["code"], ERROR_METHOD_NOT_FOUND);
+    assert!(response.get("result").is_none());
+
+    // Requests with an invalid number of parameters should be rejected.
+    let response = call(
+        server.socket_path(),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": METHOD_SYNC_GET,
+            "params": [],
+            "id": 2,
+        }),
+    );
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["error"]["code"], ERROR_INVALID_PARAMS);
+
+    // Malformed parameters should be rejected.
+    let response = call(
+        server.socket_path(),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": METHOD_SYNC_ITERATE,
+            "params": [{"payload": "not base64!"}],
+            "id": 3,
+        }),
+    );
+    assert_eq!(response["id"], 3);
+    assert_eq!(response["error"]["code"], ERROR_INVALID_PARAMS);
+
+    // Writes should be rejected by read-only servers.
+    let response = call(
+        server.socket_path(),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": METHOD_APPLY,
+            "params": [],
+            "id": 4,
+        }),
+    );
+    assert_eq!(response["id"], 4);
+    assert_eq!(response["error"]["code"], ERROR_METHOD_NOT_ALLOWED);
+
+    // Stopping the server should remove the socket.
+    let socket_path = server.socket_path().to_path_buf();
+    drop(server);
+    assert!(!socket_path.exists());
+}
+
+#[test]
+fn test_server_writes() {
+    let datadir = tempfile::Builder::default()
+        .prefix("oasis-test-mkvs-server")
+        .tempdir()
+        .expect("failed to create temporary data directory");
+    let db = FileNodeDB::open(datadir.path().join("db")).expect("open");
+    let server = Server::new(db)
+        .with_writes(true)
+        .start(datadir.path().join("socket"))
+        .expect("start");
+
+    // Sockets of writable servers should only be accessible to their owner.
+    let mode = fs::metadata(server.socket_path())
+        .expect("socket metadata")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, WRITABLE_SOCKET_MODE);
+
+    // Writes should be allowed.
+    let request = ApplyRequest {
+        root_type: RootType::State,
+        src_root: Hash::empty_hash(),
+        dst_root: Hash::empty_hash(),
+        ..Default::default()
+    };
+    let response = call(
+        server.socket_path(),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": METHOD_APPLY,
+            "params": [{"payload": base64::encode(cbor::to_vec(request))}],
+            "id": 1,
+        }),
+    );
+    assert_eq!(response["id"], 1);
+    assert_ne!(response["error"]["code"], ERROR_METHOD_NOT_ALLOWED);
+}
+
+#[test]
+fn test_server_max_connections() {
+    let datadir = tempfile::Builder::default()
+        .prefix("oasis-test-mkvs-server")
+        .tempdir()
+        .expect("failed to create temporary data directory");
+    let db = FileNodeDB::open(datadir.path().join("db")).expect("open");
+    let server = Server::new(db)
+        .with_max_connections(1)
+        .start(datadir.path().join("socket"))
+        .expect("start");
+
+    // Keep a connection open, which takes the only connection slot.
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "Database.Unknown",
+        "params": [],
+        "id": 1,
+    });
+    let mut stream = UnixStream::connect(server.socket_path()).expect("connect");
+    serde_json::to_writer(&mut stream, &request).expect("write request");
+    let response = serde_json::Deserializer::from_reader(&mut stream)
+        .into_iter::<serde_json::Value>()
+        .next()
+        .expect("response")
+        .expect("valid response");
+    assert_eq!(response["id"], 1);
+
+    // Connections above the limit should be closed right away.
+    let mut rejected = UnixStream::connect(server.socket_path()).expect("connect");
+    let mut buffer = Vec::new();
+    rejected
+        .read_to_end(&mut buffer)
+        .expect("read from closed connection");
+    assert!(buffer.is_empty(), "connection should be closed");
+
+    // Closing the connection should free its slot.
+    drop(stream);
+    for attempt in 0.. {
+        let mut stream = UnixStream::connect(server.socket_path()).expect("connect");
+        // Writes fail if the connection was closed before they are made.
+        drop(serde_json::to_writer(&mut stream, &request));
+        let response = serde_json::Deserializer::from_reader(stream)
+            .into_iter::<serde_json::Value>()
+            .next();
+        if let Some(Ok(response)) = response {
+            assert_eq!(response["id"], 1);
+            break;
+        }
+        assert!(attempt < 100, "connection slot should be freed");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
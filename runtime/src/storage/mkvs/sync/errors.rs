@@ -4,4 +4,8 @@ use thiserror::Error;
 pub enum SyncerError {
     #[error("mkvs: method not supported")]
     Unsupported,
+    #[error("mkvs: invalid root")]
+    InvalidRoot,
+    #[error("mkvs: root is dirty")]
+    DirtyRoot,
 }
@@ -1,11 +1,11 @@
-use std::{any::Any, sync::Arc};
+use std::{any::Any, sync::Arc, time::Instant};
 
 use anyhow::Result;
 use io_context::Context;
 
 use crate::{
     protocol::{Protocol, ProtocolError},
-    storage::mkvs::sync::*,
+    storage::mkvs::{sync::*, tree::RootType},
     types::{
         Body, HostStorageEndpoint, StorageSyncRequest, StorageSyncRequestWithEndpoint,
         StorageSyncResponse,
@@ -13,33 +13,53 @@ use crate::{
 };
 
 /// A proxy read syncer which forwards calls to the runtime host.
+///
+/// All requests are recorded in the read syncer metrics (see `sync_metrics`), tagged with the
+/// root type of the request and the operation the syncer was created for.
 pub struct HostReadSyncer {
     protocol: Arc<Protocol>,
     endpoint: HostStorageEndpoint,
+    operation: SyncOperation,
 }
 
 impl HostReadSyncer {
     /// Construct a new host proxy instance.
     pub fn new(protocol: Arc<Protocol>, endpoint: HostStorageEndpoint) -> HostReadSyncer {
-        HostReadSyncer { protocol, endpoint }
+        HostReadSyncer {
+            protocol,
+            endpoint,
+            operation: SyncOperation::Other,
+        }
+    }
+
+    /// Set the operation that requests made by this syncer are attributed to.
+    pub fn with_operation(mut self, operation: SyncOperation) -> Self {
+        self.operation = operation;
+        self
     }
 
     fn call_host_with_proof(
         &self,
         ctx: Context,
+        method: &'static str,
+        root_type: RootType,
         request: StorageSyncRequest,
     ) -> Result<ProofResponse> {
         let request = Body::HostStorageSyncRequest(StorageSyncRequestWithEndpoint {
             endpoint: self.endpoint,
             request,
         });
-        match self.protocol.call_host(ctx, request) {
+        let start = Instant::now();
+        let result = match self.protocol.call_host(ctx, request) {
             Ok(Body::HostStorageSyncResponse(StorageSyncResponse::ProofResponse(response))) => {
                 Ok(response)
             }
             Ok(_) => Err(ProtocolError::InvalidResponse.into()),
             Err(error) => Err(error.into()),
-        }
+        };
+        record_request(root_type, self.operation, method, start.elapsed(), &result);
+
+        result
     }
 }
 
@@ -49,7 +69,13 @@ impl ReadSync for HostReadSyncer {
     }
 
     fn sync_get(&mut self, ctx: Context, request: GetRequest) -> Result<ProofResponse> {
-        self.call_host_with_proof(ctx, StorageSyncRequest::SyncGet(request))
+        let root_type = request.tree.root.root_type;
+        self.call_host_with_proof(
+            ctx,
+            "sync_get",
+            root_type,
+            StorageSyncRequest::SyncGet(request),
+        )
     }
 
     fn sync_get_prefixes(
@@ -57,10 +83,22 @@ impl ReadSync for HostReadSyncer {
         ctx: Context,
         request: GetPrefixesRequest,
     ) -> Result<ProofResponse> {
-        self.call_host_with_proof(ctx, StorageSyncRequest::SyncGetPrefixes(request))
+        let root_type = request.tree.root.root_type;
+        self.call_host_with_proof(
+            ctx,
+            "sync_get_prefixes",
+            root_type,
+            StorageSyncRequest::SyncGetPrefixes(request),
+        )
     }
 
     fn sync_iterate(&mut self, ctx: Context, request: IterateRequest) -> Result<ProofResponse> {
-        self.call_host_with_proof(ctx, StorageSyncRequest::SyncIterate(request))
+        let root_type = request.tree.root.root_type;
+        self.call_host_with_proof(
+            ctx,
+            "sync_iterate",
+            root_type,
+            StorageSyncRequest::SyncIterate(request),
+        )
     }
 }
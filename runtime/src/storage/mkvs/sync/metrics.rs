@@ -0,0 +1,392 @@
+//! Metrics of read syncer requests made to the runtime host.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use slog::{info, Logger};
+
+use crate::storage::mkvs::{
+    sync::{proof::PROOF_ENTRY_FULL, *},
+    tree::RootType,
+};
+
+/// Bucket upper bounds for request latencies, in microseconds.
+const LATENCY_BUCKETS: &[u64] = &[
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+/// Bucket upper bounds for proof sizes, in bytes.
+const PROOF_SIZE_BUCKETS: &[u64] = &[
+    256, 1_024, 4_096, 16_384, 65_536, 262_144, 1_048_576, 4_194_304,
+];
+/// Bucket upper bounds for the number of nodes in a proof.
+const PROOF_NODES_BUCKETS: &[u64] = &[1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1_024];
+
+lazy_static! {
+    static ref METRICS: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+/// Operation on behalf of which storage is accessed.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, cbor::Encode, cbor::Decode,
+)]
+#[repr(u8)]
+pub enum SyncOperation {
+    /// Storage access not tied to a specific operation.
+    #[default]
+    Other = 0,
+    /// Transaction execution.
+    Execute = 1,
+    /// Transaction checking.
+    Check = 2,
+    /// Queries.
+    Query = 3,
+}
+
+/// Histogram of observed values.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct Histogram {
+    /// Inclusive upper bounds of the buckets, in increasing order.
+    pub bounds: Vec<u64>,
+    /// Number of observed values in each bucket. The last bucket holds values larger than the
+    /// last bound.
+    pub counts: Vec<u64>,
+    /// Sum of all observed values.
+    pub sum: u64,
+    /// Number of observed values.
+    pub count: u64,
+}
+
+impl Histogram {
+    /// Create a new empty histogram with the given bucket bounds.
+    pub fn new(bounds: &[u64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0,
+            count: 0,
+        }
+    }
+
+    /// Record an observed value.
+    pub fn observe(&mut self, value: u64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.count += 1;
+    }
+
+    /// Mean of all observed values, if any.
+    pub fn mean(&self) -> Option<u64> {
+        self.sum.checked_div(self.count)
+    }
+}
+
+/// Metrics of read syncer requests of a given method.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct SyncRequestMetrics {
+    /// Type of the root the requests were made for.
+    pub root_type: RootType,
+    /// Operation which caused the requests.
+    pub operation: SyncOperation,
+    /// Read syncer method (e.g. `sync_get`).
+    pub method: String,
+    /// Number of failed requests.
+    pub error_count: u64,
+    /// Latencies of all requests, in microseconds.
+    pub latency: Histogram,
+    /// Sizes of the proofs returned by successful requests, in bytes.
+    pub proof_size: Histogram,
+    /// Number of full nodes in the proofs returned by successful requests.
+    pub proof_nodes: Histogram,
+}
+
+impl SyncRequestMetrics {
+    fn new(root_type: RootType, operation: SyncOperation, method: &str) -> Self {
+        Self {
+            root_type,
+            operation,
+            method: method.to_string(),
+            error_count: 0,
+            latency: Histogram::new(LATENCY_BUCKETS),
+            proof_size: Histogram::new(PROOF_SIZE_BUCKETS),
+            proof_nodes: Histogram::new(PROOF_NODES_BUCKETS),
+        }
+    }
+}
+
+/// Metrics of the tree caches used for a given operation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct SyncCacheMetrics {
+    /// Type of the root of the cached trees.
+    pub root_type: RootType,
+    /// Operation the caches were used for.
+    pub operation: SyncOperation,
+    /// Number of node dereferences served from the cache.
+    pub hit_count: u64,
+    /// Number of node dereferences which required fetching nodes from the read syncer.
+    pub miss_count: u64,
+}
+
+impl SyncCacheMetrics {
+    /// Ratio of node dereferences served from the cache, if there were any.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let total = self.hit_count + self.miss_count;
+        if total == 0 {
+            return None;
+        }
+        Some(self.hit_count as f64 / total as f64)
+    }
+}
+
+/// Snapshot of all read syncer metrics.
+#[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub struct SyncMetrics {
+    /// Request metrics, ordered by root type, operation and method.
+    pub requests: Vec<SyncRequestMetrics>,
+    /// Cache metrics, ordered by root type and operation.
+    pub caches: Vec<SyncCacheMetrics>,
+}
+
+/// Counters of node dereferences shared by all tree caches used for a given operation.
+///
+/// Caches hold on to their counters, so recording a lookup doesn't need to take any locks.
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    /// Record a node dereference which was either served from the cache or not.
+    pub fn record_lookup(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    requests: HashMap<(RootType, SyncOperation, &'static str), SyncRequestMetrics>,
+    caches: HashMap<(RootType, SyncOperation), Arc<CacheCounters>>,
+}
+
+/// Record a read syncer request made to the runtime host.
+pub(super) fn record_request(
+    root_type: RootType,
+    operation: SyncOperation,
+    method: &'static str,
+    latency: Duration,
+    result: &Result<ProofResponse>,
+) {
+    let mut metrics = METRICS.lock().unwrap();
+    let request = metrics
+        .requests
+        .entry((root_type, operation, method))
+        .or_insert_with(|| SyncRequestMetrics::new(root_type, operation, method));
+
+    request.latency.observe(latency.as_micros() as u64);
+    match result {
+        Ok(response) => {
+            let entries = response.proof.entries.iter().flatten();
+            let size: usize = entries.clone().map(|entry| entry.len()).sum();
+            let full_nodes = entries
+                .filter(|entry| entry.first() == Some(&PROOF_ENTRY_FULL))
+                .count();
+            request.proof_size.observe(size as u64);
+            request.proof_nodes.observe(full_nodes as u64);
+        }
+        Err(_) => request.error_count += 1,
+    }
+}
+
+/// Return the counters of node dereferences of tree caches used for the given operation.
+pub fn cache_counters(root_type: RootType, operation: SyncOperation) -> Arc<CacheCounters> {
+    let mut metrics = METRICS.lock().unwrap();
+    metrics
+        .caches
+        .entry((root_type, operation))
+        .or_default()
+        .clone()
+}
+
+/// Return a snapshot of all read syncer metrics.
+pub fn sync_metrics() -> SyncMetrics {
+    let metrics = METRICS.lock().unwrap();
+
+    let mut requests: Vec<SyncRequestMetrics> = metrics.requests.values().cloned().collect();
+    requests.sort_by(|a, b| {
+        (a.root_type as u8, a.operation, &a.method).cmp(&(
+            b.root_type as u8,
+            b.operation,
+            &b.method,
+        ))
+    });
+    let mut caches: Vec<SyncCacheMetrics> = metrics
+        .caches
+        .iter()
+        .map(|(&(root_type, operation), counters)| SyncCacheMetrics {
+            root_type,
+            operation,
+            hit_count: counters.hits.load(Ordering::Relaxed),
+            miss_count: counters.misses.load(Ordering::Relaxed),
+        })
+        .filter(|cache| cache.hit_count > 0 || cache.miss_count > 0)
+        .collect();
+    caches.sort_by_key(|cache| (cache.root_type as u8, cache.operation));
+
+    SyncMetrics { requests, caches }
+}
+
+/// Log a snapshot of all read syncer metrics, one entry per request method and cache.
+pub fn log_sync_metrics(logger: &Logger) {
+    let metrics = sync_metrics();
+    for request in &metrics.requests {
+        info!(logger, "storage read syncer request metrics";
+            "root_type" => ?request.root_type,
+            "operation" => ?request.operation,
+            "method" => &request.method,
+            "count" => request.latency.count,
+            "error_count" => request.error_count,
+            "mean_latency_us" => request.latency.mean(),
+            "mean_proof_size" => request.proof_size.mean(),
+            "mean_proof_nodes" => request.proof_nodes.mean(),
+        );
+    }
+    for cache in &metrics.caches {
+        info!(logger, "storage cache metrics";
+            "root_type" => ?cache.root_type,
+            "operation" => ?cache.operation,
+            "hit_count" => cache.hit_count,
+            "miss_count" => cache.miss_count,
+            "hit_ratio" => cache.hit_ratio(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use io_context::Context;
+
+    use super::{super::proof::PROOF_ENTRY_HASH, *};
+    use crate::storage::mkvs::tree::{Root, Tree};
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new(&[10, 100]);
+        assert_eq!(histogram.mean(), None);
+
+        for value in &[1, 10, 11, 100, 1000] {
+            histogram.observe(*value);
+        }
+        assert_eq!(histogram.counts, vec![2, 2, 1]);
+        assert_eq!(histogram.count, 5);
+        assert_eq!(histogram.sum, 1122);
+        assert_eq!(histogram.mean(), Some(224));
+    }
+
+    #[test]
+    fn test_record() {
+        let response = ProofResponse {
+            proof: Proof {
+                entries: vec![
+                    Some(RawProofEntry(vec![PROOF_ENTRY_FULL; 10])),
+                    None,
+                    Some(RawProofEntry(vec![PROOF_ENTRY_FULL; 20])),
+                    Some(RawProofEntry(vec![PROOF_ENTRY_HASH; 33])),
+                ],
+                ..Default::default()
+            },
+        };
+        let latency = Duration::from_millis(2);
+        record_request(
+            RootType::IO,
+            SyncOperation::Check,
+            "sync_get",
+            latency,
+            &Ok(response),
+        );
+        record_request(
+            RootType::IO,
+            SyncOperation::Check,
+            "sync_get",
+            latency,
+            &Err(SyncerError::Unsupported.into()),
+        );
+        let counters = cache_counters(RootType::IO, SyncOperation::Check);
+        for hit in &[true, true, false, true] {
+            counters.record_lookup(*hit);
+        }
+
+        let metrics = sync_metrics();
+        let request = metrics
+            .requests
+            .iter()
+            .find(|r| r.root_type == RootType::IO && r.operation == SyncOperation::Check)
+            .expect("request metrics should be recorded");
+        assert_eq!(request.method, "sync_get");
+        assert_eq!(request.latency.count, 2);
+        assert_eq!(request.error_count, 1);
+        assert_eq!(request.proof_size.sum, 63);
+        assert_eq!(
+            request.proof_nodes.sum, 2,
+            "only full nodes should be counted"
+        );
+
+        let cache = metrics
+            .caches
+            .iter()
+            .find(|c| c.root_type == RootType::IO && c.operation == SyncOperation::Check)
+            .expect("cache metrics should be recorded");
+        assert_eq!(cache.hit_ratio(), Some(0.75));
+    }
+
+    #[test]
+    fn test_record_cache_lookups() {
+        let mut tree = Tree::builder()
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+        tree.insert(Context::background(), b"foo", b"bar")
+            .expect("insert");
+        let hash =
+            Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+        let remote = Tree::builder()
+            .with_root(Root {
+                root_type: RootType::State,
+                hash,
+                ..Default::default()
+            })
+            .with_cache_metrics(SyncOperation::Execute)
+            .build(Box::new(tree));
+
+        // The first lookup needs to fetch the node, the second one is served from the cache.
+        for _ in 0..2 {
+            let value = remote.get(Context::background(), b"foo").expect("get");
+            assert_eq!(value, Some(b"bar".to_vec()));
+        }
+
+        let metrics = sync_metrics();
+        let cache = metrics
+            .caches
+            .iter()
+            .find(|c| c.root_type == RootType::State && c.operation == SyncOperation::Execute)
+            .expect("cache metrics should be recorded");
+        assert!(cache.hit_count >= 1);
+        assert!(cache.miss_count >= 1);
+    }
+}
@@ -2,6 +2,7 @@
 mod errors;
 mod host;
 mod merge;
+mod metrics;
 mod noop;
 mod proof;
 mod stats;
@@ -9,6 +10,7 @@ mod stats;
 pub use errors::*;
 pub use host::*;
 pub use merge::*;
+pub use metrics::*;
 pub use noop::*;
 pub use proof::*;
 pub use stats::*;
@@ -42,6 +44,13 @@ pub struct GetRequest {
     pub include_siblings: bool,
 }
 
+/// Request for the SyncGetMany operation.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct GetManyRequest {
+    pub tree: TreeID,
+    pub keys: Vec<Vec<u8>>,
+}
+
 /// Request for the SyncGetPrefixes operation.
 #[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
 pub struct GetPrefixesRequest {
@@ -56,6 +65,13 @@ pub struct IterateRequest {
     pub tree: TreeID,
     pub key: Vec<u8>,
     pub prefetch: u16,
+    /// Whether to seek to the given key or the previous smaller key and then fetch the
+    /// preceding items instead of the following ones.
+    #[cbor(optional)]
+    pub reverse: bool,
+    /// Whether to ignore the key and seek to the last item in the tree instead. Implies reverse.
+    #[cbor(optional)]
+    pub seek_last: bool,
 }
 
 /// Response for requests that produce proofs.
@@ -73,6 +89,35 @@ pub trait ReadSync {
     /// Fetch a single key and returns the corresponding proof.
     fn sync_get(&mut self, ctx: Context, request: GetRequest) -> Result<ProofResponse>;
 
+    /// Fetch multiple keys and return a single proof for all of them.
+    ///
+    /// The default implementation fetches each key using `sync_get` and combines the proofs.
+    fn sync_get_many(&mut self, ctx: Context, request: GetManyRequest) -> Result<ProofResponse> {
+        let ctx = ctx.freeze();
+        let mut builder = ProofBuilder::new(request.tree.root.hash, request.tree.position);
+        for key in request.keys {
+            let rsp = self.sync_get(
+                Context::create_child(&ctx),
+                GetRequest {
+                    tree: request.tree.clone(),
+                    key,
+                    include_siblings: false,
+                },
+            )?;
+
+            // Verify the proof to obtain the included nodes.
+            let ptr = ProofVerifier.verify_proof(
+                Context::create_child(&ctx),
+                rsp.proof.untrusted_root,
+                &rsp.proof,
+            )?;
+            include_subtree(&mut builder, &ptr);
+        }
+
+        let proof = builder.build(Context::create_child(&ctx))?;
+        Ok(ProofResponse { proof })
+    }
+
     /// Fetch all keys under the given prefixes and returns the corresponding proofs.
     fn sync_get_prefixes(
         &mut self,
@@ -80,10 +125,25 @@ pub trait ReadSync {
         request: GetPrefixesRequest,
     ) -> Result<ProofResponse>;
 
-    /// Seek to a given key and then fetch the specified number of following items
-    /// based on key iteration order.
+    /// Seek to a given key and then fetch the specified number of following (or preceding
+    /// when iterating in reverse) items based on key iteration order.
     fn sync_iterate(&mut self, ctx: Context, request: IterateRequest) -> Result<ProofResponse>;
 }
 
+/// Include all nodes of a verified subtree in the proof.
+fn include_subtree(builder: &mut ProofBuilder, ptr: &NodePtrRef) {
+    let node_ref = match ptr.borrow().node {
+        Some(ref node_ref) => node_ref.clone(),
+        None => return,
+    };
+    let node = node_ref.borrow();
+    builder.include(&node);
+
+    if let NodeBox::Internal(ref n) = *node {
+        include_subtree(builder, &n.left);
+        include_subtree(builder, &n.right);
+    }
+}
+
 #[cfg(test)]
 mod test;
@@ -1,4 +1,7 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
 
 use anyhow::{anyhow, Result};
 use arbitrary::Arbitrary;
@@ -10,9 +13,9 @@ use crate::{
 };
 
 /// Proof entry type for full nodes.
-const PROOF_ENTRY_FULL: u8 = 0x01;
+pub(super) const PROOF_ENTRY_FULL: u8 = 0x01;
 /// Proof entry type for subtree hashes.
-const PROOF_ENTRY_HASH: u8 = 0x02;
+pub(super) const PROOF_ENTRY_HASH: u8 = 0x02;
 
 /// A raw proof entry.
 #[derive(Clone, Debug, Default, PartialEq, Eq, cbor::Encode, cbor::Decode, Arbitrary)]
@@ -62,6 +65,131 @@ pub struct Proof {
     pub entries: Vec<Option<RawProofEntry>>,
 }
 
+/// A node included in a proof that is being built.
+struct ProofNode {
+    serialized: Vec<u8>,
+    children: Vec<Hash>,
+}
+
+/// A Merkle proof builder.
+pub struct ProofBuilder {
+    root: Hash,
+    subtree: Hash,
+    included: HashMap<Hash, ProofNode>,
+    size: u64,
+}
+
+impl ProofBuilder {
+    /// Create a new Merkle proof builder for the given root.
+    pub fn new(root: Hash, subtree: Hash) -> Self {
+        Self {
+            root,
+            subtree,
+            included: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    /// Add a node to the set of included nodes.
+    ///
+    /// The node must be clean.
+    pub fn include(&mut self, node: &NodeBox) {
+        assert!(node.is_clean(), "proof: attempted to add a dirty node");
+
+        // If node is already included, skip it.
+        let hash = node.get_hash();
+        if self.included.contains_key(&hash) {
+            return;
+        }
+
+        // Node is available, serialize it.
+        let serialized = node
+            .compact_marshal_binary()
+            .expect("proof: failed to marshal node");
+
+        // For internal nodes, also add any children.
+        // NOTE: The leaf node is always included with the internal node.
+        let children = match node {
+            NodeBox::Internal(ref n) => vec![n.left.borrow().hash, n.right.borrow().hash],
+            NodeBox::Leaf(_) => vec![],
+        };
+
+        self.size += 1 + serialized.len() as u64;
+        self.included.insert(
+            hash,
+            ProofNode {
+                serialized,
+                children,
+            },
+        );
+    }
+
+    /// Return true if the subtree root node has already been included.
+    pub fn has_subtree_root(&self) -> bool {
+        self.included.contains_key(&self.subtree)
+    }
+
+    /// Return the subtree root hash for this proof.
+    pub fn get_subtree_root(&self) -> Hash {
+        self.subtree
+    }
+
+    /// Return the current size of this proof.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Build the proof.
+    pub fn build(&self, _ctx: Context) -> Result<Proof> {
+        let untrusted_root = if self.has_subtree_root() {
+            // A partial proof for the subtree is available, include that.
+            self.subtree
+        } else {
+            // No partial proof available, we need to use the tree root.
+            self.root
+        };
+
+        let mut proof = Proof {
+            untrusted_root,
+            entries: Vec::new(),
+        };
+        self._build(&mut proof, untrusted_root);
+
+        Ok(proof)
+    }
+
+    fn _build(&self, proof: &mut Proof, hash: Hash) {
+        if hash.is_empty() {
+            // Append null for empty nodes.
+            proof.entries.push(None);
+            return;
+        }
+
+        let node = match self.included.get(&hash) {
+            Some(node) => node,
+            None => {
+                // Node is not included in this proof, just add hash of subtree.
+                let mut entry = Vec::with_capacity(1 + Hash::len());
+                entry.push(PROOF_ENTRY_HASH);
+                entry.extend_from_slice(hash.as_ref());
+                proof.entries.push(Some(entry.into()));
+                return;
+            }
+        };
+
+        // Pre-order traversal, add visited node.
+        let mut entry = Vec::with_capacity(1 + node.serialized.len());
+        entry.push(PROOF_ENTRY_FULL);
+        entry.extend_from_slice(&node.serialized);
+        proof.entries.push(Some(entry.into()));
+
+        // And then add any children.
+        for child in &node.children {
+            self._build(proof, *child);
+        }
+    }
+}
+
 /// A proof verifier enables verifying proofs returned by the ReadSyncer API.
 pub struct ProofVerifier;
 
@@ -100,6 +228,73 @@ impl ProofVerifier {
         Ok(root_node)
     }
 
+    /// Verify a proof and use it to look up the given key.
+    ///
+    /// Returns the value of the key if it is present under the given root and `None` if the
+    /// proof shows that the key is absent. Absence is proven by the path through the verified
+    /// subtree ending in a nil node, a leaf with a different key or a node label which the key
+    /// cannot match. In case the proof does not include all of the nodes on the path to the
+    /// key, an error is returned.
+    pub fn verify_proof_for_key(
+        &self,
+        ctx: Context,
+        root: Hash,
+        key: &[u8],
+        proof: &Proof,
+    ) -> Result<Option<Vec<u8>>> {
+        let root_node = self.verify_proof(ctx, root, proof)?;
+        let key = key.to_vec();
+
+        let mut ptr = root_node;
+        let mut bit_depth: Depth = 0;
+        loop {
+            let node_ref = {
+                let p = ptr.borrow();
+                if p.is_null() {
+                    // Reached a nil node, there is nothing here.
+                    return Ok(None);
+                }
+                if !p.has_node() {
+                    return Err(anyhow!(
+                        "verifier: proof does not include the path to the key (missing node {:?})",
+                        p.hash,
+                    ));
+                }
+                p.get_node()
+            };
+
+            let next = match *node_ref.borrow() {
+                NodeBox::Internal(ref n) => {
+                    let bit_length = bit_depth + n.label_bit_length;
+
+                    // Does lookup key end here? Look into LeafNode.
+                    if key.bit_length() == bit_length {
+                        n.leaf_node.clone()
+                    } else if key.bit_length() < bit_length {
+                        // Lookup key is too short for the current label. It's not stored.
+                        return Ok(None);
+                    } else {
+                        // Continue based on a bit value.
+                        bit_depth = bit_length;
+                        if key.get_bit(bit_length) {
+                            n.right.clone()
+                        } else {
+                            n.left.clone()
+                        }
+                    }
+                }
+                NodeBox::Leaf(ref n) => {
+                    // Reached a leaf node, check if key matches.
+                    if n.key == key {
+                        return Ok(Some(n.value.clone()));
+                    }
+                    return Ok(None);
+                }
+            };
+            ptr = next;
+        }
+    }
+
     fn _verify_proof(proof: &Proof, idx: usize) -> Result<(usize, NodePtrRef)> {
         if idx >= proof.entries.len() {
             return Err(anyhow!("verifier: malformed proof"));
@@ -159,6 +354,7 @@ mod test {
     use io_context::Context;
 
     use super::*;
+    use crate::storage::mkvs::sync::{GetRequest, NoopReadSyncer, TreeID};
 
     #[test]
     fn test_proof() {
@@ -271,4 +467,178 @@ uO/mFPzJZey4liX5fxf4fwcQRhM=",
         pv.verify_proof(Context::background(), root_hash, &proof)
             .expect_err("proof with extra data should fail to validate");
     }
+
+    #[test]
+    fn test_proof_builder() {
+        let mut tree = Tree::builder()
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+
+        let items = [
+            (b"foo".to_vec(), b"bar".to_vec()),
+            (b"carrot".to_vec(), b"stick".to_vec()),
+            (b"ping".to_vec(), b"pong".to_vec()),
+            (b"moo".to_vec(), b"boo".to_vec()),
+            (b"aardvark".to_vec(), b"aah".to_vec()),
+        ];
+        for (key, value) in items.iter() {
+            tree.insert(Context::background(), key, value)
+                .expect("insert");
+        }
+        let hash =
+            Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+        let root = Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        };
+
+        let pv = ProofVerifier;
+        for (key, _) in items.iter().chain(&[(b"missing".to_vec(), vec![])]) {
+            for include_siblings in [false, true] {
+                let rsp = tree
+                    .sync_get(
+                        Context::background(),
+                        GetRequest {
+                            tree: TreeID {
+                                root,
+                                position: hash,
+                            },
+                            key: key.clone(),
+                            include_siblings,
+                        },
+                    )
+                    .expect("sync_get");
+                assert_eq!(rsp.proof.untrusted_root, hash);
+
+                // Proof should verify.
+                pv.verify_proof(Context::background(), hash, &rsp.proof)
+                    .expect("verify proof should not fail with a generated proof");
+            }
+        }
+
+        // Requests for a different root should be rejected.
+        let bogus_root = Root {
+            hash: Hash::digest_bytes(b"i am a bogus hash"),
+            ..root
+        };
+        let result = tree.sync_get(
+            Context::background(),
+            GetRequest {
+                tree: TreeID {
+                    root: bogus_root,
+                    position: bogus_root.hash,
+                },
+                key: b"foo".to_vec(),
+                include_siblings: false,
+            },
+        );
+        assert!(result.is_err(), "sync_get should fail for a different root");
+
+        // Requests against a dirty tree should be rejected.
+        tree.insert(Context::background(), b"dirty", b"value")
+            .expect("insert");
+        let result = tree.sync_get(
+            Context::background(),
+            GetRequest {
+                tree: TreeID {
+                    root,
+                    position: hash,
+                },
+                key: b"foo".to_vec(),
+                include_siblings: false,
+            },
+        );
+        assert!(result.is_err(), "sync_get should fail for a dirty tree");
+    }
+
+    #[test]
+    fn test_proof_for_key() {
+        let mut tree = Tree::builder()
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+
+        let items = [
+            (b"foo".to_vec(), b"bar".to_vec()),
+            (b"foo/bar".to_vec(), b"baz".to_vec()),
+            (b"carrot".to_vec(), b"stick".to_vec()),
+            (b"ping".to_vec(), b"pong".to_vec()),
+            (b"moo".to_vec(), b"boo".to_vec()),
+        ];
+        for (key, value) in items.iter() {
+            tree.insert(Context::background(), key, value)
+                .expect("insert");
+        }
+        let hash =
+            Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+        let root = Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        };
+        let get_proof = |key: &[u8]| {
+            tree.sync_get(
+                Context::background(),
+                GetRequest {
+                    tree: TreeID {
+                        root,
+                        position: hash,
+                    },
+                    key: key.to_vec(),
+                    include_siblings: false,
+                },
+            )
+            .expect("sync_get")
+            .proof
+        };
+
+        let pv = ProofVerifier;
+
+        // Present keys should resolve to their values.
+        for (key, value) in items.iter() {
+            let proof = get_proof(key);
+            let result = pv
+                .verify_proof_for_key(Context::background(), hash, key, &proof)
+                .expect("verify proof for key should not fail with a generated proof");
+            assert_eq!(result.as_ref(), Some(value));
+        }
+
+        // Absent keys should be proven absent.
+        for key in [
+            &b"fo"[..],
+            b"foo/",
+            b"foo/bar/baz",
+            b"carrots",
+            b"zebra",
+            b"",
+        ] {
+            let proof = get_proof(key);
+            let result = pv
+                .verify_proof_for_key(Context::background(), hash, key, &proof)
+                .expect("verify proof for key should not fail with a generated proof");
+            assert_eq!(result, None, "key {:?} should be absent", key);
+        }
+
+        // Proofs for a different root should not verify.
+        let proof = get_proof(b"foo");
+        let bogus_hash = Hash::digest_bytes(b"i am a bogus hash");
+        let result = pv.verify_proof_for_key(Context::background(), bogus_hash, b"foo", &proof);
+        assert!(
+            result.is_err(),
+            "verify proof for key should fail with a proof for a different root"
+        );
+
+        // Proofs which do not include the path to the key should not verify.
+        let mut entry = vec![PROOF_ENTRY_HASH];
+        entry.extend_from_slice(hash.as_ref());
+        let proof = Proof {
+            untrusted_root: hash,
+            entries: vec![Some(entry.into())],
+        };
+        let result = pv.verify_proof_for_key(Context::background(), hash, b"foo", &proof);
+        assert!(
+            result.is_err(),
+            "verify proof for key should fail with an incomplete proof"
+        );
+    }
 }
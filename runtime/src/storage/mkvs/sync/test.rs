@@ -1,3 +1,6 @@
+use std::any::Any;
+
+use anyhow::Result;
 use io_context::Context;
 
 use crate::storage::mkvs::{
@@ -56,3 +59,67 @@ fn test_nil_pointers() {
         .insert(Context::background(), b"insert", b"key")
         .expect("insert");
 }
+
+/// A read syncer which relies on the default `sync_get_many` implementation.
+struct GetOnlyReadSyncer(Tree);
+
+impl ReadSync for GetOnlyReadSyncer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, ctx: Context, request: GetRequest) -> Result<ProofResponse> {
+        self.0.sync_get(ctx, request)
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        _ctx: Context,
+        _request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        Err(SyncerError::Unsupported.into())
+    }
+
+    fn sync_iterate(&mut self, _ctx: Context, _request: IterateRequest) -> Result<ProofResponse> {
+        Err(SyncerError::Unsupported.into())
+    }
+}
+
+#[test]
+fn test_sync_get_many_fallback() {
+    let mut tree = Tree::builder()
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+
+    let keys: Vec<Vec<u8>> = (0..20).map(|i| format!("key {}", i).into_bytes()).collect();
+    for key in &keys {
+        tree.insert(Context::background(), key, key)
+            .expect("insert");
+    }
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    let remote = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root(Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        })
+        .build(Box::new(GetOnlyReadSyncer(tree)));
+
+    // Include a key which doesn't exist.
+    let mut lookup: Vec<&[u8]> = keys.iter().step_by(3).map(|key| key.as_slice()).collect();
+    lookup.push(b"missing");
+    let values = remote
+        .get_many(Context::background(), &lookup)
+        .expect("get_many");
+    assert_eq!(values.len(), lookup.len());
+    for (key, value) in lookup.iter().zip(values) {
+        if *key == b"missing" {
+            assert_eq!(value, None);
+        } else {
+            assert_eq!(value, Some(key.to_vec()));
+        }
+    }
+}
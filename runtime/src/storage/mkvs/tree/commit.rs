@@ -45,6 +45,13 @@ pub fn _commit<C: Cache>(ptr: NodePtrRef, update_list: &mut UpdateList<C>) -> Re
                 let int_left = noderef_as!(some_node_ref, Internal).left.clone();
                 let int_right = noderef_as!(some_node_ref, Internal).right.clone();
 
+                // A clean leaf node is kept from being evicted while the internal node is
+                // dirty, so make it eligible for eviction again.
+                if int_leaf_node.borrow().clean && int_leaf_node.borrow().has_node() {
+                    let leaf_ptr = int_leaf_node.clone();
+                    update_list.push(Box::new(move |cache| cache.commit_node(leaf_ptr.clone())));
+                }
+
                 _commit(int_leaf_node, update_list)?;
                 _commit(int_left, update_list)?;
                 _commit(int_right, update_list)?;
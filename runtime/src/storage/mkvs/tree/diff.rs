@@ -0,0 +1,410 @@
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use io_context::Context;
+
+use crate::{
+    common::crypto::hash::Hash,
+    storage::mkvs::{cache::*, sync::*, tree::*, LogEntry, LogEntryKind, WriteLog},
+};
+
+use super::iterator::FetcherSyncIterate;
+
+/// Number of items to prefetch when fetching nodes while computing a diff.
+pub(super) const DIFF_PREFETCH: usize = 100;
+
+/// One of the two roots being compared by `Tree::diff`.
+struct DiffRoot {
+    pending_root: NodePtrRef,
+    root: Root,
+    /// Whether this is the root of the tree itself, whose nodes are held by its cache. Nodes of
+    /// the other root are only held by the pointers below `pending_root`.
+    cached: bool,
+}
+
+/// Position within a subtree at a given bit depth.
+///
+/// In case the position is within the label of an internal node, `offset` is the number of
+/// label bits that have already been consumed.
+#[derive(Clone, Default)]
+struct Cursor {
+    ptr: Option<NodePtrRef>,
+    node: Option<NodeRef>,
+    offset: Depth,
+}
+
+impl Cursor {
+    fn leaf(&self) -> Option<(Key, Value)> {
+        match self.node {
+            Some(ref node) => match *node.borrow() {
+                NodeBox::Leaf(ref n) => Some((n.key.clone(), n.value.clone())),
+                NodeBox::Internal(_) => None,
+            },
+            None => None,
+        }
+    }
+
+    fn hash(&self) -> Option<Hash> {
+        if self.offset != 0 {
+            return None;
+        }
+        let ptr = self.ptr.as_ref()?.borrow();
+        if !ptr.clean {
+            return None;
+        }
+        Some(ptr.hash)
+    }
+}
+
+/// How a subtree continues after a given position.
+enum Step {
+    /// The subtree is a leaf whose key ends at the position.
+    End,
+    /// The subtree continues with the given bit.
+    Bit(bool),
+    /// The label of the internal node ends at the position.
+    Children(NodePtrRef, NodePtrRef, NodePtrRef),
+}
+
+impl Tree {
+    /// Compute the write log which transforms the committed state of this tree into the state
+    /// at the given root.
+    ///
+    /// The other root is fetched using the read syncer of this tree. Subtrees which are the
+    /// same under both roots are skipped, so only the nodes along changed paths are fetched.
+    /// Entries in the resulting write log are in key order.
+    pub fn diff(&self, ctx: Context, other_root: Root) -> Result<WriteLog> {
+        let ctx = ctx.freeze();
+        let (pending_root, sync_root) = {
+            let cache = self.cache.borrow();
+            (cache.get_pending_root(), cache.get_sync_root())
+        };
+        if !pending_root.borrow().clean {
+            return Err(TreeError::DirtyRoot.into());
+        }
+        if other_root.root_type != self.root_type {
+            return Err(SyncerError::InvalidRoot.into());
+        }
+
+        let mut write_log = WriteLog::new();
+        if pending_root.borrow().hash == other_root.hash {
+            return Ok(write_log);
+        }
+
+        let ours = DiffRoot {
+            pending_root: pending_root.clone(),
+            root: sync_root,
+            cached: true,
+        };
+        let theirs = DiffRoot {
+            pending_root: Rc::new(RefCell::new(NodePointer {
+                clean: true,
+                hash: other_root.hash,
+                ..Default::default()
+            })),
+            root: other_root,
+            cached: false,
+        };
+
+        let path = Key::new();
+        let a = self.cursor(&ctx, &ours, pending_root, &path)?;
+        let b = self.cursor(&ctx, &theirs, theirs.pending_root.clone(), &path)?;
+        self._diff(&ctx, &ours, &theirs, a, b, 0, path, &mut write_log)?;
+
+        Ok(write_log)
+    }
+
+    /// Apply the given write log to this tree and commit the result, checking that it
+    /// results in the expected root.
+    ///
+    /// In case the resulting root does not match, the tree is left at the resulting root and
+    /// an error is returned.
+    pub fn apply_write_log(
+        &mut self,
+        ctx: Context,
+        write_log: &WriteLog,
+        expected_root: Root,
+    ) -> Result<()> {
+        let ctx = ctx.freeze();
+        if expected_root.root_type != self.root_type {
+            return Err(SyncerError::InvalidRoot.into());
+        }
+
+        for entry in write_log {
+            match entry.kind() {
+                LogEntryKind::Insert => {
+                    let value = entry.value.as_ref().unwrap();
+                    self.insert(Context::create_child(&ctx), &entry.key, value)?;
+                }
+                LogEntryKind::Delete => {
+                    self.remove(Context::create_child(&ctx), &entry.key)?;
+                }
+            }
+        }
+
+        let hash = self.commit(
+            Context::create_child(&ctx),
+            expected_root.namespace,
+            expected_root.version,
+        )?;
+        if hash != expected_root.hash {
+            return Err(TreeError::RootMismatch {
+                expected: expected_root.hash,
+                got: hash,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Dereference a node pointer reachable from the given root.
+    fn deref_in(
+        &self,
+        ctx: &Arc<Context>,
+        root: &DiffRoot,
+        ptr: NodePtrRef,
+        path: &Key,
+    ) -> Result<Option<NodeRef>> {
+        let fetcher = FetcherSyncIterate::new(path, DIFF_PREFETCH, false);
+        let mut cache = self.cache.borrow_mut();
+        if root.cached {
+            return cache.deref_node_ptr(ctx, ptr, Some(fetcher));
+        }
+
+        // Nodes of the other root are not reachable from this tree, so they are fetched
+        // without being added to the cache.
+        {
+            let ptr = ptr.borrow();
+            if ptr.node.is_some() || ptr.is_null() {
+                return Ok(ptr.node.clone());
+            }
+        }
+        cache.sync_detached(
+            ctx,
+            root.root,
+            root.pending_root.clone(),
+            ptr.clone(),
+            fetcher,
+        )?;
+
+        let ptr = ptr.borrow();
+        if ptr.node.is_none() {
+            return Err(anyhow!("mkvs: received result did not contain node"));
+        }
+        Ok(ptr.node.clone())
+    }
+
+    fn cursor(
+        &self,
+        ctx: &Arc<Context>,
+        root: &DiffRoot,
+        ptr: NodePtrRef,
+        path: &Key,
+    ) -> Result<Cursor> {
+        let node = self.deref_in(ctx, root, ptr.clone(), path)?;
+        Ok(Cursor {
+            ptr: Some(ptr),
+            node,
+            offset: 0,
+        })
+    }
+
+    /// Split the subtree at the given position into the leaf ending exactly at the position and
+    /// the subtrees continuing with a zero and a one bit respectively.
+    fn _expand(
+        &self,
+        ctx: &Arc<Context>,
+        root: &DiffRoot,
+        cursor: Cursor,
+        bit_depth: Depth,
+        path: &Key,
+    ) -> Result<(Cursor, Cursor, Cursor)> {
+        let node_ref = match cursor.node {
+            Some(ref node_ref) => node_ref.clone(),
+            None => return Ok(Default::default()),
+        };
+
+        let step = match *node_ref.borrow() {
+            NodeBox::Leaf(ref n) if n.key.bit_length() == bit_depth => Step::End,
+            NodeBox::Leaf(ref n) => Step::Bit(n.key.get_bit(bit_depth)),
+            NodeBox::Internal(ref n) if cursor.offset < n.label_bit_length => {
+                Step::Bit(n.label.get_bit(cursor.offset))
+            }
+            NodeBox::Internal(ref n) => {
+                Step::Children(n.leaf_node.clone(), n.left.clone(), n.right.clone())
+            }
+        };
+        let bit = match step {
+            // The leaf ends exactly at this position.
+            Step::End => return Ok((cursor, Default::default(), Default::default())),
+            // Still within a leaf key or a node label.
+            Step::Bit(bit) => bit,
+            // The label has been consumed, continue with the children.
+            Step::Children(leaf_node, left, right) => {
+                let leaf_node = self.cursor(ctx, root, leaf_node, path)?;
+                let left = self.cursor(ctx, root, left, &path.append_bit(bit_depth, false))?;
+                let right = self.cursor(ctx, root, right, &path.append_bit(bit_depth, true))?;
+                let (_, left, _) = self._expand(ctx, root, left, bit_depth, path)?;
+                let (_, _, right) = self._expand(ctx, root, right, bit_depth, path)?;
+                return Ok((leaf_node, left, right));
+            }
+        };
+
+        let next = Cursor {
+            offset: match *node_ref.borrow() {
+                NodeBox::Internal(_) => cursor.offset + 1,
+                NodeBox::Leaf(_) => cursor.offset,
+            },
+            ..cursor
+        };
+        if bit {
+            Ok((Default::default(), Default::default(), next))
+        } else {
+            Ok((Default::default(), next, Default::default()))
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn _diff(
+        &self,
+        ctx: &Arc<Context>,
+        ours: &DiffRoot,
+        theirs: &DiffRoot,
+        a: Cursor,
+        b: Cursor,
+        bit_depth: Depth,
+        path: Key,
+        write_log: &mut WriteLog,
+    ) -> Result<()> {
+        match (&a.node, &b.node) {
+            (None, None) => return Ok(()),
+            (Some(_), None) => {
+                return self._collect(ctx, ours, a, bit_depth, &path, false, write_log)
+            }
+            (None, Some(_)) => {
+                return self._collect(ctx, theirs, b, bit_depth, &path, true, write_log)
+            }
+            (Some(_), Some(_)) => {}
+        }
+
+        // Equal hashes mean equal subtrees.
+        if a.hash().is_some() && a.hash() == b.hash() {
+            return Ok(());
+        }
+
+        if let (Some((a_key, a_value)), Some((b_key, b_value))) = (a.leaf(), b.leaf()) {
+            let delete = LogEntry {
+                key: a_key.clone(),
+                value: None,
+            };
+            let insert = LogEntry {
+                key: b_key.clone(),
+                value: Some(b_value.clone()),
+            };
+            if a_key == b_key {
+                if a_value != b_value {
+                    write_log.push(insert);
+                }
+            } else if a_key < b_key {
+                write_log.push(delete);
+                write_log.push(insert);
+            } else {
+                write_log.push(insert);
+                write_log.push(delete);
+            }
+            return Ok(());
+        }
+
+        // Compare the next bit of both subtrees. The leaf ending at the current position sorts
+        // before all keys continuing with a zero bit, which sort before all keys continuing
+        // with a one bit.
+        let (a_leaf, a_left, a_right) = self._expand(ctx, ours, a, bit_depth, &path)?;
+        let (b_leaf, b_left, b_right) = self._expand(ctx, theirs, b, bit_depth, &path)?;
+        self._diff(
+            ctx,
+            ours,
+            theirs,
+            a_leaf,
+            b_leaf,
+            bit_depth,
+            path.clone(),
+            write_log,
+        )?;
+        self._diff(
+            ctx,
+            ours,
+            theirs,
+            a_left,
+            b_left,
+            bit_depth + 1,
+            path.append_bit(bit_depth, false),
+            write_log,
+        )?;
+        self._diff(
+            ctx,
+            ours,
+            theirs,
+            a_right,
+            b_right,
+            bit_depth + 1,
+            path.append_bit(bit_depth, true),
+            write_log,
+        )
+    }
+
+    /// Append an entry for each key in the subtree at the given position to the write log in
+    /// key order, either inserting the current value or deleting the key.
+    #[allow(clippy::too_many_arguments)]
+    fn _collect(
+        &self,
+        ctx: &Arc<Context>,
+        root: &DiffRoot,
+        cursor: Cursor,
+        bit_depth: Depth,
+        path: &Key,
+        insert: bool,
+        write_log: &mut WriteLog,
+    ) -> Result<()> {
+        if let Some((key, value)) = cursor.leaf() {
+            write_log.push(LogEntry {
+                key,
+                value: if insert { Some(value) } else { None },
+            });
+            return Ok(());
+        }
+
+        let node_ref = match cursor.node {
+            Some(node_ref) => node_ref,
+            None => return Ok(()),
+        };
+        let (bit_length, new_path, leaf_node, left, right) = match *node_ref.borrow() {
+            NodeBox::Internal(ref n) => {
+                // Go back to where the node starts.
+                let node_depth = bit_depth - cursor.offset;
+                let (node_path, _) = path.split(node_depth, bit_depth);
+                let bit_length = node_depth + n.label_bit_length;
+                (
+                    bit_length,
+                    node_path.merge(node_depth, &n.label, n.label_bit_length),
+                    n.leaf_node.clone(),
+                    n.left.clone(),
+                    n.right.clone(),
+                )
+            }
+            NodeBox::Leaf(_) => unreachable!("node kind is Internal"),
+        };
+
+        let children = [
+            (leaf_node, new_path.clone()),
+            (left, new_path.append_bit(bit_length, false)),
+            (right, new_path.append_bit(bit_length, true)),
+        ];
+        for (ptr, path) in children.iter() {
+            let cursor = self.cursor(ctx, root, ptr.clone(), path)?;
+            self._collect(ctx, root, cursor, bit_length, path, insert, write_log)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,9 +1,17 @@
 use thiserror::Error;
 
+use crate::common::crypto::hash::Hash;
+
 #[derive(Error, Debug)]
 pub enum TreeError {
     #[error("mkvs: malformed node")]
     MalformedNode,
     #[error("mkvs: malformed key")]
     MalformedKey,
+    #[error("mkvs: tree has uncommitted changes")]
+    DirtyRoot,
+    #[error("mkvs: tree has changed since it was forked")]
+    ForkDiverged,
+    #[error("mkvs: unexpected root after applying write log (expected: {expected:?} got {got:?})")]
+    RootMismatch { expected: Hash, got: Hash },
 }
@@ -0,0 +1,203 @@
+use std::{cell::RefCell, fmt, ops::Deref, rc::Rc};
+
+use anyhow::Result;
+use io_context::Context;
+
+use crate::{
+    common::{crypto::hash::Hash, namespace::Namespace},
+    storage::mkvs::{self, cache::*, sync::*, tree::*},
+};
+
+/// A copy-on-write fork of a tree, created by `Tree::fork`.
+///
+/// The fork shares nodes with the tree it was forked from, so it borrows that tree for as long
+/// as it exists. This keeps both of them on the same thread, which is also why a fork is not
+/// `Send`. The fork can be dropped to discard its changes or merged back using `merge`.
+pub struct TreeFork<'a> {
+    tree: Tree,
+    parent: &'a Tree,
+    /// Root at which the tree was forked.
+    forked_from: Root,
+}
+
+impl<'a> TreeFork<'a> {
+    /// Merge the fork back into the tree it was forked from, replacing the state of that tree
+    /// with the (possibly uncommitted) state of the fork.
+    ///
+    /// Merging fails in case the tree has been changed since the fork was created, e.g., by
+    /// merging another fork.
+    pub fn merge(self) -> Result<()> {
+        let (pending_root, sync_root) = {
+            let fork_cache = self.tree.cache.borrow();
+            (fork_cache.get_pending_root(), fork_cache.get_sync_root())
+        };
+
+        let mut cache = self.parent.cache.borrow_mut();
+        if cache.get_sync_root() != self.forked_from
+            || cache.get_pending_root().borrow().hash != self.forked_from.hash
+            || !cache.get_pending_root().borrow().clean
+        {
+            return Err(TreeError::ForkDiverged.into());
+        }
+
+        cache.adopt(&mut self.tree.cache.borrow_mut());
+        cache.set_pending_root(pending_root);
+        cache.set_sync_root(sync_root);
+
+        Ok(())
+    }
+
+    /// Insert a key/value pair into the fork.
+    pub fn insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.tree.insert(ctx, key, value)
+    }
+
+    /// Remove entry with given key from the fork, returning the value at the key if the key
+    /// was previously in the fork.
+    pub fn remove(&mut self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.tree.remove(ctx, key)
+    }
+
+    /// Remove all keys with the given prefix from the fork.
+    pub fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> Result<mkvs::WriteLog> {
+        self.tree.remove_prefix(ctx, prefix)
+    }
+
+    /// Commit updates of the fork and return the new merkle root.
+    pub fn commit(&mut self, ctx: Context, namespace: Namespace, version: u64) -> Result<Hash> {
+        self.tree.commit(ctx, namespace, version)
+    }
+}
+
+impl<'a> Deref for TreeFork<'a> {
+    type Target = Tree;
+
+    fn deref(&self) -> &Tree {
+        // NOTE: DerefMut is intentionally not implemented as it would allow the forked tree
+        //       to be moved out of the fork.
+        &self.tree
+    }
+}
+
+impl<'a> fmt::Debug for TreeFork<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        self.tree.fmt(f)
+    }
+}
+
+impl<'a> mkvs::FallibleMKVS for TreeFork<'a> {
+    fn get(&self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Tree::get(&self.tree, ctx, key)
+    }
+
+    fn cache_contains_key(&self, ctx: Context, key: &[u8]) -> bool {
+        Tree::cache_contains_key(&self.tree, ctx, key)
+    }
+
+    fn insert(&mut self, ctx: Context, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        TreeFork::insert(self, ctx, key, value)
+    }
+
+    fn remove(&mut self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        TreeFork::remove(self, ctx, key)
+    }
+
+    fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> Result<mkvs::WriteLog> {
+        TreeFork::remove_prefix(self, ctx, prefix)
+    }
+
+    fn prefetch_prefixes(&self, ctx: Context, prefixes: &[mkvs::Prefix], limit: u16) -> Result<()> {
+        Tree::prefetch_prefixes(&self.tree, ctx, prefixes, limit)
+    }
+
+    fn prefetch_keys(&self, ctx: Context, keys: &[&[u8]]) -> Result<()> {
+        Tree::prefetch_keys(&self.tree, ctx, keys)
+    }
+
+    fn iter(&self, ctx: Context) -> Box<dyn mkvs::Iterator + '_> {
+        Box::new(Tree::iter(&self.tree, ctx))
+    }
+
+    fn commit(&mut self, ctx: Context, namespace: Namespace, version: u64) -> Result<Hash> {
+        TreeFork::commit(self, ctx, namespace, version)
+    }
+}
+
+impl Tree {
+    /// Create a copy-on-write fork of the tree at its current committed root.
+    ///
+    /// The fork shares all nodes with this tree, so creating it is cheap. Any nodes modified in
+    /// either tree afterwards are copied first, so changes in the fork are never visible in
+    /// this tree and vice versa. The fork has a cache of the same capacity as this tree, and
+    /// nodes cached by one of them are never evicted by the other.
+    ///
+    /// The given read syncer is used by the fork to fetch any nodes which are not available in
+    /// the cache.
+    pub fn fork(&self, read_syncer: Box<dyn ReadSync>) -> Result<TreeFork<'_>> {
+        let cache = self.cache.borrow();
+        if !cache.get_pending_root().borrow().clean {
+            return Err(TreeError::DirtyRoot.into());
+        }
+
+        Ok(TreeFork {
+            tree: Tree {
+                cache: RefCell::new(cache.fork(read_syncer)),
+                root_type: self.root_type,
+            },
+            parent: self,
+            forked_from: cache.get_sync_root(),
+        })
+    }
+
+    /// Prepare a node for being modified in place, returning the pointer and node to modify.
+    ///
+    /// In case the tree has been forked, clean nodes may be shared with other trees, so a
+    /// dirty copy of the node is returned instead. The caller must then replace the pointer
+    /// in the parent node.
+    pub(super) fn copy_on_write(
+        &self,
+        ptr: NodePtrRef,
+        node_ref: NodeRef,
+    ) -> (NodePtrRef, NodeRef) {
+        if !ptr.borrow().clean || !self.cache.borrow().is_forked() {
+            return (ptr, node_ref);
+        }
+
+        let node = match *node_ref.borrow() {
+            NodeBox::Internal(ref n) => NodeBox::Internal(InternalNode {
+                clean: false,
+                hash: n.hash,
+                label: n.label.clone(),
+                label_bit_length: n.label_bit_length,
+                leaf_node: n.leaf_node.clone(),
+                left: n.left.clone(),
+                right: n.right.clone(),
+            }),
+            NodeBox::Leaf(ref n) => NodeBox::Leaf(LeafNode {
+                clean: false,
+                ..n.copy()
+            }),
+        };
+        let node_ref = Rc::new(RefCell::new(node));
+        let ptr = Rc::new(RefCell::new(NodePointer {
+            clean: false,
+            hash: ptr.borrow().hash,
+            node: Some(node_ref.clone()),
+            ..Default::default()
+        }));
+
+        (ptr, node_ref)
+    }
+
+    /// Remove a node which is no longer part of the tree from the cache.
+    ///
+    /// In case the tree has been forked, clean nodes may still be used by other trees, so they
+    /// are left to be evicted instead.
+    pub(super) fn discard_node(&self, ptr: NodePtrRef) {
+        let mut cache = self.cache.borrow_mut();
+        if ptr.borrow().clean && cache.is_forked() {
+            return;
+        }
+        cache.remove_node(ptr);
+    }
+}
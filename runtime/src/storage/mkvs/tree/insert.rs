@@ -1,4 +1,4 @@
-use std::{mem, sync::Arc};
+use std::{mem, rc::Rc, sync::Arc};
 
 use anyhow::{anyhow, Result};
 use io_context::Context;
@@ -47,65 +47,71 @@ impl Tree {
             NodeKind::Internal => {
                 let node_ref = node_ref.unwrap();
                 let (leaf_node, left, right): (NodePtrRef, NodePtrRef, NodePtrRef);
-                let cp_len: Depth;
-                let label_prefix: Key;
-                if let NodeBox::Internal(ref mut n) = *node_ref.borrow_mut() {
-                    cp_len = n.label.common_prefix_len(
+                let (cp_len, label_bit_length) = match *node_ref.borrow() {
+                    NodeBox::Internal(ref n) => (
+                        n.label.common_prefix_len(
+                            n.label_bit_length,
+                            &key_remainder,
+                            key.bit_length() - bit_depth,
+                        ),
                         n.label_bit_length,
-                        &key_remainder,
-                        key.bit_length() - bit_depth,
-                    );
+                    ),
+                    _ => {
+                        return Err(anyhow!(
+                            "insert.rs: unknown internal node_ref {:?}",
+                            node_ref
+                        ))
+                    }
+                };
+
+                if cp_len == label_bit_length {
+                    // The current part of key matched the node's Label. Do recursion.
+                    let bit_length = bit_depth + label_bit_length;
+                    let child = if key.bit_length() == bit_length {
+                        // Key to insert ends exactly at this node. Add it to the
+                        // existing internal node as LeafNode.
+                        noderef_as!(node_ref, Internal).leaf_node.clone()
+                    } else if key.get_bit(bit_length) {
+                        // Insert recursively based on the bit value.
+                        noderef_as!(node_ref, Internal).right.clone()
+                    } else {
+                        noderef_as!(node_ref, Internal).left.clone()
+                    };
+                    let r = self._insert(ctx, child.clone(), bit_length, key, val)?;
 
-                    if cp_len == n.label_bit_length {
-                        // The current part of key matched the node's Label. Do recursion.
-                        let r: (NodePtrRef, Option<Value>);
-                        if key.bit_length() == bit_depth + n.label_bit_length {
-                            // Key to insert ends exactly at this node. Add it to the
-                            // existing internal node as LeafNode.
-                            r = self._insert(
-                                ctx,
-                                n.leaf_node.clone(),
-                                bit_depth + n.label_bit_length,
-                                key,
-                                val,
-                            )?;
+                    if Rc::ptr_eq(&r.0, &child) && r.0.borrow().clean {
+                        // Nothing has changed.
+                        return Ok((ptr, r.1));
+                    }
+
+                    let (ptr, node_ref) = self.copy_on_write(ptr, node_ref);
+                    if let NodeBox::Internal(ref mut n) = *node_ref.borrow_mut() {
+                        if key.bit_length() == bit_length {
                             n.leaf_node = r.0;
-                        } else if key.get_bit(bit_depth + n.label_bit_length) {
-                            // Insert recursively based on the bit value.
-                            r = self._insert(
-                                ctx,
-                                n.right.clone(),
-                                bit_depth + n.label_bit_length,
-                                key,
-                                val,
-                            )?;
+                        } else if key.get_bit(bit_length) {
                             n.right = r.0;
                         } else {
-                            r = self._insert(
-                                ctx,
-                                n.left.clone(),
-                                bit_depth + n.label_bit_length,
-                                key,
-                                val,
-                            )?;
                             n.left = r.0;
                         }
-
-                        if !n.leaf_node.borrow().clean
-                            || !n.left.borrow().clean
-                            || !n.right.borrow().clean
-                        {
-                            n.clean = false;
-                            ptr.borrow_mut().clean = false;
-                            // No longer eligible for eviction as it is dirty.
-                            self.cache
-                                .borrow_mut()
-                                .rollback_node(ptr.clone(), NodeKind::Internal);
-                        }
-
-                        return Ok((ptr, r.1));
+                        n.clean = false;
                     }
+                    ptr.borrow_mut().clean = false;
+                    // No longer eligible for eviction as it is dirty.
+                    self.cache
+                        .borrow_mut()
+                        .rollback_node(ptr.clone(), NodeKind::Internal);
+
+                    return Ok((ptr, r.1));
+                }
 
+                let (ptr, node_ref) = self.copy_on_write(ptr, node_ref);
+                ptr.borrow_mut().clean = false;
+                // No longer eligible for eviction as it is dirty.
+                self.cache
+                    .borrow_mut()
+                    .rollback_node(ptr.clone(), NodeKind::Internal);
+                let label_prefix: Key;
+                if let NodeBox::Internal(ref mut n) = *node_ref.borrow_mut() {
                     // Key mismatches the label at position cp_len. Split the edge and
                     // insert new leaf.
                     let label_split = n.label.split(cp_len, n.label_bit_length);
@@ -113,11 +119,6 @@ impl Tree {
                     n.label = label_split.1;
                     n.label_bit_length -= cp_len;
                     n.clean = false;
-                    ptr.borrow_mut().clean = false;
-                    // No longer eligible for eviction as it is dirty.
-                    self.cache
-                        .borrow_mut()
-                        .rollback_node(ptr.clone(), NodeKind::Internal);
 
                     let new_leaf = self.cache.borrow_mut().new_leaf_node(key, val);
                     if key.bit_length() - bit_depth == cp_len {
@@ -164,23 +165,25 @@ impl Tree {
                 let (leaf_node, left, right): (NodePtrRef, NodePtrRef, NodePtrRef);
                 let cp_len: Depth;
                 let label_prefix: Key;
-                if let NodeBox::Leaf(ref mut n) = *node_ref.borrow_mut() {
-                    // Should always succeed.
-                    if n.key == *key {
-                        // If the key matches, we can just update the value.
-                        if n.value == val {
-                            return Ok((ptr, Some(val)));
-                        }
-                        let old_val = mem::replace(&mut n.value, val);
-                        n.clean = false;
-                        ptr.borrow_mut().clean = false;
-                        // No longer eligible for eviction as it is dirty.
-                        self.cache
-                            .borrow_mut()
-                            .rollback_node(ptr.clone(), NodeKind::Leaf);
-                        return Ok((ptr, Some(old_val)));
+
+                // If the key matches, we can just update the value.
+                if noderef_as!(node_ref, Leaf).key == *key {
+                    if noderef_as!(node_ref, Leaf).value == val {
+                        return Ok((ptr, Some(val)));
                     }
 
+                    let (ptr, node_ref) = self.copy_on_write(ptr, node_ref);
+                    noderef_as_mut!(node_ref, Leaf).clean = false;
+                    let old_val = mem::replace(&mut noderef_as_mut!(node_ref, Leaf).value, val);
+                    ptr.borrow_mut().clean = false;
+                    // No longer eligible for eviction as it is dirty.
+                    self.cache
+                        .borrow_mut()
+                        .rollback_node(ptr.clone(), NodeKind::Leaf);
+                    return Ok((ptr, Some(old_val)));
+                }
+
+                if let NodeBox::Leaf(ref n) = *node_ref.borrow() {
                     let (_, leaf_key_remainder) = n.key.split(bit_depth, n.key.bit_length());
                     cp_len = leaf_key_remainder.common_prefix_len(
                         n.key.bit_length() - bit_depth,
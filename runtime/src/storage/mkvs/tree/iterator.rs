@@ -1,19 +1,35 @@
 //! Tree iterator.
-use std::{collections::VecDeque, fmt, sync::Arc};
+use std::{collections::VecDeque, fmt, ops::RangeBounds, sync::Arc};
 
 use anyhow::{Error, Result};
 use io_context::Context;
 
-use crate::storage::mkvs::{self, cache::*, sync::*, tree::*};
+use crate::{
+    common::crypto::hash::Hash,
+    storage::mkvs::{self, cache::*, sync::*, tree::*},
+};
 
 pub(super) struct FetcherSyncIterate<'a> {
     key: &'a Key,
     prefetch: usize,
+    reverse: bool,
+    seek_last: bool,
 }
 
 impl<'a> FetcherSyncIterate<'a> {
-    pub(super) fn new(key: &'a Key, prefetch: usize) -> Self {
-        Self { key, prefetch }
+    pub(super) fn new(key: &'a Key, prefetch: usize, reverse: bool) -> Self {
+        Self {
+            key,
+            prefetch,
+            reverse,
+            seek_last: false,
+        }
+    }
+
+    /// Fetch the nodes on the path to the last item in the tree, ignoring the key.
+    pub(super) fn with_seek_last(mut self, seek_last: bool) -> Self {
+        self.seek_last = seek_last;
+        self
     }
 }
 
@@ -25,6 +41,14 @@ impl<'a> ReadSyncFetcher for FetcherSyncIterate<'a> {
         ptr: NodePtrRef,
         rs: &mut Box<dyn ReadSync>,
     ) -> Result<Proof> {
+        // When iterating in reverse, the key is the current key of the iterator and the nodes
+        // that are needed are on the path to the previous key, so always fetch at least one.
+        let prefetch = if self.reverse {
+            self.prefetch.max(1)
+        } else {
+            self.prefetch
+        };
+
         let rsp = rs.sync_iterate(
             ctx,
             IterateRequest {
@@ -33,7 +57,9 @@ impl<'a> ReadSyncFetcher for FetcherSyncIterate<'a> {
                     position: ptr.borrow().hash,
                 },
                 key: self.key.clone(),
-                prefetch: self.prefetch as u16,
+                prefetch: prefetch as u16,
+                reverse: self.reverse,
+                seek_last: self.seek_last,
             },
         )?;
         Ok(rsp.proof)
@@ -41,10 +67,14 @@ impl<'a> ReadSyncFetcher for FetcherSyncIterate<'a> {
 }
 
 /// Visit state of a node.
+///
+/// Forward iteration goes through `Before`, `At`, `AtLeft` and `After` while reverse iteration
+/// goes through `Before`, `AtRight`, `AtLeft` and `After`.
 #[derive(Debug, PartialEq)]
 enum VisitState {
     Before,
     At,
+    AtRight,
     AtLeft,
     After,
 }
@@ -77,11 +107,15 @@ pub struct TreeIterator<'tree> {
     key: Option<Key>,
     value: Option<Vec<u8>>,
     error: Option<Error>,
+    proof_builder: Option<ProofBuilder>,
+    reverse: bool,
+    /// Whether the iterator is seeking to the last item in the tree.
+    seeking_last: bool,
 }
 
 impl<'tree> TreeIterator<'tree> {
     /// Create a new tree iterator.
-    fn new(ctx: Context, tree: &'tree Tree) -> Self {
+    pub(super) fn new(ctx: Context, tree: &'tree Tree) -> Self {
         Self {
             ctx: ctx.freeze(),
             tree,
@@ -90,6 +124,35 @@ impl<'tree> TreeIterator<'tree> {
             key: None,
             value: None,
             error: None,
+            proof_builder: None,
+            reverse: false,
+            seeking_last: false,
+        }
+    }
+
+    /// Configure the iterator for generating proofs of all visited nodes.
+    pub(super) fn with_proof(mut self, root: Hash) -> Self {
+        self.proof_builder = Some(ProofBuilder::new(root, root));
+        self
+    }
+
+    /// Build a proof for all items iterated over by the iterator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator has not been configured for generating proofs.
+    pub fn get_proof(&self) -> Result<Proof> {
+        self.proof_builder
+            .as_ref()
+            .expect("iterator: called get_proof on an iterator without a proof builder")
+            .build(Context::create_child(&self.ctx))
+    }
+
+    /// Return the error that occurred during iteration, if any, consuming it.
+    pub(super) fn take_error(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
         }
     }
 
@@ -100,6 +163,60 @@ impl<'tree> TreeIterator<'tree> {
     }
 
     fn next(&mut self) {
+        if self.reverse {
+            // Changing direction, reposition the iterator at the current key first.
+            match self.key.clone() {
+                Some(key) => self.seek_to(&key, false),
+                None => return,
+            }
+        }
+        self.advance();
+    }
+
+    fn prev(&mut self) {
+        if !self.reverse {
+            // Changing direction, reposition the iterator at the current key first.
+            match self.key.clone() {
+                Some(key) => self.seek_to(&key, true),
+                None => return,
+            }
+        }
+        self.advance();
+    }
+
+    fn seek_to(&mut self, key: &[u8], reverse: bool) {
+        if self.error.is_some() {
+            return;
+        }
+
+        self.reset();
+        self.reverse = reverse;
+        let pending_root = self.tree.cache.borrow().get_pending_root();
+        let result = if reverse {
+            self._prev(
+                pending_root,
+                0,
+                Key::new(),
+                &key.to_vec(),
+                false,
+                VisitState::Before,
+            )
+        } else {
+            self._next(
+                pending_root,
+                0,
+                Key::new(),
+                key.to_vec(),
+                VisitState::Before,
+            )
+        };
+        if let Err(error) = result {
+            self.error = Some(error);
+            self.reset();
+        }
+    }
+
+    fn advance(&mut self) {
         if self.error.is_some() {
             return;
         }
@@ -121,9 +238,13 @@ impl<'tree> TreeIterator<'tree> {
             // next node.
             let key = self.key.take().expect("iterator is valid");
             self.reset();
-            if let Err(error) =
+            let result = if self.reverse {
+                // All remaining nodes only contain keys smaller than the current key.
+                self._prev(atom.ptr, atom.bit_depth, atom.path, &key, true, atom.state)
+            } else {
                 self._next(atom.ptr, atom.bit_depth, atom.path, key.clone(), atom.state)
-            {
+            };
+            if let Err(error) = result {
                 self.error = Some(error);
                 self.reset();
                 return;
@@ -154,9 +275,14 @@ impl<'tree> TreeIterator<'tree> {
         let node_ref = self.tree.cache.borrow_mut().deref_node_ptr(
             &self.ctx,
             ptr.clone(),
-            Some(FetcherSyncIterate::new(&key, self.prefetch)),
+            Some(FetcherSyncIterate::new(&key, self.prefetch, false)),
         )?;
 
+        // Include nodes in proof if we have a proof builder.
+        if let (Some(pb), Some(node_ref)) = (self.proof_builder.as_mut(), &node_ref) {
+            pb.include(&node_ref.borrow());
+        }
+
         match classify_noderef!(?node_ref) {
             NodeKind::None => {
                 // Reached a nil node, there is nothing here.
@@ -277,6 +403,152 @@ impl<'tree> TreeIterator<'tree> {
             }
         }
     }
+
+    fn _prev(
+        &mut self,
+        ptr: NodePtrRef,
+        bit_depth: Depth,
+        path: Key,
+        key: &Key,
+        mut take_last: bool,
+        mut state: VisitState,
+    ) -> Result<()> {
+        let node_ref = self.tree.cache.borrow_mut().deref_node_ptr(
+            &self.ctx,
+            ptr.clone(),
+            Some(
+                FetcherSyncIterate::new(key, self.prefetch, true).with_seek_last(self.seeking_last),
+            ),
+        )?;
+
+        // Include nodes in proof if we have a proof builder.
+        if let (Some(pb), Some(node_ref)) = (self.proof_builder.as_mut(), &node_ref) {
+            pb.include(&node_ref.borrow());
+        }
+
+        match classify_noderef!(?node_ref) {
+            NodeKind::None => {
+                // Reached a nil node, there is nothing here.
+                Ok(())
+            }
+            NodeKind::Internal => {
+                let node_ref = node_ref.unwrap();
+                if let NodeBox::Internal(ref n) = *node_ref.borrow() {
+                    // Internal node.
+                    let bit_length = bit_depth + n.label_bit_length;
+                    let new_path = path.merge(bit_depth, &n.label, n.label_bit_length);
+
+                    // Check whether the key diverges from the path to this subtree. In this case
+                    // either everything in the subtree is larger (and there is nothing here) or
+                    // everything is smaller and we need to take the last value.
+                    if state == VisitState::Before && !take_last {
+                        let cp = key.common_prefix_len(key.bit_length(), &new_path, bit_length);
+                        if cp < bit_length {
+                            if cp == key.bit_length() || !key.get_bit(cp) {
+                                return Ok(());
+                            }
+                            take_last = true;
+                        }
+                    }
+
+                    // The right subtree contains the largest keys so visit it first.
+                    if state == VisitState::Before {
+                        let go_right =
+                            take_last || (key.bit_length() > bit_length && key.get_bit(bit_length));
+                        if go_right {
+                            self._prev(
+                                n.right.clone(),
+                                bit_length,
+                                new_path.append_bit(bit_length, true),
+                                key,
+                                take_last,
+                                VisitState::Before,
+                            )?;
+                            if self.key.is_some() {
+                                // Key has been found.
+                                self.pos.push_back(PathAtom {
+                                    ptr,
+                                    bit_depth,
+                                    path,
+                                    state: VisitState::AtRight,
+                                });
+                                return Ok(());
+                            }
+                            // Key has not been found, everything to the left is smaller.
+                            take_last = true;
+                        }
+                        state = VisitState::AtRight;
+                    }
+
+                    // Continue with the left subtree unless the lookup key ends here.
+                    if state == VisitState::AtRight {
+                        if take_last || key.bit_length() > bit_length {
+                            self._prev(
+                                n.left.clone(),
+                                bit_length,
+                                new_path.append_bit(bit_length, false),
+                                key,
+                                take_last,
+                                VisitState::Before,
+                            )?;
+                            if self.key.is_some() {
+                                // Key has been found.
+                                self.pos.push_back(PathAtom {
+                                    ptr,
+                                    bit_depth,
+                                    path,
+                                    state: VisitState::AtLeft,
+                                });
+                                return Ok(());
+                            }
+                        }
+                        state = VisitState::AtLeft;
+                    }
+
+                    // The leaf node contains the smallest key in this subtree and is never
+                    // larger than the lookup key at this point.
+                    if state == VisitState::AtLeft {
+                        self._prev(
+                            n.leaf_node.clone(),
+                            bit_length,
+                            path.clone(),
+                            key,
+                            true,
+                            VisitState::Before,
+                        )?;
+                        if self.key.is_some() {
+                            // Key has been found.
+                            self.pos.push_back(PathAtom {
+                                ptr,
+                                bit_depth,
+                                path,
+                                state: VisitState::After,
+                            });
+                            return Ok(());
+                        }
+                    }
+
+                    return Ok(());
+                }
+
+                unreachable!("node kind is internal node");
+            }
+            NodeKind::Leaf => {
+                // Reached a leaf node.
+                let node_ref = node_ref.unwrap();
+                if let NodeBox::Leaf(ref n) = *node_ref.borrow() {
+                    if take_last || n.key <= *key {
+                        self.key = Some(n.key.clone());
+                        self.value = Some(n.value.clone());
+                    }
+                } else {
+                    unreachable!("node kind is leaf node");
+                }
+
+                Ok(())
+            }
+        }
+    }
 }
 
 impl<'tree> Iterator for TreeIterator<'tree> {
@@ -310,29 +582,46 @@ impl<'tree> mkvs::Iterator for TreeIterator<'tree> {
         &self.error
     }
 
+    fn take_error(&mut self) -> Result<()> {
+        TreeIterator::take_error(self)
+    }
+
     fn rewind(&mut self) {
         self.seek(&[])
     }
 
     fn seek(&mut self, key: &[u8]) {
+        self.seek_to(key, false)
+    }
+
+    fn seek_last(&mut self) {
         if self.error.is_some() {
             return;
         }
 
         self.reset();
+        self.reverse = true;
+        self.seeking_last = true;
         let pending_root = self.tree.cache.borrow().get_pending_root();
-        if let Err(error) = self._next(
+        let result = self._prev(
             pending_root,
             0,
             Key::new(),
-            key.to_vec(),
+            &Key::new(),
+            true,
             VisitState::Before,
-        ) {
+        );
+        self.seeking_last = false;
+        if let Err(error) = result {
             self.error = Some(error);
             self.reset();
         }
     }
 
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        self.seek_to(key, true)
+    }
+
     fn get_key(&self) -> &Option<Key> {
         &self.key
     }
@@ -344,6 +633,10 @@ impl<'tree> mkvs::Iterator for TreeIterator<'tree> {
     fn next(&mut self) {
         TreeIterator::next(self)
     }
+
+    fn prev(&mut self) {
+        TreeIterator::prev(self)
+    }
 }
 
 impl Tree {
@@ -351,11 +644,63 @@ impl Tree {
     pub fn iter(&self, ctx: Context) -> TreeIterator {
         TreeIterator::new(ctx, self)
     }
+
+    /// Return an iterator over all items with keys in the given range.
+    pub fn range<K, R>(&self, ctx: Context, range: R) -> mkvs::RangeIterator<TreeIterator>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let ctx = ctx.freeze();
+        mkvs::RangeIterator::new(
+            TreeIterator::new(Context::create_child(&ctx), self),
+            TreeIterator::new(Context::create_child(&ctx), self),
+            range,
+        )
+    }
+
+    /// Seek to a given key and then fetch the specified number of following (or preceding
+    /// when iterating in reverse) items based on key iteration order and return the
+    /// corresponding proof.
+    pub fn sync_iterate(&self, ctx: Context, request: IterateRequest) -> Result<ProofResponse> {
+        self.check_sync_root(&request.tree.root)?;
+
+        // Create an iterator which generates proofs. Always anchor the proof at the
+        // root as an iterator may encompass many subtrees. Make sure to propagate
+        // prefetching to any upstream remote syncers.
+        let mut it = TreeIterator::new(ctx, self).with_proof(request.tree.root.hash);
+        it.prefetch = request.prefetch as usize;
+
+        let reverse = request.reverse || request.seek_last;
+        if request.seek_last {
+            mkvs::Iterator::seek_last(&mut it);
+        } else if reverse {
+            mkvs::Iterator::seek_for_prev(&mut it, &request.key);
+        } else {
+            mkvs::Iterator::seek(&mut it, &request.key);
+        }
+        for _ in 0..request.prefetch {
+            if it.key.is_none() {
+                break;
+            }
+            if reverse {
+                TreeIterator::prev(&mut it);
+            } else {
+                TreeIterator::next(&mut it);
+            }
+        }
+        it.take_error()?;
+
+        // Retrieve the proof for the items iterated over.
+        let proof = it.get_proof()?;
+
+        Ok(ProofResponse { proof })
+    }
 }
 
 #[cfg(test)]
 pub(super) mod test {
-    use std::iter;
+    use std::{iter, ops::Bound};
 
     use io_context::Context;
     use rustc_hex::FromHex;
@@ -610,6 +955,287 @@ pub(super) mod test {
         assert_eq!(2, stats.sync_iterate_count, "sync_iterate_count");
     }
 
+    #[test]
+    fn test_iterator_reverse() {
+        let mut tree = Tree::builder()
+            .with_capacity(0, 0)
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+
+        // Test with an empty tree.
+        let mut it = tree.iter(Context::background());
+        it.seek_last();
+        assert!(
+            !it.is_valid(),
+            "iterator should be invalid on an empty tree"
+        );
+
+        let items = vec![
+            (b"key".to_vec(), b"first".to_vec()),
+            (b"key 1".to_vec(), b"one".to_vec()),
+            (b"key 2".to_vec(), b"two".to_vec()),
+            (b"key 5".to_vec(), b"five".to_vec()),
+            (b"key 8".to_vec(), b"eight".to_vec()),
+            (b"key 9".to_vec(), b"nine".to_vec()),
+        ];
+        for (key, value) in items.iter() {
+            tree.insert(Context::background(), key, value).unwrap();
+        }
+
+        let tests = vec![
+            (b"k".to_vec(), -1),
+            (b"key".to_vec(), 0),
+            (b"key 0".to_vec(), 0),
+            (b"key 1".to_vec(), 1),
+            (b"key 3".to_vec(), 2),
+            (b"key 5".to_vec(), 3),
+            (b"key 6".to_vec(), 3),
+            (b"key 9".to_vec(), 5),
+            (b"key A".to_vec(), 5),
+        ];
+
+        // Direct.
+        let it = tree.iter(Context::background());
+        test_reverse_iterator_with(&items, it, &tests);
+
+        // Changing direction.
+        let mut it = tree.iter(Context::background());
+        it.seek(b"key 5");
+        mkvs::Iterator::next(&mut it);
+        assert_eq!(it.get_key(), &Some(b"key 8".to_vec()));
+        it.prev();
+        assert_eq!(it.get_key(), &Some(b"key 5".to_vec()));
+        it.prev();
+        assert_eq!(it.get_key(), &Some(b"key 2".to_vec()));
+        mkvs::Iterator::next(&mut it);
+        assert_eq!(it.get_key(), &Some(b"key 5".to_vec()));
+
+        // Forward-only iterators.
+        let it = ForwardIterator(tree.iter(Context::background()));
+        test_reverse_iterator_with(&items, it, &tests);
+
+        // Remote with prefetch (3).
+        let hash = tree
+            .commit(Context::background(), Default::default(), 0)
+            .expect("commit");
+        let remote_tree = Tree::builder()
+            .with_capacity(0, 0)
+            .with_root(Root {
+                root_type: RootType::State,
+                hash,
+                ..Default::default()
+            })
+            .build(Box::new(StatsCollector::new(Box::new(tree))));
+
+        let mut it = remote_tree.iter(Context::background());
+        it.set_prefetch(3);
+        test_reverse_iterator_with(&items, it, &tests);
+
+        let cache = remote_tree.cache.borrow();
+        let stats = cache
+            .get_read_syncer()
+            .as_any()
+            .downcast_ref::<StatsCollector>()
+            .expect("stats");
+        assert_eq!(0, stats.sync_get_count, "sync_get_count");
+        assert_eq!(0, stats.sync_get_prefixes_count, "sync_get_prefixes_count");
+        assert!(stats.sync_iterate_count > 0, "sync_iterate_count");
+    }
+
+    #[test]
+    fn test_iterator_reverse_empty_key() {
+        for with_empty_key in [false, true] {
+            let mut tree = Tree::builder()
+                .with_capacity(0, 0)
+                .with_root_type(RootType::State)
+                .build(Box::new(NoopReadSyncer));
+            let mut keys = vec![b"key 1".to_vec(), b"key 2".to_vec()];
+            if with_empty_key {
+                keys.insert(0, vec![]);
+            }
+            for key in &keys {
+                tree.insert(Context::background(), key, b"value").unwrap();
+            }
+            let hash = tree
+                .commit(Context::background(), Default::default(), 0)
+                .expect("commit");
+            let root = Root {
+                root_type: RootType::State,
+                hash,
+                ..Default::default()
+            };
+
+            // Seeking to the empty key must not be confused with seeking to the end of the tree.
+            let proof = tree
+                .sync_iterate(
+                    Context::background(),
+                    IterateRequest {
+                        tree: TreeID {
+                            root,
+                            position: hash,
+                        },
+                        key: vec![],
+                        prefetch: 0,
+                        reverse: true,
+                        seek_last: false,
+                    },
+                )
+                .expect("sync_iterate")
+                .proof;
+            assert!(
+                !proof
+                    .entries
+                    .iter()
+                    .flatten()
+                    .any(|entry| entry.windows(5).any(|w| w == b"key 2")),
+                "proof should not include the last item"
+            );
+
+            let remote_tree = Tree::builder()
+                .with_capacity(0, 0)
+                .with_root(root)
+                .build(Box::new(tree));
+            let mut it = remote_tree.iter(Context::background());
+            it.seek_for_prev(b"");
+            assert!(it.error.is_none(), "iterator should not fail");
+            if with_empty_key {
+                assert_eq!(it.get_key(), &Some(vec![]));
+                it.prev();
+            }
+            assert!(!it.is_valid(), "iterator should be invalid");
+
+            let mut it = remote_tree.iter(Context::background());
+            it.seek_last();
+            assert_eq!(it.get_key(), &Some(b"key 2".to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_iterator_range() {
+        let mut tree = Tree::builder()
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+
+        let (keys, values) = generate_key_value_pairs_ex("R".to_owned(), 20);
+        let mut items: Vec<(Vec<u8>, Vec<u8>)> = keys.into_iter().zip(values).collect();
+        for (key, value) in &items {
+            tree.insert(Context::background(), key, value).unwrap();
+        }
+        items.sort();
+
+        let ctx = Context::background;
+        let collect = |range: mkvs::RangeIterator<TreeIterator>| range.collect::<Vec<_>>();
+        let collect_rev =
+            |range: mkvs::RangeIterator<TreeIterator>| range.rev().collect::<Vec<_>>();
+        let reversed =
+            |items: &[(Vec<u8>, Vec<u8>)]| items.iter().rev().cloned().collect::<Vec<_>>();
+
+        let (start, end) = (items[5].0.clone(), items[15].0.clone());
+        assert_eq!(collect(tree.range::<[u8], _>(ctx(), ..)), items);
+        assert_eq!(
+            collect_rev(tree.range::<[u8], _>(ctx(), ..)),
+            reversed(&items)
+        );
+        assert_eq!(
+            collect(tree.range(ctx(), start.clone()..end.clone())),
+            items[5..15].to_vec()
+        );
+        assert_eq!(
+            collect(tree.range(ctx(), start.clone()..=end.clone())),
+            items[5..=15].to_vec()
+        );
+        assert_eq!(
+            collect(tree.range(ctx(), start.clone()..)),
+            items[5..].to_vec()
+        );
+        assert_eq!(
+            collect(tree.range(ctx(), ..end.clone())),
+            items[..15].to_vec()
+        );
+        assert_eq!(
+            collect_rev(tree.range(ctx(), start.clone()..end.clone())),
+            reversed(&items[5..15])
+        );
+        assert_eq!(
+            collect_rev(tree.range(ctx(), start.clone()..=end.clone())),
+            reversed(&items[5..=15])
+        );
+        assert_eq!(
+            collect(tree.range::<[u8], _>(ctx(), (Bound::Excluded(&start[..]), Bound::Unbounded))),
+            items[6..].to_vec()
+        );
+        assert_eq!(
+            collect_rev(
+                tree.range::<[u8], _>(ctx(), (Bound::Excluded(&start[..]), Bound::Unbounded))
+            ),
+            reversed(&items[6..])
+        );
+        assert!(collect(tree.range(ctx(), end.clone()..start.clone())).is_empty());
+        assert!(collect_rev(tree.range(ctx(), start.clone()..start.clone())).is_empty());
+
+        // Consuming from both ends should never yield an item twice.
+        let mut range = tree.range(ctx(), start.clone()..end.clone());
+        let mut front = vec![];
+        let mut back = vec![];
+        while let Some(item) = range.next() {
+            front.push(item);
+            match range.next_back() {
+                Some(item) => back.push(item),
+                None => break,
+            }
+        }
+        assert!(range.error().is_none(), "range should not error");
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, items[5..15].to_vec());
+    }
+
+    /// Iterator which only implements forward iteration and relies on the default
+    /// implementations of reverse iteration.
+    struct ForwardIterator<I>(I);
+
+    impl<I: mkvs::Iterator> iter::Iterator for ForwardIterator<I> {
+        type Item = (Vec<u8>, Vec<u8>);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            iter::Iterator::next(&mut self.0)
+        }
+    }
+
+    impl<I: mkvs::Iterator> mkvs::Iterator for ForwardIterator<I> {
+        fn set_prefetch(&mut self, prefetch: usize) {
+            self.0.set_prefetch(prefetch)
+        }
+
+        fn is_valid(&self) -> bool {
+            self.0.is_valid()
+        }
+
+        fn error(&self) -> &Option<Error> {
+            self.0.error()
+        }
+
+        fn rewind(&mut self) {
+            self.0.rewind()
+        }
+
+        fn seek(&mut self, key: &[u8]) {
+            self.0.seek(key)
+        }
+
+        fn get_key(&self) -> &Option<Key> {
+            self.0.get_key()
+        }
+
+        fn get_value(&self) -> &Option<Vec<u8>> {
+            self.0.get_value()
+        }
+
+        fn next(&mut self) {
+            mkvs::Iterator::next(&mut self.0)
+        }
+    }
+
     pub(in super::super) fn test_iterator_with<I: mkvs::Iterator>(
         items: &[(Vec<u8>, Vec<u8>)],
         mut it: I,
@@ -648,4 +1274,53 @@ pub(super) mod test {
             }
         }
     }
+
+    pub(in super::super) fn test_reverse_iterator_with<I: mkvs::Iterator>(
+        items: &[(Vec<u8>, Vec<u8>)],
+        mut it: I,
+        tests: &[(Vec<u8>, isize)],
+    ) {
+        // Iterate through the whole tree in reverse.
+        let mut iterations = 0;
+        it.seek_last();
+        for (key, value) in items.iter().rev() {
+            assert!(it.is_valid(), "iterator should be valid");
+            assert_eq!(
+                it.get_key().as_ref(),
+                Some(key),
+                "iterator should have the correct key"
+            );
+            assert_eq!(
+                it.get_value().as_ref(),
+                Some(value),
+                "iterator should have the correct value"
+            );
+            it.prev();
+            iterations += 1;
+        }
+        assert!(!it.is_valid(), "iterator should be exhausted");
+        assert!(it.error().is_none(), "iterator should not error");
+        assert_eq!(iterations, items.len(), "iterator should go over all items");
+
+        for (seek, pos) in tests {
+            it.seek_for_prev(&seek);
+            if *pos == -1 {
+                assert!(
+                    !it.is_valid(),
+                    "iterator should not be valid after seek_for_prev"
+                );
+                continue;
+            }
+
+            for expected in items[..=*pos as usize].iter().rev() {
+                assert_eq!(
+                    it.get_key().as_ref(),
+                    Some(&expected.0),
+                    "iterator should have the correct key"
+                );
+                it.prev();
+            }
+            assert!(!it.is_valid(), "iterator should be exhausted");
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{cell::RefCell, sync::Arc};
 
 use anyhow::Result;
 use io_context::Context;
@@ -42,12 +42,89 @@ impl<'a> ReadSyncFetcher for FetcherSyncGet<'a> {
     }
 }
 
+pub(super) struct FetcherSyncGetMany<'a> {
+    keys: &'a [&'a [u8]],
+}
+
+impl<'a> FetcherSyncGetMany<'a> {
+    pub(super) fn new(keys: &'a [&'a [u8]]) -> Self {
+        Self { keys }
+    }
+}
+
+impl<'a> ReadSyncFetcher for FetcherSyncGetMany<'a> {
+    fn fetch(
+        &self,
+        ctx: Context,
+        root: Root,
+        ptr: NodePtrRef,
+        rs: &mut Box<dyn ReadSync>,
+    ) -> Result<Proof> {
+        let rsp = rs.sync_get_many(
+            ctx,
+            GetManyRequest {
+                tree: TreeID {
+                    root,
+                    position: ptr.borrow().hash,
+                },
+                keys: self.keys.iter().map(|key| key.to_vec()).collect(),
+            },
+        )?;
+        Ok(rsp.proof)
+    }
+}
+
+/// Options for the lookup operation.
+#[derive(Clone, Copy, Default)]
+struct GetOptions<'a> {
+    /// Only check the local cache without invoking the read syncer.
+    check_only: bool,
+    /// Whether to also fetch the siblings of nodes on the path.
+    include_siblings: bool,
+    /// Proof builder to include the visited nodes in.
+    proof_builder: Option<&'a RefCell<ProofBuilder>>,
+}
+
 impl Tree {
     /// Get an existing key.
     pub fn get(&self, ctx: Context, key: &[u8]) -> Result<Option<Vec<u8>>> {
         self._get_top(ctx, key, false)
     }
 
+    /// Get multiple existing keys, returning the values in the same order as the keys.
+    ///
+    /// Any keys which are not available in the local cache are fetched using a single
+    /// `sync_get_many` request.
+    pub fn get_many(&self, ctx: Context, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let ctx = ctx.freeze();
+
+        // Keys which cannot be looked up locally are missing some nodes.
+        let missing: Vec<&[u8]> = keys
+            .iter()
+            .filter(|key| {
+                self._get_top(Context::create_child(&ctx), key, true)
+                    .is_err()
+            })
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            self.prefetch_keys(Context::create_child(&ctx), &missing)?;
+        }
+
+        keys.iter()
+            .map(|key| self.get(Context::create_child(&ctx), key))
+            .collect()
+    }
+
+    /// Populate the in-memory tree with nodes for the given keys.
+    pub fn prefetch_keys(&self, ctx: Context, keys: &[&[u8]]) -> Result<()> {
+        let ctx = ctx.freeze();
+        let pending_root = self.cache.borrow().get_pending_root();
+        self.cache
+            .borrow_mut()
+            .remote_sync(&ctx, pending_root, FetcherSyncGetMany::new(keys))
+    }
+
     /// Check if the key exists in the local cache.
     pub fn cache_contains_key(&self, ctx: Context, key: &[u8]) -> bool {
         match self._get_top(ctx, key, true) {
@@ -57,6 +134,71 @@ impl Tree {
         }
     }
 
+    /// Fetch a single key and return the corresponding proof.
+    pub fn sync_get(&self, ctx: Context, request: GetRequest) -> Result<ProofResponse> {
+        let ctx = ctx.freeze();
+        self.check_sync_root(&request.tree.root)?;
+
+        let pending_root = self.cache.borrow().get_pending_root();
+
+        // Remember where the path from root to target node ends (will end).
+        self.cache.borrow_mut().mark_position();
+
+        let pb = RefCell::new(ProofBuilder::new(
+            request.tree.root.hash,
+            request.tree.position,
+        ));
+        let opts = GetOptions {
+            include_siblings: request.include_siblings,
+            proof_builder: Some(&pb),
+            ..Default::default()
+        };
+        self._get(&ctx, pending_root, 0, &request.key, opts, false)?;
+        let proof = pb.into_inner().build(Context::create_child(&ctx))?;
+
+        Ok(ProofResponse { proof })
+    }
+
+    /// Fetch multiple keys and return a single proof for all of them.
+    pub fn sync_get_many(&self, ctx: Context, request: GetManyRequest) -> Result<ProofResponse> {
+        let ctx = ctx.freeze();
+        self.check_sync_root(&request.tree.root)?;
+
+        // First, trigger same prefetching locally if a remote read syncer
+        // is available. This is needed to ensure that the same optimization
+        // carries on to the next layer.
+        let has_remote = !self
+            .cache
+            .borrow()
+            .get_read_syncer()
+            .as_any()
+            .is::<NoopReadSyncer>();
+        if has_remote {
+            let keys: Vec<&[u8]> = request.keys.iter().map(|key| &key[..]).collect();
+            self.prefetch_keys(Context::create_child(&ctx), &keys)?;
+        }
+
+        let pending_root = self.cache.borrow().get_pending_root();
+
+        // Remember where the path from root to target node ends (will end).
+        self.cache.borrow_mut().mark_position();
+
+        let pb = RefCell::new(ProofBuilder::new(
+            request.tree.root.hash,
+            request.tree.position,
+        ));
+        let opts = GetOptions {
+            proof_builder: Some(&pb),
+            ..Default::default()
+        };
+        for key in &request.keys {
+            self._get(&ctx, pending_root.clone(), 0, key, opts, false)?;
+        }
+        let proof = pb.into_inner().build(Context::create_child(&ctx))?;
+
+        Ok(ProofResponse { proof })
+    }
+
     fn _get_top(&self, ctx: Context, key: &[u8], check_only: bool) -> Result<Option<Vec<u8>>> {
         let ctx = ctx.freeze();
         let boxed_key = key.to_vec();
@@ -65,7 +207,11 @@ impl Tree {
         // Remember where the path from root to target node ends (will end).
         self.cache.borrow_mut().mark_position();
 
-        self._get(&ctx, pending_root, 0, &boxed_key, check_only)
+        let opts = GetOptions {
+            check_only,
+            ..Default::default()
+        };
+        self._get(&ctx, pending_root, 0, &boxed_key, opts, false)
     }
 
     fn _get(
@@ -74,18 +220,30 @@ impl Tree {
         ptr: NodePtrRef,
         bit_depth: Depth,
         key: &Key,
-        check_only: bool,
+        opts: GetOptions,
+        stop: bool,
     ) -> Result<Option<Value>> {
         let node_ref = self.cache.borrow_mut().deref_node_ptr(
             ctx,
             ptr,
-            if check_only {
+            if opts.check_only {
                 None
             } else {
-                Some(FetcherSyncGet::new(key, false))
+                Some(FetcherSyncGet::new(key, opts.include_siblings))
             },
         )?;
 
+        // Include nodes in proof if we have a proof builder.
+        if let (Some(pb), Some(node_ref)) = (opts.proof_builder, &node_ref) {
+            pb.borrow_mut().include(&node_ref.borrow());
+        }
+
+        // This may be used to only include the given node in a proof and not
+        // traverse the tree further (e.g., to fetch a sibling).
+        if stop {
+            return Ok(None);
+        }
+
         match classify_noderef!(?node_ref) {
             NodeKind::None => {
                 // Reached a nil node, there is nothing here.
@@ -93,42 +251,47 @@ impl Tree {
             }
             NodeKind::Internal => {
                 let node_ref = node_ref.unwrap();
-                if let NodeBox::Internal(ref mut n) = *node_ref.borrow_mut() {
+                if let NodeBox::Internal(ref n) = *node_ref.borrow() {
                     // Internal node.
+                    let bit_length = bit_depth + n.label_bit_length;
+
                     // Does lookup key end here? Look into LeafNode.
-                    if key.bit_length() == bit_depth + n.label_bit_length {
-                        return self._get(
-                            ctx,
-                            n.leaf_node.clone(),
-                            bit_depth + n.label_bit_length,
-                            key,
-                            check_only,
-                        );
+                    if key.bit_length() == bit_length {
+                        // Include siblings before disabling the proof builder for the leaf node.
+                        if opts.include_siblings {
+                            // Also fetch the left and right siblings.
+                            self._get(ctx, n.left.clone(), bit_length, key, opts, true)?;
+                            self._get(ctx, n.right.clone(), bit_length, key, opts, true)?;
+                        }
+
+                        // Omit the proof builder as the leaf node is always included with
+                        // the internal node itself.
+                        let opts = GetOptions {
+                            proof_builder: None,
+                            ..opts
+                        };
+                        return self._get(ctx, n.leaf_node.clone(), bit_length, key, opts, false);
                     }
 
                     // Lookup key is too short for the current n.Label. It's not stored.
-                    if key.bit_length() < bit_depth + n.label_bit_length {
+                    if key.bit_length() < bit_length {
                         return Ok(None);
                     }
 
                     // Continue recursively based on a bit value.
-                    if key.get_bit(bit_depth + n.label_bit_length) {
-                        return self._get(
-                            ctx,
-                            n.right.clone(),
-                            bit_depth + n.label_bit_length,
-                            key,
-                            check_only,
-                        );
+                    let (next, sibling) = if key.get_bit(bit_length) {
+                        (n.right.clone(), n.left.clone())
                     } else {
-                        return self._get(
-                            ctx,
-                            n.left.clone(),
-                            bit_depth + n.label_bit_length,
-                            key,
-                            check_only,
-                        );
+                        (n.left.clone(), n.right.clone())
+                    };
+                    let value = self._get(ctx, next, bit_length, key, opts, false)?;
+
+                    if opts.include_siblings {
+                        // Also fetch the sibling.
+                        self._get(ctx, sibling, bit_length, key, opts, true)?;
                     }
+
+                    return Ok(value);
                 }
 
                 unreachable!("node kind is internal node");
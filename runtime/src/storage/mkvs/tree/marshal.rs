@@ -70,8 +70,24 @@ impl Marshal for NodeKind {
     }
 }
 
-impl Marshal for InternalNode {
-    fn marshal_binary(&self) -> Result<Vec<u8>> {
+impl NodeBox {
+    /// Marshal the node into its compact binary form.
+    ///
+    /// For internal nodes the compact form omits the hashes of the left and right
+    /// children, for leaf nodes it is the same as the regular binary form.
+    pub fn compact_marshal_binary(&self) -> Result<Vec<u8>> {
+        match self {
+            NodeBox::Internal(ref n) => n.compact_marshal_binary(),
+            NodeBox::Leaf(ref n) => n.marshal_binary(),
+        }
+    }
+}
+
+impl InternalNode {
+    /// Marshal the internal node into a compact binary form which omits the hashes
+    /// of the left and right children.
+    pub fn compact_marshal_binary(&self) -> Result<Vec<u8>> {
+        // Internal node's leaf node is always marshalled along the internal node.
         let leaf_node_binary = if self.leaf_node.borrow().is_null() {
             vec![NodeKind::None as u8]
         } else {
@@ -83,6 +99,14 @@ impl Marshal for InternalNode {
         result.append(&mut self.label_bit_length.marshal_binary()?);
         result.extend_from_slice(&self.label);
         result.extend_from_slice(leaf_node_binary.as_ref());
+
+        Ok(result)
+    }
+}
+
+impl Marshal for InternalNode {
+    fn marshal_binary(&self) -> Result<Vec<u8>> {
+        let mut result = self.compact_marshal_binary()?;
         result.extend_from_slice(self.left.borrow().hash.as_ref());
         result.extend_from_slice(self.right.borrow().hash.as_ref());
 
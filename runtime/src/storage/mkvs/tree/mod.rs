@@ -2,7 +2,9 @@
 mod macros;
 
 mod commit;
+mod diff;
 mod errors;
+mod fork;
 mod insert;
 mod iterator;
 mod lookup;
@@ -14,13 +16,14 @@ mod remove;
 
 pub use commit::*;
 pub use errors::*;
+pub use fork::*;
 pub use insert::*;
 pub use iterator::*;
 pub use node::*;
 pub use overlay::*;
 pub use remove::*;
 
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{any::Any, cell::RefCell, fmt, rc::Rc};
 
 use anyhow::Result;
 use io_context::Context;
@@ -34,8 +37,10 @@ use crate::{
 pub struct Options {
     node_capacity: usize,
     value_capacity: usize,
+    eviction_policy: EvictionPolicy,
     root: Option<Root>,
     root_type: Option<RootType>,
+    metrics_operation: Option<SyncOperation>,
 }
 
 impl Default for Options {
@@ -43,8 +48,10 @@ impl Default for Options {
         Self {
             node_capacity: 50_000,
             value_capacity: 16 * 1024 * 1024,
+            eviction_policy: EvictionPolicy::default(),
             root: None,
             root_type: None,
+            metrics_operation: None,
         }
     }
 }
@@ -78,6 +85,14 @@ impl Builder {
         self
     }
 
+    /// Set the policy used to select nodes to evict from the underlying in-memory cache.
+    ///
+    /// If left unspecified, the least recently used nodes are evicted first.
+    pub fn with_eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.options.eviction_policy = policy;
+        self
+    }
+
     /// Set an existing root as the root for the new tree.
     ///
     /// Either this or a root type must be specified to construct a new
@@ -98,6 +113,13 @@ impl Builder {
         self
     }
 
+    /// Record cache hits and misses of the tree in the read syncer metrics, tagged by the given
+    /// operation.
+    pub fn with_cache_metrics(mut self, operation: SyncOperation) -> Self {
+        self.options.metrics_operation = Some(operation);
+        self
+    }
+
     /// Commit the options set so far into a newly constructed tree instance.
     pub fn build(self, read_syncer: Box<dyn ReadSync>) -> Tree {
         assert!(
@@ -123,6 +145,7 @@ pub struct Tree {
 }
 
 // Tree is Send as long as ownership of internal Rcs cannot leak out via any of its methods.
+// Forks share Rcs with the tree they were forked from, so `TreeFork` borrows that tree.
 unsafe impl Send for Tree {}
 
 impl Tree {
@@ -137,12 +160,16 @@ impl Tree {
             cache: RefCell::new(LRUCache::new(
                 opts.node_capacity,
                 opts.value_capacity,
+                opts.eviction_policy,
                 read_syncer,
                 root_type,
             )),
             root_type,
         };
 
+        if let Some(operation) = opts.metrics_operation {
+            tree.cache.borrow_mut().set_metrics_operation(operation);
+        }
         if let Some(root) = opts.root {
             tree.cache
                 .borrow_mut()
@@ -161,6 +188,23 @@ impl Tree {
     pub fn builder() -> Builder {
         Builder::new()
     }
+
+    /// Return statistics about the in-memory cache of this tree.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.borrow().stats()
+    }
+
+    /// Check that the tree can serve sync requests for the given root.
+    fn check_sync_root(&self, root: &Root) -> Result<()> {
+        let cache = self.cache.borrow();
+        if cache.get_sync_root() != *root {
+            return Err(SyncerError::InvalidRoot.into());
+        }
+        if !cache.get_pending_root().borrow().clean {
+            return Err(SyncerError::DirtyRoot.into());
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Debug for Tree {
@@ -186,10 +230,18 @@ impl mkvs::FallibleMKVS for Tree {
         Tree::remove(self, ctx, key)
     }
 
+    fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> Result<mkvs::WriteLog> {
+        Tree::remove_prefix(self, ctx, prefix)
+    }
+
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &[mkvs::Prefix], limit: u16) -> Result<()> {
         Tree::prefetch_prefixes(self, ctx, prefixes, limit)
     }
 
+    fn prefetch_keys(&self, ctx: Context, keys: &[&[u8]]) -> Result<()> {
+        Tree::prefetch_keys(self, ctx, keys)
+    }
+
     fn iter(&self, ctx: Context) -> Box<dyn mkvs::Iterator + '_> {
         Box::new(Tree::iter(self, ctx))
     }
@@ -199,6 +251,32 @@ impl mkvs::FallibleMKVS for Tree {
     }
 }
 
+impl ReadSync for Tree {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, ctx: Context, request: GetRequest) -> Result<ProofResponse> {
+        Tree::sync_get(self, ctx, request)
+    }
+
+    fn sync_get_many(&mut self, ctx: Context, request: GetManyRequest) -> Result<ProofResponse> {
+        Tree::sync_get_many(self, ctx, request)
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        ctx: Context,
+        request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        Tree::sync_get_prefixes(self, ctx, request)
+    }
+
+    fn sync_iterate(&mut self, ctx: Context, request: IterateRequest) -> Result<ProofResponse> {
+        Tree::sync_iterate(self, ctx, request)
+    }
+}
+
 #[cfg(test)]
 mod node_test;
 #[cfg(test)]
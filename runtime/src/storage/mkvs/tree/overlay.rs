@@ -1,9 +1,10 @@
 use std::{
-    collections::{btree_map, BTreeMap, HashSet},
-    iter::{Iterator, Peekable},
+    collections::{BTreeMap, HashSet},
+    iter::Iterator,
+    ops::{Bound, RangeBounds},
 };
 
-use anyhow::{Error, Result};
+use anyhow::{Context as _, Error, Result};
 use io_context::Context;
 
 use crate::{
@@ -70,11 +71,57 @@ impl<T: mkvs::FallibleMKVS> OverlayTree<T> {
         Ok(value)
     }
 
+    /// Remove all entries with keys starting with the given prefix, returning a write log
+    /// with a delete entry for each removed key.
+    ///
+    /// The removals are only applied to the underlying tree on commit.
+    pub fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> Result<mkvs::WriteLog> {
+        let ctx = ctx.freeze();
+
+        // Collect all keys under the prefix from both the overlay and the inner tree.
+        let mut keys = Vec::new();
+        {
+            let mut it = self.iter(Context::create_child(&ctx));
+            mkvs::Iterator::set_prefetch(&mut it, REMOVE_PREFIX_PREFETCH);
+            mkvs::Iterator::seek(&mut it, prefix);
+            while let Some(key) = mkvs::Iterator::get_key(&it) {
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                keys.push(key.clone());
+                mkvs::Iterator::next(&mut it);
+            }
+            mkvs::Iterator::take_error(&mut it).context("failed to iterate over keys to remove")?;
+        }
+
+        let mut log = mkvs::WriteLog::with_capacity(keys.len());
+        for key in keys {
+            self.remove(Context::create_child(&ctx), &key)?;
+            log.push(mkvs::LogEntry { key, value: None });
+        }
+
+        Ok(log)
+    }
+
     /// Return an iterator over the tree.
     pub fn iter(&self, ctx: Context) -> OverlayTreeIterator<T> {
         OverlayTreeIterator::new(ctx, self)
     }
 
+    /// Return an iterator over all items with keys in the given range.
+    pub fn range<K, R>(&self, ctx: Context, range: R) -> mkvs::RangeIterator<OverlayTreeIterator<T>>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let ctx = ctx.freeze();
+        mkvs::RangeIterator::new(
+            OverlayTreeIterator::new(Context::create_child(&ctx), self),
+            OverlayTreeIterator::new(Context::create_child(&ctx), self),
+            range,
+        )
+    }
+
     /// Commit any modifications to the underlying tree.
     pub fn commit(&mut self, ctx: Context) -> Result<mkvs::WriteLog> {
         let ctx = ctx.freeze();
@@ -131,8 +178,9 @@ pub struct OverlayTreeIterator<'tree, T: mkvs::FallibleMKVS> {
     tree: &'tree OverlayTree<T>,
 
     inner: Box<dyn mkvs::Iterator + 'tree>,
-    overlay: Peekable<btree_map::Range<'tree, Vec<u8>, Vec<u8>>>,
+    overlay: Option<(&'tree Vec<u8>, &'tree Vec<u8>)>,
     overlay_valid: bool,
+    reverse: bool,
 
     key: Option<Vec<u8>>,
     value: Option<Vec<u8>>,
@@ -143,8 +191,9 @@ impl<'tree, T: mkvs::FallibleMKVS> OverlayTreeIterator<'tree, T> {
         Self {
             tree,
             inner: tree.inner.iter(ctx),
-            overlay: tree.overlay.range(vec![]..).peekable(),
+            overlay: tree.overlay.iter().next(),
             overlay_valid: true,
+            reverse: false,
             key: None,
             value: None,
         }
@@ -161,22 +210,34 @@ impl<'tree, T: mkvs::FallibleMKVS> OverlayTreeIterator<'tree, T> {
             {
                 break;
             }
-            self.inner.next();
+            if self.reverse {
+                self.inner.prev();
+            } else {
+                self.inner.next();
+            }
         }
 
         let i_key = self.inner.get_key();
-        let o_item = self.overlay.peek();
+        let o_item = self.overlay;
         self.overlay_valid = o_item.is_some();
 
         if self.inner.is_valid()
-            && (!self.overlay_valid
-                || i_key.as_ref().expect("inner.is_valid") < o_item.expect("overlay_valid").0)
+            && (!self.overlay_valid || {
+                let i_key = i_key.as_ref().expect("inner.is_valid");
+                let o_key = o_item.expect("overlay_valid").0;
+                // When iterating in reverse, the larger key comes first.
+                if self.reverse {
+                    i_key > o_key
+                } else {
+                    i_key < o_key
+                }
+            })
         {
-            // Key of inner iterator is smaller than the key of the overlay iterator.
+            // Key of inner iterator comes before the key of the overlay iterator.
             self.key = i_key.clone();
             self.value = self.inner.get_value().clone();
         } else if self.overlay_valid {
-            // Key of overlay iterator is smaller than or equal to the key of the inner iterator.
+            // Key of overlay iterator comes before or is equal to the key of the inner iterator.
             let (o_key, o_value) = o_item.expect("overlay_valid");
             self.key = Some(o_key.to_vec());
             self.value = Some(o_value.to_vec());
@@ -188,16 +249,58 @@ impl<'tree, T: mkvs::FallibleMKVS> OverlayTreeIterator<'tree, T> {
     }
 
     fn next(&mut self) {
+        if self.reverse {
+            // Changing direction, reposition the iterator at the current key first.
+            match self.key.clone() {
+                Some(key) => mkvs::Iterator::seek(self, &key),
+                None => return,
+            }
+        }
+
         if !self.overlay_valid
             || (self.inner.is_valid()
                 && self.inner.get_key().as_ref().expect("inner.is_valid")
-                    <= self.overlay.peek().expect("overlay_valid").0)
+                    <= self.overlay.expect("overlay_valid").0)
         {
             // Key of inner iterator is smaller or equal than the key of the overlay iterator.
             self.inner.next();
         } else {
             // Key of inner iterator is greater than the key of the overlay iterator.
-            self.overlay.next();
+            let o_key = self.overlay.expect("overlay_valid").0;
+            self.overlay = self
+                .tree
+                .overlay
+                .range::<[u8], _>((Bound::Excluded(&o_key[..]), Bound::Unbounded))
+                .next();
+        }
+
+        self.update_iterator_position();
+    }
+
+    fn prev(&mut self) {
+        if !self.reverse {
+            // Changing direction, reposition the iterator at the current key first.
+            match self.key.clone() {
+                Some(key) => mkvs::Iterator::seek_for_prev(self, &key),
+                None => return,
+            }
+        }
+
+        if !self.overlay_valid
+            || (self.inner.is_valid()
+                && self.inner.get_key().as_ref().expect("inner.is_valid")
+                    >= self.overlay.expect("overlay_valid").0)
+        {
+            // Key of inner iterator is greater or equal than the key of the overlay iterator.
+            self.inner.prev();
+        } else {
+            // Key of inner iterator is smaller than the key of the overlay iterator.
+            let o_key = self.overlay.expect("overlay_valid").0;
+            self.overlay = self
+                .tree
+                .overlay
+                .range::<[u8], _>((Bound::Unbounded, Bound::Excluded(&o_key[..])))
+                .next_back();
         }
 
         self.update_iterator_position();
@@ -236,13 +339,42 @@ impl<'tree, T: mkvs::FallibleMKVS> mkvs::Iterator for OverlayTreeIterator<'tree,
         self.inner.error()
     }
 
+    fn take_error(&mut self) -> Result<()> {
+        self.inner.take_error()
+    }
+
     fn rewind(&mut self) {
         self.seek(&[]);
     }
 
     fn seek(&mut self, key: &[u8]) {
+        self.reverse = false;
         self.inner.seek(key);
-        self.overlay = self.tree.overlay.range(key.to_vec()..).peekable();
+        self.overlay = self
+            .tree
+            .overlay
+            .range::<[u8], _>((Bound::Included(key), Bound::Unbounded))
+            .next();
+
+        self.update_iterator_position();
+    }
+
+    fn seek_last(&mut self) {
+        self.reverse = true;
+        self.inner.seek_last();
+        self.overlay = self.tree.overlay.iter().next_back();
+
+        self.update_iterator_position();
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        self.reverse = true;
+        self.inner.seek_for_prev(key);
+        self.overlay = self
+            .tree
+            .overlay
+            .range::<[u8], _>((Bound::Unbounded, Bound::Included(key)))
+            .next_back();
 
         self.update_iterator_position();
     }
@@ -258,6 +390,10 @@ impl<'tree, T: mkvs::FallibleMKVS> mkvs::Iterator for OverlayTreeIterator<'tree,
     fn next(&mut self) {
         OverlayTreeIterator::next(self)
     }
+
+    fn prev(&mut self) {
+        OverlayTreeIterator::prev(self)
+    }
 }
 
 impl<T: mkvs::FallibleMKVS> mkvs::MKVS for OverlayTree<T> {
@@ -281,10 +417,18 @@ impl<T: mkvs::FallibleMKVS> mkvs::MKVS for OverlayTree<T> {
         self.remove(ctx, key).unwrap()
     }
 
+    fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> mkvs::WriteLog {
+        self.remove_prefix(ctx, prefix).unwrap()
+    }
+
     fn prefetch_prefixes(&self, ctx: Context, prefixes: &[mkvs::Prefix], limit: u16) {
         self.inner.prefetch_prefixes(ctx, prefixes, limit).unwrap()
     }
 
+    fn prefetch_keys(&self, ctx: Context, keys: &[&[u8]]) {
+        self.inner.prefetch_keys(ctx, keys).unwrap()
+    }
+
     fn iter(&self, ctx: Context) -> Box<dyn mkvs::Iterator + '_> {
         Box::new(self.iter(ctx))
     }
@@ -302,7 +446,10 @@ impl<T: mkvs::FallibleMKVS> mkvs::MKVS for OverlayTree<T> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::storage::mkvs::{sync::NoopReadSyncer, tree::iterator::test::test_iterator_with};
+    use crate::storage::mkvs::{
+        sync::{NoopReadSyncer, SyncerError},
+        tree::iterator::test::{test_iterator_with, test_reverse_iterator_with},
+    };
 
     #[test]
     fn test_overlay() {
@@ -332,6 +479,17 @@ mod test {
             (b"key 9".to_vec(), 5),
             (b"key A".to_vec(), -1),
         ];
+        let reverse_tests = vec![
+            (b"k".to_vec(), -1),
+            (b"key".to_vec(), 0),
+            (b"key 0".to_vec(), 0),
+            (b"key 1".to_vec(), 1),
+            (b"key 3".to_vec(), 2),
+            (b"key 5".to_vec(), 3),
+            (b"key 6".to_vec(), 3),
+            (b"key 9".to_vec(), 5),
+            (b"key A".to_vec(), 5),
+        ];
 
         // Create an overlay over an empty tree and insert some items into the overlay.
         let mut overlay = OverlayTree::new(&mut tree);
@@ -342,6 +500,8 @@ mod test {
         // Test that an overlay-only iterator works correctly.
         let it = overlay.iter(Context::background());
         test_iterator_with(&items, it, &tests);
+        let it = overlay.iter(Context::background());
+        test_reverse_iterator_with(&items, it, &reverse_tests);
 
         // Insert some items into the underlying tree.
         for (key, value) in items.iter() {
@@ -364,6 +524,8 @@ mod test {
         // the same as for the inner tree).
         let it = overlay.iter(Context::background());
         test_iterator_with(&items, it, &tests);
+        let it = overlay.iter(Context::background());
+        test_reverse_iterator_with(&items, it, &reverse_tests);
 
         // Add some updates to the overlay.
         overlay.remove(Context::background(), b"key 2").unwrap();
@@ -412,6 +574,17 @@ mod test {
             (b"key 9".to_vec(), 5),
             (b"key A".to_vec(), -1),
         ];
+        let reverse_tests = vec![
+            (b"k".to_vec(), -1),
+            (b"key".to_vec(), 0),
+            (b"key 1".to_vec(), 1),
+            (b"key 3".to_vec(), 1),
+            (b"key 5".to_vec(), 2),
+            (b"key 6".to_vec(), 2),
+            (b"key 7".to_vec(), 3),
+            (b"key 9".to_vec(), 5),
+            (b"key A".to_vec(), 5),
+        ];
 
         // Test that all keys can be fetched from an updated overlay.
         for (k, expected_v) in &items {
@@ -422,6 +595,19 @@ mod test {
         // Make sure that merged overlay iterator works.
         let it = overlay.iter(Context::background());
         test_iterator_with(&items, it, &tests);
+        let it = overlay.iter(Context::background());
+        test_reverse_iterator_with(&items, it, &reverse_tests);
+
+        // Make sure that range scans over the merged overlay work in both directions.
+        let range: Vec<_> = overlay
+            .range(Context::background(), b"key 2".to_vec()..b"key 8".to_vec())
+            .collect();
+        assert_eq!(range, items[2..4].to_vec());
+        let range: Vec<_> = overlay
+            .range(Context::background(), b"key 1".to_vec()..=b"key 8".to_vec())
+            .rev()
+            .collect();
+        assert_eq!(range, items[1..5].iter().rev().cloned().collect::<Vec<_>>());
 
         // Commit the overlay.
         overlay.commit(Context::background()).unwrap();
@@ -436,4 +622,29 @@ mod test {
         let it = tree.iter(Context::background());
         test_iterator_with(&items, it, &tests);
     }
+
+    #[test]
+    fn test_overlay_remove_prefix_error() {
+        // A tree whose nodes can't be fetched.
+        let tree = Tree::builder()
+            .with_root(Root {
+                root_type: RootType::State,
+                hash: Hash::digest_bytes(b"missing"),
+                ..Default::default()
+            })
+            .build(Box::new(NoopReadSyncer));
+        let mut overlay = OverlayTree::new(tree);
+
+        let err = overlay
+            .remove_prefix(Context::background(), b"key")
+            .expect_err("remove_prefix should fail");
+        assert!(
+            matches!(
+                err.downcast_ref::<SyncerError>(),
+                Some(SyncerError::Unsupported)
+            ),
+            "the original error should be propagated: {:#}",
+            err
+        );
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use io_context::Context;
 
-use crate::storage::mkvs::{cache::*, sync::*, tree::*, Prefix};
+use crate::storage::mkvs::{self, cache::*, sync::*, tree::*, Prefix};
 
 pub(super) struct FetcherSyncGetPrefixes<'a> {
     prefixes: &'a [Prefix],
@@ -48,4 +48,57 @@ impl Tree {
             FetcherSyncGetPrefixes::new(prefixes, limit),
         )
     }
+
+    /// Fetch all keys under the given prefixes and return the corresponding proof.
+    pub fn sync_get_prefixes(
+        &self,
+        ctx: Context,
+        request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        let ctx = ctx.freeze();
+        self.check_sync_root(&request.tree.root)?;
+
+        // First, trigger same prefetching locally if a remote read syncer
+        // is available. This is needed to ensure that the same optimization
+        // carries on to the next layer.
+        let has_remote = !self
+            .cache
+            .borrow()
+            .get_read_syncer()
+            .as_any()
+            .is::<NoopReadSyncer>();
+        if has_remote {
+            self.prefetch_prefixes(
+                Context::create_child(&ctx),
+                &request.prefixes,
+                request.limit,
+            )?;
+        }
+
+        let mut it =
+            TreeIterator::new(Context::create_child(&ctx), self).with_proof(request.tree.root.hash);
+
+        let mut total = 0;
+        'prefixes: for prefix in &request.prefixes {
+            mkvs::Iterator::seek(&mut it, prefix);
+            while mkvs::Iterator::is_valid(&it) {
+                if total >= request.limit {
+                    break 'prefixes;
+                }
+                let key = mkvs::Iterator::get_key(&it)
+                    .as_ref()
+                    .expect("iterator is valid");
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                mkvs::Iterator::next(&mut it);
+                total += 1;
+            }
+            it.take_error()?;
+        }
+
+        let proof = it.get_proof()?;
+
+        Ok(ProofResponse { proof })
+    }
 }
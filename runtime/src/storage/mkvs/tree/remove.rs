@@ -3,9 +3,12 @@ use std::sync::Arc;
 use anyhow::Result;
 use io_context::Context;
 
-use crate::storage::mkvs::{cache::*, tree::*};
+use crate::storage::mkvs::{cache::*, tree::*, LogEntry, WriteLog};
 
-use super::lookup::FetcherSyncGet;
+use super::{iterator::FetcherSyncIterate, lookup::FetcherSyncGet};
+
+/// Number of items to prefetch when fetching subtrees that are removed by prefix.
+pub(super) const REMOVE_PREFIX_PREFETCH: usize = 1000;
 
 impl Tree {
     /// Remove entry with given key, returning the value at the key if the key was previously
@@ -24,6 +27,33 @@ impl Tree {
         Ok(old_val)
     }
 
+    /// Remove all entries with keys starting with the given prefix, returning the write log
+    /// containing a delete entry for each removed key in key order.
+    ///
+    /// Subtrees whose keys all start with the prefix are pruned as a whole, so only the nodes
+    /// needed to enumerate the removed keys are fetched.
+    pub fn remove_prefix(&mut self, ctx: Context, prefix: &[u8]) -> Result<WriteLog> {
+        let ctx = ctx.freeze();
+        let boxed_prefix = prefix.to_vec();
+        let pending_root = self.cache.borrow().get_pending_root();
+
+        // Remember where the path from root to target node ends (will end).
+        self.cache.borrow_mut().mark_position();
+
+        let mut write_log = WriteLog::new();
+        let (new_root, _) = self._remove_prefix(
+            &ctx,
+            pending_root,
+            0,
+            Key::new(),
+            &boxed_prefix,
+            &mut write_log,
+        )?;
+        self.cache.borrow_mut().set_pending_root(new_root);
+
+        Ok(write_log)
+    }
+
     fn _remove(
         &mut self,
         ctx: &Arc<Context>,
@@ -45,147 +75,318 @@ impl Tree {
             NodeKind::Internal => {
                 // Remove from internal node and recursively collapse the path, if needed.
                 let node_ref = node_ref.unwrap();
-                let (changed, old_val): (bool, Option<Value>);
-                let (remaining_leaf, remaining_left, remaining_right): (
-                    Option<NodeRef>,
-                    Option<NodeRef>,
-                    Option<NodeRef>,
-                );
-                if let NodeBox::Internal(ref mut n) = *node_ref.borrow_mut() {
-                    // Remove from internal node and recursively collapse the branch, if
-                    // needed.
-                    let bit_length = bit_depth + n.label_bit_length;
-
-                    if key.bit_length() < bit_length {
-                        // Lookup key is too short for the current n.Label, so it doesn't exist.
-                        return Ok((ptr, false, None));
-                    }
+                let (bit_length, child) = match *node_ref.borrow() {
+                    NodeBox::Internal(ref n) => {
+                        let bit_length = bit_depth + n.label_bit_length;
+                        if key.bit_length() < bit_length {
+                            // Lookup key is too short for the current n.Label, so it doesn't
+                            // exist.
+                            return Ok((ptr, false, None));
+                        }
 
-                    let (new_child, c, o) = if key.bit_length() == bit_length {
-                        self._remove(ctx, n.leaf_node.clone(), bit_depth, key)?
-                    } else if key.get_bit(bit_length) {
-                        self._remove(ctx, n.right.clone(), bit_length, key)?
-                    } else {
-                        self._remove(ctx, n.left.clone(), bit_length, key)?
-                    };
-
-                    changed = c;
-                    old_val = o;
-
-                    if key.bit_length() == bit_length {
-                        n.leaf_node = new_child;
-                    } else if key.get_bit(bit_length) {
-                        n.right = new_child;
-                    } else {
-                        n.left = new_child;
+                        let child = if key.bit_length() == bit_length {
+                            n.leaf_node.clone()
+                        } else if key.get_bit(bit_length) {
+                            n.right.clone()
+                        } else {
+                            n.left.clone()
+                        };
+                        (bit_length, child)
                     }
+                    _ => unreachable!("node kind is Internal"),
+                };
 
-                    // Fetch and check the remaining children.
-                    // NOTE: The leaf node is always included with the internal node.
-                    remaining_leaf = n.leaf_node.borrow().node.clone();
-                    remaining_left = self.cache.borrow_mut().deref_node_ptr(
-                        ctx,
-                        n.left.clone(),
-                        Some(FetcherSyncGet::new(key, true)),
-                    )?;
-                    remaining_right = self.cache.borrow_mut().deref_node_ptr(
-                        ctx,
-                        n.right.clone(),
-                        Some(FetcherSyncGet::new(key, true)),
-                    )?;
+                // Remove from internal node and recursively collapse the branch, if needed.
+                let (new_child, changed, old_val) = if key.bit_length() == bit_length {
+                    self._remove(ctx, child, bit_depth, key)?
+                } else {
+                    self._remove(ctx, child, bit_length, key)?
+                };
+                if !changed {
+                    return Ok((ptr, false, None));
+                }
+
+                let (ptr, node_ref) = self.copy_on_write(ptr, node_ref);
+                if key.bit_length() == bit_length {
+                    noderef_as_mut!(node_ref, Internal).leaf_node = new_child;
+                } else if key.get_bit(bit_length) {
+                    noderef_as_mut!(node_ref, Internal).right = new_child;
                 } else {
-                    unreachable!("node kind is Internal");
+                    noderef_as_mut!(node_ref, Internal).left = new_child;
                 }
 
-                // If exactly one child including LeafNode remains, collapse it.
-                match remaining_leaf {
-                    Some(_) => match remaining_left {
+                let (new_ptr, changed) = self._collapse(ctx, ptr, node_ref, changed, key)?;
+                Ok((new_ptr, changed, old_val))
+            }
+            NodeKind::Leaf => {
+                // Remove from leaf node.
+                let node_ref = node_ref.unwrap();
+                if noderef_as!(node_ref, Leaf).key == *key {
+                    let old_val = noderef_as!(node_ref, Leaf).value.clone();
+                    self.discard_node(ptr);
+                    return Ok((NodePointer::null_ptr(), true, Some(old_val)));
+                }
+
+                Ok((ptr, false, None))
+            }
+        }
+    }
+
+    /// Collapse an internal node after one of its children has been updated by a removal.
+    ///
+    /// If only one child (including the leaf node) remains, the internal node is replaced by
+    /// that child. Otherwise the node is marked dirty in case anything has changed.
+    fn _collapse(
+        &mut self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        node_ref: NodeRef,
+        changed: bool,
+        key: &Key,
+    ) -> Result<(NodePtrRef, bool)> {
+        // Fetch and check the remaining children.
+        // NOTE: The leaf node is always included with the internal node.
+        let (remaining_leaf, left, right) = match *node_ref.borrow() {
+            NodeBox::Internal(ref n) => (
+                n.leaf_node.borrow().node.clone(),
+                n.left.clone(),
+                n.right.clone(),
+            ),
+            _ => unreachable!("node kind is Internal"),
+        };
+        let remaining_left = self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            left,
+            Some(FetcherSyncGet::new(key, true)),
+        )?;
+        let remaining_right = self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            right,
+            Some(FetcherSyncGet::new(key, true)),
+        )?;
+
+        // If exactly one child including LeafNode remains, collapse it.
+        match remaining_leaf {
+            Some(_) => match remaining_left {
+                Some(_) => (),
+                None => match remaining_right {
+                    None => {
+                        let nd_leaf = noderef_as!(node_ref, Internal).leaf_node.clone();
+                        noderef_as_mut!(node_ref, Internal).leaf_node = NodePointer::null_ptr();
+                        self.discard_node(ptr);
+                        return Ok((nd_leaf, true));
+                    }
+                    Some(_) => (),
+                },
+            },
+            None => {
+                let mut nd_child: Option<NodeRef> = None;
+                let mut node_ptr: NodePtrRef = NodePointer::null_ptr();
+                let mut both_children = true;
+                match remaining_left {
+                    Some(_) => match remaining_right {
+                        None => {
+                            node_ptr = noderef_as!(node_ref, Internal).left.clone();
+                            noderef_as_mut!(node_ref, Internal).left = NodePointer::null_ptr();
+                            nd_child = remaining_left;
+                            both_children = false;
+                        }
                         Some(_) => (),
-                        None => match remaining_right {
-                            None => {
-                                let nd_leaf = noderef_as!(node_ref, Internal).leaf_node.clone();
-                                noderef_as_mut!(node_ref, Internal).leaf_node =
-                                    NodePointer::null_ptr();
-                                self.cache.borrow_mut().remove_node(ptr);
-                                return Ok((nd_leaf, true, old_val));
-                            }
-                            Some(_) => (),
-                        },
                     },
-                    None => {
-                        let mut nd_child: Option<NodeRef> = None;
-                        let mut node_ptr: NodePtrRef = NodePointer::null_ptr();
-                        let mut both_children = true;
-                        match remaining_left {
-                            Some(_) => match remaining_right {
-                                None => {
-                                    node_ptr = noderef_as!(node_ref, Internal).left.clone();
-                                    noderef_as_mut!(node_ref, Internal).left =
-                                        NodePointer::null_ptr();
-                                    nd_child = remaining_left;
-                                    both_children = false;
-                                }
-                                Some(_) => (),
-                            },
-                            None => match remaining_right {
-                                None => (),
-                                Some(_) => {
-                                    node_ptr = noderef_as!(node_ref, Internal).right.clone();
-                                    noderef_as_mut!(node_ref, Internal).right =
-                                        NodePointer::null_ptr();
-                                    nd_child = remaining_right;
-                                    both_children = false;
-                                }
-                            },
+                    None => match remaining_right {
+                        None => (),
+                        Some(_) => {
+                            node_ptr = noderef_as!(node_ref, Internal).right.clone();
+                            noderef_as_mut!(node_ref, Internal).right = NodePointer::null_ptr();
+                            nd_child = remaining_right;
+                            both_children = false;
                         }
+                    },
+                }
 
-                        if !both_children {
-                            // If child is an internal node, also fix the label.
-                            if let Some(nd_child) = nd_child {
-                                if let NodeKind::Internal = classify_noderef!(nd_child) {
-                                    if let NodeBox::Internal(ref mut inode) = *nd_child.borrow_mut()
-                                    {
-                                        inode.label = noderef_as!(node_ref, Internal).label.merge(
-                                            noderef_as!(node_ref, Internal).label_bit_length,
-                                            &inode.label,
-                                            inode.label_bit_length,
-                                        );
-                                        inode.label_bit_length +=
-                                            noderef_as!(node_ref, Internal).label_bit_length;
-                                        inode.clean = false;
-                                        node_ptr.borrow_mut().clean = false;
-                                    }
-                                }
-                            }
-
-                            self.cache.borrow_mut().remove_node(ptr);
-                            return Ok((node_ptr, true, old_val));
+                if !both_children {
+                    // If child is an internal node, also fix the label.
+                    if let Some(nd_child) = nd_child {
+                        if let NodeKind::Internal = classify_noderef!(nd_child) {
+                            let (new_ptr, nd_child) = self.copy_on_write(node_ptr, nd_child);
+                            node_ptr = new_ptr;
+                            let (label, label_bit_length) = match *node_ref.borrow() {
+                                NodeBox::Internal(ref n) => (n.label.clone(), n.label_bit_length),
+                                _ => unreachable!("node kind is Internal"),
+                            };
+                            if let NodeBox::Internal(ref mut inode) = *nd_child.borrow_mut() {
+                                inode.label = label.merge(
+                                    label_bit_length,
+                                    &inode.label,
+                                    inode.label_bit_length,
+                                );
+                                inode.label_bit_length += label_bit_length;
+                                inode.clean = false;
+                            };
+                            node_ptr.borrow_mut().clean = false;
                         }
                     }
+
+                    self.discard_node(ptr);
+                    return Ok((node_ptr, true));
+                }
+            }
+        };
+
+        // Two or more children including leaf_node remain, just mark dirty bit.
+        if changed {
+            noderef_as_mut!(node_ref, Internal).clean = false;
+            ptr.borrow_mut().clean = false;
+            // No longer eligible for eviction as it is dirty.
+            self.cache
+                .borrow_mut()
+                .rollback_node(ptr.clone(), NodeKind::Internal);
+        }
+
+        Ok((ptr, changed))
+    }
+
+    fn _remove_prefix(
+        &mut self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        bit_depth: Depth,
+        path: Key,
+        prefix: &Key,
+        write_log: &mut WriteLog,
+    ) -> Result<(NodePtrRef, bool)> {
+        let node_ref = self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            ptr.clone(),
+            Some(FetcherSyncGet::new(prefix, true)),
+        )?;
+
+        match classify_noderef!(?node_ref) {
+            NodeKind::None => {
+                // Remove from nil node.
+                Ok((NodePointer::null_ptr(), false))
+            }
+            NodeKind::Internal => {
+                let node_ref = node_ref.unwrap();
+                let (bit_length, new_path, left, right) = match *node_ref.borrow() {
+                    NodeBox::Internal(ref n) => (
+                        bit_depth + n.label_bit_length,
+                        path.merge(bit_depth, &n.label, n.label_bit_length),
+                        n.left.clone(),
+                        n.right.clone(),
+                    ),
+                    _ => unreachable!("node kind is Internal"),
                 };
 
-                // Two or more children including leaf_node remain, just mark dirty bit.
-                if changed {
-                    noderef_as_mut!(node_ref, Internal).clean = false;
-                    ptr.borrow_mut().clean = false;
-                    // No longer eligible for eviction as it is dirty.
-                    self.cache
-                        .borrow_mut()
-                        .rollback_node(ptr.clone(), NodeKind::Internal);
+                let cp = prefix.common_prefix_len(prefix.bit_length(), &new_path, bit_length);
+                if cp == prefix.bit_length() {
+                    // All keys in this subtree start with the prefix, remove the whole subtree.
+                    self._collect_subtree(ctx, ptr.clone(), bit_depth, path, write_log)?;
+                    self.discard_node(ptr);
+                    return Ok((NodePointer::null_ptr(), true));
+                }
+                if cp < bit_length {
+                    // The prefix diverges from the path to this subtree, nothing to remove.
+                    return Ok((ptr, false));
+                }
+
+                // The prefix is longer than the path so the leaf node cannot match and only one
+                // of the children needs to be visited.
+                let go_right = prefix.get_bit(bit_length);
+                let (new_child, changed) = self._remove_prefix(
+                    ctx,
+                    if go_right { right } else { left },
+                    bit_length,
+                    new_path.append_bit(bit_length, go_right),
+                    prefix,
+                    write_log,
+                )?;
+                if !changed {
+                    return Ok((ptr, false));
+                }
+
+                let (ptr, node_ref) = self.copy_on_write(ptr, node_ref);
+                if go_right {
+                    noderef_as_mut!(node_ref, Internal).right = new_child;
+                } else {
+                    noderef_as_mut!(node_ref, Internal).left = new_child;
                 }
 
-                Ok((ptr, changed, old_val))
+                self._collapse(ctx, ptr, node_ref, changed, prefix)
             }
             NodeKind::Leaf => {
                 // Remove from leaf node.
                 let node_ref = node_ref.unwrap();
-                if noderef_as!(node_ref, Leaf).key == *key {
-                    let old_val = noderef_as!(node_ref, Leaf).value.clone();
-                    self.cache.borrow_mut().remove_node(ptr);
-                    return Ok((NodePointer::null_ptr(), true, Some(old_val)));
+                let key = noderef_as!(node_ref, Leaf).key.clone();
+                if key.starts_with(prefix) {
+                    self.discard_node(ptr);
+                    write_log.push(LogEntry { key, value: None });
+                    return Ok((NodePointer::null_ptr(), true));
                 }
 
-                Ok((ptr, false, None))
+                Ok((ptr, false))
+            }
+        }
+    }
+
+    /// Append a delete entry for each key in the given subtree to the write log in key order.
+    fn _collect_subtree(
+        &mut self,
+        ctx: &Arc<Context>,
+        ptr: NodePtrRef,
+        bit_depth: Depth,
+        path: Key,
+        write_log: &mut WriteLog,
+    ) -> Result<()> {
+        // Fetch any missing nodes in key order, starting with the smallest key in the subtree.
+        let node_ref = self.cache.borrow_mut().deref_node_ptr(
+            ctx,
+            ptr,
+            Some(FetcherSyncIterate::new(
+                &path,
+                REMOVE_PREFIX_PREFETCH,
+                false,
+            )),
+        )?;
+
+        match classify_noderef!(?node_ref) {
+            NodeKind::None => Ok(()),
+            NodeKind::Internal => {
+                let node_ref = node_ref.unwrap();
+                let (bit_length, new_path, leaf_node, left, right) = match *node_ref.borrow() {
+                    NodeBox::Internal(ref n) => (
+                        bit_depth + n.label_bit_length,
+                        path.merge(bit_depth, &n.label, n.label_bit_length),
+                        n.leaf_node.clone(),
+                        n.left.clone(),
+                        n.right.clone(),
+                    ),
+                    _ => unreachable!("node kind is Internal"),
+                };
+
+                self._collect_subtree(ctx, leaf_node, bit_length, new_path.clone(), write_log)?;
+                self._collect_subtree(
+                    ctx,
+                    left,
+                    bit_length,
+                    new_path.append_bit(bit_length, false),
+                    write_log,
+                )?;
+                self._collect_subtree(
+                    ctx,
+                    right,
+                    bit_length,
+                    new_path.append_bit(bit_length, true),
+                    write_log,
+                )
+            }
+            NodeKind::Leaf => {
+                let node_ref = node_ref.unwrap();
+                write_log.push(LogEntry {
+                    key: noderef_as!(node_ref, Leaf).key.clone(),
+                    value: None,
+                });
+                Ok(())
             }
         }
     }
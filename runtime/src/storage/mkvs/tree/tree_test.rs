@@ -1,14 +1,26 @@
+use anyhow::Result;
 use io_context::Context;
 use serde_json;
-use std::{collections::HashSet, fs::File, io::BufReader, iter, iter::FromIterator, path::Path};
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::BufReader,
+    iter,
+    iter::FromIterator,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
 
 use crate::{
     common::crypto::hash::Hash,
     storage::mkvs::{
+        db::FileNodeDB,
         interop::{Driver, ProtocolServer},
+        sync::*,
         tests,
         tree::*,
-        Iterator, LogEntry, LogEntryKind, WriteLog, MKVS,
+        CacheStats, EvictionPolicy, Iterator, LogEntry, LogEntryKind, WriteLog, MKVS,
     },
 };
 
@@ -666,6 +678,409 @@ fn test_remove() {
     assert_eq!(hash, Hash::empty_hash());
 }
 
+#[test]
+fn test_remove_prefix() {
+    let build = |prefixes: &[&str]| {
+        let mut tree = Tree::builder()
+            .with_capacity(0, 0)
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+        let mut keys = Vec::new();
+        for prefix in prefixes {
+            let (k, v) = generate_key_value_pairs_ex(prefix.to_string(), 100);
+            for i in 0..k.len() {
+                tree.insert(Context::background(), &k[i], &v[i])
+                    .expect("insert");
+            }
+            keys.extend(k);
+        }
+        keys.sort();
+        let hash =
+            Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+        (tree, keys, hash)
+    };
+    let deletes = |keys: &[Vec<u8>], prefix: &[u8]| -> WriteLog {
+        keys.iter()
+            .filter(|key| key.starts_with(prefix))
+            .map(|key| LogEntry {
+                key: key.clone(),
+                value: None,
+            })
+            .collect()
+    };
+
+    let (mut tree, keys, _) = build(&["A", "B", "BB"]);
+
+    // Removing a prefix without any keys should not change anything.
+    let (_, _, expected_hash) = build(&["A", "B", "BB"]);
+    let write_log = tree
+        .remove_prefix(Context::background(), b"C")
+        .expect("remove_prefix");
+    assert!(write_log.is_empty(), "write log should be empty");
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    assert_eq!(hash, expected_hash);
+
+    // Remove a prefix which is a whole subtree.
+    let (_, _, expected_hash) = build(&["A"]);
+    let write_log = tree
+        .remove_prefix(Context::background(), b"B")
+        .expect("remove_prefix");
+    assert_eq!(write_log, deletes(&keys, b"B"));
+    for key in &keys {
+        let value = tree.get(Context::background(), key).expect("get");
+        assert_eq!(value.is_some(), !key.starts_with(b"B"));
+    }
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    assert_eq!(hash, expected_hash);
+
+    // Remove a prefix which ends within a node label.
+    let write_log = tree
+        .remove_prefix(Context::background(), b"Akey 1")
+        .expect("remove_prefix");
+    assert_eq!(write_log.len(), 11);
+    assert_eq!(write_log, deletes(&keys, b"Akey 1"));
+    for key in &keys {
+        let value = tree.get(Context::background(), key).expect("get");
+        assert_eq!(
+            value.is_some(),
+            key.starts_with(b"A") && !key.starts_with(b"Akey 1")
+        );
+    }
+
+    // Remove everything.
+    let write_log = tree
+        .remove_prefix(Context::background(), b"")
+        .expect("remove_prefix");
+    assert_eq!(write_log.len(), 89);
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    assert_eq!(hash, Hash::empty_hash());
+
+    // Removing from an overlay should only remove from the inner tree on commit.
+    let (tree, keys, _) = build(&["A", "B"]);
+    let (_, _, expected_hash) = build(&["A"]);
+    let mut overlay = OverlayTree::new(tree);
+    overlay
+        .insert(Context::background(), b"Bnew", b"value")
+        .expect("insert");
+    let write_log = overlay
+        .remove_prefix(Context::background(), b"B")
+        .expect("remove_prefix");
+    let mut expected_keys = keys.clone();
+    expected_keys.push(b"Bnew".to_vec());
+    expected_keys.sort();
+    assert_eq!(write_log, deletes(&expected_keys, b"B"));
+    for key in &expected_keys {
+        let value = overlay.get(Context::background(), key).expect("get");
+        assert_eq!(value.is_some(), !key.starts_with(b"B"));
+    }
+    let (write_log, hash) = overlay
+        .commit_both(Context::background(), Default::default(), 0)
+        .expect("commit");
+    assert_eq!(write_log.len(), 101);
+    assert_eq!(hash, expected_hash);
+}
+
+#[test]
+fn test_fork() {
+    let mut tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+    let (keys, values) = generate_key_value_pairs_ex("".to_string(), 100);
+    for i in 0..keys.len() {
+        tree.insert(Context::background(), &keys[i], &values[i])
+            .expect("insert");
+    }
+
+    // Forking a tree with uncommitted changes should fail.
+    let err = tree
+        .fork(Box::new(NoopReadSyncer))
+        .expect_err("fork should fail");
+    assert!(matches!(err.downcast_ref(), Some(TreeError::DirtyRoot)));
+
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    // Changes in the fork should not be visible in the original tree.
+    let mut fork = tree.fork(Box::new(NoopReadSyncer)).expect("fork");
+    assert!(tree.cache.borrow().is_forked());
+    fork.insert(Context::background(), b"new key", b"new value")
+        .expect("insert");
+    fork.insert(Context::background(), &keys[0], b"updated value")
+        .expect("insert");
+    for key in &keys[1..50] {
+        fork.remove(Context::background(), key).expect("remove");
+    }
+    let fork_hash = fork
+        .commit(Context::background(), Default::default(), 1)
+        .expect("commit");
+    assert_ne!(fork_hash, hash);
+
+    assert_eq!(
+        tree.get(Context::background(), b"new key").expect("get"),
+        None
+    );
+    for i in 0..keys.len() {
+        assert_eq!(
+            tree.get(Context::background(), &keys[i]).expect("get"),
+            Some(values[i].clone())
+        );
+    }
+
+    // Changes in another fork should not be visible in the fork.
+    let mut other = tree.fork(Box::new(NoopReadSyncer)).expect("fork");
+    other
+        .remove(Context::background(), &keys[0])
+        .expect("remove");
+    assert_eq!(
+        fork.get(Context::background(), &keys[0]).expect("get"),
+        Some(b"updated value".to_vec())
+    );
+    assert_eq!(
+        tree.get(Context::background(), &keys[0]).expect("get"),
+        Some(values[0].clone())
+    );
+
+    // Merging a fork should replace the state of the original tree.
+    fork.merge().expect("merge");
+    assert_eq!(
+        tree.get(Context::background(), b"new key").expect("get"),
+        Some(b"new value".to_vec())
+    );
+
+    // Merging into a tree which has changed since forking should fail.
+    let err = other.merge().expect_err("merge should fail");
+    assert!(matches!(err.downcast_ref(), Some(TreeError::ForkDiverged)));
+
+    // Clean nodes should no longer be shared once all forks are gone.
+    assert!(!tree.cache.borrow().is_forked());
+    let merged_hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 1).expect("commit");
+    assert_eq!(merged_hash, fork_hash);
+
+    // Forks of forks should be merged into their parent fork.
+    let mut fork = tree.fork(Box::new(NoopReadSyncer)).expect("fork");
+    let mut nested = fork.fork(Box::new(NoopReadSyncer)).expect("fork");
+    nested
+        .insert(Context::background(), b"nested key", b"nested value")
+        .expect("insert");
+    nested
+        .commit(Context::background(), Default::default(), 2)
+        .expect("commit");
+    nested.merge().expect("merge");
+    assert_eq!(
+        fork.get(Context::background(), b"nested key").expect("get"),
+        Some(b"nested value".to_vec())
+    );
+    assert_eq!(
+        tree.get(Context::background(), b"nested key").expect("get"),
+        None
+    );
+    fork.remove(Context::background(), b"nested key")
+        .expect("remove");
+    let fork_hash = fork
+        .commit(Context::background(), Default::default(), 2)
+        .expect("commit");
+    assert_eq!(fork_hash, merged_hash);
+}
+
+#[test]
+fn test_fork_cache() {
+    let mut tree = Tree::builder()
+        .with_capacity(128, 0)
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+    let (keys, values) = generate_key_value_pairs_ex("".to_string(), 50);
+    for i in 0..keys.len() {
+        tree.insert(Context::background(), &keys[i], &values[i])
+            .expect("insert");
+    }
+    Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+
+    // Filling the fork to capacity should only evict nodes cached by the fork.
+    let mut fork = tree.fork(Box::new(NoopReadSyncer)).expect("fork");
+    let (fork_keys, fork_values) = generate_key_value_pairs_ex("fork ".to_string(), 200);
+    for i in 0..fork_keys.len() {
+        fork.insert(Context::background(), &fork_keys[i], &fork_values[i])
+            .expect("insert");
+    }
+    fork.commit(Context::background(), Default::default(), 1)
+        .expect("commit");
+    let stats = fork.cache_stats();
+    assert!(stats.eviction_count > 0, "fork cache.eviction_count");
+    assert!(
+        stats.internal_node_count <= 128,
+        "fork cache.internal_node_count"
+    );
+
+    // Reading through the parent should not need to fetch any nodes.
+    for i in 0..keys.len() {
+        assert_eq!(
+            tree.get(Context::background(), &keys[i]).expect("get"),
+            Some(values[i].clone())
+        );
+    }
+    let stats = tree.cache_stats();
+    assert_eq!(stats.eviction_count, 0, "cache.eviction_count");
+    assert_eq!(stats.miss_count, 0, "cache.miss_count");
+}
+
+#[test]
+fn test_diff() {
+    let datadir = tempfile::Builder::default()
+        .prefix("oasis-test-mkvs-diff")
+        .tempdir()
+        .expect("failed to create temporary data directory");
+    let db = FileNodeDB::open(datadir.path()).expect("open");
+    let commit = |items: &BTreeMap<Vec<u8>, Vec<u8>>, version: u64| {
+        let mut tree = Tree::builder()
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+        for (key, value) in items {
+            tree.insert(Context::background(), key, value)
+                .expect("insert");
+        }
+        tree.commit(Context::background(), Default::default(), version)
+            .expect("commit");
+        db.commit_tree(Context::background(), &tree)
+            .expect("commit_tree")
+    };
+    let expected_diff = |from: &BTreeMap<Vec<u8>, Vec<u8>>, to: &BTreeMap<Vec<u8>, Vec<u8>>| {
+        let mut keys: Vec<&Vec<u8>> = from.keys().chain(to.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter(|key| from.get(*key) != to.get(*key))
+            .map(|key| LogEntry {
+                key: key.clone(),
+                value: to.get(key).cloned(),
+            })
+            .collect::<WriteLog>()
+    };
+
+    let (keys, values) = generate_key_value_pairs();
+    let items_a: BTreeMap<Vec<u8>, Vec<u8>> = keys.into_iter().zip(values).collect();
+    let mut items_b = items_a.clone();
+    // Updates, removals and insertions, including keys which are prefixes of existing keys
+    // and keys which split existing node labels.
+    items_b.insert(b"key 1".to_vec(), b"updated".to_vec());
+    items_b.insert(b"key 999".to_vec(), b"updated".to_vec());
+    for i in 100..200 {
+        items_b.remove(format!("key {}", i).as_bytes());
+    }
+    items_b.insert(b"key".to_vec(), b"new".to_vec());
+    items_b.insert(b"key 1000".to_vec(), b"new".to_vec());
+    items_b.insert(b"a new key".to_vec(), b"new".to_vec());
+    items_b.insert(b"key 10a".to_vec(), b"new".to_vec());
+    let empty = BTreeMap::new();
+
+    let root_a = commit(&items_a, 1);
+    let root_b = commit(&items_b, 2);
+    let root_empty = commit(&empty, 3);
+
+    for &(from, from_root, to, to_root) in &[
+        (&items_a, root_a, &items_b, root_b),
+        (&items_b, root_b, &items_a, root_a),
+        (&items_a, root_a, &items_a, root_a),
+        (&empty, root_empty, &items_b, root_b),
+        (&items_b, root_b, &empty, root_empty),
+    ] {
+        let tree = db.open_tree(from_root).expect("open_tree");
+        let write_log = tree.diff(Context::background(), to_root).expect("diff");
+        assert_eq!(write_log, expected_diff(from, to));
+
+        // Applying the diff should result in the other root.
+        let mut tree = db.open_tree(from_root).expect("open_tree");
+        tree.apply_write_log(Context::background(), &write_log, to_root)
+            .expect("apply_write_log");
+        for (key, value) in to {
+            assert_eq!(
+                tree.get(Context::background(), key).expect("get"),
+                Some(value.clone())
+            );
+        }
+    }
+
+    // Applying a write log resulting in a different root should fail.
+    let mut tree = db.open_tree(root_a).expect("open_tree");
+    let err = tree
+        .apply_write_log(Context::background(), &WriteLog::new(), root_b)
+        .expect_err("apply_write_log should fail");
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(TreeError::RootMismatch { .. })
+    ));
+
+    // Computing a diff with uncommitted changes should fail.
+    tree.insert(Context::background(), b"key", b"value")
+        .expect("insert");
+    let err = tree
+        .diff(Context::background(), root_b)
+        .expect_err("diff should fail");
+    assert!(matches!(err.downcast_ref(), Some(TreeError::DirtyRoot)));
+}
+
+/// A read syncer which panics on any request.
+struct PanickingReadSyncer;
+
+impl ReadSync for PanickingReadSyncer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn sync_get(&mut self, _ctx: Context, _request: GetRequest) -> Result<ProofResponse> {
+        panic!("sync_get called");
+    }
+
+    fn sync_get_prefixes(
+        &mut self,
+        _ctx: Context,
+        _request: GetPrefixesRequest,
+    ) -> Result<ProofResponse> {
+        panic!("sync_get_prefixes called");
+    }
+
+    fn sync_iterate(&mut self, _ctx: Context, _request: IterateRequest) -> Result<ProofResponse> {
+        panic!("sync_iterate called");
+    }
+}
+
+#[test]
+fn test_diff_panic() {
+    let mut tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root_type(RootType::State)
+        .build(Box::new(PanickingReadSyncer));
+    let (keys, values) = generate_key_value_pairs_ex("".to_string(), 10);
+    for i in 0..keys.len() {
+        tree.insert(Context::background(), &keys[i], &values[i])
+            .expect("insert");
+    }
+    Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    let root = tree.cache.borrow().get_sync_root();
+
+    // A panic while fetching the other root should leave the tree intact.
+    let other_root = Root {
+        hash: Hash::digest_bytes(b"other root"),
+        ..root
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        tree.diff(Context::background(), other_root)
+    }));
+    assert!(result.is_err(), "diff should panic");
+
+    assert_eq!(tree.cache.borrow().get_sync_root(), root);
+    for i in 0..keys.len() {
+        assert_eq!(
+            tree.get(Context::background(), &keys[i]).expect("get"),
+            Some(values[i].clone())
+        );
+    }
+}
+
 #[test]
 fn test_syncer_basic() {
     let server = ProtocolServer::new(None);
@@ -794,6 +1209,145 @@ fn test_syncer_remove() {
     assert_eq!(0, stats.sync_iterate_count, "sync_iterate count");
 }
 
+#[test]
+fn test_syncer_remove_prefix() {
+    let mut tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+    let mut expected_tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+        if !keys[i].starts_with(b"key 1") {
+            expected_tree
+                .insert(
+                    Context::background(),
+                    keys[i].as_slice(),
+                    values[i].as_slice(),
+                )
+                .expect("insert");
+        }
+    }
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    assert_eq!(format!("{:?}", hash), ALL_ITEMS_ROOT);
+    let expected_hash = Tree::commit(
+        &mut expected_tree,
+        Context::background(),
+        Default::default(),
+        0,
+    )
+    .expect("commit");
+
+    let stats = StatsCollector::new(Box::new(tree));
+    let mut remote_tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root(Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        })
+        .build(Box::new(stats));
+
+    let write_log = remote_tree
+        .remove_prefix(Context::background(), b"key 1")
+        .expect("remove_prefix");
+    assert_eq!(write_log.len(), 111);
+
+    let hash = Tree::commit(
+        &mut remote_tree,
+        Context::background(),
+        Default::default(),
+        0,
+    )
+    .expect("commit");
+    assert_eq!(hash, expected_hash);
+
+    // Only the path to the prefix and the removed subtree should have been fetched.
+    let cache = remote_tree.cache.borrow();
+    let stats = cache
+        .get_read_syncer()
+        .as_any()
+        .downcast_ref::<StatsCollector>()
+        .expect("stats");
+    assert_eq!(1, stats.sync_get_count, "sync_get count");
+    assert_eq!(0, stats.sync_get_prefixes_count, "sync_get_prefixes count");
+    assert_eq!(1, stats.sync_iterate_count, "sync_iterate count");
+}
+
+#[test]
+fn test_syncer_get_many() {
+    let mut tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root_type(RootType::State)
+        .build(Box::new(NoopReadSyncer));
+
+    let (keys, values) = generate_key_value_pairs();
+    for i in 0..keys.len() {
+        tree.insert(
+            Context::background(),
+            keys[i].as_slice(),
+            values[i].as_slice(),
+        )
+        .expect("insert");
+    }
+    let hash =
+        Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+    assert_eq!(format!("{:?}", hash), ALL_ITEMS_ROOT);
+
+    let stats = StatsCollector::new(Box::new(tree));
+    let remote_tree = Tree::builder()
+        .with_capacity(0, 0)
+        .with_root(Root {
+            root_type: RootType::State,
+            hash,
+            ..Default::default()
+        })
+        .build(Box::new(stats));
+
+    // Fetch a set of unrelated keys, including one which does not exist.
+    let mut batch: Vec<&[u8]> = keys.iter().step_by(97).map(|key| key.as_slice()).collect();
+    batch.push(b"missing key");
+    let batch_values = remote_tree
+        .get_many(Context::background(), &batch)
+        .expect("get_many");
+    let mut expected_values: Vec<Option<Vec<u8>>> =
+        values.iter().step_by(97).cloned().map(Some).collect();
+    expected_values.push(None);
+    assert_eq!(batch_values, expected_values);
+
+    // All keys should now be available locally.
+    for key in &batch {
+        remote_tree.get(Context::background(), key).expect("get");
+    }
+    let batch_values_again = remote_tree
+        .get_many(Context::background(), &batch)
+        .expect("get_many");
+    assert_eq!(batch_values_again, batch_values);
+
+    // A single request should have been made to fetch all of the keys.
+    let cache = remote_tree.cache.borrow();
+    let stats = cache
+        .get_read_syncer()
+        .as_any()
+        .downcast_ref::<StatsCollector>()
+        .expect("stats");
+    assert_eq!(0, stats.sync_get_count, "sync_get count");
+    assert_eq!(1, stats.sync_get_many_count, "sync_get_many count");
+    assert_eq!(0, stats.sync_get_prefixes_count, "sync_get_prefixes count");
+    assert_eq!(0, stats.sync_iterate_count, "sync_iterate count");
+}
+
 #[test]
 fn test_syncer_insert() {
     let server = ProtocolServer::new(None);
@@ -949,6 +1503,103 @@ fn test_syncer_prefetch_prefixes() {
     assert_eq!(0, stats.sync_iterate_count, "sync_iterate count");
 }
 
+#[test]
+fn test_syncer_local() {
+    // Build a tree that is used to directly serve sync requests.
+    let build_local = || {
+        let mut tree = Tree::builder()
+            .with_capacity(0, 0)
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+
+        let (keys, values) = generate_key_value_pairs();
+        for i in 0..keys.len() {
+            tree.insert(
+                Context::background(),
+                keys[i].as_slice(),
+                values[i].as_slice(),
+            )
+            .expect("insert");
+        }
+        let hash =
+            Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+        assert_eq!(format!("{:?}", hash), ALL_ITEMS_ROOT);
+
+        (tree, hash)
+    };
+    let build_remote = |local: Tree, hash: Hash| {
+        Tree::builder()
+            .with_capacity(0, 0)
+            .with_root(Root {
+                root_type: RootType::State,
+                hash,
+                ..Default::default()
+            })
+            .build(Box::new(StatsCollector::new(Box::new(local))))
+    };
+    let get_stats = |tree: &Tree| {
+        let cache = tree.cache.borrow();
+        let stats = cache
+            .get_read_syncer()
+            .as_any()
+            .downcast_ref::<StatsCollector>()
+            .expect("stats");
+        (
+            stats.sync_get_count,
+            stats.sync_get_prefixes_count,
+            stats.sync_iterate_count,
+        )
+    };
+
+    let (keys, values) = generate_key_value_pairs();
+
+    // Single key fetches.
+    let (local, hash) = build_local();
+    let remote_tree = build_remote(local, hash);
+    for i in 0..keys.len() {
+        let value = remote_tree
+            .get(Context::background(), keys[i].as_slice())
+            .expect("get")
+            .expect("get_some");
+        assert_eq!(values[i], value.as_slice());
+    }
+    assert_eq!((keys.len(), 0, 0), get_stats(&remote_tree), "stats");
+
+    // Prefix fetches.
+    let (local, hash) = build_local();
+    let remote_tree = build_remote(local, hash);
+    remote_tree
+        .prefetch_prefixes(Context::background(), &[b"key".to_vec().into()], 1000)
+        .expect("prefetch_prefixes");
+    for i in 0..keys.len() {
+        let value = remote_tree
+            .get(Context::background(), keys[i].as_slice())
+            .expect("get")
+            .expect("get_some");
+        assert_eq!(values[i], value.as_slice());
+    }
+    assert_eq!((0, 1, 0), get_stats(&remote_tree), "stats");
+
+    // Iteration.
+    let (local, hash) = build_local();
+    let remote_tree = build_remote(local, hash);
+    let mut items: Vec<(Vec<u8>, Vec<u8>)> = keys.into_iter().zip(values).collect();
+    items.sort();
+
+    let mut it = remote_tree.iter(Context::background());
+    it.set_prefetch(100);
+    it.rewind();
+    let fetched: Vec<(Vec<u8>, Vec<u8>)> = it.by_ref().collect();
+    assert!(it.error().is_none(), "iterator should not error");
+    assert_eq!(items, fetched, "iterator should return all items");
+    drop(it);
+
+    let (sync_get_count, sync_get_prefixes_count, sync_iterate_count) = get_stats(&remote_tree);
+    assert_eq!(0, sync_get_count, "sync_get count");
+    assert_eq!(0, sync_get_prefixes_count, "sync_get_prefixes count");
+    assert!(sync_iterate_count > 0, "sync_iterate count");
+}
+
 #[test]
 fn test_value_eviction() {
     let mut tree = Tree::builder()
@@ -1022,6 +1673,94 @@ fn test_node_eviction() {
     );
 }
 
+#[test]
+fn test_eviction_policy() {
+    let (keys, values) = generate_key_value_pairs();
+    let build_local = || {
+        let mut tree = Tree::builder()
+            .with_capacity(0, 0)
+            .with_root_type(RootType::State)
+            .build(Box::new(NoopReadSyncer));
+        for i in 0..keys.len() {
+            tree.insert(
+                Context::background(),
+                keys[i].as_slice(),
+                values[i].as_slice(),
+            )
+            .expect("insert");
+        }
+        let hash =
+            Tree::commit(&mut tree, Context::background(), Default::default(), 0).expect("commit");
+        (tree, hash)
+    };
+
+    let hot_keys: Vec<&[u8]> = keys.iter().step_by(331).map(|key| key.as_slice()).collect();
+    let mut sorted_keys = keys.clone();
+    sorted_keys.sort();
+    let run = |policy: EvictionPolicy| -> (bool, CacheStats) {
+        let (tree, hash) = build_local();
+        let remote_tree = Tree::builder()
+            .with_capacity(128, 0)
+            .with_eviction_policy(policy)
+            .with_root(Root {
+                root_type: RootType::State,
+                hash,
+                ..Default::default()
+            })
+            .build(Box::new(tree));
+
+        let use_hot_keys = || {
+            for key in &hot_keys {
+                remote_tree
+                    .get(Context::background(), key)
+                    .expect("get")
+                    .expect("hot key should exist");
+            }
+        };
+        let scan = |start: usize, count: usize| {
+            let mut it = remote_tree.iter(Context::background());
+            it.set_prefetch(10);
+            it.seek(&sorted_keys[start]);
+            assert_eq!(
+                count,
+                it.by_ref().take(count).count(),
+                "iterator should return items"
+            );
+            assert!(it.error().is_none(), "iterator should not error");
+        };
+
+        // Use a few keys in between short scans which evict them.
+        for start in &[900, 800] {
+            use_hot_keys();
+            scan(*start, 100);
+        }
+        // Use the same keys again, then scan over all keys.
+        use_hot_keys();
+        scan(0, sorted_keys.len());
+
+        let hot_cached = hot_keys
+            .iter()
+            .all(|key| remote_tree.cache_contains_key(Context::background(), key));
+        (hot_cached, remote_tree.cache_stats())
+    };
+
+    // With plain LRU the scan flushes the keys which were used before.
+    let (hot_cached, stats) = run(EvictionPolicy::LRU);
+    assert!(!hot_cached, "hot keys should be evicted by the scan");
+    assert!(stats.hit_count > 0, "cache.hit_count");
+    assert!(stats.miss_count > 0, "cache.miss_count");
+    assert!(stats.eviction_count > 0, "cache.eviction_count");
+
+    // A scan-resistant policy keeps them in cache once they have been needed again.
+    let (hot_cached, stats) = run(EvictionPolicy::TwoQueue);
+    assert!(hot_cached, "hot keys should survive the scan");
+    assert!(stats.eviction_count > 0, "cache.eviction_count");
+    assert!(
+        stats.internal_node_count <= 128,
+        "cache.internal_node_count"
+    );
+}
+
 /// Location of the test vectors directory (from Go).
 const TEST_VECTORS_DIR: &str = "../go/storage/mkvs/testdata";
 
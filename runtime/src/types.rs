@@ -221,6 +221,12 @@ pub enum Body {
         nodes: Vec<signature::PublicKey>,
         #[cbor(optional, rename = "pf")]
         peer_feedback: Option<enclave_rpc::types::PeerFeedback>,
+        /// Node the peer feedback refers to. If not set, it refers to the last call.
+        #[cbor(optional, rename = "pf_node")]
+        peer_feedback_node: Option<signature::PublicKey>,
+        /// Timeout of the call in milliseconds.
+        #[cbor(optional)]
+        timeout: Option<u64>,
     },
     HostRPCCallResponse {
         response: Vec<u8>,